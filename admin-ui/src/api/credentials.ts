@@ -8,6 +8,16 @@ import type {
   SetPriorityRequest,
   AddCredentialRequest,
   AddCredentialResponse,
+  ApiKeysResponse,
+  CreateApiKeyRequest,
+  CreateApiKeyResponse,
+  UpdateApiKeyRequest,
+  UsageGroupBy,
+  UsageReport,
+  ConfigResponse,
+  UpdateConfigRequest,
+  UpdateConfigResponse,
+  LoadBalancingMode,
 } from '@/types/api'
 
 // 创建 axios 实例
@@ -86,13 +96,68 @@ export async function deleteCredential(id: number): Promise<SuccessResponse> {
 }
 
 // 获取负载均衡模式
-export async function getLoadBalancingMode(): Promise<{ mode: 'priority' | 'balanced' }> {
-  const { data } = await api.get<{ mode: 'priority' | 'balanced' }>('/config/load-balancing')
+export async function getLoadBalancingMode(): Promise<{ mode: LoadBalancingMode }> {
+  const { data } = await api.get<{ mode: LoadBalancingMode }>('/config/load-balancing')
   return data
 }
 
 // 设置负载均衡模式
-export async function setLoadBalancingMode(mode: 'priority' | 'balanced'): Promise<{ mode: 'priority' | 'balanced' }> {
-  const { data } = await api.put<{ mode: 'priority' | 'balanced' }>('/config/load-balancing', { mode })
+export async function setLoadBalancingMode(mode: LoadBalancingMode): Promise<{ mode: LoadBalancingMode }> {
+  const { data } = await api.put<{ mode: LoadBalancingMode }>('/config/load-balancing', { mode })
+  return data
+}
+
+// 获取运行时配置
+export async function getConfig(): Promise<ConfigResponse> {
+  const { data } = await api.get<ConfigResponse>('/config')
+  return data
+}
+
+// 修改配置
+export async function updateConfig(
+  req: UpdateConfigRequest
+): Promise<UpdateConfigResponse> {
+  const { data } = await api.patch<UpdateConfigResponse>('/config', req)
+  return data
+}
+
+// 获取所有客户端 API Key
+export async function getApiKeys(): Promise<ApiKeysResponse> {
+  const { data } = await api.get<ApiKeysResponse>('/api-keys')
+  return data
+}
+
+// 创建客户端 API Key
+export async function createApiKey(
+  req: CreateApiKeyRequest
+): Promise<CreateApiKeyResponse> {
+  const { data } = await api.post<CreateApiKeyResponse>('/api-keys', req)
+  return data
+}
+
+// 修改客户端 API Key
+export async function updateApiKey(
+  id: number,
+  req: UpdateApiKeyRequest
+): Promise<SuccessResponse> {
+  const { data } = await api.put<SuccessResponse>(`/api-keys/${id}`, req)
+  return data
+}
+
+// 删除客户端 API Key
+export async function deleteApiKey(id: number): Promise<SuccessResponse> {
+  const { data } = await api.delete<SuccessResponse>(`/api-keys/${id}`)
+  return data
+}
+
+// 查询请求用量
+export async function getUsage(params: {
+  from?: string
+  to?: string
+  groupBy: UsageGroupBy
+}): Promise<UsageReport> {
+  const { data } = await api.get<UsageReport>('/usage', {
+    params: { from: params.from, to: params.to, group_by: params.groupBy },
+  })
   return data
 }
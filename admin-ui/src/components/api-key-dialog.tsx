@@ -0,0 +1,303 @@
+import { useEffect, useState } from 'react'
+import { toast } from 'sonner'
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogHeader,
+  DialogTitle,
+  DialogFooter,
+} from '@/components/ui/dialog'
+import { Button } from '@/components/ui/button'
+import { Input } from '@/components/ui/input'
+import { useCreateApiKey, useUpdateApiKey } from '@/hooks/use-api-keys'
+import { extractErrorMessage } from '@/lib/utils'
+import type { ApiKeyItem, UpdateApiKeyRequest } from '@/types/api'
+
+interface ApiKeyDialogProps {
+  open: boolean
+  onOpenChange: (open: boolean) => void
+  /** 为空时创建新 Key，否则编辑该 Key */
+  apiKey: ApiKeyItem | null
+}
+
+// 解析配额输入，留空表示不限制
+function parseLimit(value: string): number | null {
+  const trimmed = value.trim()
+  if (!trimmed) return null
+  const n = parseInt(trimmed)
+  return Number.isFinite(n) && n >= 0 ? n : null
+}
+
+function formatLimit(value: number | null): string {
+  return value === null ? '' : String(value)
+}
+
+// RFC3339 -> datetime-local 输入值（本地时间）
+function toLocalInput(value: string | null): string {
+  if (!value) return ''
+  const date = new Date(value)
+  if (isNaN(date.getTime())) return ''
+  const offset = date.getTimezoneOffset() * 60000
+  return new Date(date.getTime() - offset).toISOString().slice(0, 16)
+}
+
+export function ApiKeyDialog({ open, onOpenChange, apiKey }: ApiKeyDialogProps) {
+  const [name, setName] = useState('')
+  const [customKey, setCustomKey] = useState('')
+  const [allowedModels, setAllowedModels] = useState('')
+  const [dailyRequestLimit, setDailyRequestLimit] = useState('')
+  const [monthlyRequestLimit, setMonthlyRequestLimit] = useState('')
+  const [dailyTokenLimit, setDailyTokenLimit] = useState('')
+  const [monthlyTokenLimit, setMonthlyTokenLimit] = useState('')
+  const [expiresAt, setExpiresAt] = useState('')
+  const [createdKey, setCreatedKey] = useState<string | null>(null)
+
+  const createApiKey = useCreateApiKey()
+  const updateApiKey = useUpdateApiKey()
+  const isPending = createApiKey.isPending || updateApiKey.isPending
+  const isEdit = apiKey !== null
+
+  useEffect(() => {
+    if (!open) return
+    setName(apiKey?.name ?? '')
+    setCustomKey('')
+    setAllowedModels(apiKey?.allowedModels.join(', ') ?? '')
+    setDailyRequestLimit(formatLimit(apiKey?.dailyRequestLimit ?? null))
+    setMonthlyRequestLimit(formatLimit(apiKey?.monthlyRequestLimit ?? null))
+    setDailyTokenLimit(formatLimit(apiKey?.dailyTokenLimit ?? null))
+    setMonthlyTokenLimit(formatLimit(apiKey?.monthlyTokenLimit ?? null))
+    setExpiresAt(toLocalInput(apiKey?.expiresAt ?? null))
+    setCreatedKey(null)
+  }, [open, apiKey])
+
+  const handleCopy = async () => {
+    if (!createdKey) return
+    try {
+      await navigator.clipboard.writeText(createdKey)
+      toast.success('已复制到剪贴板')
+    } catch {
+      toast.error('复制失败，请手动复制')
+    }
+  }
+
+  const handleSubmit = (e: React.FormEvent) => {
+    e.preventDefault()
+
+    if (!name.trim()) {
+      toast.error('请输入名称')
+      return
+    }
+
+    const req: UpdateApiKeyRequest = {
+      name: name.trim(),
+      enabled: apiKey?.enabled ?? true,
+      allowedModels: allowedModels
+        .split(/[,\n]/)
+        .map((m) => m.trim())
+        .filter(Boolean),
+      dailyRequestLimit: parseLimit(dailyRequestLimit),
+      monthlyRequestLimit: parseLimit(monthlyRequestLimit),
+      dailyTokenLimit: parseLimit(dailyTokenLimit),
+      monthlyTokenLimit: parseLimit(monthlyTokenLimit),
+      expiresAt: expiresAt ? new Date(expiresAt).toISOString() : null,
+    }
+
+    if (isEdit) {
+      updateApiKey.mutate(
+        { id: apiKey.id, req },
+        {
+          onSuccess: (data) => {
+            toast.success(data.message)
+            onOpenChange(false)
+          },
+          onError: (error: unknown) => {
+            toast.error(`保存失败: ${extractErrorMessage(error)}`)
+          },
+        }
+      )
+    } else {
+      createApiKey.mutate(
+        { ...req, key: customKey.trim() || undefined },
+        {
+          onSuccess: (data) => {
+            toast.success(data.message)
+            setCreatedKey(data.key)
+          },
+          onError: (error: unknown) => {
+            toast.error(`创建失败: ${extractErrorMessage(error)}`)
+          },
+        }
+      )
+    }
+  }
+
+  if (createdKey) {
+    return (
+      <Dialog open={open} onOpenChange={onOpenChange}>
+        <DialogContent className="sm:max-w-lg">
+          <DialogHeader>
+            <DialogTitle>API Key 已创建</DialogTitle>
+            <DialogDescription>
+              完整的 Key 仅显示这一次，请立即复制并妥善保存
+            </DialogDescription>
+          </DialogHeader>
+          <div className="py-4">
+            <Input value={createdKey} readOnly className="font-mono" />
+          </div>
+          <DialogFooter>
+            <Button type="button" variant="outline" onClick={handleCopy}>
+              复制
+            </Button>
+            <Button type="button" onClick={() => onOpenChange(false)}>
+              完成
+            </Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
+    )
+  }
+
+  return (
+    <Dialog open={open} onOpenChange={onOpenChange}>
+      <DialogContent className="sm:max-w-lg max-h-[85vh] flex flex-col">
+        <DialogHeader>
+          <DialogTitle>{isEdit ? `编辑 API Key #${apiKey.id}` : '创建 API Key'}</DialogTitle>
+        </DialogHeader>
+
+        <form onSubmit={handleSubmit} className="flex flex-col min-h-0 flex-1">
+          <div className="space-y-4 py-4 overflow-y-auto flex-1 pr-1">
+            {/* 名称 */}
+            <div className="space-y-2">
+              <label htmlFor="apiKeyName" className="text-sm font-medium">
+                名称 <span className="text-red-500">*</span>
+              </label>
+              <Input
+                id="apiKeyName"
+                placeholder="用于区分客户端，例如：团队 A"
+                value={name}
+                onChange={(e) => setName(e.target.value)}
+                disabled={isPending}
+              />
+            </div>
+
+            {/* 自定义 Key（仅创建） */}
+            {!isEdit && (
+              <div className="space-y-2">
+                <label htmlFor="apiKeyValue" className="text-sm font-medium">
+                  Key
+                </label>
+                <Input
+                  id="apiKeyValue"
+                  placeholder="留空自动生成 sk-kiro-..."
+                  value={customKey}
+                  onChange={(e) => setCustomKey(e.target.value)}
+                  disabled={isPending}
+                />
+              </div>
+            )}
+
+            {/* 允许的模型 */}
+            <div className="space-y-2">
+              <label htmlFor="allowedModels" className="text-sm font-medium">
+                允许的模型
+              </label>
+              <Input
+                id="allowedModels"
+                placeholder="多个模型用逗号分隔，留空表示不限制"
+                value={allowedModels}
+                onChange={(e) => setAllowedModels(e.target.value)}
+                disabled={isPending}
+              />
+              <p className="text-xs text-muted-foreground">
+                可填写模型 ID 或别名，例如 claude-sonnet-4-5-20250929
+              </p>
+            </div>
+
+            {/* 请求数配额 */}
+            <div className="space-y-2">
+              <label className="text-sm font-medium">请求数配额</label>
+              <div className="grid grid-cols-2 gap-2">
+                <Input
+                  id="dailyRequestLimit"
+                  type="number"
+                  min="0"
+                  placeholder="每日"
+                  value={dailyRequestLimit}
+                  onChange={(e) => setDailyRequestLimit(e.target.value)}
+                  disabled={isPending}
+                />
+                <Input
+                  id="monthlyRequestLimit"
+                  type="number"
+                  min="0"
+                  placeholder="每月"
+                  value={monthlyRequestLimit}
+                  onChange={(e) => setMonthlyRequestLimit(e.target.value)}
+                  disabled={isPending}
+                />
+              </div>
+            </div>
+
+            {/* Token 配额 */}
+            <div className="space-y-2">
+              <label className="text-sm font-medium">Token 配额</label>
+              <div className="grid grid-cols-2 gap-2">
+                <Input
+                  id="dailyTokenLimit"
+                  type="number"
+                  min="0"
+                  placeholder="每日"
+                  value={dailyTokenLimit}
+                  onChange={(e) => setDailyTokenLimit(e.target.value)}
+                  disabled={isPending}
+                />
+                <Input
+                  id="monthlyTokenLimit"
+                  type="number"
+                  min="0"
+                  placeholder="每月"
+                  value={monthlyTokenLimit}
+                  onChange={(e) => setMonthlyTokenLimit(e.target.value)}
+                  disabled={isPending}
+                />
+              </div>
+              <p className="text-xs text-muted-foreground">
+                留空表示不限制。日/月计数按 UTC 自然日、自然月重置
+              </p>
+            </div>
+
+            {/* 过期时间 */}
+            <div className="space-y-2">
+              <label htmlFor="expiresAt" className="text-sm font-medium">
+                过期时间
+              </label>
+              <Input
+                id="expiresAt"
+                type="datetime-local"
+                value={expiresAt}
+                onChange={(e) => setExpiresAt(e.target.value)}
+                disabled={isPending}
+              />
+              <p className="text-xs text-muted-foreground">留空表示永不过期</p>
+            </div>
+          </div>
+
+          <DialogFooter>
+            <Button
+              type="button"
+              variant="outline"
+              onClick={() => onOpenChange(false)}
+              disabled={isPending}
+            >
+              取消
+            </Button>
+            <Button type="submit" disabled={isPending}>
+              {isPending ? '保存中...' : isEdit ? '保存' : '创建'}
+            </Button>
+          </DialogFooter>
+        </form>
+      </DialogContent>
+    </Dialog>
+  )
+}
@@ -0,0 +1,223 @@
+import { useState } from 'react'
+import { toast } from 'sonner'
+import { KeyRound, Pencil, Plus, Trash2 } from 'lucide-react'
+import { Card, CardContent, CardHeader, CardTitle } from '@/components/ui/card'
+import { Button } from '@/components/ui/button'
+import { Badge } from '@/components/ui/badge'
+import { Switch } from '@/components/ui/switch'
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogFooter,
+  DialogHeader,
+  DialogTitle,
+} from '@/components/ui/dialog'
+import { ApiKeyDialog } from '@/components/api-key-dialog'
+import { useApiKeys, useDeleteApiKey, useUpdateApiKey } from '@/hooks/use-api-keys'
+import { extractErrorMessage } from '@/lib/utils'
+import type { ApiKeyItem } from '@/types/api'
+
+function formatNumber(n: number): string {
+  return n.toLocaleString()
+}
+
+function formatQuota(used: number, limit: number | null): string {
+  return limit === null ? formatNumber(used) : `${formatNumber(used)} / ${formatNumber(limit)}`
+}
+
+function formatDateTime(value: string | null | undefined): string {
+  if (!value) return '-'
+  const date = new Date(value)
+  return isNaN(date.getTime()) ? value : date.toLocaleString()
+}
+
+export function ApiKeysPanel() {
+  const [dialogOpen, setDialogOpen] = useState(false)
+  const [editingKey, setEditingKey] = useState<ApiKeyItem | null>(null)
+  const [deletingKey, setDeletingKey] = useState<ApiKeyItem | null>(null)
+
+  const { data, isLoading } = useApiKeys()
+  const updateApiKey = useUpdateApiKey()
+  const deleteApiKey = useDeleteApiKey()
+
+  const handleCreate = () => {
+    setEditingKey(null)
+    setDialogOpen(true)
+  }
+
+  const handleEdit = (item: ApiKeyItem) => {
+    setEditingKey(item)
+    setDialogOpen(true)
+  }
+
+  const handleToggleEnabled = (item: ApiKeyItem) => {
+    updateApiKey.mutate(
+      {
+        id: item.id,
+        req: {
+          name: item.name,
+          enabled: !item.enabled,
+          allowedModels: item.allowedModels,
+          dailyRequestLimit: item.dailyRequestLimit,
+          monthlyRequestLimit: item.monthlyRequestLimit,
+          dailyTokenLimit: item.dailyTokenLimit,
+          monthlyTokenLimit: item.monthlyTokenLimit,
+          expiresAt: item.expiresAt,
+        },
+      },
+      {
+        onSuccess: () => {
+          toast.success(`API Key「${item.name}」已${item.enabled ? '禁用' : '启用'}`)
+        },
+        onError: (error: unknown) => {
+          toast.error(`操作失败: ${extractErrorMessage(error)}`)
+        },
+      }
+    )
+  }
+
+  const handleConfirmDelete = () => {
+    if (!deletingKey) return
+    deleteApiKey.mutate(deletingKey.id, {
+      onSuccess: (res) => {
+        toast.success(res.message)
+        setDeletingKey(null)
+      },
+      onError: (error: unknown) => {
+        toast.error(`删除失败: ${extractErrorMessage(error)}`)
+      },
+    })
+  }
+
+  const keys = data?.keys ?? []
+
+  return (
+    <div className="space-y-4 mt-10">
+      <div className="flex items-center justify-between">
+        <h2 className="text-xl font-semibold">API Key 管理</h2>
+        <Button onClick={handleCreate} size="sm">
+          <Plus className="h-4 w-4 mr-2" />
+          创建 API Key
+        </Button>
+      </div>
+
+      {isLoading ? (
+        <Card>
+          <CardContent className="py-8 text-center text-muted-foreground">加载中...</CardContent>
+        </Card>
+      ) : keys.length === 0 ? (
+        <Card>
+          <CardContent className="py-8 text-center text-muted-foreground">
+            暂无客户端 API Key，所有请求使用主 API Key
+          </CardContent>
+        </Card>
+      ) : (
+        <div className="grid gap-4 md:grid-cols-2 lg:grid-cols-3">
+          {keys.map((item) => (
+            <Card key={item.id} className={!item.enabled || item.expired ? 'opacity-60' : undefined}>
+              <CardHeader className="pb-2">
+                <div className="flex items-center justify-between">
+                  <CardTitle className="text-base flex items-center gap-2">
+                    <KeyRound className="h-4 w-4" />
+                    {item.name}
+                    {item.expired && <Badge variant="destructive">已过期</Badge>}
+                    {!item.enabled && <Badge variant="secondary">已禁用</Badge>}
+                  </CardTitle>
+                  <Switch
+                    checked={item.enabled}
+                    onCheckedChange={() => handleToggleEnabled(item)}
+                    disabled={updateApiKey.isPending}
+                  />
+                </div>
+                <div className="text-xs font-mono text-muted-foreground">{item.maskedKey}</div>
+              </CardHeader>
+              <CardContent className="space-y-3 text-sm">
+                <div className="grid grid-cols-2 gap-2">
+                  <div>
+                    <span className="text-muted-foreground">今日请求：</span>
+                    {formatQuota(item.usage.dailyRequests, item.dailyRequestLimit)}
+                  </div>
+                  <div>
+                    <span className="text-muted-foreground">本月请求：</span>
+                    {formatQuota(item.usage.monthlyRequests, item.monthlyRequestLimit)}
+                  </div>
+                  <div>
+                    <span className="text-muted-foreground">今日 Token：</span>
+                    {formatQuota(item.usage.dailyTokens, item.dailyTokenLimit)}
+                  </div>
+                  <div>
+                    <span className="text-muted-foreground">本月 Token：</span>
+                    {formatQuota(item.usage.monthlyTokens, item.monthlyTokenLimit)}
+                  </div>
+                  <div>
+                    <span className="text-muted-foreground">累计请求：</span>
+                    {formatNumber(item.usage.totalRequests)}
+                  </div>
+                  <div>
+                    <span className="text-muted-foreground">累计 Token：</span>
+                    {formatNumber(item.usage.totalInputTokens + item.usage.totalOutputTokens)}
+                  </div>
+                </div>
+                <div>
+                  <span className="text-muted-foreground">允许模型：</span>
+                  {item.allowedModels.length === 0 ? '全部' : item.allowedModels.join(', ')}
+                </div>
+                <div className="grid grid-cols-2 gap-2 text-xs text-muted-foreground">
+                  <div>过期时间：{formatDateTime(item.expiresAt)}</div>
+                  <div>最后使用：{formatDateTime(item.usage.lastUsedAt)}</div>
+                </div>
+                <div className="flex justify-end gap-2 pt-1">
+                  <Button size="sm" variant="outline" onClick={() => handleEdit(item)}>
+                    <Pencil className="h-4 w-4 mr-1" />
+                    编辑
+                  </Button>
+                  <Button
+                    size="sm"
+                    variant="outline"
+                    className="text-destructive hover:text-destructive"
+                    onClick={() => setDeletingKey(item)}
+                  >
+                    <Trash2 className="h-4 w-4 mr-1" />
+                    删除
+                  </Button>
+                </div>
+              </CardContent>
+            </Card>
+          ))}
+        </div>
+      )}
+
+      {/* 创建/编辑对话框 */}
+      <ApiKeyDialog open={dialogOpen} onOpenChange={setDialogOpen} apiKey={editingKey} />
+
+      {/* 删除确认对话框 */}
+      <Dialog open={deletingKey !== null} onOpenChange={(open) => !open && setDeletingKey(null)}>
+        <DialogContent>
+          <DialogHeader>
+            <DialogTitle>确认删除 API Key</DialogTitle>
+            <DialogDescription>
+              删除后使用「{deletingKey?.name}」的客户端将无法继续访问，此操作无法撤销。
+            </DialogDescription>
+          </DialogHeader>
+          <DialogFooter>
+            <Button
+              variant="outline"
+              onClick={() => setDeletingKey(null)}
+              disabled={deleteApiKey.isPending}
+            >
+              取消
+            </Button>
+            <Button
+              variant="destructive"
+              onClick={handleConfirmDelete}
+              disabled={deleteApiKey.isPending}
+            >
+              确认删除
+            </Button>
+          </DialogFooter>
+        </DialogContent>
+      </Dialog>
+    </div>
+  )
+}
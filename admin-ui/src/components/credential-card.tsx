@@ -15,7 +15,7 @@ import {
   DialogHeader,
   DialogTitle,
 } from '@/components/ui/dialog'
-import type { CredentialStatusItem, BalanceResponse } from '@/types/api'
+import type { CredentialStatusItem, BalanceResponse, DisabledReason } from '@/types/api'
 import {
   useSetDisabled,
   useSetPriority,
@@ -48,6 +48,19 @@ function formatLastUsed(lastUsedAt: string | null): string {
   return `${days} 天前`
 }
 
+const DISABLED_REASON_LABELS: Record<DisabledReason, string> = {
+  manual: '手动禁用',
+  tooManyFailures: '连续失败',
+  quotaExceeded: '额度用尽',
+  invalidRefreshToken: 'refreshToken 失效',
+}
+
+function formatRefresh(credential: CredentialStatusItem): string {
+  if (!credential.lastRefreshAt) return '未刷新'
+  const when = formatLastUsed(credential.lastRefreshAt)
+  return credential.lastRefreshError ? `${when}（失败）` : `${when}（成功）`
+}
+
 export function CredentialCard({
   credential,
   onViewBalance,
@@ -144,7 +157,16 @@ export function CredentialCard({
                   <Badge variant="success">当前</Badge>
                 )}
                 {credential.disabled && (
-                  <Badge variant="destructive">已禁用</Badge>
+                  <Badge variant="destructive">
+                    {credential.disabledReason
+                      ? `已禁用 · ${DISABLED_REASON_LABELS[credential.disabledReason]}`
+                      : '已禁用'}
+                  </Badge>
+                )}
+                {credential.disabled && credential.reenableAt && (
+                  <Badge variant="secondary">
+                    {new Date(credential.reenableAt).toLocaleString()} 自动启用
+                  </Badge>
                 )}
               </CardTitle>
             </div>
@@ -225,6 +247,20 @@ export function CredentialCard({
               <span className="text-muted-foreground">最后调用：</span>
               <span className="font-medium">{formatLastUsed(credential.lastUsedAt)}</span>
             </div>
+            <div className="col-span-2">
+              <span className="text-muted-foreground">Token 刷新：</span>
+              <span
+                className={credential.lastRefreshError ? 'text-red-500 font-medium' : 'font-medium'}
+                title={credential.lastRefreshError ?? undefined}
+              >
+                {formatRefresh(credential)}
+              </span>
+              {credential.nextRefreshAt && (
+                <span className="text-xs text-muted-foreground ml-1">
+                  (下次 {new Date(credential.nextRefreshAt).toLocaleString()})
+                </span>
+              )}
+            </div>
             <div className="col-span-2">
               <span className="text-muted-foreground">剩余用量：</span>
               {loadingBalance ? (
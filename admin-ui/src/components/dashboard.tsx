@@ -12,15 +12,31 @@ import { AddCredentialDialog } from '@/components/add-credential-dialog'
 import { BatchImportDialog } from '@/components/batch-import-dialog'
 import { KamImportDialog } from '@/components/kam-import-dialog'
 import { BatchVerifyDialog, type VerifyResult } from '@/components/batch-verify-dialog'
+import { ApiKeysPanel } from '@/components/api-keys-panel'
+import { UsagePanel } from '@/components/usage-panel'
+import { SettingsPanel } from '@/components/settings-panel'
 import { useCredentials, useDeleteCredential, useResetFailure, useLoadBalancingMode, useSetLoadBalancingMode } from '@/hooks/use-credentials'
 import { getCredentialBalance } from '@/api/credentials'
 import { extractErrorMessage } from '@/lib/utils'
-import type { BalanceResponse } from '@/types/api'
+import type { BalanceResponse, LoadBalancingMode } from '@/types/api'
 
 interface DashboardProps {
   onLogout: () => void
 }
 
+const LOAD_BALANCING_MODE_LABELS: Record<LoadBalancingMode, string> = {
+  priority: '优先级模式',
+  balanced: '均衡负载',
+  quota: '按剩余额度',
+}
+
+// 顶部按钮按 priority → balanced → quota 循环切换
+const NEXT_LOAD_BALANCING_MODE: Record<LoadBalancingMode, LoadBalancingMode> = {
+  priority: 'balanced',
+  balanced: 'quota',
+  quota: 'priority',
+}
+
 export function Dashboard({ onLogout }: DashboardProps) {
   const [selectedCredentialId, setSelectedCredentialId] = useState<number | null>(null)
   const [balanceDialogOpen, setBalanceDialogOpen] = useState(false)
@@ -447,12 +463,11 @@ export function Dashboard({ onLogout }: DashboardProps) {
   // 切换负载均衡模式
   const handleToggleLoadBalancing = () => {
     const currentMode = loadBalancingData?.mode || 'priority'
-    const newMode = currentMode === 'priority' ? 'balanced' : 'priority'
+    const newMode = NEXT_LOAD_BALANCING_MODE[currentMode]
 
     setLoadBalancingMode(newMode, {
       onSuccess: () => {
-        const modeName = newMode === 'priority' ? '优先级模式' : '均衡负载模式'
-        toast.success(`已切换到${modeName}`)
+        toast.success(`已切换到${LOAD_BALANCING_MODE_LABELS[newMode]}`)
       },
       onError: (error) => {
         toast.error(`切换失败: ${extractErrorMessage(error)}`)
@@ -505,7 +520,7 @@ export function Dashboard({ onLogout }: DashboardProps) {
               disabled={isLoadingMode || isSettingMode}
               title="切换负载均衡模式"
             >
-              {isLoadingMode ? '加载中...' : (loadBalancingData?.mode === 'priority' ? '优先级模式' : '均衡负载')}
+              {isLoadingMode ? '加载中...' : LOAD_BALANCING_MODE_LABELS[loadBalancingData?.mode || 'priority']}
             </Button>
             <Button variant="ghost" size="icon" onClick={toggleDarkMode}>
               {darkMode ? <Sun className="h-5 w-5" /> : <Moon className="h-5 w-5" />}
@@ -689,6 +704,15 @@ export function Dashboard({ onLogout }: DashboardProps) {
             </>
           )}
         </div>
+
+        {/* 客户端 API Key */}
+        <ApiKeysPanel />
+
+        {/* 用量统计 */}
+        <UsagePanel />
+
+        {/* 系统设置 */}
+        <SettingsPanel />
       </main>
 
       {/* 余额对话框 */}
@@ -0,0 +1,448 @@
+import { useEffect, useState } from 'react'
+import { toast } from 'sonner'
+import { RotateCcw, Save, Settings } from 'lucide-react'
+import { Card, CardContent, CardHeader, CardTitle } from '@/components/ui/card'
+import { Button } from '@/components/ui/button'
+import { Badge } from '@/components/ui/badge'
+import { Input } from '@/components/ui/input'
+import { Switch } from '@/components/ui/switch'
+import { useConfig, useUpdateConfig } from '@/hooks/use-config'
+import { extractErrorMessage } from '@/lib/utils'
+import type { ConfigView, UpdateConfigRequest } from '@/types/api'
+
+// 文本类字段（可选字段留空表示清除）
+type TextField =
+  | 'host'
+  | 'region'
+  | 'authRegion'
+  | 'apiRegion'
+  | 'kiroVersion'
+  | 'systemVersion'
+  | 'nodeVersion'
+  | 'machineId'
+  | 'countTokensApiUrl'
+  | 'proxyUrl'
+  | 'proxyUsername'
+
+const OPTIONAL_FIELDS: TextField[] = [
+  'authRegion',
+  'apiRegion',
+  'machineId',
+  'countTokensApiUrl',
+  'proxyUrl',
+  'proxyUsername',
+]
+
+interface FormState {
+  text: Record<TextField, string>
+  port: string
+  usageRetentionDays: string
+  tlsBackend: ConfigView['tlsBackend']
+  countTokensAuthType: ConfigView['countTokensAuthType']
+  loadBalancingMode: ConfigView['loadBalancingMode']
+  metricsRequireAdminKey: boolean
+  // 密钥：留空保持不变，clear 为 true 时清除
+  countTokensApiKey: string
+  clearCountTokensApiKey: boolean
+  proxyPassword: string
+  clearProxyPassword: boolean
+}
+
+function toForm(config: ConfigView): FormState {
+  return {
+    text: {
+      host: config.host,
+      region: config.region,
+      authRegion: config.authRegion ?? '',
+      apiRegion: config.apiRegion ?? '',
+      kiroVersion: config.kiroVersion,
+      systemVersion: config.systemVersion,
+      nodeVersion: config.nodeVersion,
+      machineId: config.machineId ?? '',
+      countTokensApiUrl: config.countTokensApiUrl ?? '',
+      proxyUrl: config.proxyUrl ?? '',
+      proxyUsername: config.proxyUsername ?? '',
+    },
+    port: String(config.port),
+    usageRetentionDays: String(config.usageRetentionDays),
+    tlsBackend: config.tlsBackend,
+    countTokensAuthType: config.countTokensAuthType,
+    loadBalancingMode: config.loadBalancingMode,
+    metricsRequireAdminKey: config.metricsRequireAdminKey,
+    countTokensApiKey: '',
+    clearCountTokensApiKey: false,
+    proxyPassword: '',
+    clearProxyPassword: false,
+  }
+}
+
+// 只提交与当前配置不同的字段
+function buildRequest(config: ConfigView, form: FormState): UpdateConfigRequest | string {
+  const req: Record<string, unknown> = {}
+
+  for (const [field, raw] of Object.entries(form.text) as [TextField, string][]) {
+    const value = raw.trim()
+    const optional = OPTIONAL_FIELDS.includes(field)
+    const current = (config[field] as string | null) ?? ''
+    if (value === current) continue
+    if (!value && !optional) return `${field} 不能为空`
+    req[field] = optional && !value ? null : value
+  }
+
+  const port = parseInt(form.port)
+  if (!Number.isInteger(port) || port <= 0 || port > 65535) return '端口无效'
+  if (port !== config.port) req.port = port
+
+  const retention = parseInt(form.usageRetentionDays)
+  if (!Number.isInteger(retention) || retention < 0) return '用量账本保留天数无效'
+  if (retention !== config.usageRetentionDays) req.usageRetentionDays = retention
+
+  if (form.tlsBackend !== config.tlsBackend) req.tlsBackend = form.tlsBackend
+  if (form.countTokensAuthType !== config.countTokensAuthType) {
+    req.countTokensAuthType = form.countTokensAuthType
+  }
+  if (form.loadBalancingMode !== config.loadBalancingMode) {
+    req.loadBalancingMode = form.loadBalancingMode
+  }
+  if (form.metricsRequireAdminKey !== config.metricsRequireAdminKey) {
+    req.metricsRequireAdminKey = form.metricsRequireAdminKey
+  }
+
+  if (form.clearCountTokensApiKey) req.countTokensApiKey = null
+  else if (form.countTokensApiKey.trim()) req.countTokensApiKey = form.countTokensApiKey.trim()
+  if (form.clearProxyPassword) req.proxyPassword = null
+  else if (form.proxyPassword) req.proxyPassword = form.proxyPassword
+
+  return req as UpdateConfigRequest
+}
+
+interface FieldProps {
+  id: string
+  label: string
+  hint?: string
+  restart?: boolean
+  children: React.ReactNode
+}
+
+function Field({ id, label, hint, restart, children }: FieldProps) {
+  return (
+    <div className="space-y-2">
+      <label htmlFor={id} className="text-sm font-medium flex items-center gap-2">
+        {label}
+        {restart && <Badge variant="secondary">需重启</Badge>}
+      </label>
+      {children}
+      {hint && <p className="text-xs text-muted-foreground">{hint}</p>}
+    </div>
+  )
+}
+
+const selectClassName =
+  'flex h-10 w-full rounded-md border border-input bg-background px-3 py-2 text-sm disabled:opacity-50'
+
+export function SettingsPanel() {
+  const { data, isLoading, error } = useConfig()
+  const updateConfig = useUpdateConfig()
+  const [form, setForm] = useState<FormState | null>(null)
+
+  useEffect(() => {
+    if (data) setForm(toForm(data.config))
+  }, [data])
+
+  const restartFields = new Set(data?.restartRequiredFields ?? [])
+  const isPending = updateConfig.isPending
+
+  const setText = (field: TextField, value: string) =>
+    setForm((f) => (f ? { ...f, text: { ...f.text, [field]: value } } : f))
+
+  const textInput = (field: TextField, label: string, placeholder?: string, hint?: string) => (
+    <Field id={`config-${field}`} label={label} hint={hint} restart={restartFields.has(field)}>
+      <Input
+        id={`config-${field}`}
+        value={form?.text[field] ?? ''}
+        placeholder={placeholder}
+        onChange={(e) => setText(field, e.target.value)}
+        disabled={isPending}
+      />
+    </Field>
+  )
+
+  const handleSubmit = (e: React.FormEvent) => {
+    e.preventDefault()
+    if (!data || !form) return
+
+    const req = buildRequest(data.config, form)
+    if (typeof req === 'string') {
+      toast.error(req)
+      return
+    }
+    if (Object.keys(req).length === 0) {
+      toast.info('配置没有变化')
+      return
+    }
+
+    updateConfig.mutate(req, {
+      onSuccess: (res) => {
+        if (res.restartRequired.length > 0) {
+          toast.warning(`配置已保存，以下字段需重启服务后生效：${res.restartRequired.join(', ')}`)
+        } else {
+          toast.success('配置已保存并生效')
+        }
+      },
+      onError: (error: unknown) => {
+        toast.error(`保存失败: ${extractErrorMessage(error)}`)
+      },
+    })
+  }
+
+  return (
+    <div className="space-y-4 mt-10">
+      <div className="flex items-center justify-between">
+        <h2 className="text-xl font-semibold flex items-center gap-2">
+          <Settings className="h-5 w-5" />
+          系统设置
+        </h2>
+      </div>
+
+      {isLoading || !form ? (
+        <Card>
+          <CardContent className="py-8 text-center text-muted-foreground">
+            {error ? `加载失败: ${extractErrorMessage(error)}` : '加载中...'}
+          </CardContent>
+        </Card>
+      ) : (
+        <form onSubmit={handleSubmit} className="space-y-4">
+          <div className="grid gap-4 lg:grid-cols-2">
+            <Card>
+              <CardHeader className="pb-2">
+                <CardTitle className="text-base">Kiro 上游</CardTitle>
+              </CardHeader>
+              <CardContent className="space-y-4">
+                {textInput('region', 'Region', 'us-east-1')}
+                <div className="grid grid-cols-2 gap-2">
+                  {textInput('authRegion', 'Auth Region', '默认同 Region')}
+                  {textInput('apiRegion', 'API Region', '默认同 Region')}
+                </div>
+                <div className="grid grid-cols-3 gap-2">
+                  {textInput('kiroVersion', 'Kiro 版本')}
+                  {textInput('systemVersion', '系统版本')}
+                  {textInput('nodeVersion', 'Node 版本')}
+                </div>
+                {textInput('machineId', 'Machine ID', '留空按凭据自动生成')}
+                <Field id="config-tlsBackend" label="TLS 后端">
+                  <select
+                    id="config-tlsBackend"
+                    className={selectClassName}
+                    value={form.tlsBackend}
+                    onChange={(e) =>
+                      setForm({ ...form, tlsBackend: e.target.value as FormState['tlsBackend'] })
+                    }
+                    disabled={isPending}
+                  >
+                    <option value="rustls">rustls</option>
+                    <option value="native-tls">native-tls</option>
+                  </select>
+                </Field>
+              </CardContent>
+            </Card>
+
+            <Card>
+              <CardHeader className="pb-2">
+                <CardTitle className="text-base">代理</CardTitle>
+              </CardHeader>
+              <CardContent className="space-y-4">
+                {textInput(
+                  'proxyUrl',
+                  '代理地址',
+                  'http://host:port 或 socks5://host:port',
+                  '留空表示不使用全局代理；凭据级代理优先'
+                )}
+                {textInput('proxyUsername', '用户名')}
+                <Field id="config-proxyPassword" label="密码">
+                  <div className="flex gap-2">
+                    <Input
+                      id="config-proxyPassword"
+                      type="password"
+                      placeholder={data?.config.hasProxyPassword ? '已配置，留空保持不变' : '未配置'}
+                      value={form.proxyPassword}
+                      onChange={(e) =>
+                        setForm({ ...form, proxyPassword: e.target.value, clearProxyPassword: false })
+                      }
+                      disabled={isPending || form.clearProxyPassword}
+                    />
+                    {data?.config.hasProxyPassword && (
+                      <Button
+                        type="button"
+                        variant={form.clearProxyPassword ? 'destructive' : 'outline'}
+                        onClick={() =>
+                          setForm({ ...form, proxyPassword: '', clearProxyPassword: !form.clearProxyPassword })
+                        }
+                        disabled={isPending}
+                      >
+                        清除
+                      </Button>
+                    )}
+                  </div>
+                </Field>
+              </CardContent>
+            </Card>
+
+            <Card>
+              <CardHeader className="pb-2">
+                <CardTitle className="text-base">count_tokens</CardTitle>
+              </CardHeader>
+              <CardContent className="space-y-4">
+                {textInput(
+                  'countTokensApiUrl',
+                  '外部 API 地址',
+                  'https://...',
+                  '留空时使用本地估算'
+                )}
+                <Field id="config-countTokensApiKey" label="API Key">
+                  <div className="flex gap-2">
+                    <Input
+                      id="config-countTokensApiKey"
+                      type="password"
+                      placeholder={
+                        data?.config.hasCountTokensApiKey ? '已配置，留空保持不变' : '未配置'
+                      }
+                      value={form.countTokensApiKey}
+                      onChange={(e) =>
+                        setForm({
+                          ...form,
+                          countTokensApiKey: e.target.value,
+                          clearCountTokensApiKey: false,
+                        })
+                      }
+                      disabled={isPending || form.clearCountTokensApiKey}
+                    />
+                    {data?.config.hasCountTokensApiKey && (
+                      <Button
+                        type="button"
+                        variant={form.clearCountTokensApiKey ? 'destructive' : 'outline'}
+                        onClick={() =>
+                          setForm({
+                            ...form,
+                            countTokensApiKey: '',
+                            clearCountTokensApiKey: !form.clearCountTokensApiKey,
+                          })
+                        }
+                        disabled={isPending}
+                      >
+                        清除
+                      </Button>
+                    )}
+                  </div>
+                </Field>
+                <Field id="config-countTokensAuthType" label="认证方式">
+                  <select
+                    id="config-countTokensAuthType"
+                    className={selectClassName}
+                    value={form.countTokensAuthType}
+                    onChange={(e) =>
+                      setForm({
+                        ...form,
+                        countTokensAuthType: e.target.value as FormState['countTokensAuthType'],
+                      })
+                    }
+                    disabled={isPending}
+                  >
+                    <option value="x-api-key">x-api-key</option>
+                    <option value="bearer">bearer</option>
+                  </select>
+                </Field>
+              </CardContent>
+            </Card>
+
+            <Card>
+              <CardHeader className="pb-2">
+                <CardTitle className="text-base">服务</CardTitle>
+              </CardHeader>
+              <CardContent className="space-y-4">
+                <div className="grid grid-cols-2 gap-2">
+                  {textInput('host', '监听地址')}
+                  <Field id="config-port" label="端口" restart={restartFields.has('port')}>
+                    <Input
+                      id="config-port"
+                      type="number"
+                      min="1"
+                      max="65535"
+                      value={form.port}
+                      onChange={(e) => setForm({ ...form, port: e.target.value })}
+                      disabled={isPending}
+                    />
+                  </Field>
+                </div>
+                <Field id="config-loadBalancingMode" label="负载均衡模式">
+                  <select
+                    id="config-loadBalancingMode"
+                    className={selectClassName}
+                    value={form.loadBalancingMode}
+                    onChange={(e) =>
+                      setForm({
+                        ...form,
+                        loadBalancingMode: e.target.value as FormState['loadBalancingMode'],
+                      })
+                    }
+                    disabled={isPending}
+                  >
+                    <option value="priority">优先级（priority）</option>
+                    <option value="balanced">均衡（balanced）</option>
+                    <option value="quota">按剩余额度（quota）</option>
+                  </select>
+                </Field>
+                <Field
+                  id="config-usageRetentionDays"
+                  label="用量账本保留天数"
+                  hint="0 表示永久保留"
+                  restart={restartFields.has('usageRetentionDays')}
+                >
+                  <Input
+                    id="config-usageRetentionDays"
+                    type="number"
+                    min="0"
+                    value={form.usageRetentionDays}
+                    onChange={(e) => setForm({ ...form, usageRetentionDays: e.target.value })}
+                    disabled={isPending}
+                  />
+                </Field>
+                <div className="flex items-center justify-between">
+                  <label htmlFor="config-metricsRequireAdminKey" className="text-sm font-medium flex items-center gap-2">
+                    /metrics 需要 Admin API Key
+                    {restartFields.has('metricsRequireAdminKey') && (
+                      <Badge variant="secondary">需重启</Badge>
+                    )}
+                  </label>
+                  <Switch
+                    id="config-metricsRequireAdminKey"
+                    checked={form.metricsRequireAdminKey}
+                    onCheckedChange={(checked) =>
+                      setForm({ ...form, metricsRequireAdminKey: checked })
+                    }
+                    disabled={isPending}
+                  />
+                </div>
+              </CardContent>
+            </Card>
+          </div>
+
+          <div className="flex justify-end gap-2">
+            <Button
+              type="button"
+              variant="outline"
+              onClick={() => data && setForm(toForm(data.config))}
+              disabled={isPending}
+            >
+              <RotateCcw className="h-4 w-4 mr-2" />
+              重置
+            </Button>
+            <Button type="submit" disabled={isPending}>
+              <Save className="h-4 w-4 mr-2" />
+              {isPending ? '保存中...' : '保存配置'}
+            </Button>
+          </div>
+        </form>
+      )}
+    </div>
+  )
+}
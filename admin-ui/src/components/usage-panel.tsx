@@ -0,0 +1,215 @@
+import { useMemo, useState } from 'react'
+import { BarChart3 } from 'lucide-react'
+import { Card, CardContent, CardHeader, CardTitle } from '@/components/ui/card'
+import { Button } from '@/components/ui/button'
+import { useUsage } from '@/hooks/use-usage'
+import type { UsageBucket, UsageGroupBy } from '@/types/api'
+
+const RANGE_OPTIONS = [7, 30, 90]
+
+const GROUP_OPTIONS: { value: UsageGroupBy; label: string }[] = [
+  { value: 'day', label: '按日期' },
+  { value: 'model', label: '按模型' },
+  { value: 'credential', label: '按凭据' },
+]
+
+function formatNumber(n: number): string {
+  return n.toLocaleString()
+}
+
+// 紧凑显示 token 数量（如 1.2M）
+function formatCompact(n: number): string {
+  if (n >= 1_000_000) return `${(n / 1_000_000).toFixed(1)}M`
+  if (n >= 1_000) return `${(n / 1_000).toFixed(1)}K`
+  return String(n)
+}
+
+// N 天前（含今天）的 UTC 日期
+function daysAgo(days: number): string {
+  const date = new Date(Date.now() - (days - 1) * 24 * 60 * 60 * 1000)
+  return date.toISOString().slice(0, 10)
+}
+
+function bucketLabel(groupBy: UsageGroupBy, key: string): string {
+  if (groupBy === 'credential') return key === '-' ? '未到达上游' : `凭据 #${key}`
+  if (groupBy === 'day') return key.slice(5)
+  return key
+}
+
+export function UsagePanel() {
+  const [days, setDays] = useState(7)
+  const [groupBy, setGroupBy] = useState<UsageGroupBy>('day')
+  const from = useMemo(() => daysAgo(days), [days])
+
+  const { data, isLoading, error } = useUsage(from, groupBy)
+  const buckets = data?.buckets ?? []
+  const maxTokens = Math.max(1, ...buckets.map((b) => b.inputTokens + b.outputTokens))
+
+  return (
+    <div className="space-y-4 mt-10">
+      <div className="flex flex-wrap items-center justify-between gap-2">
+        <h2 className="text-xl font-semibold">用量统计</h2>
+        <div className="flex flex-wrap gap-2">
+          <div className="flex gap-1">
+            {RANGE_OPTIONS.map((d) => (
+              <Button
+                key={d}
+                size="sm"
+                variant={days === d ? 'default' : 'outline'}
+                onClick={() => setDays(d)}
+              >
+                {d} 天
+              </Button>
+            ))}
+          </div>
+          <div className="flex gap-1">
+            {GROUP_OPTIONS.map((opt) => (
+              <Button
+                key={opt.value}
+                size="sm"
+                variant={groupBy === opt.value ? 'default' : 'outline'}
+                onClick={() => setGroupBy(opt.value)}
+              >
+                {opt.label}
+              </Button>
+            ))}
+          </div>
+        </div>
+      </div>
+
+      {isLoading ? (
+        <Card>
+          <CardContent className="py-8 text-center text-muted-foreground">加载中...</CardContent>
+        </Card>
+      ) : error || !data ? (
+        <Card>
+          <CardContent className="py-8 text-center text-red-500">加载用量统计失败</CardContent>
+        </Card>
+      ) : (
+        <>
+          {/* 汇总 */}
+          <div className="grid gap-4 grid-cols-2 md:grid-cols-5">
+            <SummaryCard title="请求数" value={formatNumber(data.total.requests)} />
+            <SummaryCard title="失败数" value={formatNumber(data.total.errors)} />
+            <SummaryCard title="输入 Token" value={formatCompact(data.total.inputTokens)} />
+            <SummaryCard title="输出 Token" value={formatCompact(data.total.outputTokens)} />
+            <SummaryCard title="平均耗时" value={`${(data.total.avgLatencyMs / 1000).toFixed(1)}s`} />
+          </div>
+
+          {/* 图表 */}
+          <Card>
+            <CardHeader className="pb-2">
+              <CardTitle className="text-base flex items-center gap-2">
+                <BarChart3 className="h-4 w-4" />
+                Token 用量
+                <span className="flex items-center gap-3 ml-auto text-xs font-normal text-muted-foreground">
+                  <span className="flex items-center gap-1">
+                    <span className="inline-block h-2 w-2 rounded-sm bg-blue-500" />
+                    输入
+                  </span>
+                  <span className="flex items-center gap-1">
+                    <span className="inline-block h-2 w-2 rounded-sm bg-green-500" />
+                    输出
+                  </span>
+                </span>
+              </CardTitle>
+            </CardHeader>
+            <CardContent>
+              {buckets.length === 0 ? (
+                <div className="py-8 text-center text-muted-foreground">暂无数据</div>
+              ) : groupBy === 'day' ? (
+                <DayChart buckets={buckets} maxTokens={maxTokens} />
+              ) : (
+                <BarList buckets={buckets} maxTokens={maxTokens} groupBy={groupBy} />
+              )}
+            </CardContent>
+          </Card>
+        </>
+      )}
+    </div>
+  )
+}
+
+function SummaryCard({ title, value }: { title: string; value: string }) {
+  return (
+    <Card>
+      <CardHeader className="pb-2">
+        <CardTitle className="text-sm font-medium text-muted-foreground">{title}</CardTitle>
+      </CardHeader>
+      <CardContent>
+        <div className="text-2xl font-bold">{value}</div>
+      </CardContent>
+    </Card>
+  )
+}
+
+function bucketTitle(bucket: UsageBucket): string {
+  return [
+    `请求 ${formatNumber(bucket.requests)}（失败 ${formatNumber(bucket.errors)}）`,
+    `输入 ${formatNumber(bucket.inputTokens)} / 输出 ${formatNumber(bucket.outputTokens)}`,
+    `平均耗时 ${(bucket.avgLatencyMs / 1000).toFixed(1)}s`,
+  ].join('\n')
+}
+
+// 按日期的纵向堆叠柱状图
+function DayChart({ buckets, maxTokens }: { buckets: UsageBucket[]; maxTokens: number }) {
+  return (
+    <div className="flex items-end gap-1 h-48">
+      {buckets.map((bucket) => {
+        const inputHeight = (bucket.inputTokens / maxTokens) * 100
+        const outputHeight = (bucket.outputTokens / maxTokens) * 100
+        return (
+          <div
+            key={bucket.key}
+            className="flex-1 min-w-0 flex flex-col items-center h-full"
+            title={`${bucket.key}\n${bucketTitle(bucket)}`}
+          >
+            <div className="flex-1 w-full flex flex-col justify-end">
+              <div className="w-full bg-green-500 rounded-t-sm" style={{ height: `${outputHeight}%` }} />
+              <div className="w-full bg-blue-500" style={{ height: `${inputHeight}%` }} />
+            </div>
+            {buckets.length <= 31 && (
+              <div className="mt-1 text-[10px] text-muted-foreground truncate w-full text-center">
+                {bucketLabel('day', bucket.key)}
+              </div>
+            )}
+          </div>
+        )
+      })}
+    </div>
+  )
+}
+
+// 按模型/凭据的横向条形图
+function BarList({
+  buckets,
+  maxTokens,
+  groupBy,
+}: {
+  buckets: UsageBucket[]
+  maxTokens: number
+  groupBy: UsageGroupBy
+}) {
+  return (
+    <div className="space-y-3">
+      {buckets.map((bucket) => {
+        const inputWidth = (bucket.inputTokens / maxTokens) * 100
+        const outputWidth = (bucket.outputTokens / maxTokens) * 100
+        return (
+          <div key={bucket.key} className="space-y-1" title={bucketTitle(bucket)}>
+            <div className="flex justify-between text-sm">
+              <span className="font-medium truncate">{bucketLabel(groupBy, bucket.key)}</span>
+              <span className="text-muted-foreground shrink-0 ml-2">
+                {formatNumber(bucket.requests)} 次 · {formatCompact(bucket.inputTokens + bucket.outputTokens)} Token
+              </span>
+            </div>
+            <div className="flex h-3 w-full overflow-hidden rounded-full bg-secondary">
+              <div className="h-full bg-blue-500" style={{ width: `${inputWidth}%` }} />
+              <div className="h-full bg-green-500" style={{ width: `${outputWidth}%` }} />
+            </div>
+          </div>
+        )
+      })}
+    </div>
+  )
+}
@@ -0,0 +1,46 @@
+import { useQuery, useMutation, useQueryClient } from '@tanstack/react-query'
+import { getApiKeys, createApiKey, updateApiKey, deleteApiKey } from '@/api/credentials'
+import type { CreateApiKeyRequest, UpdateApiKeyRequest } from '@/types/api'
+
+// 查询客户端 API Key 列表
+export function useApiKeys() {
+  return useQuery({
+    queryKey: ['api-keys'],
+    queryFn: getApiKeys,
+    refetchInterval: 30000, // 每 30 秒刷新一次
+  })
+}
+
+// 创建客户端 API Key
+export function useCreateApiKey() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: (req: CreateApiKeyRequest) => createApiKey(req),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['api-keys'] })
+    },
+  })
+}
+
+// 修改客户端 API Key
+export function useUpdateApiKey() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: ({ id, req }: { id: number; req: UpdateApiKeyRequest }) =>
+      updateApiKey(id, req),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['api-keys'] })
+    },
+  })
+}
+
+// 删除客户端 API Key
+export function useDeleteApiKey() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: (id: number) => deleteApiKey(id),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['api-keys'] })
+    },
+  })
+}
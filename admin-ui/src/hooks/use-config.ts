@@ -0,0 +1,24 @@
+import { useQuery, useMutation, useQueryClient } from '@tanstack/react-query'
+import { getConfig, updateConfig } from '@/api/credentials'
+import type { UpdateConfigRequest } from '@/types/api'
+
+// 查询运行时配置
+export function useConfig() {
+  return useQuery({
+    queryKey: ['config'],
+    queryFn: getConfig,
+  })
+}
+
+// 修改配置
+export function useUpdateConfig() {
+  const queryClient = useQueryClient()
+  return useMutation({
+    mutationFn: (req: UpdateConfigRequest) => updateConfig(req),
+    onSuccess: () => {
+      queryClient.invalidateQueries({ queryKey: ['config'] })
+      // 负载均衡模式也可能被修改
+      queryClient.invalidateQueries({ queryKey: ['loadBalancingMode'] })
+    },
+  })
+}
@@ -0,0 +1,12 @@
+import { useQuery } from '@tanstack/react-query'
+import { getUsage } from '@/api/credentials'
+import type { UsageGroupBy } from '@/types/api'
+
+// 查询请求用量（from 为 YYYY-MM-DD，UTC）
+export function useUsage(from: string, groupBy: UsageGroupBy) {
+  return useQuery({
+    queryKey: ['usage', from, groupBy],
+    queryFn: () => getUsage({ from, groupBy }),
+    refetchInterval: 60000, // 每 60 秒刷新一次
+  })
+}
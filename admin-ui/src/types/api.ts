@@ -7,10 +7,20 @@ export interface CredentialsStatusResponse {
 }
 
 // 单个凭据状态
+// 凭据禁用原因
+export type DisabledReason =
+  | 'manual'
+  | 'tooManyFailures'
+  | 'quotaExceeded'
+  | 'invalidRefreshToken'
+
 export interface CredentialStatusItem {
   id: number
   priority: number
   disabled: boolean
+  disabledReason: DisabledReason | null
+  disabledAt: string | null
+  reenableAt: string | null
   failureCount: number
   isCurrent: boolean
   expiresAt: string | null
@@ -22,6 +32,9 @@ export interface CredentialStatusItem {
   lastUsedAt: string | null
   hasProxy: boolean
   proxyUrl?: string
+  lastRefreshAt: string | null
+  lastRefreshError: string | null
+  nextRefreshAt: string | null
 }
 
 // 余额响应
@@ -80,3 +93,154 @@ export interface AddCredentialResponse {
   credentialId: number
   email?: string
 }
+
+// 客户端 API Key 使用量
+export interface ApiKeyUsage {
+  day: string
+  month: string
+  dailyRequests: number
+  dailyTokens: number
+  monthlyRequests: number
+  monthlyTokens: number
+  totalRequests: number
+  totalInputTokens: number
+  totalOutputTokens: number
+  lastUsedAt?: string
+}
+
+// 客户端 API Key 信息
+export interface ApiKeyItem {
+  id: number
+  name: string
+  maskedKey: string
+  enabled: boolean
+  expired: boolean
+  allowedModels: string[]
+  dailyRequestLimit: number | null
+  monthlyRequestLimit: number | null
+  dailyTokenLimit: number | null
+  monthlyTokenLimit: number | null
+  expiresAt: string | null
+  createdAt: string | null
+  usage: ApiKeyUsage
+}
+
+// 客户端 API Key 列表响应
+export interface ApiKeysResponse {
+  total: number
+  keys: ApiKeyItem[]
+}
+
+// 客户端 API Key 配置（修改请求）
+export interface UpdateApiKeyRequest {
+  name: string
+  enabled: boolean
+  allowedModels: string[]
+  dailyRequestLimit: number | null
+  monthlyRequestLimit: number | null
+  dailyTokenLimit: number | null
+  monthlyTokenLimit: number | null
+  expiresAt: string | null
+}
+
+// 创建客户端 API Key 请求
+export interface CreateApiKeyRequest extends UpdateApiKeyRequest {
+  key?: string
+}
+
+// 创建客户端 API Key 响应
+export interface CreateApiKeyResponse {
+  success: boolean
+  message: string
+  id: number
+  key: string
+}
+
+// --- 用量统计 ---
+
+export type UsageGroupBy = 'day' | 'model' | 'credential'
+
+// 用量统计分组
+export interface UsageBucket {
+  key: string
+  requests: number
+  errors: number
+  inputTokens: number
+  outputTokens: number
+  avgLatencyMs: number
+}
+
+// 用量统计响应
+export interface UsageReport {
+  from: string
+  to: string
+  groupBy: UsageGroupBy
+  total: UsageBucket
+  buckets: UsageBucket[]
+}
+
+// --- 运行时配置 ---
+
+export type TlsBackend = 'rustls' | 'native-tls'
+
+// 负载均衡模式
+export type LoadBalancingMode = 'priority' | 'balanced' | 'quota'
+
+// 运行时配置（密钥只返回是否已配置）
+export interface ConfigView {
+  host: string
+  port: number
+  region: string
+  authRegion: string | null
+  apiRegion: string | null
+  kiroVersion: string
+  systemVersion: string
+  nodeVersion: string
+  machineId: string | null
+  tlsBackend: TlsBackend
+  countTokensApiUrl: string | null
+  hasCountTokensApiKey: boolean
+  countTokensAuthType: 'x-api-key' | 'bearer'
+  proxyUrl: string | null
+  proxyUsername: string | null
+  hasProxyPassword: boolean
+  loadBalancingMode: LoadBalancingMode
+  metricsRequireAdminKey: boolean
+  usageRetentionDays: number
+}
+
+// 获取配置响应
+export interface ConfigResponse {
+  config: ConfigView
+  restartRequiredFields: string[]
+}
+
+// 修改配置请求（只包含需要修改的字段，可选字段传 null 表示清除）
+export interface UpdateConfigRequest {
+  host?: string
+  port?: number
+  region?: string
+  authRegion?: string | null
+  apiRegion?: string | null
+  kiroVersion?: string
+  systemVersion?: string
+  nodeVersion?: string
+  machineId?: string | null
+  tlsBackend?: TlsBackend
+  countTokensApiUrl?: string | null
+  countTokensApiKey?: string | null
+  countTokensAuthType?: 'x-api-key' | 'bearer'
+  proxyUrl?: string | null
+  proxyUsername?: string | null
+  proxyPassword?: string | null
+  loadBalancingMode?: LoadBalancingMode
+  metricsRequireAdminKey?: boolean
+  usageRetentionDays?: number
+}
+
+// 修改配置响应
+export interface UpdateConfigResponse {
+  config: ConfigView
+  changed: string[]
+  restartRequired: string[]
+}
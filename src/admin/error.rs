@@ -20,6 +20,27 @@ pub enum AdminServiceError {
 
     /// 凭据无效（验证失败）
     InvalidCredential(String),
+
+    /// 客户端 API Key 不存在
+    ApiKeyNotFound { id: u64 },
+
+    /// 客户端 API Key 参数无效
+    InvalidApiKey(String),
+
+    /// 查询参数无效
+    InvalidQuery(String),
+
+    /// 响应缓存未启用
+    CacheDisabled,
+
+    /// 配置重载失败（文件缺失或校验未通过）
+    ReloadFailed(String),
+
+    /// 配置修改无效（校验未通过或无法写回）
+    InvalidConfig(String),
+
+    /// dry-run 的请求无法转换
+    ConversionFailed(String),
 }
 
 impl fmt::Display for AdminServiceError {
@@ -31,6 +52,13 @@ impl fmt::Display for AdminServiceError {
             AdminServiceError::UpstreamError(msg) => write!(f, "上游服务错误: {}", msg),
             AdminServiceError::InternalError(msg) => write!(f, "内部错误: {}", msg),
             AdminServiceError::InvalidCredential(msg) => write!(f, "凭据无效: {}", msg),
+            AdminServiceError::ApiKeyNotFound { id } => write!(f, "API Key 不存在: {}", id),
+            AdminServiceError::InvalidApiKey(msg) => write!(f, "API Key 参数无效: {}", msg),
+            AdminServiceError::InvalidQuery(msg) => write!(f, "查询参数无效: {}", msg),
+            AdminServiceError::CacheDisabled => write!(f, "响应缓存未启用"),
+            AdminServiceError::ReloadFailed(msg) => write!(f, "配置重载失败: {}", msg),
+            AdminServiceError::InvalidConfig(msg) => write!(f, "配置修改失败: {}", msg),
+            AdminServiceError::ConversionFailed(msg) => write!(f, "请求转换失败: {}", msg),
         }
     }
 }
@@ -45,6 +73,13 @@ impl AdminServiceError {
             AdminServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
             AdminServiceError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AdminServiceError::InvalidCredential(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::ApiKeyNotFound { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::InvalidApiKey(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::CacheDisabled => StatusCode::BAD_REQUEST,
+            AdminServiceError::ReloadFailed(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::InvalidConfig(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::ConversionFailed(_) => StatusCode::BAD_REQUEST,
         }
     }
 
@@ -59,6 +94,27 @@ impl AdminServiceError {
             AdminServiceError::InvalidCredential(_) => {
                 AdminErrorResponse::invalid_request(self.to_string())
             }
+            AdminServiceError::ApiKeyNotFound { .. } => {
+                AdminErrorResponse::not_found(self.to_string())
+            }
+            AdminServiceError::InvalidApiKey(_) => {
+                AdminErrorResponse::invalid_request(self.to_string())
+            }
+            AdminServiceError::InvalidQuery(_) => {
+                AdminErrorResponse::invalid_request(self.to_string())
+            }
+            AdminServiceError::CacheDisabled => {
+                AdminErrorResponse::invalid_request(self.to_string())
+            }
+            AdminServiceError::ReloadFailed(_) => {
+                AdminErrorResponse::invalid_request(self.to_string())
+            }
+            AdminServiceError::InvalidConfig(_) => {
+                AdminErrorResponse::invalid_request(self.to_string())
+            }
+            AdminServiceError::ConversionFailed(_) => {
+                AdminErrorResponse::invalid_request(self.to_string())
+            }
         }
     }
 }
@@ -2,15 +2,16 @@
 
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::IntoResponse,
 };
 
 use super::{
     middleware::AdminState,
     types::{
-        AddCredentialRequest, SetDisabledRequest, SetLoadBalancingModeRequest, SetPriorityRequest,
-        SuccessResponse,
+        AddCredentialRequest, CreateApiKeyRequest, PromptRulesDryRunRequest, SetDisabledRequest,
+        SetLoadBalancingModeRequest, SetPriorityRequest, SuccessResponse, UpdateApiKeyRequest,
+        UpdateConfigRequest, UsageQuery,
     },
 };
 
@@ -124,3 +125,128 @@ pub async fn set_load_balancing_mode(
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
+
+/// GET /api/admin/api-keys
+/// 获取所有客户端 API Key
+pub async fn get_api_keys(State(state): State<AdminState>) -> impl IntoResponse {
+    let response = state.service.list_api_keys();
+    Json(response)
+}
+
+/// POST /api/admin/api-keys
+/// 创建客户端 API Key
+pub async fn create_api_key(
+    State(state): State<AdminState>,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> impl IntoResponse {
+    match state.service.create_api_key(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// PUT /api/admin/api-keys/:id
+/// 修改客户端 API Key 配置
+pub async fn update_api_key(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<UpdateApiKeyRequest>,
+) -> impl IntoResponse {
+    match state.service.update_api_key(id, payload) {
+        Ok(_) => Json(SuccessResponse::new(format!("API Key #{} 已更新", id))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/api-keys/:id
+/// 删除客户端 API Key
+pub async fn delete_api_key(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.delete_api_key(id) {
+        Ok(_) => Json(SuccessResponse::new(format!("API Key #{} 已删除", id))).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/usage
+/// 按模型、凭据或日期聚合查询请求用量
+pub async fn get_usage(
+    State(state): State<AdminState>,
+    Query(query): Query<UsageQuery>,
+) -> impl IntoResponse {
+    match state.service.query_usage(query) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/cache
+/// 获取响应缓存状态与命中统计
+pub async fn get_response_cache(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_response_cache())
+}
+
+/// DELETE /api/admin/cache
+/// 清空响应缓存
+pub async fn clear_response_cache(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.clear_response_cache() {
+        Ok(count) => {
+            Json(SuccessResponse::new(format!("已清除 {} 条响应缓存", count))).into_response()
+        }
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/count-tokens
+/// 获取远程 count_tokens 的熔断器状态与结果缓存统计
+pub async fn get_count_tokens_status(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_count_tokens_status())
+}
+
+/// POST /api/admin/count-tokens/reset
+/// 关闭熔断器并清空 count_tokens 结果缓存
+pub async fn reset_count_tokens(State(state): State<AdminState>) -> impl IntoResponse {
+    state.service.reset_count_tokens();
+    Json(SuccessResponse::new("已重置 count_tokens 熔断器与结果缓存"))
+}
+
+/// GET /api/admin/config
+/// 获取运行时配置（密钥已脱敏）
+pub async fn get_config(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_config())
+}
+
+/// PATCH /api/admin/config
+/// 修改配置并写回配置文件
+pub async fn update_config(
+    State(state): State<AdminState>,
+    Json(payload): Json<UpdateConfigRequest>,
+) -> impl IntoResponse {
+    match state.service.update_config(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/prompt-rules/dry-run
+/// 按当前提示词改写规则转换请求，返回改写后的 Kiro 请求
+pub async fn dry_run_prompt_rules(
+    State(state): State<AdminState>,
+    Json(payload): Json<PromptRulesDryRunRequest>,
+) -> impl IntoResponse {
+    match state.service.dry_run_prompt_rules(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/reload
+/// 重新加载 config.json 与 credentials.json，返回变化摘要
+pub async fn reload_config(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.reload() {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
@@ -8,10 +8,21 @@
 //! - 修改凭据优先级
 //! - 重置失败计数
 //! - 查询凭据余额
+//! - 管理客户端 API Key（名称、启用状态、模型权限、配额、过期时间）
+//! - 按模型/凭据/日期查询请求用量
+//! - 查看响应缓存命中统计、清空缓存
+//! - 查看、修改运行时配置
+//! - 热重载配置文件与凭据文件
 //!
 //! # 使用
 //! ```ignore
-//! let admin_service = AdminService::new(token_manager.clone());
+//! let admin_service = AdminService::new(
+//!     token_manager.clone(),
+//!     api_key_store.clone(),
+//!     usage_ledger.clone(),
+//!     response_cache.clone(),
+//!     reloader.clone(),
+//! );
 //! let admin_state = AdminState::new(admin_api_key, admin_service);
 //! let admin_router = create_admin_router(admin_state);
 //! ```
@@ -2,14 +2,17 @@
 
 use axum::{
     Router, middleware,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
 };
 
 use super::{
     handlers::{
-        add_credential, delete_credential, get_all_credentials, get_credential_balance,
-        get_load_balancing_mode, reset_failure_count, set_credential_disabled,
-        set_credential_priority, set_load_balancing_mode,
+        add_credential, clear_response_cache, create_api_key, delete_api_key, delete_credential,
+        dry_run_prompt_rules, get_all_credentials, get_api_keys, get_config,
+        get_count_tokens_status, get_credential_balance, get_load_balancing_mode,
+        get_response_cache, get_usage, reload_config, reset_count_tokens, reset_failure_count,
+        set_credential_disabled, set_credential_priority, set_load_balancing_mode, update_api_key,
+        update_config,
     },
     middleware::{AdminState, admin_auth_middleware},
 };
@@ -26,6 +29,19 @@ use super::{
 /// - `GET /credentials/:id/balance` - 获取凭据余额
 /// - `GET /config/load-balancing` - 获取负载均衡模式
 /// - `PUT /config/load-balancing` - 设置负载均衡模式
+/// - `GET /config` - 获取运行时配置（密钥已脱敏）
+/// - `PATCH /config` - 修改配置并写回配置文件
+/// - `GET /api-keys` - 获取所有客户端 API Key
+/// - `POST /api-keys` - 创建客户端 API Key
+/// - `PUT /api-keys/:id` - 修改客户端 API Key
+/// - `DELETE /api-keys/:id` - 删除客户端 API Key
+/// - `GET /usage` - 按模型/凭据/日期聚合查询请求用量
+/// - `GET /cache` - 获取响应缓存状态与命中统计
+/// - `DELETE /cache` - 清空响应缓存
+/// - `GET /count-tokens` - 获取远程 count_tokens 的熔断器状态与结果缓存统计
+/// - `POST /count-tokens/reset` - 关闭熔断器并清空 count_tokens 结果缓存
+/// - `POST /prompt-rules/dry-run` - 预览提示词改写规则改写后的 Kiro 请求
+/// - `POST /reload` - 重新加载配置文件与凭据文件
 ///
 /// # 认证
 /// 需要 Admin API Key 认证，支持：
@@ -46,6 +62,18 @@ pub fn create_admin_router(state: AdminState) -> Router {
             "/config/load-balancing",
             get(get_load_balancing_mode).put(set_load_balancing_mode),
         )
+        .route("/config", get(get_config).patch(update_config))
+        .route("/api-keys", get(get_api_keys).post(create_api_key))
+        .route("/api-keys/{id}", put(update_api_key).delete(delete_api_key))
+        .route("/usage", get(get_usage))
+        .route(
+            "/cache",
+            get(get_response_cache).delete(clear_response_cache),
+        )
+        .route("/count-tokens", get(get_count_tokens_status))
+        .route("/count-tokens/reset", post(reset_count_tokens))
+        .route("/prompt-rules/dry-run", post(dry_run_prompt_rules))
+        .route("/reload", post(reload_config))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             admin_auth_middleware,
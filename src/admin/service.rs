@@ -4,22 +4,39 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use chrono::Utc;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 
+use crate::anthropic::{self, RequestOrigin};
+use crate::apikey::{ApiKeyEntry, ApiKeyStore, ApiKeyUsage, ClientKey};
+use crate::cache::ResponseCache;
 use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::requests::kiro::KiroRequest;
 use crate::kiro::token_manager::MultiTokenManager;
+use crate::ledger::{GroupBy, UsageLedger, UsageReport};
+use crate::model::config::{Config, is_valid_load_balancing_mode};
+use crate::reload::{ConfigReloader, RESTART_REQUIRED_FIELDS, ReloadReport};
+use crate::token;
 
 use super::error::AdminServiceError;
 use super::types::{
-    AddCredentialRequest, AddCredentialResponse, BalanceResponse, CredentialStatusItem,
-    CredentialsStatusResponse, LoadBalancingModeResponse, SetLoadBalancingModeRequest,
+    AddCredentialRequest, AddCredentialResponse, ApiKeyItem, ApiKeysResponse, BalanceResponse,
+    ConfigResponse, ConfigView, CreateApiKeyRequest, CreateApiKeyResponse, CredentialStatusItem,
+    CredentialsStatusResponse, LoadBalancingModeResponse, PromptRulesDryRunRequest,
+    PromptRulesDryRunResponse, ResponseCacheResponse, SetLoadBalancingModeRequest,
+    UpdateApiKeyRequest, UpdateConfigRequest, UpdateConfigResponse, UsageQuery,
 };
 
 /// 余额缓存过期时间（秒），5 分钟
 const BALANCE_CACHE_TTL_SECS: i64 = 300;
 
+/// 用量查询默认天数
+const USAGE_DEFAULT_DAYS: i64 = 7;
+
+/// 用量查询最大跨度（天）
+const USAGE_MAX_DAYS: i64 = 366;
+
 /// 缓存的余额条目（含时间戳）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CachedBalance {
@@ -34,12 +51,22 @@ struct CachedBalance {
 /// 封装所有 Admin API 的业务逻辑
 pub struct AdminService {
     token_manager: Arc<MultiTokenManager>,
+    api_keys: Arc<ApiKeyStore>,
+    usage_ledger: Arc<UsageLedger>,
+    response_cache: Option<Arc<ResponseCache>>,
+    reloader: Arc<ConfigReloader>,
     balance_cache: Mutex<HashMap<u64, CachedBalance>>,
     cache_path: Option<PathBuf>,
 }
 
 impl AdminService {
-    pub fn new(token_manager: Arc<MultiTokenManager>) -> Self {
+    pub fn new(
+        token_manager: Arc<MultiTokenManager>,
+        api_keys: Arc<ApiKeyStore>,
+        usage_ledger: Arc<UsageLedger>,
+        response_cache: Option<Arc<ResponseCache>>,
+        reloader: Arc<ConfigReloader>,
+    ) -> Self {
         let cache_path = token_manager
             .cache_dir()
             .map(|d| d.join("kiro_balance_cache.json"));
@@ -48,6 +75,10 @@ impl AdminService {
 
         Self {
             token_manager,
+            api_keys,
+            usage_ledger,
+            response_cache,
+            reloader,
             balance_cache: Mutex::new(balance_cache),
             cache_path,
         }
@@ -64,6 +95,9 @@ impl AdminService {
                 id: entry.id,
                 priority: entry.priority,
                 disabled: entry.disabled,
+                disabled_reason: entry.disabled_reason,
+                disabled_at: entry.disabled_at,
+                reenable_at: entry.reenable_at,
                 failure_count: entry.failure_count,
                 is_current: entry.id == snapshot.current_id,
                 expires_at: entry.expires_at,
@@ -75,6 +109,9 @@ impl AdminService {
                 last_used_at: entry.last_used_at.clone(),
                 has_proxy: entry.has_proxy,
                 proxy_url: entry.proxy_url,
+                last_refresh_at: entry.last_refresh_at,
+                last_refresh_error: entry.last_refresh_error,
+                next_refresh_at: entry.next_refresh_at,
             })
             .collect();
 
@@ -208,6 +245,9 @@ impl AdminService {
             proxy_username: req.proxy_username,
             proxy_password: req.proxy_password,
             disabled: false, // 新添加的凭据默认启用
+            disabled_reason: None,
+            disabled_at: None,
+            reenable_at: None,
         };
 
         // 调用 token_manager 添加凭据
@@ -259,9 +299,9 @@ impl AdminService {
         req: SetLoadBalancingModeRequest,
     ) -> Result<LoadBalancingModeResponse, AdminServiceError> {
         // 验证模式值
-        if req.mode != "priority" && req.mode != "balanced" {
+        if !is_valid_load_balancing_mode(&req.mode) {
             return Err(AdminServiceError::InvalidCredential(
-                "mode 必须是 'priority' 或 'balanced'".to_string(),
+                "mode 必须是 'priority'、'balanced' 或 'quota'".to_string(),
             ));
         }
 
@@ -272,6 +312,99 @@ impl AdminService {
         Ok(LoadBalancingModeResponse { mode: req.mode })
     }
 
+    // ============ 客户端 API Key ============
+
+    /// 获取所有客户端 API Key
+    pub fn list_api_keys(&self) -> ApiKeysResponse {
+        let now = Utc::now();
+        let keys: Vec<ApiKeyItem> = self
+            .api_keys
+            .list()
+            .into_iter()
+            .map(|entry| ApiKeyItem {
+                id: entry.id,
+                masked_key: entry.masked_key(),
+                expired: entry.is_expired(now),
+                name: entry.name,
+                enabled: entry.enabled,
+                allowed_models: entry.allowed_models,
+                daily_request_limit: entry.daily_request_limit,
+                monthly_request_limit: entry.monthly_request_limit,
+                daily_token_limit: entry.daily_token_limit,
+                monthly_token_limit: entry.monthly_token_limit,
+                expires_at: entry.expires_at,
+                created_at: entry.created_at,
+                usage: entry.usage,
+            })
+            .collect();
+
+        ApiKeysResponse {
+            total: keys.len(),
+            keys,
+        }
+    }
+
+    /// 创建客户端 API Key
+    pub fn create_api_key(
+        &self,
+        req: CreateApiKeyRequest,
+    ) -> Result<CreateApiKeyResponse, AdminServiceError> {
+        let entry = ApiKeyEntry {
+            id: 0,
+            name: req.name.trim().to_string(),
+            key: req.key.map(|k| k.trim().to_string()).unwrap_or_default(),
+            enabled: req.enabled,
+            allowed_models: normalize_models(req.allowed_models),
+            daily_request_limit: req.daily_request_limit,
+            monthly_request_limit: req.monthly_request_limit,
+            daily_token_limit: req.daily_token_limit,
+            monthly_token_limit: req.monthly_token_limit,
+            expires_at: normalize_expires_at(req.expires_at),
+            created_at: None,
+            usage: ApiKeyUsage::default(),
+        };
+
+        let created = self
+            .api_keys
+            .add(entry)
+            .map_err(|e| self.classify_api_key_error(e, 0))?;
+
+        Ok(CreateApiKeyResponse {
+            success: true,
+            message: format!("API Key「{}」创建成功，ID: {}", created.name, created.id),
+            id: created.id,
+            key: created.key,
+        })
+    }
+
+    /// 修改客户端 API Key 配置
+    pub fn update_api_key(
+        &self,
+        id: u64,
+        req: UpdateApiKeyRequest,
+    ) -> Result<(), AdminServiceError> {
+        self.api_keys
+            .update(id, |entry| {
+                entry.name = req.name.trim().to_string();
+                entry.enabled = req.enabled;
+                entry.allowed_models = normalize_models(req.allowed_models);
+                entry.daily_request_limit = req.daily_request_limit;
+                entry.monthly_request_limit = req.monthly_request_limit;
+                entry.daily_token_limit = req.daily_token_limit;
+                entry.monthly_token_limit = req.monthly_token_limit;
+                entry.expires_at = normalize_expires_at(req.expires_at);
+            })
+            .map(|_| ())
+            .map_err(|e| self.classify_api_key_error(e, id))
+    }
+
+    /// 删除客户端 API Key
+    pub fn delete_api_key(&self, id: u64) -> Result<(), AdminServiceError> {
+        self.api_keys
+            .delete(id)
+            .map_err(|e| self.classify_api_key_error(e, id))
+    }
+
     // ============ 余额缓存持久化 ============
 
     fn load_balance_cache_from(cache_path: &Option<PathBuf>) -> HashMap<u64, CachedBalance> {
@@ -341,6 +474,162 @@ impl AdminService {
         }
     }
 
+    /// 按时间范围聚合查询用量账本
+    ///
+    /// 未指定 `to` 时为当前时间，未指定 `from` 时为 `to` 所在日期往前共 7 天
+    pub fn query_usage(&self, query: UsageQuery) -> Result<UsageReport, AdminServiceError> {
+        let group_by = match query.group_by.as_deref() {
+            None | Some("") => GroupBy::Day,
+            Some(value) => GroupBy::parse(value).ok_or_else(|| {
+                AdminServiceError::InvalidQuery(format!(
+                    "group_by 只能是 model、credential 或 day: {}",
+                    value
+                ))
+            })?,
+        };
+
+        let to = match query.to.as_deref() {
+            Some(value) => parse_query_time(value, true)?,
+            None => Utc::now(),
+        };
+        let from = match query.from.as_deref() {
+            Some(value) => parse_query_time(value, false)?,
+            None => (to.date_naive() - Duration::days(USAGE_DEFAULT_DAYS - 1))
+                .and_time(NaiveTime::MIN)
+                .and_utc(),
+        };
+
+        if from > to {
+            return Err(AdminServiceError::InvalidQuery(
+                "from 不能晚于 to".to_string(),
+            ));
+        }
+        if to - from > Duration::days(USAGE_MAX_DAYS) {
+            return Err(AdminServiceError::InvalidQuery(format!(
+                "查询跨度不能超过 {} 天",
+                USAGE_MAX_DAYS
+            )));
+        }
+
+        self.usage_ledger
+            .query(from, to, group_by)
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))
+    }
+
+    /// 获取响应缓存状态与命中统计
+    pub fn get_response_cache(&self) -> ResponseCacheResponse {
+        ResponseCacheResponse {
+            enabled: self.response_cache.is_some(),
+            stats: self.response_cache.as_ref().map(|cache| cache.stats()),
+        }
+    }
+
+    /// 清空响应缓存，返回清除的条目数
+    pub fn clear_response_cache(&self) -> Result<usize, AdminServiceError> {
+        let cache = self
+            .response_cache
+            .as_ref()
+            .ok_or(AdminServiceError::CacheDisabled)?;
+        Ok(cache.clear())
+    }
+
+    /// 获取远程 count_tokens 的熔断器状态与结果缓存统计
+    pub fn get_count_tokens_status(&self) -> token::CountTokensStatus {
+        token::remote_status()
+    }
+
+    /// 关闭熔断器并清空 count_tokens 结果缓存
+    pub fn reset_count_tokens(&self) {
+        token::reset_remote();
+    }
+
+    /// 获取运行时配置（密钥已脱敏）
+    pub fn get_config(&self) -> ConfigResponse {
+        ConfigResponse {
+            config: self.config_view(),
+            restart_required_fields: RESTART_REQUIRED_FIELDS
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+        }
+    }
+
+    /// 修改配置：写回配置文件并立即应用可热更新的字段
+    pub fn update_config(
+        &self,
+        req: UpdateConfigRequest,
+    ) -> Result<UpdateConfigResponse, AdminServiceError> {
+        let (changed, restart_required) = self
+            .reloader
+            .update_config(|config| apply_config_update(config, req))
+            .map_err(|e| AdminServiceError::InvalidConfig(format!("{:#}", e)))?;
+
+        if !changed.is_empty() || !restart_required.is_empty() {
+            tracing::info!(
+                changed = ?changed,
+                restart_required = ?restart_required,
+                "配置已通过 Admin API 修改"
+            );
+        }
+        Ok(UpdateConfigResponse {
+            config: self.config_view(),
+            changed,
+            restart_required,
+        })
+    }
+
+    /// 构建当前配置视图
+    ///
+    /// 负载均衡模式可单独通过 Admin API 修改，以 token manager 中的实时值为准
+    fn config_view(&self) -> ConfigView {
+        let mut view = config_view(&self.token_manager.config());
+        view.load_balancing_mode = self.token_manager.get_load_balancing_mode();
+        view
+    }
+
+    /// 按当前提示词改写规则转换请求，返回改写后的 Kiro 请求（不调用上游）
+    pub fn dry_run_prompt_rules(
+        &self,
+        req: PromptRulesDryRunRequest,
+    ) -> Result<PromptRulesDryRunResponse, AdminServiceError> {
+        let client = match req.client_id {
+            Some(id) => {
+                let entry = self
+                    .api_keys
+                    .list()
+                    .into_iter()
+                    .find(|e| e.id == id)
+                    .ok_or(AdminServiceError::ApiKeyNotFound { id })?;
+                Some(ClientKey {
+                    id: entry.id,
+                    name: entry.name,
+                })
+            }
+            None => None,
+        };
+        let origin = RequestOrigin {
+            route: req.route,
+            client,
+        };
+        let result = anthropic::preview_request(req.request, &origin)
+            .map_err(|e| AdminServiceError::ConversionFailed(e.to_string()))?;
+
+        Ok(PromptRulesDryRunResponse {
+            applied_rules: result.applied_rules,
+            kiro_request: KiroRequest {
+                conversation_state: result.conversation_state,
+                profile_arn: None,
+            },
+        })
+    }
+
+    /// 重新加载 config.json 与 credentials.json
+    pub fn reload(&self) -> Result<ReloadReport, AdminServiceError> {
+        self.reloader
+            .reload()
+            .map_err(|e| AdminServiceError::ReloadFailed(format!("{:#}", e)))
+    }
+
     /// 分类余额查询错误（可能涉及上游 API 调用）
     fn classify_balance_error(&self, e: anyhow::Error, id: u64) -> AdminServiceError {
         let msg = e.to_string();
@@ -400,15 +689,269 @@ impl AdminService {
         }
     }
 
+    /// 分类客户端 API Key 操作错误
+    fn classify_api_key_error(&self, e: anyhow::Error, id: u64) -> AdminServiceError {
+        let msg = e.to_string();
+        if msg.contains("不存在") {
+            AdminServiceError::ApiKeyNotFound { id }
+        } else if msg.contains("写入") || msg.contains("序列化") {
+            AdminServiceError::InternalError(msg)
+        } else {
+            AdminServiceError::InvalidApiKey(msg)
+        }
+    }
+
     /// 分类删除凭据错误
     fn classify_delete_error(&self, e: anyhow::Error, id: u64) -> AdminServiceError {
         let msg = e.to_string();
         if msg.contains("不存在") {
             AdminServiceError::NotFound { id }
-        } else if msg.contains("只能删除已禁用的凭据") || msg.contains("请先禁用凭据") {
+        } else if msg.contains("只能删除已禁用的凭据") || msg.contains("请先禁用凭据")
+        {
             AdminServiceError::InvalidCredential(msg)
         } else {
             AdminServiceError::InternalError(msg)
         }
     }
 }
+
+/// 去除空白的模型名
+fn normalize_models(models: Vec<String>) -> Vec<String> {
+    models
+        .into_iter()
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .collect()
+}
+
+/// 解析用量查询时间（RFC3339 或 `YYYY-MM-DD`）
+///
+/// 日期格式的 `to` 取当天结束时刻，使其包含整天
+fn parse_query_time(value: &str, end_of_day: bool) -> Result<DateTime<Utc>, AdminServiceError> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let time = if end_of_day {
+            date.and_time(NaiveTime::MIN) + Duration::days(1) - Duration::milliseconds(1)
+        } else {
+            date.and_time(NaiveTime::MIN)
+        };
+        return Ok(time.and_utc());
+    }
+    Err(AdminServiceError::InvalidQuery(format!(
+        "无法解析时间: {}（应为 RFC3339 或 YYYY-MM-DD）",
+        value
+    )))
+}
+
+/// 空字符串视为未设置过期时间
+fn normalize_expires_at(expires_at: Option<String>) -> Option<String> {
+    expires_at
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// 构建脱敏后的配置视图
+fn config_view(config: &Config) -> ConfigView {
+    ConfigView {
+        host: config.host.clone(),
+        port: config.port,
+        region: config.region.clone(),
+        auth_region: config.auth_region.clone(),
+        api_region: config.api_region.clone(),
+        kiro_version: config.kiro_version.clone(),
+        system_version: config.system_version.clone(),
+        node_version: config.node_version.clone(),
+        machine_id: config.machine_id.clone(),
+        tls_backend: config.tls_backend,
+        count_tokens_api_url: config.count_tokens_api_url.clone(),
+        has_count_tokens_api_key: config.count_tokens_api_key.is_some(),
+        count_tokens_auth_type: config.count_tokens_auth_type.clone(),
+        count_tokens_remote: config.count_tokens_remote.clone(),
+        proxy_url: config.proxy_url.clone(),
+        proxy_username: config.proxy_username.clone(),
+        has_proxy_password: config.proxy_password.is_some(),
+        load_balancing_mode: config.load_balancing_mode.clone(),
+        metrics_require_admin_key: config.metrics_require_admin_key,
+        usage_retention_days: config.usage_retention_days,
+        response_cache: config.response_cache.clone(),
+        documents: config.documents.clone(),
+        web_fetch: config.web_fetch.clone(),
+        batches: config.batches.clone(),
+        token_refresh: config.token_refresh.clone(),
+        quota_balancing: config.quota_balancing.clone(),
+        auto_reenable: config.auto_reenable.clone(),
+        system_prompt: config.system_prompt,
+        context_management: config.context_management.clone(),
+        tokenizer: config.tokenizer.clone(),
+        prompt_rules: config.prompt_rules.clone(),
+        prompt_rules_file: config.prompt_rules_file.clone(),
+    }
+}
+
+/// 将修改请求中出现的字段写入配置
+fn apply_config_update(config: &mut Config, req: UpdateConfigRequest) {
+    fn trimmed(value: String) -> String {
+        value.trim().to_string()
+    }
+    // 显式 null 与空字符串都表示清除
+    fn optional(value: Option<String>) -> Option<String> {
+        value.map(trimmed).filter(|s| !s.is_empty())
+    }
+
+    if let Some(v) = req.host {
+        config.host = trimmed(v);
+    }
+    if let Some(v) = req.port {
+        config.port = v;
+    }
+    if let Some(v) = req.region {
+        config.region = trimmed(v);
+    }
+    if let Some(v) = req.auth_region {
+        config.auth_region = optional(v);
+    }
+    if let Some(v) = req.api_region {
+        config.api_region = optional(v);
+    }
+    if let Some(v) = req.kiro_version {
+        config.kiro_version = trimmed(v);
+    }
+    if let Some(v) = req.system_version {
+        config.system_version = trimmed(v);
+    }
+    if let Some(v) = req.node_version {
+        config.node_version = trimmed(v);
+    }
+    if let Some(v) = req.machine_id {
+        config.machine_id = optional(v);
+    }
+    if let Some(v) = req.tls_backend {
+        config.tls_backend = v;
+    }
+    if let Some(v) = req.count_tokens_api_url {
+        config.count_tokens_api_url = optional(v);
+    }
+    if let Some(v) = req.count_tokens_api_key {
+        config.count_tokens_api_key = optional(v);
+    }
+    if let Some(v) = req.count_tokens_auth_type {
+        config.count_tokens_auth_type = trimmed(v);
+    }
+    if let Some(v) = req.count_tokens_remote {
+        config.count_tokens_remote = v;
+    }
+    if let Some(v) = req.proxy_url {
+        config.proxy_url = optional(v);
+    }
+    if let Some(v) = req.proxy_username {
+        config.proxy_username = optional(v);
+    }
+    if let Some(v) = req.proxy_password {
+        config.proxy_password = optional(v);
+    }
+    if let Some(v) = req.load_balancing_mode {
+        config.load_balancing_mode = trimmed(v);
+    }
+    if let Some(v) = req.metrics_require_admin_key {
+        config.metrics_require_admin_key = v;
+    }
+    if let Some(v) = req.usage_retention_days {
+        config.usage_retention_days = v;
+    }
+    if let Some(v) = req.response_cache {
+        config.response_cache = v;
+    }
+    if let Some(v) = req.documents {
+        config.documents = v;
+    }
+    if let Some(v) = req.web_fetch {
+        config.web_fetch = v;
+    }
+    if let Some(v) = req.batches {
+        config.batches = v;
+    }
+    if let Some(v) = req.token_refresh {
+        config.token_refresh = v;
+    }
+    if let Some(v) = req.quota_balancing {
+        config.quota_balancing = v;
+    }
+    if let Some(v) = req.auto_reenable {
+        config.auto_reenable = v;
+    }
+    if let Some(v) = req.system_prompt {
+        config.system_prompt = v;
+    }
+    if let Some(v) = req.context_management {
+        config.context_management = v;
+    }
+    if let Some(v) = req.tokenizer {
+        config.tokenizer = v;
+    }
+    if let Some(v) = req.prompt_rules {
+        config.prompt_rules = v;
+    }
+    if let Some(v) = req.prompt_rules_file {
+        config.prompt_rules_file = optional(v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::CredentialsConfig;
+
+    #[test]
+    fn test_get_config_reflects_load_balancing_mode_update() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config_path = dir.join("config.json");
+        let credentials_path = dir.join("credentials.json");
+        std::fs::write(&config_path, r#"{"apiKey": "k"}"#).unwrap();
+        std::fs::write(
+            &credentials_path,
+            r#"[{"id": 1, "refreshToken": "r1", "authMethod": "social"}]"#,
+        )
+        .unwrap();
+
+        let manager = Arc::new(
+            MultiTokenManager::new(
+                Config::load(&config_path).unwrap(),
+                CredentialsConfig::load(&credentials_path)
+                    .unwrap()
+                    .into_sorted_credentials(),
+                None,
+                Some(credentials_path.clone()),
+                true,
+            )
+            .unwrap(),
+        );
+        let reloader = Arc::new(ConfigReloader::new(
+            &config_path,
+            &credentials_path,
+            manager.clone(),
+        ));
+        let service = AdminService::new(
+            manager,
+            Arc::new(ApiKeyStore::new(Vec::new(), None)),
+            Arc::new(UsageLedger::new(dir.join("usage"), 0)),
+            None,
+            reloader,
+        );
+        assert_eq!(service.get_config().config.load_balancing_mode, "priority");
+
+        service
+            .set_load_balancing_mode(SetLoadBalancingModeRequest {
+                mode: "balanced".to_string(),
+            })
+            .unwrap();
+        assert_eq!(service.get_config().config.load_balancing_mode, "balanced");
+        assert_eq!(
+            Config::load(&config_path).unwrap().load_balancing_mode,
+            "balanced"
+        );
+    }
+}
@@ -1,6 +1,19 @@
 //! Admin API 类型定义
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::anthropic::types::MessagesRequest;
+use crate::apikey::ApiKeyUsage;
+use crate::cache::CacheStats;
+use crate::kiro::model::credentials::DisabledReason;
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::model::config::{
+    AutoReenableConfig, BatchConfig, ContextManagementConfig, CountTokensRemoteConfig,
+    DocumentConfig, QuotaBalancingConfig, ResponseCacheConfig, TlsBackend, TokenRefreshConfig,
+    TokenizerConfig, WebFetchConfig,
+};
+use crate::model::prompt_rules::{PromptRule, Route};
+use crate::model::registry::SystemPromptStrategy;
 
 // ============ 凭据状态 ============
 
@@ -28,6 +41,12 @@ pub struct CredentialStatusItem {
     pub priority: u32,
     /// 是否被禁用
     pub disabled: bool,
+    /// 禁用原因
+    pub disabled_reason: Option<DisabledReason>,
+    /// 禁用时间（RFC3339 格式）
+    pub disabled_at: Option<String>,
+    /// 计划自动重新启用的时间（RFC3339 格式）
+    pub reenable_at: Option<String>,
     /// 连续失败次数
     pub failure_count: u32,
     /// 是否为当前活跃凭据
@@ -51,6 +70,12 @@ pub struct CredentialStatusItem {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 最近一次 Token 刷新时间（RFC3339 格式）
+    pub last_refresh_at: Option<String>,
+    /// 最近一次 Token 刷新失败原因（刷新成功时为 None）
+    pub last_refresh_error: Option<String>,
+    /// 计划的下一次后台刷新时间（RFC3339 格式）
+    pub next_refresh_at: Option<String>,
 }
 
 // ============ 操作请求 ============
@@ -164,7 +189,7 @@ pub struct BalanceResponse {
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LoadBalancingModeResponse {
-    /// 当前模式（"priority" 或 "balanced"）
+    /// 当前模式（"priority"、"balanced" 或 "quota"）
     pub mode: String,
 }
 
@@ -172,10 +197,292 @@ pub struct LoadBalancingModeResponse {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SetLoadBalancingModeRequest {
-    /// 模式（"priority" 或 "balanced"）
+    /// 模式（"priority"、"balanced" 或 "quota"）
     pub mode: String,
 }
 
+// ============ 客户端 API Key ============
+
+/// 客户端 API Key 列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeysResponse {
+    /// Key 总数
+    pub total: usize,
+    /// Key 列表（Key 已脱敏）
+    pub keys: Vec<ApiKeyItem>,
+}
+
+/// 单个客户端 API Key 信息
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyItem {
+    /// Key ID
+    pub id: u64,
+    /// 名称
+    pub name: String,
+    /// 脱敏后的 Key
+    pub masked_key: String,
+    /// 是否启用
+    pub enabled: bool,
+    /// 是否已过期
+    pub expired: bool,
+    /// 允许的模型（为空表示不限制）
+    pub allowed_models: Vec<String>,
+    /// 每日请求数上限
+    pub daily_request_limit: Option<u64>,
+    /// 每月请求数上限
+    pub monthly_request_limit: Option<u64>,
+    /// 每日 token 上限
+    pub daily_token_limit: Option<u64>,
+    /// 每月 token 上限
+    pub monthly_token_limit: Option<u64>,
+    /// 过期时间（RFC3339 格式）
+    pub expires_at: Option<String>,
+    /// 创建时间（RFC3339 格式）
+    pub created_at: Option<String>,
+    /// 使用量统计
+    pub usage: ApiKeyUsage,
+}
+
+/// 创建客户端 API Key 请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiKeyRequest {
+    /// 名称（必填）
+    pub name: String,
+    /// 自定义 Key（可选，留空自动生成）
+    pub key: Option<String>,
+    /// 是否启用（默认 true）
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// 允许的模型（为空表示不限制）
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// 每日请求数上限
+    pub daily_request_limit: Option<u64>,
+    /// 每月请求数上限
+    pub monthly_request_limit: Option<u64>,
+    /// 每日 token 上限
+    pub daily_token_limit: Option<u64>,
+    /// 每月 token 上限
+    pub monthly_token_limit: Option<u64>,
+    /// 过期时间（RFC3339 格式）
+    pub expires_at: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 创建客户端 API Key 成功响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiKeyResponse {
+    pub success: bool,
+    pub message: String,
+    /// 新 Key 的 ID
+    pub id: u64,
+    /// 完整的 Key（仅在创建时返回一次）
+    pub key: String,
+}
+
+/// 修改客户端 API Key 请求
+///
+/// 整体替换 Key 的配置项（Key 本身与使用量不变），未提供的配额字段视为不限制
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateApiKeyRequest {
+    /// 名称
+    pub name: String,
+    /// 是否启用
+    pub enabled: bool,
+    /// 允许的模型（为空表示不限制）
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// 每日请求数上限
+    pub daily_request_limit: Option<u64>,
+    /// 每月请求数上限
+    pub monthly_request_limit: Option<u64>,
+    /// 每日 token 上限
+    pub daily_token_limit: Option<u64>,
+    /// 每月 token 上限
+    pub monthly_token_limit: Option<u64>,
+    /// 过期时间（RFC3339 格式）
+    pub expires_at: Option<String>,
+}
+
+// ============ 用量查询 ============
+
+/// 用量查询参数
+///
+/// `from` / `to` 支持 RFC3339 时间或 `YYYY-MM-DD` 日期（UTC），
+/// `group_by` 可选 `model`、`credential`、`day`
+#[derive(Debug, Default, Deserialize)]
+pub struct UsageQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub group_by: Option<String>,
+}
+
+// ============ 响应缓存 ============
+
+/// 响应缓存状态
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseCacheResponse {
+    pub enabled: bool,
+    /// 缓存统计（未启用时省略）
+    #[serde(flatten)]
+    pub stats: Option<CacheStats>,
+}
+
+// ============ 运行时配置 ============
+
+/// 运行时配置（密钥类字段只返回是否已配置）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigView {
+    pub host: String,
+    pub port: u16,
+    pub region: String,
+    pub auth_region: Option<String>,
+    pub api_region: Option<String>,
+    pub kiro_version: String,
+    pub system_version: String,
+    pub node_version: String,
+    pub machine_id: Option<String>,
+    pub tls_backend: TlsBackend,
+    pub count_tokens_api_url: Option<String>,
+    /// 是否已配置 countTokensApiKey
+    pub has_count_tokens_api_key: bool,
+    pub count_tokens_auth_type: String,
+    pub count_tokens_remote: CountTokensRemoteConfig,
+    pub proxy_url: Option<String>,
+    pub proxy_username: Option<String>,
+    /// 是否已配置 proxyPassword
+    pub has_proxy_password: bool,
+    pub load_balancing_mode: String,
+    pub metrics_require_admin_key: bool,
+    pub usage_retention_days: u32,
+    pub response_cache: ResponseCacheConfig,
+    pub documents: DocumentConfig,
+    pub web_fetch: WebFetchConfig,
+    pub batches: BatchConfig,
+    pub token_refresh: TokenRefreshConfig,
+    pub quota_balancing: QuotaBalancingConfig,
+    pub auto_reenable: AutoReenableConfig,
+    pub system_prompt: SystemPromptStrategy,
+    pub context_management: ContextManagementConfig,
+    pub tokenizer: TokenizerConfig,
+    pub prompt_rules: Vec<PromptRule>,
+    pub prompt_rules_file: Option<String>,
+}
+
+/// 获取配置响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigResponse {
+    pub config: ConfigView,
+    /// 修改后需要重启服务才能生效的字段
+    pub restart_required_fields: Vec<String>,
+}
+
+/// 修改配置请求（只包含需要修改的字段）
+///
+/// 可选字段传 `null` 或空字符串表示清除；`apiKey`、`adminApiKey`、`webSearch`、`models`
+/// 等字段不支持通过 API 修改
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct UpdateConfigRequest {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub region: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub auth_region: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub api_region: Option<Option<String>>,
+    pub kiro_version: Option<String>,
+    pub system_version: Option<String>,
+    pub node_version: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub machine_id: Option<Option<String>>,
+    pub tls_backend: Option<TlsBackend>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub count_tokens_api_url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub count_tokens_api_key: Option<Option<String>>,
+    pub count_tokens_auth_type: Option<String>,
+    pub count_tokens_remote: Option<CountTokensRemoteConfig>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub proxy_url: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub proxy_username: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub proxy_password: Option<Option<String>>,
+    pub load_balancing_mode: Option<String>,
+    pub metrics_require_admin_key: Option<bool>,
+    pub usage_retention_days: Option<u32>,
+    pub response_cache: Option<ResponseCacheConfig>,
+    pub documents: Option<DocumentConfig>,
+    pub web_fetch: Option<WebFetchConfig>,
+    pub batches: Option<BatchConfig>,
+    pub token_refresh: Option<TokenRefreshConfig>,
+    pub quota_balancing: Option<QuotaBalancingConfig>,
+    pub auto_reenable: Option<AutoReenableConfig>,
+    pub system_prompt: Option<SystemPromptStrategy>,
+    pub context_management: Option<ContextManagementConfig>,
+    pub tokenizer: Option<TokenizerConfig>,
+    pub prompt_rules: Option<Vec<PromptRule>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub prompt_rules_file: Option<Option<String>>,
+}
+
+/// 区分字段缺失（`None`）与显式 `null`（`Some(None)`）
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// 修改配置响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateConfigResponse {
+    pub config: ConfigView,
+    /// 已立即生效的变化字段
+    pub changed: Vec<String>,
+    /// 已保存但需要重启服务才能生效的变化字段
+    pub restart_required: Vec<String>,
+}
+
+// ============ 提示词改写规则 ============
+
+/// 提示词改写 dry-run 请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptRulesDryRunRequest {
+    /// 待转换的 Anthropic Messages 请求
+    pub request: MessagesRequest,
+    /// 模拟的请求入口（默认 `v1`）
+    #[serde(default)]
+    pub route: Route,
+    /// 模拟的客户端 API Key ID（未填写表示主 API Key）
+    pub client_id: Option<u64>,
+}
+
+/// 提示词改写 dry-run 响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptRulesDryRunResponse {
+    /// 命中的规则名称（按应用顺序）
+    pub applied_rules: Vec<String>,
+    /// 改写后发往 Kiro 的请求体
+    pub kiro_request: KiroRequest,
+}
+
 // ============ 通用响应 ============
 
 /// 操作成功响应
@@ -0,0 +1,467 @@
+//! Message Batches API 端点与后台执行
+//!
+//! 批处理中的每个请求以非流式方式在进程内调用 `/v1/messages` 的处理逻辑，
+//! 因此客户端 Key 的模型权限、配额与用量账本同样适用于批处理请求。
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::apikey::ClientKey;
+use crate::batch::{BatchError, BatchRequest, BatchResult, BatchStore, MessageBatch};
+
+use super::handlers::post_messages;
+use super::middleware::AppState;
+use super::types::{ErrorResponse, MessagesRequest};
+
+/// 单个批处理最多包含的请求数
+const MAX_BATCH_REQUESTS: usize = 100_000;
+
+/// 列表默认每页数量
+const DEFAULT_LIST_LIMIT: usize = 20;
+
+/// 列表每页数量上限
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// 没有可用凭据时重新检查的间隔
+const CREDENTIAL_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 创建批处理请求体
+#[derive(Debug, Deserialize)]
+pub struct CreateBatchRequest {
+    pub requests: Vec<BatchRequest>,
+}
+
+/// 列表查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct ListBatchesQuery {
+    pub limit: Option<usize>,
+    pub before_id: Option<String>,
+    pub after_id: Option<String>,
+}
+
+/// 列表响应
+#[derive(Debug, Serialize)]
+pub struct ListBatchesResponse {
+    pub data: Vec<MessageBatch>,
+    pub has_more: bool,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+}
+
+/// POST /v1/messages/batches
+pub async fn create_batch(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    Json(payload): Json<CreateBatchRequest>,
+) -> Response {
+    let Some(store) = &state.batch_store else {
+        return batches_unavailable();
+    };
+    if let Err(message) = validate_requests(&payload.requests) {
+        return error_response(StatusCode::BAD_REQUEST, "invalid_request_error", message);
+    }
+
+    let client = client.map(|Extension(c)| c);
+    let count = payload.requests.len();
+    match store.create(payload.requests, client.as_ref()) {
+        Ok(batch) => {
+            tracing::info!(batch_id = %batch.id, requests = count, "已创建批处理");
+            Json(batch).into_response()
+        }
+        Err(e) => {
+            tracing::error!("创建批处理失败: {}", e);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                format!("创建批处理失败: {}", e),
+            )
+        }
+    }
+}
+
+/// GET /v1/messages/batches
+pub async fn list_batches(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    Query(query): Query<ListBatchesQuery>,
+) -> Response {
+    let Some(store) = &state.batch_store else {
+        return batches_unavailable();
+    };
+    let client = client.map(|Extension(c)| c);
+    Json(paginate(store.list(client.as_ref()), &query)).into_response()
+}
+
+/// GET /v1/messages/batches/{batch_id}
+pub async fn get_batch(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    Path(batch_id): Path<String>,
+) -> Response {
+    let Some(store) = &state.batch_store else {
+        return batches_unavailable();
+    };
+    let client = client.map(|Extension(c)| c);
+    match store.get(&batch_id, client.as_ref()) {
+        Ok(batch) => Json(batch).into_response(),
+        Err(e) => batch_error_response(&batch_id, e),
+    }
+}
+
+/// POST /v1/messages/batches/{batch_id}/cancel
+pub async fn cancel_batch(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    Path(batch_id): Path<String>,
+) -> Response {
+    let Some(store) = &state.batch_store else {
+        return batches_unavailable();
+    };
+    let client = client.map(|Extension(c)| c);
+    match store.cancel(&batch_id, client.as_ref()) {
+        Ok(batch) => Json(batch).into_response(),
+        Err(e) => batch_error_response(&batch_id, e),
+    }
+}
+
+/// DELETE /v1/messages/batches/{batch_id}
+pub async fn delete_batch(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    Path(batch_id): Path<String>,
+) -> Response {
+    let Some(store) = &state.batch_store else {
+        return batches_unavailable();
+    };
+    let client = client.map(|Extension(c)| c);
+    match store.delete(&batch_id, client.as_ref()) {
+        Ok(()) => Json(json!({"id": batch_id, "type": "message_batch_deleted"})).into_response(),
+        Err(e) => batch_error_response(&batch_id, e),
+    }
+}
+
+/// GET /v1/messages/batches/{batch_id}/results
+///
+/// 以 JSONL 返回每个请求的结果（`succeeded` / `errored` / `canceled` / `expired`）
+pub async fn get_batch_results(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    Path(batch_id): Path<String>,
+) -> Response {
+    let Some(store) = &state.batch_store else {
+        return batches_unavailable();
+    };
+    let client = client.map(|Extension(c)| c);
+    match store.results(&batch_id, client.as_ref()) {
+        Ok(content) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/x-jsonl")],
+            Body::from(content),
+        )
+            .into_response(),
+        Err(e) => batch_error_response(&batch_id, e),
+    }
+}
+
+/// 启动后台 worker，持续执行队列中的批处理请求
+///
+/// 实际并发为 `min(concurrency, 可用凭据数)`；没有可用凭据时暂停执行，定期重新检查
+pub fn spawn_worker(state: AppState, store: Arc<BatchStore>, concurrency: usize) {
+    tokio::spawn(run_worker(state, store, concurrency.max(1)));
+}
+
+async fn run_worker(state: AppState, store: Arc<BatchStore>, concurrency: usize) {
+    let running = Arc::new(AtomicUsize::new(0));
+    let mut paused = false;
+
+    loop {
+        let available = state
+            .kiro_provider
+            .as_ref()
+            .map(|p| p.token_manager().available_count())
+            .unwrap_or(0);
+
+        if available == 0 {
+            if store.pending_count() > 0 {
+                if !paused {
+                    tracing::warn!("没有可用凭据，批处理暂停执行");
+                    paused = true;
+                }
+                tokio::select! {
+                    _ = store.changed() => {}
+                    _ = tokio::time::sleep(CREDENTIAL_RETRY_INTERVAL) => {}
+                }
+            } else {
+                store.changed().await;
+            }
+            continue;
+        }
+        if paused {
+            tracing::info!("凭据已恢复，批处理继续执行");
+            paused = false;
+        }
+
+        if running.load(Ordering::SeqCst) >= concurrency.min(available) {
+            store.changed().await;
+            continue;
+        }
+        let Some(job) = store.next_job() else {
+            store.changed().await;
+            continue;
+        };
+
+        running.fetch_add(1, Ordering::SeqCst);
+        let state = state.clone();
+        let store = store.clone();
+        let running = running.clone();
+        tokio::spawn(async move {
+            let result = execute(&state, job.client, job.request.params).await;
+            // 先释放并发名额，complete 会唤醒 worker 调度下一个请求
+            running.fetch_sub(1, Ordering::SeqCst);
+            store.complete(&job.batch_id, job.request.custom_id, result);
+        });
+    }
+}
+
+/// 以非流式方式执行单个请求
+async fn execute(state: &AppState, client: Option<ClientKey>, params: Value) -> BatchResult {
+    let mut payload: MessagesRequest = match serde_json::from_value(params) {
+        Ok(p) => p,
+        Err(e) => return errored("invalid_request_error", format!("请求参数无效: {}", e)),
+    };
+    payload.stream = false;
+
+    let response = post_messages(
+        State(state.clone()),
+        client.map(Extension),
+        HeaderMap::new(),
+        Json(payload),
+    )
+    .await;
+    let status = response.status();
+    let body = match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return errored("api_error", format!("读取响应失败: {}", e)),
+    };
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    if status.is_success() {
+        return BatchResult::Succeeded { message: body };
+    }
+    match body.get("error") {
+        Some(error) => BatchResult::Errored {
+            error: json!({"type": "error", "error": error}),
+        },
+        None => errored("api_error", format!("请求失败: {}", status)),
+    }
+}
+
+fn errored(error_type: &str, message: String) -> BatchResult {
+    BatchResult::Errored {
+        error: json!({"type": "error", "error": {"type": error_type, "message": message}}),
+    }
+}
+
+/// 校验批处理请求：数量、custom_id 格式与唯一性、params 结构
+fn validate_requests(requests: &[BatchRequest]) -> Result<(), String> {
+    if requests.is_empty() {
+        return Err("requests 不能为空".to_string());
+    }
+    if requests.len() > MAX_BATCH_REQUESTS {
+        return Err(format!("单个批处理最多包含 {} 个请求", MAX_BATCH_REQUESTS));
+    }
+
+    let mut seen = HashSet::new();
+    for (index, request) in requests.iter().enumerate() {
+        let id = &request.custom_id;
+        let valid_id = (1..=64).contains(&id.len())
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_id {
+            return Err(format!(
+                "requests.{}.custom_id 必须为 1-64 个字母、数字、下划线或连字符",
+                index
+            ));
+        }
+        if !seen.insert(id.as_str()) {
+            return Err(format!("requests.{}.custom_id 重复: {}", index, id));
+        }
+        if let Err(e) = serde_json::from_value::<MessagesRequest>(request.params.clone()) {
+            return Err(format!("requests.{}.params: {}", index, e));
+        }
+    }
+    Ok(())
+}
+
+/// 按 `before_id` / `after_id` 对按创建时间倒序排列的批处理分页
+fn paginate(batches: Vec<MessageBatch>, query: &ListBatchesQuery) -> ListBatchesResponse {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let position = |id: &str| batches.iter().position(|b| b.id == id);
+
+    let (start, end) = if let Some(after_id) = &query.after_id {
+        let start = position(after_id).map(|i| i + 1).unwrap_or(batches.len());
+        (start, (start + limit).min(batches.len()))
+    } else if let Some(before_id) = &query.before_id {
+        let end = position(before_id).unwrap_or(0);
+        (end.saturating_sub(limit), end)
+    } else {
+        (0, limit.min(batches.len()))
+    };
+    let has_more = if query.before_id.is_some() && query.after_id.is_none() {
+        start > 0
+    } else {
+        end < batches.len()
+    };
+
+    let data = batches[start..end].to_vec();
+    ListBatchesResponse {
+        first_id: data.first().map(|b| b.id.clone()),
+        last_id: data.last().map(|b| b.id.clone()),
+        data,
+        has_more,
+    }
+}
+
+fn error_response(status: StatusCode, error_type: &str, message: impl Into<String>) -> Response {
+    (status, Json(ErrorResponse::new(error_type, message))).into_response()
+}
+
+fn batch_error_response(batch_id: &str, error: BatchError) -> Response {
+    match error {
+        BatchError::NotFound => error_response(
+            StatusCode::NOT_FOUND,
+            "not_found_error",
+            format!("批处理不存在: {}", batch_id),
+        ),
+        BatchError::NotEnded => error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!("批处理尚未结束: {}", batch_id),
+        ),
+        BatchError::Storage(e) => {
+            tracing::error!(batch_id = %batch_id, "批处理存储错误: {}", e);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                format!("批处理存储错误: {}", e),
+            )
+        }
+    }
+}
+
+fn batches_unavailable() -> Response {
+    error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "service_unavailable",
+        "Message Batches 未启用",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn request(custom_id: &str) -> BatchRequest {
+        BatchRequest {
+            custom_id: custom_id.to_string(),
+            params: json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": 16,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+        }
+    }
+
+    fn batches(count: usize) -> Vec<MessageBatch> {
+        (0..count)
+            .map(|i| {
+                let created_at = Utc.timestamp_opt(1_000_000 - i as i64, 0).unwrap();
+                serde_json::from_value(json!({
+                    "id": format!("msgbatch_{}", i),
+                    "type": "message_batch",
+                    "processing_status": "ended",
+                    "request_counts": {"processing": 0, "succeeded": 1, "errored": 0, "canceled": 0, "expired": 0},
+                    "ended_at": created_at,
+                    "created_at": created_at,
+                    "expires_at": created_at,
+                    "archived_at": null,
+                    "cancel_initiated_at": null,
+                    "results_url": null
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn ids(response: &ListBatchesResponse) -> Vec<&str> {
+        response.data.iter().map(|b| b.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_validate_requests() {
+        assert!(validate_requests(&[request("a-1"), request("b_2")]).is_ok());
+        assert!(validate_requests(&[]).is_err());
+
+        let err = validate_requests(&[request("a"), request("a")]).unwrap_err();
+        assert!(err.contains("requests.1.custom_id"));
+        assert!(validate_requests(&[request("has space")]).is_err());
+        assert!(validate_requests(&[request(&"x".repeat(65))]).is_err());
+
+        let mut invalid = request("a");
+        invalid.params = json!({"model": "claude-sonnet-4-5"});
+        let err = validate_requests(&[invalid]).unwrap_err();
+        assert!(err.starts_with("requests.0.params"));
+    }
+
+    #[test]
+    fn test_paginate() {
+        let query = ListBatchesQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page = paginate(batches(5), &query);
+        assert_eq!(ids(&page), ["msgbatch_0", "msgbatch_1"]);
+        assert!(page.has_more);
+        assert_eq!(page.last_id.as_deref(), Some("msgbatch_1"));
+
+        let query = ListBatchesQuery {
+            limit: Some(2),
+            after_id: Some("msgbatch_2".to_string()),
+            ..Default::default()
+        };
+        let page = paginate(batches(5), &query);
+        assert_eq!(ids(&page), ["msgbatch_3", "msgbatch_4"]);
+        assert!(!page.has_more);
+
+        let query = ListBatchesQuery {
+            limit: Some(2),
+            before_id: Some("msgbatch_3".to_string()),
+            ..Default::default()
+        };
+        let page = paginate(batches(5), &query);
+        assert_eq!(ids(&page), ["msgbatch_1", "msgbatch_2"]);
+        assert!(page.has_more);
+
+        let page = paginate(Vec::new(), &ListBatchesQuery::default());
+        assert!(page.data.is_empty());
+        assert_eq!(page.first_id, None);
+        assert!(!page.has_more);
+    }
+}
@@ -0,0 +1,302 @@
+//! 响应缓存的请求侧处理：`cache-control` 解析、命中回放与写入
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use bytes::Bytes;
+use futures::stream;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::cache::{CachedResponse, ResponseCache};
+use crate::kiro::model::requests::conversation::ConversationState;
+
+use super::middleware::AppState;
+use super::prompt_cache::PromptCacheUsage;
+use super::stream::SseEvent;
+use super::types::MessagesRequest;
+use super::usage::UsageReporter;
+
+/// 命中缓存时附加的响应头
+const CACHE_STATUS_HEADER: &str = "x-cache";
+
+/// 单个请求的缓存句柄
+pub(crate) struct CacheHandle {
+    cache: Arc<ResponseCache>,
+    key: String,
+    /// 是否允许读取缓存（`cache-control: no-cache` 时为 false）
+    lookup: bool,
+}
+
+impl CacheHandle {
+    /// 为请求创建缓存句柄
+    ///
+    /// 缓存未启用或客户端指定 `cache-control: no-store` 时返回 None
+    pub(crate) fn resolve(
+        state: &AppState,
+        headers: &HeaderMap,
+        payload: &MessagesRequest,
+        conversation_state: &ConversationState,
+    ) -> Option<Self> {
+        let cache = state.response_cache.clone()?;
+
+        let (no_cache, no_store) = parse_cache_control(headers);
+        if no_cache || no_store {
+            cache.record_bypass();
+        }
+        if no_store {
+            return None;
+        }
+
+        Some(Self {
+            key: ResponseCache::key(&payload.model, conversation_state, &output_params(payload)),
+            cache,
+            lookup: !no_cache,
+        })
+    }
+
+    /// 查询缓存
+    pub(crate) fn lookup(&self) -> Option<CachedResponse> {
+        if !self.lookup {
+            return None;
+        }
+        self.cache.get(&self.key)
+    }
+
+    /// 写入成功的非流式响应（空响应不缓存）
+    pub(crate) fn store(
+        &self,
+        content: &[serde_json::Value],
+        stop_reason: &str,
+        stop_sequence: Option<&str>,
+        input_tokens: i32,
+        output_tokens: i32,
+    ) {
+        if content.is_empty() {
+            return;
+        }
+        self.cache.put(
+            &self.key,
+            CachedResponse {
+                content: content.to_vec(),
+                stop_reason: stop_reason.to_string(),
+                stop_sequence: stop_sequence.map(str::to_string),
+                input_tokens,
+                output_tokens,
+                created_at: chrono::Utc::now(),
+            },
+        );
+    }
+}
+
+/// 影响输出但不体现在 `ConversationState` 中的请求参数，参与缓存 key 计算
+fn output_params(payload: &MessagesRequest) -> serde_json::Value {
+    json!({
+        "stop_sequences": payload.stop_sequences,
+        "max_tokens": payload.max_tokens
+    })
+}
+
+/// 解析 `cache-control` 请求头，返回 (no-cache, no-store)
+fn parse_cache_control(headers: &HeaderMap) -> (bool, bool) {
+    let mut no_cache = false;
+    let mut no_store = false;
+    for value in headers.get_all(header::CACHE_CONTROL) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for directive in value.split(',') {
+            match directive.trim().to_ascii_lowercase().as_str() {
+                "no-cache" => no_cache = true,
+                "no-store" => no_store = true,
+                _ => {}
+            }
+        }
+    }
+    (no_cache, no_store)
+}
+
+/// 以缓存内容响应请求（流式请求合成为 SSE 事件流）
+pub(crate) fn replay(
+    cached: CachedResponse,
+    model: &str,
+    stream: bool,
+    usage: UsageReporter,
+) -> Response {
+    tracing::info!(model = %model, stream = stream, "命中响应缓存");
+    usage.report(
+        cached.input_tokens,
+        cached.output_tokens,
+        &cached.stop_reason,
+    );
+
+    let message_id = format!("msg_{}", Uuid::new_v4().to_string().replace('-', ""));
+    if !stream {
+        let body = super::handlers::build_message_body(
+            &message_id,
+            model,
+            cached.content,
+            &cached.stop_reason,
+            cached.stop_sequence.as_deref(),
+            PromptCacheUsage::default().usage_json(cached.input_tokens, cached.output_tokens),
+        );
+        let mut response = (StatusCode::OK, Json(body)).into_response();
+        response
+            .headers_mut()
+            .insert(CACHE_STATUS_HEADER, "HIT".parse().unwrap());
+        return response;
+    }
+
+    let events: Vec<Result<Bytes, Infallible>> = replay_events(&message_id, model, &cached)
+        .into_iter()
+        .map(|e| Ok(Bytes::from(e.to_sse_string())))
+        .collect();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::CONNECTION, "keep-alive")
+        .header(CACHE_STATUS_HEADER, "HIT")
+        .body(Body::from_stream(stream::iter(events)))
+        .unwrap()
+}
+
+/// 将缓存的内容块合成为完整的 SSE 事件序列
+fn replay_events(message_id: &str, model: &str, cached: &CachedResponse) -> Vec<SseEvent> {
+    let usage = PromptCacheUsage::default();
+    let mut events = vec![SseEvent::new(
+        "message_start",
+        json!({
+            "type": "message_start",
+            "message": {
+                "id": message_id,
+                "type": "message",
+                "role": "assistant",
+                "content": [],
+                "model": model,
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": usage.usage_json(cached.input_tokens, 1)
+            }
+        }),
+    )];
+
+    for (index, block) in cached.content.iter().enumerate() {
+        let (start, delta) = match block["type"].as_str() {
+            Some("text") => (
+                json!({"type": "text", "text": ""}),
+                Some(json!({"type": "text_delta", "text": block["text"]})),
+            ),
+            Some("tool_use") => (
+                json!({
+                    "type": "tool_use",
+                    "id": block["id"],
+                    "name": block["name"],
+                    "input": {}
+                }),
+                Some(json!({
+                    "type": "input_json_delta",
+                    "partial_json": serde_json::to_string(&block["input"]).unwrap_or_default()
+                })),
+            ),
+            _ => (block.clone(), None),
+        };
+
+        events.push(SseEvent::new(
+            "content_block_start",
+            json!({"type": "content_block_start", "index": index, "content_block": start}),
+        ));
+        if let Some(delta) = delta {
+            events.push(SseEvent::new(
+                "content_block_delta",
+                json!({"type": "content_block_delta", "index": index, "delta": delta}),
+            ));
+        }
+        events.push(SseEvent::new(
+            "content_block_stop",
+            json!({"type": "content_block_stop", "index": index}),
+        ));
+    }
+
+    events.push(SseEvent::new(
+        "message_delta",
+        json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": cached.stop_reason,
+                "stop_sequence": cached.stop_sequence
+            },
+            "usage": usage.usage_json(cached.input_tokens, cached.output_tokens)
+        }),
+    ));
+    events.push(SseEvent::new(
+        "message_stop",
+        json!({ "type": "message_stop" }),
+    ));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_parse_cache_control() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_cache_control(&headers), (false, false));
+
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("max-age=0, No-Cache"),
+        );
+        assert_eq!(parse_cache_control(&headers), (true, false));
+
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        assert_eq!(parse_cache_control(&headers), (false, true));
+    }
+
+    #[test]
+    fn test_replay_events_cover_all_blocks() {
+        let cached = CachedResponse {
+            content: vec![
+                json!({"type": "text", "text": "hello"}),
+                json!({"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}),
+            ],
+            stop_reason: "tool_use".to_string(),
+            stop_sequence: None,
+            input_tokens: 12,
+            output_tokens: 7,
+            created_at: chrono::Utc::now(),
+        };
+
+        let events = replay_events("msg_test", "claude-sonnet-4-5", &cached);
+        let names: Vec<&str> = events.iter().map(|e| e.event.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(
+            events[5].data["delta"]["partial_json"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(events[7].data["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[7].data["usage"]["output_tokens"], 7);
+    }
+}
@@ -0,0 +1,726 @@
+//! 上下文窗口预检
+//!
+//! 对话超出上下文窗口时，上游要么以 `ContentLengthExceededException` 拒绝请求，要么在
+//! `contextUsageEvent` 达到 100% 后截断输出（`stop_reason: model_context_window_exceeded`）。
+//! 开启 `contextManagement` 后，转换得到的 `ConversationState` 在发送前先估算大小，
+//! 超过 `thresholdPercent` 时按 `strategies` 依次处理，直到估算值回到阈值以内：
+//!
+//! - `trimToolResults`：从最早的消息开始，截断历史中过长的 tool_result（保留首尾）
+//! - `dropOldest`：丢弃最早的若干轮对话，至少保留最近 `keepRecentTurns` 轮
+//! - `summarize`：调用模型总结将被丢弃的对话，摘要并入保留下来的第一条 user 消息
+//!
+//! 系统提示词始终保留；丢弃历史后按 [`validate_tool_pairing`] 重新校验 tool_use/tool_result 配对。
+//! 处理结果通过 `x-context-trimmed` 响应头返回给客户端。
+
+use std::sync::{Arc, LazyLock};
+
+use axum::response::Response;
+use futures::StreamExt;
+use parking_lot::RwLock;
+use uuid::Uuid;
+
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::conversation::{
+    ConversationState, CurrentMessage, KiroImage, Message, UserInputMessage,
+};
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::model::requests::tool::ToolResult;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::KiroProvider;
+use crate::model::config::{ContextManagementConfig, ContextStrategy};
+use crate::model::registry;
+use crate::token;
+
+use super::converter::{
+    ConversionResult, map_model, prepend_preamble, remove_orphaned_tool_uses, validate_tool_pairing,
+};
+
+/// 发生裁剪时附加的响应头
+const CONTEXT_TRIMMED_HEADER: &str = "x-context-trimmed";
+
+/// 总结历史时使用的提示词
+const SUMMARY_INSTRUCTION: &str = "\
+Summarize the earlier part of the conversation below between a user and an AI assistant, \
+so that the assistant can continue the work without it. Keep the user's requests and constraints, \
+decisions made, files, commands and identifiers involved, tool results that still matter, \
+and any unfinished tasks. Reply with the summary only.";
+
+static CONTEXT_CONFIG: LazyLock<RwLock<Arc<ContextManagementConfig>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(ContextManagementConfig::default())));
+
+/// 初始化（或替换）上下文窗口预检配置
+///
+/// 应在应用启动和配置热重载时调用，未调用时不做预检
+pub fn init_config(config: ContextManagementConfig) {
+    *CONTEXT_CONFIG.write() = Arc::new(config);
+}
+
+fn config() -> Arc<ContextManagementConfig> {
+    CONTEXT_CONFIG.read().clone()
+}
+
+/// 预检结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ContextReport {
+    /// 处理前的估算 tokens
+    pub(crate) estimated_tokens: u64,
+    /// 处理后的估算 tokens
+    pub(crate) final_tokens: u64,
+    /// 直接丢弃的历史消息数
+    pub(crate) dropped_messages: usize,
+    /// 被截断的 tool_result 数
+    pub(crate) trimmed_tool_results: usize,
+    /// 以摘要代替的历史消息数
+    pub(crate) summarized_messages: usize,
+}
+
+impl ContextReport {
+    /// 响应头取值，如 `dropped=4; trimmed=2; summarized=0; tokens=231000->178000`
+    fn header_value(&self) -> String {
+        format!(
+            "dropped={}; trimmed={}; summarized={}; tokens={}->{}",
+            self.dropped_messages,
+            self.trimmed_tool_results,
+            self.summarized_messages,
+            self.estimated_tokens,
+            self.final_tokens
+        )
+    }
+
+    /// 在响应上附加 `x-context-trimmed` 头
+    pub(crate) fn apply(&self, response: &mut Response) {
+        if let Ok(value) = self.header_value().parse() {
+            response.headers_mut().insert(CONTEXT_TRIMMED_HEADER, value);
+        }
+    }
+}
+
+/// 预检转换结果，超出阈值时按配置的策略裁剪历史
+///
+/// 未启用或未超出阈值时返回 None
+pub(crate) async fn fit(
+    result: &mut ConversionResult,
+    model: &str,
+    provider: &KiroProvider,
+    profile_arn: Option<&str>,
+) -> Option<ContextReport> {
+    let config = config();
+    if !config.enabled {
+        return None;
+    }
+
+    let context_window = registry::registry()
+        .resolve(model)
+        .map(|entry| entry.context_window)
+        .unwrap_or(registry::DEFAULT_CONTEXT_WINDOW);
+    let budget = context_window.max(0) as u64 * u64::from(config.threshold_percent) / 100;
+
+    let mut fitter = Fitter::new(result, budget);
+    if fitter.fits() {
+        return None;
+    }
+    tracing::info!(
+        model = %model,
+        estimated_tokens = fitter.report.estimated_tokens,
+        budget,
+        "估算输入超出上下文窗口阈值，开始裁剪历史"
+    );
+
+    for strategy in &config.strategies {
+        match strategy {
+            ContextStrategy::TrimToolResults => {
+                fitter.trim_tool_results(config.max_tool_result_chars);
+            }
+            ContextStrategy::DropOldest => {
+                if let Some(cut) = fitter.cut_point(config.keep_recent_turns) {
+                    fitter.drop_until(cut, None);
+                }
+            }
+            ContextStrategy::Summarize => {
+                let Some(cut) = fitter.cut_point(config.keep_recent_turns) else {
+                    continue;
+                };
+                let transcript = fitter.transcript(cut, config.max_tool_result_chars);
+                if token::count_tokens(&transcript) > budget {
+                    tracing::warn!("待总结的历史超出上下文窗口阈值，跳过 summarize");
+                    continue;
+                }
+                let model_id = config
+                    .summary_model
+                    .as_deref()
+                    .and_then(map_model)
+                    .unwrap_or_else(|| fitter.model_id());
+                match summarize(provider, profile_arn, &model_id, &transcript).await {
+                    Ok(summary) => fitter.drop_until(cut, Some(&summary)),
+                    Err(e) => tracing::warn!("总结历史失败: {:#}", e),
+                }
+            }
+        }
+        if fitter.fits() {
+            break;
+        }
+    }
+
+    let report = fitter.finish();
+    if report.final_tokens > budget {
+        tracing::warn!(
+            final_tokens = report.final_tokens,
+            budget,
+            "裁剪后估算输入仍超出上下文窗口阈值"
+        );
+    }
+    tracing::info!("上下文窗口预检: {}", report.header_value());
+    Some(report)
+}
+
+/// 对单个转换结果执行裁剪
+struct Fitter<'a> {
+    result: &'a mut ConversionResult,
+    budget: u64,
+    /// 每条历史消息的估算 tokens
+    history_tokens: Vec<u64>,
+    /// 当前消息（含工具定义）的估算 tokens
+    current_tokens: u64,
+    report: ContextReport,
+}
+
+impl<'a> Fitter<'a> {
+    fn new(result: &'a mut ConversionResult, budget: u64) -> Self {
+        let state = &result.conversation_state;
+        let history_tokens: Vec<u64> = state.history.iter().map(message_tokens).collect();
+        let current_tokens = current_message_tokens(&state.current_message);
+        let mut fitter = Self {
+            result,
+            budget,
+            history_tokens,
+            current_tokens,
+            report: ContextReport::default(),
+        };
+        fitter.report.estimated_tokens = fitter.total();
+        fitter
+    }
+
+    fn total(&self) -> u64 {
+        self.history_tokens.iter().sum::<u64>() + self.current_tokens
+    }
+
+    fn fits(&self) -> bool {
+        self.total() <= self.budget
+    }
+
+    fn model_id(&self) -> String {
+        self.result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .model_id
+            .clone()
+    }
+
+    /// 从最早的消息开始截断过长的 tool_result，估算值回到阈值以内即停止
+    fn trim_tool_results(&mut self, max_chars: usize) {
+        let pinned = self.result.pinned_history;
+        for i in pinned..self.history_tokens.len() {
+            if self.fits() {
+                return;
+            }
+            let Message::User(msg) = &mut self.result.conversation_state.history[i] else {
+                continue;
+            };
+            let results = &mut msg
+                .user_input_message
+                .user_input_message_context
+                .tool_results;
+            let trimmed = results
+                .iter_mut()
+                .map(|r| trim_tool_result(r, max_chars))
+                .filter(|&trimmed| trimmed)
+                .count();
+            if trimmed > 0 {
+                self.report.trimmed_tool_results += trimmed;
+                self.history_tokens[i] = message_tokens(&self.result.conversation_state.history[i]);
+            }
+        }
+    }
+
+    /// 选择丢弃的终点（不含）：最小的能让估算值回到阈值以内的位置
+    ///
+    /// 终点必须是 user 消息（或历史末尾），且之后至少保留 `keep_recent_turns` 条 user 消息；
+    /// 无法丢弃任何消息时返回 None
+    fn cut_point(&self, keep_recent_turns: usize) -> Option<usize> {
+        let history = &self.result.conversation_state.history;
+        let pinned = self.result.pinned_history;
+        let len = history.len();
+
+        let mut candidates: Vec<usize> = (pinned + 1..len)
+            .filter(|&i| matches!(history[i], Message::User(_)))
+            .collect();
+        candidates.push(len);
+        // 每个候选位置之后的 user 消息数即为保留的轮数
+        let allowed = candidates.len().saturating_sub(keep_recent_turns);
+        let candidates = &candidates[..allowed.min(candidates.len())];
+
+        let total = self.total();
+        let mut dropped = 0;
+        let mut start = pinned;
+        let mut cut = None;
+        for &candidate in candidates {
+            dropped += self.history_tokens[start..candidate].iter().sum::<u64>();
+            start = candidate;
+            cut = Some(candidate);
+            if total - dropped <= self.budget {
+                break;
+            }
+        }
+        cut
+    }
+
+    /// 把 `[pinned, cut)` 范围内的历史渲染为纯文本记录（供总结使用）
+    fn transcript(&self, cut: usize, max_tool_result_chars: usize) -> String {
+        let history = &self.result.conversation_state.history;
+        let mut lines = Vec::new();
+        for msg in &history[self.result.pinned_history..cut] {
+            match msg {
+                Message::User(user) => {
+                    let msg = &user.user_input_message;
+                    lines.push("[user]".to_string());
+                    if !msg.content.is_empty() {
+                        lines.push(msg.content.clone());
+                    }
+                    for result in &msg.user_input_message_context.tool_results {
+                        let mut result = result.clone();
+                        trim_tool_result(&mut result, max_tool_result_chars);
+                        lines.push(format!("[tool_result {}]", result.tool_use_id));
+                        lines.push(tool_result_text(&result));
+                    }
+                }
+                Message::Assistant(assistant) => {
+                    let msg = &assistant.assistant_response_message;
+                    lines.push("[assistant]".to_string());
+                    if !msg.content.is_empty() {
+                        lines.push(msg.content.clone());
+                    }
+                    for tool_use in msg.tool_uses.iter().flatten() {
+                        lines.push(format!(
+                            "[tool_use {} {}] {}",
+                            tool_use.name, tool_use.tool_use_id, tool_use.input
+                        ));
+                    }
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// 丢弃 `[pinned, cut)` 范围内的历史，系统提示词（inline）与摘要并入新的第一条 user 消息
+    fn drop_until(&mut self, cut: usize, summary: Option<&str>) {
+        let pinned = self.result.pinned_history;
+        if cut <= pinned {
+            return;
+        }
+        let state = &mut self.result.conversation_state;
+        let removed = cut - pinned;
+        state.history.drain(pinned..cut);
+        self.history_tokens.drain(pinned..cut);
+        match summary {
+            Some(_) => self.report.summarized_messages += removed,
+            None => self.report.dropped_messages += removed,
+        }
+
+        let summary = summary.map(|s| {
+            format!(
+                "<conversation_summary>\nThe earlier part of this conversation was removed to fit the context window. Summary:\n{}\n</conversation_summary>",
+                s.trim()
+            )
+        });
+        let prefix = [self.result.history_preamble.clone(), summary]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        if !prefix.is_empty() {
+            match state.history.get_mut(pinned) {
+                Some(Message::User(first)) => {
+                    let text = std::mem::take(&mut first.user_input_message.content);
+                    first.user_input_message.content = prepend_preamble(&prefix, text);
+                    self.history_tokens[pinned] = message_tokens(&state.history[pinned]);
+                }
+                _ => {
+                    let current = &mut state.current_message.user_input_message;
+                    let text = std::mem::take(&mut current.content);
+                    current.content = prepend_preamble(&prefix, text);
+                    self.current_tokens = current_message_tokens(&state.current_message);
+                    self.result.history_preamble = None;
+                }
+            }
+        }
+
+        repair_tool_pairing(state);
+        // 配对修复可能移除了 tool_result，重新估算
+        self.history_tokens = state.history.iter().map(message_tokens).collect();
+        self.current_tokens = current_message_tokens(&state.current_message);
+    }
+
+    fn finish(mut self) -> ContextReport {
+        self.report.final_tokens = self.total();
+        self.report
+    }
+}
+
+/// 丢弃历史后重新校验 tool_use/tool_result 配对
+///
+/// 被丢弃的 assistant 消息中的 tool_use 对应的 tool_result 成为孤立结果，需要一并移除
+fn repair_tool_pairing(state: &mut ConversationState) {
+    for i in 0..state.history.len() {
+        let Message::User(msg) = &state.history[i] else {
+            continue;
+        };
+        let results = &msg
+            .user_input_message
+            .user_input_message_context
+            .tool_results;
+        if results.is_empty() {
+            continue;
+        }
+        let (validated, _) = validate_tool_pairing(&state.history[..i], results);
+        if let Message::User(msg) = &mut state.history[i] {
+            msg.user_input_message
+                .user_input_message_context
+                .tool_results = validated;
+        }
+    }
+
+    let current = &mut state
+        .current_message
+        .user_input_message
+        .user_input_message_context;
+    let (validated, orphaned) = validate_tool_pairing(&state.history, &current.tool_results);
+    current.tool_results = validated;
+    remove_orphaned_tool_uses(&mut state.history, &orphaned);
+}
+
+/// 截断过长的 tool_result 文本（保留首尾），返回是否发生截断
+fn trim_tool_result(result: &mut ToolResult, max_chars: usize) -> bool {
+    let mut trimmed = false;
+    for block in &mut result.content {
+        let Some(serde_json::Value::String(text)) = block.get_mut("text") else {
+            continue;
+        };
+        let len = text.chars().count();
+        if len <= max_chars {
+            continue;
+        }
+        let head: String = text.chars().take(max_chars / 2).collect();
+        let tail: String = text.chars().skip(len - max_chars / 2).collect();
+        *text = format!(
+            "{}\n\n[... {} characters truncated to fit the context window ...]\n\n{}",
+            head,
+            len - head.chars().count() - tail.chars().count(),
+            tail
+        );
+        trimmed = true;
+    }
+    trimmed
+}
+
+fn tool_result_text(result: &ToolResult) -> String {
+    result
+        .content
+        .iter()
+        .map(|block| match block.get("text") {
+            Some(serde_json::Value::String(text)) => text.clone(),
+            _ => serde_json::Value::Object(block.clone()).to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn tool_results_tokens(results: &[ToolResult]) -> u64 {
+    results
+        .iter()
+        .map(|r| token::count_tokens(&tool_result_text(r)))
+        .sum()
+}
+
+/// 估算单条历史消息的 tokens
+fn message_tokens(msg: &Message) -> u64 {
+    match msg {
+        Message::User(user) => {
+            let msg = &user.user_input_message;
+            token::count_tokens(&msg.content)
+                + tool_results_tokens(&msg.user_input_message_context.tool_results)
+                + images_tokens(&msg.images)
+        }
+        Message::Assistant(assistant) => {
+            let msg = &assistant.assistant_response_message;
+            token::count_tokens(&msg.content)
+                + msg
+                    .tool_uses
+                    .iter()
+                    .flatten()
+                    .map(|t| token::count_tokens(&format!("{}{}", t.name, t.input)))
+                    .sum::<u64>()
+        }
+    }
+}
+
+/// 估算当前消息（含工具定义）的 tokens
+fn current_message_tokens(current: &CurrentMessage) -> u64 {
+    let msg = &current.user_input_message;
+    let context = &msg.user_input_message_context;
+    token::count_tokens(&msg.content)
+        + tool_results_tokens(&context.tool_results)
+        + context
+            .tools
+            .iter()
+            .map(|t| token::count_tokens(&serde_json::to_string(t).unwrap_or_default()))
+            .sum::<u64>()
+        + images_tokens(&msg.images)
+}
+
+/// 按尺寸估算图片的 tokens
+fn images_tokens(images: &[KiroImage]) -> u64 {
+    images
+        .iter()
+        .map(|image| token::base64_image_tokens(&image.source.bytes))
+        .sum()
+}
+
+/// 调用模型总结历史记录
+async fn summarize(
+    provider: &KiroProvider,
+    profile_arn: Option<&str>,
+    model_id: &str,
+    transcript: &str,
+) -> anyhow::Result<String> {
+    let content = format!(
+        "{}\n\n<transcript>\n{}\n</transcript>",
+        SUMMARY_INSTRUCTION, transcript
+    );
+    let state = ConversationState::new(Uuid::new_v4().to_string())
+        .with_agent_continuation_id(Uuid::new_v4().to_string())
+        .with_agent_task_type("vibe")
+        .with_chat_trigger_type("MANUAL")
+        .with_current_message(CurrentMessage::new(UserInputMessage::new(
+            content, model_id,
+        )));
+    let body = serde_json::to_string(&KiroRequest {
+        conversation_state: state,
+        profile_arn: profile_arn.map(str::to_string),
+    })?;
+
+    let response = provider.call_api(&body).await?;
+    let mut decoder = EventStreamDecoder::new();
+    let mut summary = String::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        if let Err(e) = decoder.feed(&chunk?) {
+            tracing::warn!("缓冲区溢出: {}", e);
+        }
+        for frame in decoder.decode_iter() {
+            match frame.map(Event::from_frame) {
+                Ok(Ok(Event::AssistantResponse(event))) => summary.push_str(&event.content),
+                Ok(Ok(Event::Exception {
+                    exception_type,
+                    message,
+                })) => {
+                    anyhow::bail!("{}: {}", exception_type, message)
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("解码事件失败: {}", e),
+            }
+        }
+    }
+
+    if summary.trim().is_empty() {
+        anyhow::bail!("模型未返回摘要");
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::converter::{RequestOrigin, convert_request};
+    use crate::anthropic::types::MessagesRequest;
+
+    /// 构造 `turns` 轮 Read 工具调用的对话，每个 tool_result 约 `result_chars` 个字符
+    fn long_conversation(turns: usize, result_chars: usize) -> ConversionResult {
+        let mut messages =
+            vec![serde_json::json!({"role": "user", "content": "Review the project"})];
+        for i in 0..turns {
+            let id = format!("toolu_{:02}", i);
+            messages.push(serde_json::json!({
+                "role": "assistant",
+                "content": [{"type": "tool_use", "id": id, "name": "Read", "input": {"file_path": format!("/src/{}.rs", i)}}]
+            }));
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": [{"type": "tool_result", "tool_use_id": id, "content": "x".repeat(result_chars)}]
+            }));
+        }
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 1024,
+            "system": [{"type": "text", "text": "You are a code reviewer."}],
+            "tools": [{"name": "Read", "description": "Reads a file", "input_schema": {"type": "object"}}],
+            "messages": messages
+        }))
+        .unwrap();
+        convert_request(&req, &RequestOrigin::default()).unwrap()
+    }
+
+    fn tool_result_ids(state: &ConversationState) -> Vec<String> {
+        state
+            .history
+            .iter()
+            .filter_map(|m| match m {
+                Message::User(u) => {
+                    Some(&u.user_input_message.user_input_message_context.tool_results)
+                }
+                _ => None,
+            })
+            .flatten()
+            .chain(
+                &state
+                    .current_message
+                    .user_input_message
+                    .user_input_message_context
+                    .tool_results,
+            )
+            .map(|r| r.tool_use_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_trim_tool_results_keeps_head_and_tail() {
+        let mut result = ToolResult::success(
+            "toolu_1",
+            format!("{}{}", "a".repeat(5000), "b".repeat(5000)),
+        );
+        assert!(trim_tool_result(&mut result, 1000));
+        let text = tool_result_text(&result);
+        assert!(text.starts_with(&"a".repeat(500)));
+        assert!(text.ends_with(&"b".repeat(500)));
+        assert!(text.contains("[... 9000 characters truncated"));
+        assert!(!trim_tool_result(&mut result, 20_000));
+    }
+
+    #[test]
+    fn test_trim_tool_results_stops_when_fitting() {
+        let mut result = long_conversation(6, 20_000);
+        let mut fitter = Fitter::new(&mut result, 0);
+        let estimated = fitter.total();
+        fitter.budget = estimated - 4_000;
+        fitter.trim_tool_results(2_000);
+
+        assert!(fitter.fits());
+        // 只截断了最早的一部分 tool_result，当前消息不受影响
+        let trimmed = fitter.report.trimmed_tool_results;
+        assert!((1..5).contains(&trimmed), "{}", trimmed);
+        let report = fitter.finish();
+        assert!(report.final_tokens < report.estimated_tokens);
+        let current = &result.conversation_state.current_message.user_input_message;
+        assert_eq!(
+            tool_result_text(&current.user_input_message_context.tool_results[0]).len(),
+            20_000
+        );
+    }
+
+    #[test]
+    fn test_drop_oldest_preserves_system_prompt_and_pairing() {
+        let mut result = long_conversation(6, 4_000);
+        let history_len = result.conversation_state.history.len();
+        assert_eq!(result.pinned_history, 2);
+
+        let mut fitter = Fitter::new(&mut result, 0);
+        fitter.budget = fitter.total() / 2;
+        let cut = fitter.cut_point(2).unwrap();
+        fitter.drop_until(cut, None);
+        assert!(fitter.fits());
+        let report = fitter.finish();
+        assert_eq!(report.dropped_messages, cut - 2);
+
+        let state = &result.conversation_state;
+        assert_eq!(state.history.len(), history_len - report.dropped_messages);
+        match &state.history[0] {
+            Message::User(u) => assert!(
+                u.user_input_message
+                    .content
+                    .contains("You are a code reviewer.")
+            ),
+            _ => panic!("history should start with the system prompt"),
+        }
+        assert!(matches!(state.history[2], Message::User(_)));
+        // 保留下来的第一条 user 消息的 tool_result 对应的 tool_use 已被丢弃，随之移除
+        let ids = tool_result_ids(state);
+        let current = &state.current_message.user_input_message;
+        let results = &current.user_input_message_context.tool_results;
+        let (validated, orphaned) = validate_tool_pairing(&state.history, results);
+        assert_eq!(validated.len(), 1);
+        assert!(orphaned.is_empty(), "{:?}", orphaned);
+        assert!(!ids.contains(&"toolu_00".to_string()));
+        assert!(ids.contains(&"toolu_05".to_string()));
+    }
+
+    #[test]
+    fn test_cut_point_respects_keep_recent_turns() {
+        let mut result = long_conversation(3, 4_000);
+        let mut fitter = Fitter::new(&mut result, 0);
+        // 历史：系统提示词对 + 3 轮 user/assistant；当前消息为最后一个 tool_result
+        let cut = fitter.cut_point(1).unwrap();
+        assert_eq!(cut, fitter.result.conversation_state.history.len() - 2);
+        assert_eq!(fitter.cut_point(3), None);
+        let cut = fitter.cut_point(0).unwrap();
+        assert_eq!(cut, fitter.result.conversation_state.history.len());
+
+        // 全部丢弃后当前消息的 tool_result 也失去配对
+        fitter.drop_until(
+            cut,
+            Some("The user asked for a review; files 0-1 were read."),
+        );
+        let state = &fitter.result.conversation_state;
+        assert_eq!(state.history.len(), 2);
+        let current = &state.current_message.user_input_message;
+        assert!(current.content.starts_with("<conversation_summary>"));
+        assert!(current.user_input_message_context.tool_results.is_empty());
+        assert_eq!(fitter.report.summarized_messages, 6);
+    }
+
+    #[test]
+    fn test_inline_preamble_moves_to_new_first_message() {
+        let mut result = long_conversation(4, 4_000);
+        // 模拟 inline 策略：系统提示词并入第一条 user 消息
+        result.conversation_state.history.drain(..2);
+        result.pinned_history = 0;
+        result.history_preamble = Some("<system>\nYou are a code reviewer.\n</system>".to_string());
+
+        let mut fitter = Fitter::new(&mut result, u64::MAX);
+        let cut = fitter.cut_point(1).unwrap();
+        fitter.drop_until(cut, None);
+        let state = &fitter.result.conversation_state;
+        match &state.history[0] {
+            Message::User(u) => assert!(
+                u.user_input_message
+                    .content
+                    .starts_with("<system>\nYou are a code reviewer.")
+            ),
+            _ => panic!("history should start with a user message"),
+        }
+    }
+
+    #[test]
+    fn test_report_header_value() {
+        let report = ContextReport {
+            estimated_tokens: 231_000,
+            final_tokens: 178_000,
+            dropped_messages: 4,
+            trimmed_tool_results: 2,
+            summarized_messages: 0,
+        };
+        assert_eq!(
+            report.header_value(),
+            "dropped=4; trimmed=2; summarized=0; tokens=231000->178000"
+        );
+    }
+}
@@ -12,7 +12,12 @@ use crate::kiro::model::requests::tool::{
     InputSchema, Tool, ToolResult, ToolSpecification, ToolUseEntry,
 };
 
-use super::types::{ContentBlock, MessagesRequest};
+use crate::apikey::ClientKey;
+use crate::model::prompt_rules::{self, PromptRewriter, Rewrite, Route, RuleContext};
+use crate::model::registry::{self, SystemPromptStrategy};
+
+use super::document;
+use super::types::{ContentBlock, MessagesRequest, ToolChoice};
 
 /// 规范化 JSON Schema，修复 MCP 工具定义中常见的类型问题
 ///
@@ -59,46 +64,49 @@ fn normalize_json_schema(schema: serde_json::Value) -> serde_json::Value {
     serde_json::Value::Object(obj)
 }
 
-/// 追加到 Write 工具 description 末尾的内容
-const WRITE_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the content to write exceeds 150 lines, you MUST only write the first 50 lines using this tool, then use `Edit` tool to append the remaining content in chunks of no more than 50 lines each. If needed, leave a unique placeholder to help append content. Do NOT attempt to write all content at once.";
+/// tool_choice 为 none 时追加的系统指令
+const TOOL_CHOICE_NONE_INSTRUCTION: &str =
+    "Do not call any tools in this response. Reply with text only.";
 
-/// 追加到 Edit 工具 description 末尾的内容
-const EDIT_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the `new_string` content exceeds 50 lines, you MUST split it into multiple Edit calls, each replacing no more than 50 lines at a time. If used to append content, leave a unique placeholder to help append content. On the final chunk, do NOT include the placeholder.";
+/// tool_choice 为 any 时追加的系统指令
+const TOOL_CHOICE_ANY_INSTRUCTION: &str = "\
+You must respond by calling at least one of the available tools. \
+Do not reply with text only.";
 
-/// 追加到系统提示词的分块写入策略
-const SYSTEM_CHUNKED_POLICY: &str = "\
-When the Write or Edit tool has content size limits, always comply silently. \
-Never suggest bypassing these limits via alternative tools. \
-Never ask the user whether to switch approaches. \
-Complete all chunked operations without commentary.";
+/// disable_parallel_tool_use 为 true 时追加的系统指令
+const DISABLE_PARALLEL_TOOL_USE_INSTRUCTION: &str =
+    "Call at most one tool in this response. Never issue multiple tool calls at once.";
 
 /// 模型映射：将 Anthropic 模型名映射到 Kiro 模型 ID
 ///
-/// 按照用户要求：
+/// 映射规则来自全局模型注册表（`config.json` 的 `models` 字段），
+/// 默认配置下：
 /// - sonnet 4.6/4-6 → claude-sonnet-4.6
 /// - 其他 sonnet → claude-sonnet-4.5
 /// - opus 4.5/4-5 → claude-opus-4.5
 /// - 其他 opus → claude-opus-4.6
 /// - 所有 haiku → claude-haiku-4.5
 pub fn map_model(model: &str) -> Option<String> {
-    let model_lower = model.to_lowercase();
+    registry::registry()
+        .resolve(model)
+        .map(|entry| entry.kiro_model_id.clone())
+}
 
-    if model_lower.contains("sonnet") {
-        if model_lower.contains("4-6") || model_lower.contains("4.6") {
-            Some("claude-sonnet-4.6".to_string())
-        } else {
-            Some("claude-sonnet-4.5".to_string())
-        }
-    } else if model_lower.contains("opus") {
-        if model_lower.contains("4-5") || model_lower.contains("4.5") {
-            Some("claude-opus-4.5".to_string())
-        } else {
-            Some("claude-opus-4.6".to_string())
+/// 请求来源（用于匹配提示词改写规则）
+#[derive(Debug, Clone, Default)]
+pub struct RequestOrigin {
+    /// 请求入口
+    pub route: Route,
+    /// 客户端 API Key（使用主 API Key 时为 None）
+    pub client: Option<ClientKey>,
+}
+
+impl RequestOrigin {
+    pub fn new(route: Route, client: Option<&ClientKey>) -> Self {
+        Self {
+            route,
+            client: client.cloned(),
         }
-    } else if model_lower.contains("haiku") {
-        Some("claude-haiku-4.5".to_string())
-    } else {
-        None
     }
 }
 
@@ -107,20 +115,56 @@ pub fn map_model(model: &str) -> Option<String> {
 pub struct ConversionResult {
     /// 转换后的 Kiro 请求
     pub conversation_state: ConversationState,
+    /// 命中的提示词改写规则名称
+    pub applied_rules: Vec<String>,
+    /// 历史开头承载系统提示词的消息数（pair 策略），裁剪历史时必须保留
+    pub pinned_history: usize,
+    /// 并入历史第一条 user 消息的系统提示词（inline 策略），裁剪掉该消息时需要移到新的开头
+    pub history_preamble: Option<String>,
+}
+
+/// 请求中 Kiro 不支持、转换时被忽略的采样参数
+///
+/// Kiro 的 ConversationState 没有 temperature / top_p / top_k 对应字段，
+/// 调用方记录 warn 日志并通过响应头告知客户端
+pub fn ignored_sampling_params(req: &MessagesRequest) -> Vec<&'static str> {
+    [
+        ("temperature", req.temperature.is_some()),
+        ("top_p", req.top_p.is_some()),
+        ("top_k", req.top_k.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, present)| present.then_some(name))
+    .collect()
 }
 
 /// 转换错误
 #[derive(Debug)]
 pub enum ConversionError {
-    UnsupportedModel(String),
+    /// 模型不支持（附带可用的模型 ID 列表）
+    UnsupportedModel {
+        model: String,
+        supported: Vec<String>,
+    },
     EmptyMessages,
+    /// tool_choice 与 tools 不匹配
+    InvalidToolChoice(String),
+    /// document 块或 URL 来源无法处理
+    InvalidDocument(String),
 }
 
 impl std::fmt::Display for ConversionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ConversionError::UnsupportedModel(model) => write!(f, "模型不支持: {}", model),
+            ConversionError::UnsupportedModel { model, supported } => write!(
+                f,
+                "模型不支持: {}，可用模型: {}",
+                model,
+                supported.join(", ")
+            ),
             ConversionError::EmptyMessages => write!(f, "消息列表为空"),
+            ConversionError::InvalidToolChoice(message) => write!(f, "{}", message),
+            ConversionError::InvalidDocument(message) => write!(f, "{}", message),
         }
     }
 }
@@ -186,10 +230,39 @@ fn create_placeholder_tool(name: &str) -> Tool {
 }
 
 /// 将 Anthropic 请求转换为 Kiro 请求
-pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, ConversionError> {
+///
+/// 系统提示词与工具描述按全局提示词改写规则改写，系统提示词按模型注册表中的策略承载
+pub fn convert_request(
+    req: &MessagesRequest,
+    origin: &RequestOrigin,
+) -> Result<ConversionResult, ConversionError> {
+    let strategy = registry::registry().system_prompt_strategy(&req.model);
+    convert_request_with(req, origin, &prompt_rules::rewriter(), strategy)
+}
+
+/// 预览转换结果（Admin API 的提示词改写 dry-run 使用）
+///
+/// 与 `/v1/messages` 相同，先按模型名的 `-thinking` 后缀覆写 thinking 配置再转换
+pub fn preview_request(
+    mut req: MessagesRequest,
+    origin: &RequestOrigin,
+) -> Result<ConversionResult, ConversionError> {
+    super::handlers::override_thinking_from_model_name(&mut req);
+    convert_request(&req, origin)
+}
+
+/// 使用指定的提示词改写器与系统提示词策略转换请求
+fn convert_request_with(
+    req: &MessagesRequest,
+    origin: &RequestOrigin,
+    rewriter: &PromptRewriter,
+    strategy: SystemPromptStrategy,
+) -> Result<ConversionResult, ConversionError> {
     // 1. 映射模型
-    let model_id = map_model(&req.model)
-        .ok_or_else(|| ConversionError::UnsupportedModel(req.model.clone()))?;
+    let model_id = map_model(&req.model).ok_or_else(|| ConversionError::UnsupportedModel {
+        model: req.model.clone(),
+        supported: registry::registry().public_ids(),
+    })?;
 
     // 2. 检查消息列表
     if req.messages.is_empty() {
@@ -220,18 +293,80 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         .unwrap_or_else(|| Uuid::new_v4().to_string());
     let agent_continuation_id = Uuid::new_v4().to_string();
 
+    // 3.5. 选出命中当前请求的提示词改写规则
+    let system_text = join_system(req);
+    let tool_names: Vec<&str> = req
+        .tools
+        .iter()
+        .flatten()
+        .map(|t| t.name.as_str())
+        .collect();
+    let rewrite = rewriter.select(&RuleContext {
+        model: &req.model,
+        client: origin.client.as_ref().map(|c| (c.id, c.name.as_str())),
+        route: origin.route,
+        tools: &tool_names,
+        system: &system_text,
+    });
+    let applied_rules = rewrite.rule_names();
+    if !applied_rules.is_empty() {
+        tracing::debug!(rules = ?applied_rules, "应用提示词改写规则");
+    }
+
     // 4. 确定触发类型
     let chat_trigger_type = determine_chat_trigger_type(req);
 
     // 5. 处理最后一条消息作为 current_message（经过 prefill 预处理，末尾必为 user）
     let last_message = messages.last().unwrap();
-    let (text_content, images, tool_results) = process_message_content(&last_message.content)?;
+    let (mut text_content, mut images, mut tool_results) =
+        process_message_content(&last_message.content)?;
 
-    // 6. 转换工具定义
-    let mut tools = convert_tools(&req.tools);
+    // 6. 转换工具定义，并按 tool_choice 裁剪
+    let mut tools = apply_tool_choice(
+        convert_tools(&req.tools, &rewrite),
+        req.tool_choice.as_ref(),
+    )?;
 
     // 7. 构建历史消息（需要先构建，以便收集历史中使用的工具）
-    let mut history = build_history(req, messages, &model_id)?;
+    let BuiltHistory {
+        mut history,
+        preamble,
+        pending_users,
+        pinned_history,
+        history_preamble,
+    } = build_history(
+        req,
+        messages,
+        &model_id,
+        rewrite.system(system_text),
+        strategy,
+    )?;
+
+    // 7.5. inline 策略：历史末尾未配对的 user 消息与系统提示词并入当前消息
+    if !pending_users.is_empty() {
+        let mut parts = Vec::new();
+        let mut pending_images = Vec::new();
+        let mut pending_results = Vec::new();
+        for msg in &pending_users {
+            let (text, imgs, results) = process_message_content(&msg.content)?;
+            if !text.is_empty() {
+                parts.push(text);
+            }
+            pending_images.extend(imgs);
+            pending_results.extend(results);
+        }
+        if !text_content.is_empty() {
+            parts.push(text_content);
+        }
+        text_content = parts.join("\n");
+        pending_images.append(&mut images);
+        images = pending_images;
+        pending_results.append(&mut tool_results);
+        tool_results = pending_results;
+    }
+    if let Some(preamble) = preamble {
+        text_content = prepend_preamble(&preamble, text_content);
+    }
 
     // 8. 验证并过滤 tool_use/tool_result 配对
     // 移除孤立的 tool_result（没有对应的 tool_use）
@@ -288,7 +423,12 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         .with_current_message(current_message)
         .with_history(history);
 
-    Ok(ConversionResult { conversation_state })
+    Ok(ConversionResult {
+        conversation_state,
+        applied_rules,
+        pinned_history,
+        history_preamble,
+    })
 }
 
 /// 确定聊天触发类型
@@ -320,11 +460,20 @@ fn process_message_content(
                         }
                         "image" => {
                             if let Some(source) = block.source {
+                                if source.source_type == "url" {
+                                    return Err(ConversionError::InvalidDocument(format!(
+                                        "图片的 URL 来源未下载: {}",
+                                        source.url.unwrap_or_default()
+                                    )));
+                                }
                                 if let Some(format) = get_image_format(&source.media_type) {
                                     images.push(KiroImage::from_base64(format, source.data));
                                 }
                             }
                         }
+                        "document" => {
+                            text_parts.push(document::render_document(&block)?);
+                        }
                         "tool_result" => {
                             if let Some(tool_use_id) = block.tool_use_id {
                                 let result_content = extract_tool_result_content(&block.content);
@@ -356,7 +505,7 @@ fn process_message_content(
 }
 
 /// 从 media_type 获取图片格式
-fn get_image_format(media_type: &str) -> Option<String> {
+pub(super) fn get_image_format(media_type: &str) -> Option<String> {
     match media_type {
         "image/jpeg" => Some("jpeg".to_string()),
         "image/png" => Some("png".to_string()),
@@ -395,7 +544,7 @@ fn extract_tool_result_content(content: &Option<serde_json::Value>) -> String {
 ///
 /// # Returns
 /// 元组：(经过验证和过滤后的 tool_result 列表, 孤立的 tool_use_id 集合)
-fn validate_tool_pairing(
+pub(super) fn validate_tool_pairing(
     history: &[Message],
     tool_results: &[ToolResult],
 ) -> (Vec<ToolResult>, std::collections::HashSet<String>) {
@@ -476,7 +625,7 @@ fn validate_tool_pairing(
 /// # Arguments
 /// * `history` - 可变的历史消息列表
 /// * `orphaned_ids` - 需要移除的孤立 tool_use_id 集合
-fn remove_orphaned_tool_uses(
+pub(super) fn remove_orphaned_tool_uses(
     history: &mut [Message],
     orphaned_ids: &std::collections::HashSet<String>,
 ) {
@@ -504,8 +653,8 @@ fn remove_orphaned_tool_uses(
     }
 }
 
-/// 转换工具定义
-fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
+/// 转换工具定义（描述按提示词改写规则改写）
+fn convert_tools(tools: &Option<Vec<super::types::Tool>>, rewrite: &Rewrite<'_>) -> Vec<Tool> {
     let Some(tools) = tools else {
         return Vec::new();
     };
@@ -513,18 +662,7 @@ fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
     tools
         .iter()
         .map(|t| {
-            let mut description = t.description.clone();
-
-            // 对 Write/Edit 工具追加自定义描述后缀
-            let suffix = match t.name.as_str() {
-                "Write" => WRITE_TOOL_DESCRIPTION_SUFFIX,
-                "Edit" => EDIT_TOOL_DESCRIPTION_SUFFIX,
-                _ => "",
-            };
-            if !suffix.is_empty() {
-                description.push('\n');
-                description.push_str(suffix);
-            }
+            let description = rewrite.tool_description(&t.name, t.description.clone());
 
             // 限制描述长度为 10000 字符（安全截断 UTF-8，单次遍历）
             let description = match description.char_indices().nth(10000) {
@@ -543,6 +681,60 @@ fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
         .collect()
 }
 
+/// 按 tool_choice 裁剪工具列表
+///
+/// - `none`：移除全部工具（历史中引用的工具仍会生成占位符定义）
+/// - `tool`：只保留指定的工具
+/// - `any`：要求至少提供一个工具
+fn apply_tool_choice(
+    mut tools: Vec<Tool>,
+    tool_choice: Option<&ToolChoice>,
+) -> Result<Vec<Tool>, ConversionError> {
+    match tool_choice {
+        Some(ToolChoice::None) => tools.clear(),
+        Some(ToolChoice::Any { .. }) if tools.is_empty() => {
+            return Err(ConversionError::InvalidToolChoice(
+                "tool_choice.type 为 any 时必须提供 tools".to_string(),
+            ));
+        }
+        Some(ToolChoice::Tool { name, .. }) => {
+            tools.retain(|t| t.tool_specification.name == *name);
+            if tools.is_empty() {
+                return Err(ConversionError::InvalidToolChoice(format!(
+                    "tool_choice 指定的工具不存在: {}",
+                    name
+                )));
+            }
+        }
+        _ => {}
+    }
+    Ok(tools)
+}
+
+/// 生成 tool_choice 对应的系统指令
+fn generate_tool_choice_instruction(req: &MessagesRequest) -> Option<String> {
+    let tool_choice = req.tool_choice.as_ref()?;
+    let mut parts = Vec::new();
+    match tool_choice {
+        ToolChoice::Auto { .. } => {}
+        ToolChoice::Any { .. } => parts.push(TOOL_CHOICE_ANY_INSTRUCTION.to_string()),
+        ToolChoice::Tool { name, .. } => parts.push(format!(
+            "You must respond by calling the `{}` tool. \
+             Do not call any other tool and do not reply with text only.",
+            name
+        )),
+        ToolChoice::None => parts.push(TOOL_CHOICE_NONE_INSTRUCTION.to_string()),
+    }
+    if tool_choice.disable_parallel_tool_use() {
+        parts.push(DISABLE_PARALLEL_TOOL_USE_INSTRUCTION.to_string());
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("\n"))
+    }
+}
+
 /// 生成thinking标签前缀
 fn generate_thinking_prefix(req: &MessagesRequest) -> Option<String> {
     if let Some(t) = &req.thinking {
@@ -571,6 +763,16 @@ fn has_thinking_tags(content: &str) -> bool {
     content.contains("<thinking_mode>") || content.contains("<max_thinking_length>")
 }
 
+/// 合并系统提示词的各个文本块
+fn join_system(req: &MessagesRequest) -> String {
+    req.system
+        .iter()
+        .flatten()
+        .map(|s| s.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// 构建历史消息
 ///
 /// # Arguments
@@ -579,49 +781,52 @@ fn has_thinking_tags(content: &str) -> bool {
 ///   注意：该切片与 `req.messages` 可能不同（prefill 时会截断末尾的 assistant 消息），
 ///   调用方应始终使用此参数而非 `req.messages`。
 /// * `model_id` - 已映射的 Kiro 模型 ID
-fn build_history(req: &MessagesRequest, messages: &[super::types::Message], model_id: &str) -> Result<Vec<Message>, ConversionError> {
+/// * `system_content` - 已按提示词改写规则改写的系统提示词
+/// * `strategy` - 系统提示词承载方式
+fn build_history<'a>(
+    req: &MessagesRequest,
+    messages: &'a [super::types::Message],
+    model_id: &str,
+    system_content: String,
+    strategy: SystemPromptStrategy,
+) -> Result<BuiltHistory<'a>, ConversionError> {
     let mut history = Vec::new();
 
-    // 生成thinking前缀（如果需要）
-    let thinking_prefix = generate_thinking_prefix(req);
-
-    // 1. 处理系统消息
-    if let Some(ref system) = req.system {
-        let system_content: String = system
-            .iter()
-            .map(|s| s.text.clone())
-            .collect::<Vec<_>>()
-            .join("\n");
-
+    // 1. 处理系统消息：改写后的系统提示词 + tool_choice 指令
+    let mut system_content = system_content;
+    if let Some(instruction) = generate_tool_choice_instruction(req) {
         if !system_content.is_empty() {
-            // 追加分块写入策略到系统消息
-            let system_content = format!("{}\n{}", system_content, SYSTEM_CHUNKED_POLICY);
-
-            // 注入thinking标签到系统消息最前面（如果需要且不存在）
-            let final_content = if let Some(ref prefix) = thinking_prefix {
-                if !has_thinking_tags(&system_content) {
-                    format!("{}\n{}", prefix, system_content)
-                } else {
-                    system_content
-                }
-            } else {
-                system_content
-            };
-
-            // 系统消息作为 user + assistant 配对
-            let user_msg = HistoryUserMessage::new(final_content, model_id);
-            history.push(Message::User(user_msg));
-
-            let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
-            history.push(Message::Assistant(assistant_msg));
+            system_content.push('\n');
         }
-    } else if let Some(ref prefix) = thinking_prefix {
-        // 没有系统消息但有thinking配置，插入新的系统消息
-        let user_msg = HistoryUserMessage::new(prefix.clone(), model_id);
+        system_content.push_str(&instruction);
+    }
+    // thinking 标签放在最前面（系统提示词中已包含时不重复注入）
+    let thinking_prefix =
+        generate_thinking_prefix(req).filter(|_| !has_thinking_tags(&system_content));
+    let system_block = match strategy {
+        SystemPromptStrategy::Pair => system_content,
+        SystemPromptStrategy::Inline if system_content.is_empty() => system_content,
+        SystemPromptStrategy::Inline => format!("<system>\n{}\n</system>", system_content),
+    };
+    let preamble = [thinking_prefix, Some(system_block)]
+        .into_iter()
+        .flatten()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut preamble = (!preamble.is_empty()).then_some(preamble);
+    let mut pinned_history = 0;
+    if strategy == SystemPromptStrategy::Pair
+        && let Some(content) = preamble.take()
+    {
+        // 系统消息作为 user + assistant 配对
+        let user_msg = HistoryUserMessage::new(content, model_id);
         history.push(Message::User(user_msg));
 
         let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
         history.push(Message::Assistant(assistant_msg));
+        pinned_history = history.len();
     }
 
     // 2. 处理常规消息历史
@@ -663,16 +868,64 @@ fn build_history(req: &MessagesRequest, messages: &[super::types::Message], mode
     }
 
     // 处理结尾的孤立 user 消息
+    let mut pending_users = Vec::new();
     if !user_buffer.is_empty() {
-        let merged_user = merge_user_messages(&user_buffer, model_id)?;
-        history.push(Message::User(merged_user));
+        if strategy == SystemPromptStrategy::Inline {
+            // 交给调用方并入当前消息
+            pending_users = user_buffer;
+        } else {
+            let merged_user = merge_user_messages(&user_buffer, model_id)?;
+            history.push(Message::User(merged_user));
 
-        // 自动配对一个 "OK" 的 assistant 响应
-        let auto_assistant = HistoryAssistantMessage::new("OK");
-        history.push(Message::Assistant(auto_assistant));
+            // 自动配对一个 "OK" 的 assistant 响应
+            let auto_assistant = HistoryAssistantMessage::new("OK");
+            history.push(Message::Assistant(auto_assistant));
+        }
     }
 
-    Ok(history)
+    // inline 策略：系统提示词并入第一条 user 消息（历史为空时由调用方并入当前消息）
+    let history_preamble = preamble.take_if(|_| !history.is_empty());
+    if let Some(content) = history_preamble.clone() {
+        match &mut history[0] {
+            Message::User(first) => {
+                let text = std::mem::take(&mut first.user_input_message.content);
+                first.user_input_message.content = prepend_preamble(&content, text);
+            }
+            Message::Assistant(_) => {
+                history.insert(0, Message::User(HistoryUserMessage::new(content, model_id)));
+            }
+        }
+    }
+
+    Ok(BuiltHistory {
+        history,
+        preamble,
+        pending_users,
+        pinned_history,
+        history_preamble,
+    })
+}
+
+/// 构建出的历史消息
+struct BuiltHistory<'a> {
+    history: Vec<Message>,
+    /// inline 策略下需要并入当前消息的系统提示词（历史为空时）
+    preamble: Option<String>,
+    /// inline 策略下历史末尾未配对、需要并入当前消息的 user 消息
+    pending_users: Vec<&'a super::types::Message>,
+    /// pair 策略下承载系统提示词的消息数
+    pinned_history: usize,
+    /// inline 策略下并入历史第一条 user 消息的系统提示词
+    history_preamble: Option<String>,
+}
+
+/// 将系统提示词放在 user 消息内容之前
+pub(super) fn prepend_preamble(preamble: &str, content: String) -> String {
+    if content.is_empty() {
+        preamble.to_string()
+    } else {
+        format!("{}\n\n{}", preamble, content)
+    }
 }
 
 /// 合并多个 user 消息
@@ -860,6 +1113,40 @@ mod tests {
         assert!(map_model("gpt-4").is_none());
     }
 
+    #[test]
+    fn test_convert_request_unsupported_model_lists_choices() {
+        let req = MessagesRequest {
+            model: "gpt-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![super::super::types::Message {
+                role: "user".to_string(),
+                content: serde_json::json!("Hello"),
+            }],
+            stream: false,
+            system: None,
+            tools: None,
+            tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            thinking: None,
+            output_config: None,
+            metadata: None,
+        };
+
+        let err = convert_request(&req, &RequestOrigin::default()).unwrap_err();
+        match &err {
+            ConversionError::UnsupportedModel { model, supported } => {
+                assert_eq!(model, "gpt-4");
+                assert!(supported.contains(&"claude-sonnet-4-6".to_string()));
+                assert!(supported.contains(&"claude-opus-4-6-thinking".to_string()));
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        assert!(err.to_string().contains("claude-haiku-4-5-20251001"));
+    }
+
     #[test]
     fn test_map_model_thinking_suffix_sonnet() {
         // thinking 后缀不应影响 sonnet 模型映射
@@ -899,6 +1186,10 @@ mod tests {
             system: None,
             tools: None,
             tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
             thinking: None,
             output_config: None,
             metadata: None,
@@ -978,12 +1269,16 @@ mod tests {
             system: None,
             tools: None, // 没有提供工具定义
             tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
             thinking: None,
             output_config: None,
             metadata: None,
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &RequestOrigin::default()).unwrap();
 
         // 验证 tools 列表中包含了历史中使用的工具的占位符定义
         let tools = &result
@@ -1043,6 +1338,10 @@ mod tests {
             system: None,
             tools: None,
             tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
             thinking: None,
             output_config: None,
             metadata: Some(Metadata {
@@ -1052,7 +1351,7 @@ mod tests {
             }),
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &RequestOrigin::default()).unwrap();
         assert_eq!(
             result.conversation_state.conversation_id,
             "a0662283-7fd3-4399-a7eb-52b9a717ae88"
@@ -1075,12 +1374,16 @@ mod tests {
             system: None,
             tools: None,
             tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
             thinking: None,
             output_config: None,
             metadata: None,
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &RequestOrigin::default()).unwrap();
         // 验证生成的是有效的 UUID 格式
         assert_eq!(result.conversation_state.conversation_id.len(), 36);
         assert_eq!(
@@ -1505,12 +1808,16 @@ mod tests {
             system: None,
             tools: None,
             tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
             thinking: None,
             output_config: None,
             metadata: None,
         };
 
-        let result = convert_request(&req);
+        let result = convert_request(&req, &RequestOrigin::default());
         assert!(result.is_ok(), "连续 assistant 消息场景不应报错: {:?}", result.err());
 
         let state = result.unwrap().conversation_state;
@@ -1527,4 +1834,376 @@ mod tests {
         }
         assert!(found_tool_use, "合并后的 assistant 消息应包含 tool_use");
     }
+
+    fn tool_choice_request(tool_choice: serde_json::Value) -> MessagesRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "What's the weather in Paris?"}],
+            "tools": [
+                {"name": "get_weather", "description": "Get weather", "input_schema": {"type": "object"}},
+                {"name": "extract", "description": "Extract fields", "input_schema": {"type": "object"}}
+            ],
+            "tool_choice": tool_choice
+        }))
+        .unwrap()
+    }
+
+    fn current_tool_names(result: &ConversionResult) -> Vec<String> {
+        result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .user_input_message_context
+            .tools
+            .iter()
+            .map(|t| t.tool_specification.name.clone())
+            .collect()
+    }
+
+    fn system_prompt(result: &ConversionResult) -> String {
+        match &result.conversation_state.history[0] {
+            Message::User(msg) => msg.user_input_message.content.clone(),
+            _ => panic!("history should start with the system prompt"),
+        }
+    }
+
+    #[test]
+    fn test_tool_choice_tool_keeps_only_named_tool() {
+        let req = tool_choice_request(serde_json::json!({
+            "type": "tool",
+            "name": "extract",
+            "disable_parallel_tool_use": true
+        }));
+        let result = convert_request(&req, &RequestOrigin::default()).unwrap();
+
+        assert_eq!(current_tool_names(&result), vec!["extract"]);
+        let system = system_prompt(&result);
+        assert!(system.contains("`extract` tool"));
+        assert!(system.contains(DISABLE_PARALLEL_TOOL_USE_INSTRUCTION));
+    }
+
+    #[test]
+    fn test_tool_choice_tool_unknown_name_is_rejected() {
+        let req = tool_choice_request(serde_json::json!({"type": "tool", "name": "missing"}));
+        let err = convert_request(&req, &RequestOrigin::default()).unwrap_err();
+        assert!(matches!(err, ConversionError::InvalidToolChoice(_)));
+        assert!(err.to_string().contains("missing"));
+    }
+
+    #[test]
+    fn test_tool_choice_any_and_none() {
+        let req = tool_choice_request(serde_json::json!({"type": "any"}));
+        let result = convert_request(&req, &RequestOrigin::default()).unwrap();
+        assert_eq!(current_tool_names(&result).len(), 2);
+        assert!(system_prompt(&result).contains(TOOL_CHOICE_ANY_INSTRUCTION));
+
+        let req = tool_choice_request(serde_json::json!({"type": "none"}));
+        let result = convert_request(&req, &RequestOrigin::default()).unwrap();
+        assert!(current_tool_names(&result).is_empty());
+        assert!(system_prompt(&result).contains(TOOL_CHOICE_NONE_INSTRUCTION));
+
+        // auto 不追加任何指令
+        let req = tool_choice_request(serde_json::json!({"type": "auto"}));
+        let result = convert_request(&req, &RequestOrigin::default()).unwrap();
+        assert_eq!(current_tool_names(&result).len(), 2);
+        assert!(result.conversation_state.history.is_empty());
+    }
+
+    fn write_tool_request() -> MessagesRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 1024,
+            "system": [{"type": "text", "text": "You are Claude Code."}],
+            "messages": [{"role": "user", "content": "Create main.rs"}],
+            "tools": [
+                {"name": "Write", "description": "Writes a file", "input_schema": {"type": "object"}},
+                {"name": "Read", "description": "Reads a file", "input_schema": {"type": "object"}}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn tool_description(result: &ConversionResult, name: &str) -> String {
+        result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .user_input_message_context
+            .tools
+            .iter()
+            .find(|t| t.tool_specification.name == name)
+            .map(|t| t.tool_specification.description.clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_default_prompt_rules_append_chunked_write_policy() {
+        let result = convert_request_with(
+            &write_tool_request(),
+            &RequestOrigin::default(),
+            &PromptRewriter::default(),
+            SystemPromptStrategy::Pair,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.applied_rules,
+            vec!["chunked-write-tools", "chunked-write-policy"]
+        );
+        let write = tool_description(&result, "Write");
+        assert!(write.starts_with("Writes a file\n- IMPORTANT: If the content to write exceeds 150 lines"));
+        assert_eq!(tool_description(&result, "Read"), "Reads a file");
+        let system = system_prompt(&result);
+        assert!(system.starts_with("You are Claude Code.\nWhen the Write or Edit tool has content size limits"));
+    }
+
+    #[test]
+    fn test_prompt_rules_match_route_and_client() {
+        let rules = serde_json::from_value(serde_json::json!([{
+            "name": "support-bot",
+            "match": {"routes": ["v1"], "clients": ["support"]},
+            "actions": [
+                {"target": "system", "op": "replace", "text": "You are a support agent."},
+                {"target": "toolDescription", "tool": "read", "op": "append", "text": "Read-only."}
+            ]
+        }]))
+        .unwrap();
+        let rewriter = PromptRewriter::new(rules).unwrap();
+        let client = ClientKey {
+            id: 1,
+            name: "support".to_string(),
+        };
+
+        let origin = RequestOrigin::new(Route::V1, Some(&client));
+        let result = convert_request_with(
+            &write_tool_request(),
+            &origin,
+            &rewriter,
+            SystemPromptStrategy::Pair,
+        )
+        .unwrap();
+        assert_eq!(result.applied_rules, vec!["support-bot"]);
+        assert_eq!(system_prompt(&result), "You are a support agent.");
+        assert_eq!(tool_description(&result, "Read"), "Reads a file\nRead-only.");
+        // 没有默认规则时 Write 工具描述保持原样
+        assert_eq!(tool_description(&result, "Write"), "Writes a file");
+
+        // /cc/v1 或主 API Key 的请求不命中
+        for origin in [
+            RequestOrigin::new(Route::Cc, Some(&client)),
+            RequestOrigin::new(Route::V1, None),
+        ] {
+            let result = convert_request_with(
+                &write_tool_request(),
+                &origin,
+                &rewriter,
+                SystemPromptStrategy::Pair,
+            )
+            .unwrap();
+            assert!(result.applied_rules.is_empty());
+            assert_eq!(system_prompt(&result), "You are Claude Code.");
+        }
+    }
+
+    /// Claude Code 会话形态的金样（golden）测试
+    ///
+    /// `testdata/<dir>/<name>.json` 为请求，`<name>.<strategy>.json` 为期望的
+    /// `conversationState`。修改转换逻辑或新增样本后可用 `UPDATE_GOLDEN=1 cargo test` 重新生成。
+    ///
+    /// - `claude_code/`：脱敏后的真实 Claude Code 请求录制，录制与脱敏方法见该目录下的 README.md；
+    ///   目录中没有录制时金样测试只覆盖合成数据
+    /// - `synthetic/`：按 Claude Code 请求结构手写的合成数据，并非录制的真实会话。它们只覆盖消息结构
+    ///   （多段 system、工具循环、中断后的 tool_result、首轮标题请求），不代表真实提示词的长度与内容
+    const TRANSCRIPT_DIRS: &[&str] = &["claude_code", "synthetic"];
+
+    fn testdata_dir(dir: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/anthropic/testdata")
+            .join(dir)
+    }
+
+    /// 列出所有请求样本（`<name>.json`，不含 `<name>.<strategy>.json` 金样）
+    fn transcripts() -> Vec<std::path::PathBuf> {
+        let mut paths = TRANSCRIPT_DIRS
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(testdata_dir(dir)).ok())
+            .flatten()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "json")
+                    && path
+                        .file_stem()
+                        .is_some_and(|stem| !stem.to_string_lossy().contains('.'))
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    fn convert_transcript(
+        path: &std::path::Path,
+        strategy: SystemPromptStrategy,
+    ) -> ConversionResult {
+        let req: MessagesRequest =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        convert_request_with(
+            &req,
+            &RequestOrigin::default(),
+            &PromptRewriter::default(),
+            strategy,
+        )
+        .unwrap()
+    }
+
+    fn convert_synthetic(name: &str, strategy: SystemPromptStrategy) -> ConversionResult {
+        convert_transcript(
+            &testdata_dir("synthetic").join(format!("{}.json", name)),
+            strategy,
+        )
+    }
+
+    fn history_texts(result: &ConversionResult) -> Vec<String> {
+        result
+            .conversation_state
+            .history
+            .iter()
+            .map(|m| match m {
+                Message::User(u) => u.user_input_message.content.clone(),
+                Message::Assistant(a) => a.assistant_response_message.content.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_claude_code_transcripts_golden() {
+        let paths = transcripts();
+        assert!(!paths.is_empty());
+        for path in &paths {
+            for (strategy, suffix) in [
+                (SystemPromptStrategy::Pair, "pair"),
+                (SystemPromptStrategy::Inline, "inline"),
+            ] {
+                let result = convert_transcript(path, strategy);
+                let mut actual = serde_json::to_value(&result.conversation_state).unwrap();
+                // agentContinuationId 每次随机生成
+                actual["agentContinuationId"] = serde_json::json!("<random>");
+                let actual = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+
+                let golden = path.with_extension(format!("{}.json", suffix));
+                if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                    std::fs::write(&golden, &actual).unwrap();
+                    continue;
+                }
+                let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+                assert!(
+                    expected == actual,
+                    "{} 与金样不一致（UPDATE_GOLDEN=1 重新生成）:\n{}",
+                    golden.display(),
+                    actual
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_inline_strategy_has_no_synthetic_turns() {
+        for path in transcripts() {
+            let name = path.display();
+            let pair = convert_transcript(&path, SystemPromptStrategy::Pair);
+            let inline = convert_transcript(&path, SystemPromptStrategy::Inline);
+
+            let pair_texts = history_texts(&pair);
+            assert!(pair_texts.iter().any(|t| t == "I will follow these instructions."));
+            let inline_texts = history_texts(&inline);
+            assert!(
+                !inline_texts
+                    .iter()
+                    .any(|t| t == "I will follow these instructions." || t == "OK"),
+                "{}: {:?}",
+                name,
+                inline_texts
+            );
+            assert!(inline_texts.len() < pair_texts.len());
+            // history 交替且以 user 开头
+            for (i, msg) in inline.conversation_state.history.iter().enumerate() {
+                assert_eq!(matches!(msg, Message::User(_)), i % 2 == 0, "{}", name);
+            }
+
+            // 系统提示词恰好出现一次：历史第一条或当前消息
+            let current = &inline.conversation_state.current_message.user_input_message.content;
+            let occurrences = inline_texts
+                .iter()
+                .chain(std::iter::once(current))
+                .map(|t| t.matches("<system>\n").count())
+                .sum::<usize>();
+            assert_eq!(occurrences, 1, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_inline_strategy_merges_trailing_users_into_current() {
+        let inline = convert_synthetic("interrupted", SystemPromptStrategy::Inline);
+        let current = &inline.conversation_state.current_message.user_input_message;
+        assert!(current.content.starts_with("[Request interrupted by user for tool use]\n"));
+        assert!(current.content.ends_with("Use chi's router instead of net/http for the new route."));
+        // 被拒绝的 tool_result 随之进入当前消息，与历史中的 tool_use 配对
+        let results = &current.user_input_message_context.tool_results;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].tool_use_id, "toolu_01Lw3Nq8Rv5Tx2Zc7Bm4Kd9H");
+        // thinking 前缀随系统提示词并入第一条 user 消息
+        let first = &history_texts(&inline)[0];
+        assert!(first.starts_with("<thinking_mode>enabled</thinking_mode>"));
+        assert!(first.ends_with("Add a /health route to server.go"));
+
+        // 首轮请求没有历史，系统提示词直接并入当前消息
+        let inline = convert_synthetic("first_turn", SystemPromptStrategy::Inline);
+        assert!(inline.conversation_state.history.is_empty());
+        let current = &inline.conversation_state.current_message.user_input_message.content;
+        assert!(current.starts_with("<system>\nYou are Claude Code"));
+        assert!(current.ends_with("</system>\n\nwhy does cargo build fail on the ci runner?"));
+    }
+
+    #[test]
+    fn test_document_blocks_are_inlined() {
+        let (text, _, _) = process_message_content(&serde_json::json!([
+            {"type": "document", "title": "Notes", "source": {"type": "text", "media_type": "text/plain", "data": "alpha beta"}},
+            {"type": "text", "text": "Summarize the notes"}
+        ]))
+        .unwrap();
+        assert!(text.contains("title=\"Notes\""));
+        assert!(text.contains("alpha beta"));
+        assert!(text.ends_with("Summarize the notes"));
+
+        // 未下载的 URL 来源直接报错，而不是静默丢弃
+        let err = process_message_content(&serde_json::json!([
+            {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}
+        ]))
+        .unwrap_err();
+        assert!(matches!(err, ConversionError::InvalidDocument(_)));
+    }
+
+    #[test]
+    fn test_ignored_sampling_params() {
+        let request = |extra: serde_json::Value| -> MessagesRequest {
+            let mut value = serde_json::json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 1024,
+                "messages": [{"role": "user", "content": "Hello"}]
+            });
+            value
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            serde_json::from_value(value).unwrap()
+        };
+
+        assert!(ignored_sampling_params(&request(serde_json::json!({}))).is_empty());
+        assert_eq!(
+            ignored_sampling_params(&request(
+                serde_json::json!({"temperature": 0.2, "top_k": 5})
+            )),
+            vec!["temperature", "top_k"]
+        );
+    }
 }
@@ -0,0 +1,542 @@
+//! document 内容块与 URL 来源处理
+//!
+//! Kiro 只接受文本和 base64 图片，这里把 Anthropic 的 `document` 块渲染为带标记的文本：
+//! - `base64` PDF：本地提取文本，按页输出
+//! - `text`：原文内联
+//! - `content`：逐个文本块内联
+//!
+//! 每个文档的 ID 由内容哈希生成，同一文档在多轮对话中保持不变；文档内每页/每段有独立的
+//! 块 ID（如 `doc-1a2b3c4d.p3`），便于模型引用出处。
+//!
+//! `url` 来源（图片和文档）在转换前由 [`resolve_url_sources`] 下载并改写为 `base64`/`text`
+//! 来源；未启用下载时直接拒绝请求，不会静默丢弃。下载只允许访问公网地址，重定向的每一跳都会重新检查。
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use parking_lot::RwLock;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::common::io::blocking_io;
+use crate::http_client::{ProxyConfig, build_public_client, get_public_url};
+use crate::model::config::{DocumentConfig, TlsBackend};
+
+use super::converter::{ConversionError, get_image_format};
+use super::types::{ContentBlock, ImageSource, MessagesRequest};
+
+/// document 处理配置
+#[derive(Clone, Default)]
+pub struct DocumentOptions {
+    /// 大小与下载限制
+    pub limits: DocumentConfig,
+    /// 下载 URL 使用的代理
+    pub proxy: Option<ProxyConfig>,
+    /// TLS 后端
+    pub tls_backend: TlsBackend,
+}
+
+static DOCUMENT_OPTIONS: LazyLock<RwLock<Arc<DocumentOptions>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(DocumentOptions::default())));
+
+/// 初始化（或替换）document 处理配置
+///
+/// 应在应用启动和配置热重载时调用，未调用时使用默认限制
+pub fn init_config(options: DocumentOptions) {
+    *DOCUMENT_OPTIONS.write() = Arc::new(options);
+}
+
+fn options() -> Arc<DocumentOptions> {
+    DOCUMENT_OPTIONS.read().clone()
+}
+
+fn invalid(message: impl Into<String>) -> ConversionError {
+    ConversionError::InvalidDocument(message.into())
+}
+
+/// 将 document 块渲染为文本
+pub(crate) fn render_document(block: &ContentBlock) -> Result<String, ConversionError> {
+    let source = block
+        .source
+        .as_ref()
+        .ok_or_else(|| invalid("document 块缺少 source"))?;
+    let options = options();
+    let limits = &options.limits;
+
+    let (id, chunks) = match source.source_type.as_str() {
+        "base64" => {
+            if source.media_type != "application/pdf" {
+                return Err(invalid(format!(
+                    "不支持的 base64 文档类型: {}（仅支持 application/pdf）",
+                    source.media_type
+                )));
+            }
+            let bytes = BASE64
+                .decode(source.data.trim())
+                .map_err(|e| invalid(format!("PDF 文档 base64 解码失败: {}", e)))?;
+            check_size(bytes.len(), limits.max_bytes)?;
+            let id = document_id(&bytes);
+            let pages = extract_pdf_pages(&bytes)?;
+            if pages.len() > limits.max_pdf_pages {
+                return Err(invalid(format!(
+                    "PDF 页数 {} 超过上限 {}",
+                    pages.len(),
+                    limits.max_pdf_pages
+                )));
+            }
+            if pages.iter().all(|page| page.trim().is_empty()) {
+                return Err(invalid("PDF 中没有可提取的文本（可能是扫描件）"));
+            }
+            let chunks = pages
+                .iter()
+                .enumerate()
+                .map(|(i, page)| {
+                    (
+                        format!("{}.p{}", id, i + 1),
+                        Some(i + 1),
+                        page.trim().to_string(),
+                    )
+                })
+                .collect();
+            (id, chunks)
+        }
+        "text" => {
+            check_size(source.data.len(), limits.max_bytes)?;
+            let id = document_id(source.data.as_bytes());
+            let chunks = vec![(format!("{}.c1", id), None, source.data.clone())];
+            (id, chunks)
+        }
+        "content" => {
+            let texts = content_source_texts(source)?;
+            check_size(texts.iter().map(String::len).sum(), limits.max_bytes)?;
+            let id = document_id(texts.join("\n").as_bytes());
+            let chunks = texts
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| (format!("{}.c{}", id, i + 1), None, text))
+                .collect();
+            (id, chunks)
+        }
+        "url" => {
+            return Err(invalid(format!(
+                "document 的 URL 来源未下载: {}",
+                source.url.as_deref().unwrap_or_default()
+            )));
+        }
+        "file" => return Err(invalid("不支持 file 来源的 document（Files API）")),
+        other => return Err(invalid(format!("不支持的 document 来源类型: {}", other))),
+    };
+
+    let mut output = format!("<document id=\"{}\"", id);
+    if let Some(title) = &block.title {
+        output.push_str(&format!(" title=\"{}\"", escape_attr(title)));
+    }
+    output.push_str(">\n");
+    if let Some(context) = block.context.as_deref().filter(|c| !c.is_empty()) {
+        output.push_str(&format!("<context>\n{}\n</context>\n", context));
+    }
+    for (chunk_id, page, text) in chunks {
+        match page {
+            Some(page) => output.push_str(&format!(
+                "<chunk id=\"{}\" page=\"{}\">\n{}\n</chunk>\n",
+                chunk_id, page, text
+            )),
+            None => output.push_str(&format!(
+                "<chunk id=\"{}\">\n{}\n</chunk>\n",
+                chunk_id, text
+            )),
+        }
+    }
+    output.push_str("</document>");
+    Ok(output)
+}
+
+/// 提取 `content` 来源中的文本块
+fn content_source_texts(source: &ImageSource) -> Result<Vec<String>, ConversionError> {
+    match &source.content {
+        Some(serde_json::Value::String(text)) => Ok(vec![text.clone()]),
+        Some(serde_json::Value::Array(blocks)) => blocks
+            .iter()
+            .map(|block| match block.get("type").and_then(|v| v.as_str()) {
+                Some("text") => Ok(block["text"].as_str().unwrap_or_default().to_string()),
+                other => Err(invalid(format!(
+                    "document 的 content 来源仅支持 text 块，收到: {}",
+                    other.unwrap_or("unknown")
+                ))),
+            })
+            .collect(),
+        _ => Err(invalid("document 的 content 来源缺少 content")),
+    }
+}
+
+fn check_size(size: usize, max_bytes: usize) -> Result<(), ConversionError> {
+    if size > max_bytes {
+        return Err(invalid(format!(
+            "文档大小 {} 字节超过上限 {} 字节",
+            size, max_bytes
+        )));
+    }
+    Ok(())
+}
+
+/// 按内容哈希生成文档 ID
+fn document_id(bytes: &[u8]) -> String {
+    format!("doc-{}", &hex::encode(Sha256::digest(bytes))[..8])
+}
+
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// 逐页提取 PDF 文本
+pub(super) fn extract_pdf_pages(bytes: &[u8]) -> Result<Vec<String>, ConversionError> {
+    // pdf-extract 遇到不规范的 PDF 可能 panic，这里兜底为请求错误
+    let result = blocking_io(|| {
+        std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+    });
+    match result {
+        Ok(Ok(pages)) => Ok(pages),
+        Ok(Err(e)) => Err(invalid(format!("PDF 解析失败: {}", e))),
+        Err(_) => Err(invalid("PDF 解析失败: 文件格式不受支持")),
+    }
+}
+
+/// 下载请求中 `url` 来源的图片和文档，改写为 `base64`/`text` 来源
+///
+/// 同一请求中相同的 URL 只下载一次
+pub(crate) async fn resolve_url_sources(
+    payload: &mut MessagesRequest,
+) -> Result<(), ConversionError> {
+    let options = options();
+    let mut client = None;
+    let mut fetched: HashMap<String, (String, Vec<u8>)> = HashMap::new();
+
+    for message in &mut payload.messages {
+        let serde_json::Value::Array(blocks) = &mut message.content else {
+            continue;
+        };
+        for block in blocks {
+            let kind = block
+                .get("type")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            if !matches!(kind, "image" | "document") {
+                continue;
+            }
+            let source = &block["source"];
+            if source.get("type").and_then(|v| v.as_str()) != Some("url") {
+                continue;
+            }
+            let url = source
+                .get("url")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| invalid(format!("{} 的 URL 来源缺少 url", kind)))?
+                .to_string();
+
+            if !options.limits.fetch_urls {
+                return Err(invalid(format!(
+                    "未启用 URL 来源下载，请改用 base64 来源: {}",
+                    url
+                )));
+            }
+
+            if !fetched.contains_key(&url) {
+                if client.is_none() {
+                    client = Some(
+                        build_public_client(
+                            options.proxy.as_ref(),
+                            options.limits.fetch_timeout_secs,
+                            options.tls_backend,
+                        )
+                        .map_err(|e| invalid(format!("创建 HTTP Client 失败: {}", e)))?,
+                    );
+                }
+                let resource =
+                    fetch(client.as_ref().unwrap(), &url, options.limits.max_bytes).await?;
+                fetched.insert(url.clone(), resource);
+            }
+            let (media_type, bytes) = &fetched[&url];
+
+            let new_source = if kind == "image" {
+                let media_type = if media_type.starts_with("image/") {
+                    media_type.as_str()
+                } else {
+                    sniff_media_type(bytes).unwrap_or(media_type)
+                };
+                if get_image_format(media_type).is_none() {
+                    return Err(invalid(format!("不支持的图片类型 {}: {}", media_type, url)));
+                }
+                json!({"type": "base64", "media_type": media_type, "data": BASE64.encode(bytes)})
+            } else if media_type == "application/pdf"
+                || sniff_media_type(bytes) == Some("application/pdf")
+            {
+                json!({"type": "base64", "media_type": "application/pdf", "data": BASE64.encode(bytes)})
+            } else if is_text_media_type(media_type) {
+                let text = String::from_utf8(bytes.clone())
+                    .map_err(|_| invalid(format!("文档不是有效的 UTF-8 文本: {}", url)))?;
+                json!({"type": "text", "media_type": "text/plain", "data": text})
+            } else {
+                return Err(invalid(format!(
+                    "不支持的文档类型 {}（仅支持 PDF 与文本）: {}",
+                    media_type, url
+                )));
+            };
+
+            tracing::debug!(url = %url, kind = %kind, size = bytes.len(), "已下载 URL 来源");
+            block["source"] = new_source;
+        }
+    }
+    Ok(())
+}
+
+/// 下载 URL 资源，返回 (media_type, 内容)
+async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+    max_bytes: usize,
+) -> Result<(String, Vec<u8>), ConversionError> {
+    let parsed =
+        reqwest::Url::parse(url).map_err(|e| invalid(format!("无效的 URL {}: {}", url, e)))?;
+
+    let mut response = get_public_url(client, parsed, None, |_| true)
+        .await
+        .map_err(|e| invalid(format!("下载 {} 失败: {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(invalid(format!(
+            "下载 {} 失败: HTTP {}",
+            url,
+            response.status()
+        )));
+    }
+    if response
+        .content_length()
+        .is_some_and(|len| len as usize > max_bytes)
+    {
+        return Err(invalid(format!(
+            "{} 的大小超过上限 {} 字节",
+            url, max_bytes
+        )));
+    }
+
+    let media_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| invalid(format!("下载 {} 失败: {}", url, e)))?
+    {
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(invalid(format!(
+                "{} 的大小超过上限 {} 字节",
+                url, max_bytes
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok((media_type, bytes))
+}
+
+/// 根据文件头识别类型
+pub(super) fn sniff_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+pub(super) fn is_text_media_type(media_type: &str) -> bool {
+    media_type.starts_with("text/")
+        || matches!(
+            media_type,
+            "application/json" | "application/xml" | "application/x-yaml" | "application/yaml"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造只有一行文本的单页 PDF
+    fn minimal_pdf(text: &str) -> Vec<u8> {
+        let stream = format!("BT /F1 12 Tf 72 720 Td ({}) Tj ET", text);
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> >> >>".to_string(),
+            format!("<< /Length {} >>\nstream\n{}\nendstream", stream.len(), stream),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .as_bytes(),
+        );
+        pdf
+    }
+
+    fn document(source: serde_json::Value) -> ContentBlock {
+        serde_json::from_value(json!({
+            "type": "document",
+            "title": "Q3 \"Report\"",
+            "context": "quarterly numbers",
+            "source": source
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_pdf_document() {
+        let data = BASE64.encode(minimal_pdf("Revenue grew 12 percent"));
+        let block =
+            document(json!({"type": "base64", "media_type": "application/pdf", "data": data}));
+
+        let output = render_document(&block).unwrap();
+        assert!(output.starts_with("<document id=\"doc-"));
+        assert!(output.contains("title=\"Q3 &quot;Report&quot;\""));
+        assert!(output.contains("<context>\nquarterly numbers\n</context>"));
+        assert!(output.contains(".p1\" page=\"1\">"));
+        assert!(output.contains("Revenue grew 12 percent"));
+        assert_eq!(render_document(&block).unwrap(), output);
+    }
+
+    #[test]
+    fn test_render_text_and_content_documents() {
+        let block =
+            document(json!({"type": "text", "media_type": "text/plain", "data": "plain body"}));
+        let output = render_document(&block).unwrap();
+        assert!(output.contains(".c1\">\nplain body\n</chunk>"));
+
+        let block = document(json!({
+            "type": "content",
+            "content": [{"type": "text", "text": "first"}, {"type": "text", "text": "second"}]
+        }));
+        let output = render_document(&block).unwrap();
+        assert!(output.contains(".c1\">\nfirst\n</chunk>"));
+        assert!(output.contains(".c2\">\nsecond\n</chunk>"));
+    }
+
+    #[test]
+    fn test_render_rejects_unusable_sources() {
+        let cases = [
+            json!({"type": "base64", "media_type": "application/pdf", "data": BASE64.encode(b"not a pdf")}),
+            json!({"type": "base64", "media_type": "application/msword", "data": "AAAA"}),
+            json!({"type": "url", "url": "https://example.com/a.pdf"}),
+            json!({"type": "file", "file_id": "file_123"}),
+            json!({"type": "content", "content": [{"type": "image"}]}),
+        ];
+        for source in cases {
+            let err = render_document(&document(source.clone())).unwrap_err();
+            assert!(
+                matches!(err, ConversionError::InvalidDocument(_)),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_limit() {
+        assert!(check_size(10, 10).is_ok());
+        assert!(matches!(
+            check_size(11, 10),
+            Err(ConversionError::InvalidDocument(_))
+        ));
+    }
+
+    #[test]
+    fn test_sniff_media_type() {
+        assert_eq!(sniff_media_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(
+            sniff_media_type(b"\x89PNG\r\n\x1a\n...."),
+            Some("image/png")
+        );
+        assert_eq!(
+            sniff_media_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_media_type(b"hello"), None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_leaves_inline_sources_untouched() {
+        let mut payload: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "hi"},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}}
+            ]}]
+        }))
+        .unwrap();
+        let before = payload.messages[0].content.clone();
+        resolve_url_sources(&mut payload).await.unwrap();
+        assert_eq!(payload.messages[0].content, before);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_rejects_url_sources_by_default() {
+        let mut payload: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": [
+                {"type": "document", "source": {"type": "url", "url": "https://example.com/a.pdf"}}
+            ]}]
+        }))
+        .unwrap();
+        let err = resolve_url_sources(&mut payload).await.unwrap_err();
+        assert!(err.to_string().contains("未启用"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_non_http_and_internal_urls() {
+        let client = build_public_client(None, 5, TlsBackend::Rustls).unwrap();
+        for (url, reason) in [
+            ("file:///etc/passwd", "http/https"),
+            ("http://127.0.0.1:8990/api/admin/config", "非公网"),
+            ("http://169.254.169.254/latest/meta-data/", "非公网"),
+            ("http://[fd00::1]/", "非公网"),
+            ("http://localhost/a.png", "非公网"),
+        ] {
+            let err = fetch(&client, url, 1024).await.unwrap_err();
+            assert!(err.to_string().contains(reason), "{}: {}", url, err);
+        }
+    }
+}
@@ -1,17 +1,22 @@
 //! Anthropic API Handler 函数
 
 use std::convert::Infallible;
+use std::sync::Arc;
 
-use anyhow::Error;
+use crate::apikey::ClientKey;
 use crate::kiro::model::events::Event;
 use crate::kiro::model::requests::kiro::KiroRequest;
 use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::KiroProvider;
+use crate::model::prompt_rules::Route;
+use crate::model::registry;
 use crate::token;
+use anyhow::Error;
 use axum::{
-    Json as JsonExtractor,
+    Extension, Json as JsonExtractor,
     body::Body,
     extract::State,
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
@@ -21,14 +26,54 @@ use std::time::Duration;
 use tokio::time::interval;
 use uuid::Uuid;
 
-use super::converter::{ConversionError, convert_request};
+use super::cache::{self, CacheHandle};
+use super::context_window::{self, ContextReport};
+use super::converter::{
+    ConversionError, ConversionResult, RequestOrigin, convert_request, ignored_sampling_params,
+};
+use super::document;
 use super::middleware::AppState;
+use super::output_budget::OutputBudget;
+use super::partial_json;
+use super::prompt_cache::{self, PromptCacheUsage};
+use super::stop_sequence::StopSequenceMatcher;
 use super::stream::{BufferedStreamContext, SseEvent, StreamContext};
-use super::types::{CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse, OutputConfig, Thinking};
+use super::tool_choice::{ToolChoiceGuard, ToolUseDecision};
+use super::types::{
+    CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse,
+    OutputConfig, Thinking,
+};
+use super::usage::{UsageReporter, rejection_response};
 use super::websearch;
 
+/// 请求包含被忽略的采样参数时附加的响应头
+const IGNORED_PARAMS_HEADER: &str = "x-ignored-params";
+
+/// 检查 Kiro 不支持的采样参数：记录 warn 日志，返回需要在响应头中列出的参数名
+pub(super) fn check_sampling_params(payload: &MessagesRequest) -> Vec<&'static str> {
+    let ignored = ignored_sampling_params(payload);
+    if !ignored.is_empty() {
+        tracing::warn!(
+            temperature = ?payload.temperature,
+            top_p = ?payload.top_p,
+            top_k = ?payload.top_k,
+            "Kiro 不支持采样参数，已忽略"
+        );
+    }
+    ignored
+}
+
+/// 在响应上附加 `x-ignored-params` 头，如 `temperature, top_p`
+pub(super) fn apply_ignored_params(ignored: &[&str], response: &mut Response) {
+    if !ignored.is_empty()
+        && let Ok(value) = ignored.join(", ").parse()
+    {
+        response.headers_mut().insert(IGNORED_PARAMS_HEADER, value);
+    }
+}
+
 /// 将 KiroProvider 错误映射为 HTTP 响应
-fn map_provider_error(err: Error) -> Response {
+pub(super) fn map_provider_error(err: Error) -> Response {
     let err_str = err.to_string();
 
     // 上下文窗口满了（对话历史累积超出模型上下文窗口限制）
@@ -67,104 +112,180 @@ fn map_provider_error(err: Error) -> Response {
         .into_response()
 }
 
+/// 请求准入：检查 KiroProvider 是否可用、按模型名覆写 thinking 配置，
+/// 并做客户端 API Key 准入检查（模型权限与配额）
+///
+/// `/v1/messages`、`/cc/v1/messages` 与 `/v1/chat/completions` 共用以下请求管线：
+/// [`admit_request`] → [`convert_payload`] → [`build_upstream`] → [`call_upstream`]
+pub(super) fn admit_request(
+    state: &AppState,
+    client: Option<&ClientKey>,
+    payload: &mut MessagesRequest,
+) -> Result<(Arc<KiroProvider>, UsageReporter), Response> {
+    let Some(provider) = state.kiro_provider.clone() else {
+        tracing::error!("KiroProvider 未配置");
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(ErrorResponse::new(
+                "service_unavailable",
+                "Kiro API provider not configured",
+            )),
+        )
+            .into_response());
+    };
+
+    // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
+    override_thinking_from_model_name(payload);
+
+    let usage = UsageReporter::admit(state, client, &payload.model, payload.stream)
+        .map_err(|e| rejection_response(&e))?;
+    Ok((provider, usage))
+}
+
+/// 下载 url 来源的图片和文档，并把请求转换为 Kiro 格式
+pub(super) async fn convert_payload(
+    payload: &mut MessagesRequest,
+    origin: &RequestOrigin,
+    usage: &UsageReporter,
+) -> Result<ConversionResult, Response> {
+    let bad_request = |message: String| {
+        usage.fail(
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", message)),
+            )
+                .into_response(),
+        )
+    };
+
+    if let Err(e) = document::resolve_url_sources(payload).await {
+        tracing::warn!("URL 来源处理失败: {}", e);
+        return Err(bad_request(e.to_string()));
+    }
+
+    convert_request(payload, origin).map_err(|e| {
+        tracing::warn!("请求转换失败: {}", e);
+        match e {
+            ConversionError::EmptyMessages => bad_request("消息列表为空".to_string()),
+            e => bad_request(e.to_string()),
+        }
+    })
+}
+
+/// 发往上游的请求
+pub(super) struct UpstreamRequest {
+    /// 序列化后的 Kiro 请求体
+    pub(super) body: String,
+    /// 后台估算的输入 tokens（与上游调用并发执行）
+    pub(super) input_tokens: token::InputTokensTask,
+    /// 上下文窗口预检结果（发生裁剪时附加到响应头）
+    pub(super) context_report: Option<ContextReport>,
+}
+
+/// 上下文窗口预检（超出阈值时按策略裁剪历史）、序列化 Kiro 请求，并在后台估算输入 tokens
+///
+/// 估算任务会取走 payload 中的 system / messages / tools，之后只能再读取输出约束相关字段
+pub(super) async fn build_upstream(
+    state: &AppState,
+    provider: &KiroProvider,
+    mut conversion_result: ConversionResult,
+    payload: &mut MessagesRequest,
+    usage: &UsageReporter,
+) -> Result<UpstreamRequest, Response> {
+    let context_report = context_window::fit(
+        &mut conversion_result,
+        &payload.model,
+        provider,
+        state.profile_arn.as_deref(),
+    )
+    .await;
+
+    let kiro_request = KiroRequest {
+        conversation_state: conversion_result.conversation_state,
+        profile_arn: state.profile_arn.clone(),
+    };
+    let body = match serde_json::to_string(&kiro_request) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("序列化请求失败: {}", e);
+            return Err(usage.fail(
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(
+                        "internal_error",
+                        format!("序列化请求失败: {}", e),
+                    )),
+                )
+                    .into_response(),
+            ));
+        }
+    };
+    tracing::debug!("Kiro request body: {}", body);
+
+    let input_tokens = token::InputTokensTask::spawn(
+        payload.model.clone(),
+        payload.system.take(),
+        std::mem::take(&mut payload.messages),
+        payload.tools.take(),
+    );
+    Ok(UpstreamRequest {
+        body,
+        input_tokens,
+        context_report,
+    })
+}
+
+/// 调用 Kiro API（支持多凭据故障转移），失败时上报用量并映射为错误响应
+pub(super) async fn call_upstream(
+    provider: &KiroProvider,
+    body: &str,
+    stream: bool,
+    usage: &mut UsageReporter,
+) -> Result<reqwest::Response, Response> {
+    let result = if stream {
+        provider.call_api_stream(body).await
+    } else {
+        provider.call_api(body).await
+    };
+    match result {
+        Ok(response) => {
+            usage.set_upstream(&response);
+            Ok(response)
+        }
+        Err(e) => Err(usage.fail(map_provider_error(e))),
+    }
+}
+
 /// GET /v1/models
 ///
-/// 返回可用的模型列表
+/// 返回模型注册表中的可用模型列表（含 thinking 变体）
 pub async fn get_models() -> impl IntoResponse {
     tracing::info!("Received GET /v1/models request");
 
-    let models = vec![
-        Model {
-            id: "claude-sonnet-4-5-20250929".to_string(),
-            object: "model".to_string(),
-            created: 1727568000,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-sonnet-4-5-20250929-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1727568000,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-5-20251101".to_string(),
-            object: "model".to_string(),
-            created: 1730419200,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-5-20251101-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1730419200,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-sonnet-4-6".to_string(),
-            object: "model".to_string(),
-            created: 1770314400,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.6".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-sonnet-4-6-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1770314400,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.6 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-6".to_string(),
+    let registry = registry::registry();
+    let mut models = Vec::new();
+    for entry in registry.models() {
+        models.push(Model {
+            id: entry.id.clone(),
             object: "model".to_string(),
-            created: 1770314400,
+            created: entry.created,
             owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.6".to_string(),
+            display_name: entry.display_name().to_string(),
             model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-6-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1770314400,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.6 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-haiku-4-5-20251001".to_string(),
-            object: "model".to_string(),
-            created: 1727740800,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Haiku 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-haiku-4-5-20251001-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1727740800,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Haiku 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-    ];
+            max_tokens: entry.max_tokens,
+        });
+        if entry.thinking {
+            models.push(Model {
+                id: format!("{}{}", entry.id, registry::THINKING_SUFFIX),
+                object: "model".to_string(),
+                created: entry.created,
+                owned_by: "anthropic".to_string(),
+                display_name: format!("{} (Thinking)", entry.display_name()),
+                model_type: "chat".to_string(),
+                max_tokens: entry.max_tokens,
+            });
+        }
+    }
 
     Json(ModelsResponse {
         object: "list".to_string(),
@@ -177,6 +298,8 @@ pub async fn get_models() -> impl IntoResponse {
 /// 创建消息（对话）
 pub async fn post_messages(
     State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    headers: HeaderMap,
     JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
     tracing::info!(
@@ -186,91 +309,71 @@ pub async fn post_messages(
         message_count = %payload.messages.len(),
         "Received POST /v1/messages request"
     );
-    // 检查 KiroProvider 是否可用
-    let provider = match &state.kiro_provider {
-        Some(p) => p.clone(),
-        None => {
-            tracing::error!("KiroProvider 未配置");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(ErrorResponse::new(
-                    "service_unavailable",
-                    "Kiro API provider not configured",
-                )),
-            )
-                .into_response();
-        }
+    // 检查 KiroProvider、按模型名覆写 thinking 配置并做客户端 API Key 准入检查
+    let (provider, usage) = match admit_request(&state, client.as_deref(), &mut payload) {
+        Ok(admitted) => admitted,
+        Err(response) => return response,
     };
 
-    // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
-    override_thinking_from_model_name(&mut payload);
+    // 请求来源（用于匹配提示词改写规则）
+    let origin = RequestOrigin::new(Route::V1, client.as_deref());
+
+    // Kiro 不支持的采样参数（在成功的响应上通过响应头告知客户端）
+    let ignored_params = check_sampling_params(&payload);
 
-    // 检查是否为 WebSearch 请求
-    if websearch::has_web_search_tool(&payload) {
-        tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
+    // 检查是否为服务端工具（web_search / web_fetch）请求
+    if websearch::is_server_tool_request(&payload) {
+        tracing::info!("检测到服务端工具，路由到服务端工具循环处理");
 
-        // 估算输入 tokens
-        let input_tokens = token::count_all_tokens(
+        // 在后台估算输入 tokens，与上游调用并发执行
+        let input_tokens = token::InputTokensTask::spawn(
             payload.model.clone(),
             payload.system.clone(),
             payload.messages.clone(),
             payload.tools.clone(),
-        ) as i32;
+        );
 
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+        let mut response = websearch::handle_websearch_request(
+            provider,
+            state.profile_arn.clone(),
+            &payload,
+            origin,
+            input_tokens,
+            usage,
+        )
+        .await;
+        apply_ignored_params(&ignored_params, &mut response);
+        return response;
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
+    // 下载 url 来源的图片和文档并转换请求
+    let conversion_result = match convert_payload(&mut payload, &origin, &usage).await {
         Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
-    };
-
-    // 构建 Kiro 请求
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
+        Err(response) => return response,
     };
 
-    let request_body = match serde_json::to_string(&kiro_request) {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
-    };
+    // 响应缓存（命中时直接回放，不调用上游）
+    let cache = CacheHandle::resolve(
+        &state,
+        &headers,
+        &payload,
+        &conversion_result.conversation_state,
+    );
+    if let Some(cached) = cache.as_ref().and_then(CacheHandle::lookup) {
+        let mut response = cache::replay(cached, &payload.model, payload.stream, usage);
+        apply_ignored_params(&ignored_params, &mut response);
+        return response;
+    }
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    // 估算 prompt caching 的缓存读取/写入 token
+    let prompt_cache = prompt_cache::evaluate(&state.prompt_cache, client.as_deref(), &payload);
 
-    // 估算输入 tokens
-    let input_tokens = token::count_all_tokens(
-        payload.model.clone(),
-        payload.system,
-        payload.messages,
-        payload.tools,
-    ) as i32;
+    // 上下文窗口预检、构建 Kiro 请求体，并在后台估算输入 tokens
+    let upstream =
+        match build_upstream(&state, &provider, conversion_result, &mut payload, &usage).await {
+            Ok(upstream) => upstream,
+            Err(response) => return response,
+        };
 
     // 检查是否启用了thinking
     let thinking_enabled = payload
@@ -279,44 +382,58 @@ pub async fn post_messages(
         .map(|t| t.is_enabled())
         .unwrap_or(false);
 
-    if payload.stream {
-        // 流式响应
-        handle_stream_request(
+    let mut response = if payload.stream {
+        // 流式响应（input_tokens 在上游响应后填入）
+        let ctx = StreamContext::new_with_thinking(&payload.model, 0, thinking_enabled)
+            .with_prompt_cache(prompt_cache)
+            .with_tool_choice(payload.tool_choice)
+            .with_stop_sequences(payload.stop_sequences)
+            .with_max_tokens(payload.max_tokens);
+        handle_stream_request(provider, &upstream.body, ctx, upstream.input_tokens, usage).await
+    } else {
+        // 非流式响应
+        handle_non_stream_request(
             provider,
-            &request_body,
+            &upstream.body,
             &payload.model,
-            input_tokens,
-            thinking_enabled,
+            upstream.input_tokens,
+            prompt_cache,
+            OutputConstraints {
+                tool_choice: ToolChoiceGuard::new(payload.tool_choice),
+                stop_sequences: StopSequenceMatcher::new(payload.stop_sequences),
+                budget: OutputBudget::new(Some(payload.max_tokens)),
+            },
+            usage,
+            cache,
         )
         .await
-    } else {
-        // 非流式响应
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
+    };
+    if let Some(report) = upstream.context_report {
+        report.apply(&mut response);
     }
+    apply_ignored_params(&ignored_params, &mut response);
+    response
 }
 
 /// 处理流式请求
 async fn handle_stream_request(
-    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    provider: Arc<KiroProvider>,
     request_body: &str,
-    model: &str,
-    input_tokens: i32,
-    thinking_enabled: bool,
+    mut ctx: StreamContext,
+    input_tokens: token::InputTokensTask,
+    mut usage: UsageReporter,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
+    let response = match call_upstream(&provider, request_body, true, &mut usage).await {
+        Ok(response) => response,
+        Err(response) => return response,
     };
-
-    // 创建流处理上下文
-    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled);
+    ctx.input_tokens = input_tokens.wait().await;
 
     // 生成初始事件
     let initial_events = ctx.generate_initial_events();
 
     // 创建 SSE 流
-    let stream = create_sse_stream(response, ctx, initial_events);
+    let stream = create_sse_stream(response, ctx, initial_events, usage);
 
     // 返回 SSE 响应
     Response::builder()
@@ -341,6 +458,7 @@ fn create_sse_stream(
     response: reqwest::Response,
     ctx: StreamContext,
     initial_events: Vec<SseEvent>,
+    usage: UsageReporter,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     // 先发送初始事件
     let initial_stream = stream::iter(
@@ -353,8 +471,8 @@ fn create_sse_stream(
     let body_stream = response.bytes_stream();
 
     let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS))),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS)), usage),
+        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, usage)| async move {
             if finished {
                 return None;
             }
@@ -385,32 +503,42 @@ fn create_sse_stream(
                                 }
                             }
 
+                            // 命中停止序列等停止条件时不再读取上游，直接发送最终事件
+                            let finished = ctx.should_stop();
+                            if finished {
+                                events.extend(ctx.generate_final_events());
+                                report_stream_usage(&usage, ctx.final_usage(), ctx.tool_choice_violation(), &ctx.stop_reason());
+                            }
+
                             // 转换为 SSE 字节流
                             let bytes: Vec<Result<Bytes, Infallible>> = events
                                 .into_iter()
                                 .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                 .collect();
 
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, finished, ping_interval, usage)))
                         }
                         Some(Err(e)) => {
                             tracing::error!("读取响应流失败: {}", e);
                             // 发送最终事件并结束
                             let final_events = ctx.generate_final_events();
+                            let (input_tokens, output_tokens) = ctx.final_usage();
+                            usage.report_failure("stream_error", input_tokens, output_tokens);
                             let bytes: Vec<Result<Bytes, Infallible>> = final_events
                                 .into_iter()
                                 .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                 .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage)))
                         }
                         None => {
                             // 流结束，发送最终事件
                             let final_events = ctx.generate_final_events();
+                            report_stream_usage(&usage, ctx.final_usage(), ctx.tool_choice_violation(), &ctx.stop_reason());
                             let bytes: Vec<Result<Bytes, Infallible>> = final_events
                                 .into_iter()
                                 .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                 .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage)))
                         }
                     }
                 }
@@ -418,7 +546,7 @@ fn create_sse_stream(
                 _ = ping_interval.tick() => {
                     tracing::trace!("发送 ping 保活事件");
                     let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, usage)))
                 }
             }
         },
@@ -428,35 +556,68 @@ fn create_sse_stream(
     initial_stream.chain(processing_stream)
 }
 
-/// 上下文窗口大小（200k tokens）
-const CONTEXT_WINDOW_SIZE: i32 = 200_000;
+/// 非流式响应的输出约束
+struct OutputConstraints {
+    /// tool_choice 校验
+    tool_choice: ToolChoiceGuard,
+    /// stop_sequences 模拟
+    stop_sequences: StopSequenceMatcher,
+    /// max_tokens 输出预算
+    budget: OutputBudget,
+}
+
+/// 上报正常结束（或因违反 tool_choice 中止）的流式请求用量
+pub(super) fn report_stream_usage(
+    usage: &UsageReporter,
+    (input_tokens, output_tokens): (i32, i32),
+    tool_choice_violation: Option<&str>,
+    stop_reason: &str,
+) {
+    if tool_choice_violation.is_some() {
+        usage.report_failure("tool_choice_violation", input_tokens, output_tokens);
+    } else {
+        usage.report(input_tokens, output_tokens, stop_reason);
+    }
+}
 
 /// 处理非流式请求
+#[allow(clippy::too_many_arguments)]
 async fn handle_non_stream_request(
-    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    provider: Arc<KiroProvider>,
     request_body: &str,
     model: &str,
-    input_tokens: i32,
+    input_tokens: token::InputTokensTask,
+    prompt_cache: PromptCacheUsage,
+    mut constraints: OutputConstraints,
+    mut usage: UsageReporter,
+    cache: Option<CacheHandle>,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
+    let response = match call_upstream(&provider, request_body, false, &mut usage).await {
+        Ok(response) => response,
+        Err(response) => return response,
     };
 
+    // 上下文窗口大小（来自模型注册表）
+    let context_window = registry::registry()
+        .resolve(model)
+        .map(|entry| entry.context_window)
+        .unwrap_or(registry::DEFAULT_CONTEXT_WINDOW);
+
     // 读取响应体
     let body_bytes = match response.bytes().await {
         Ok(bytes) => bytes,
         Err(e) => {
             tracing::error!("读取响应体失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("读取响应失败: {}", e),
-                )),
-            )
-                .into_response();
+            return usage.fail(
+                (
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("读取响应失败: {}", e),
+                    )),
+                )
+                    .into_response(),
+            );
         }
     };
 
@@ -483,30 +644,51 @@ async fn handle_non_stream_request(
                 if let Ok(event) = Event::from_frame(frame) {
                     match event {
                         Event::AssistantResponse(resp) => {
-                            text_content.push_str(&resp.content);
+                            let content = constraints.budget.take(&resp.content);
+                            text_content.push_str(&constraints.stop_sequences.push(content));
+                            // 命中停止序列或达到 max_tokens，丢弃之后的全部输出
+                            if constraints.stop_sequences.matched().is_some()
+                                || constraints.budget.is_exhausted()
+                            {
+                                break;
+                            }
                         }
                         Event::ToolUse(tool_use) => {
+                            match constraints
+                                .tool_choice
+                                .on_tool_use(&tool_use.tool_use_id, &tool_use.name)
+                            {
+                                ToolUseDecision::Accept => {}
+                                ToolUseDecision::Skip => continue,
+                                ToolUseDecision::Reject => break,
+                            }
                             has_tool_use = true;
 
                             // 累积工具的 JSON 输入
                             let buffer = tool_json_buffers
                                 .entry(tool_use.tool_use_id.clone())
-                                .or_insert_with(String::new);
-                            buffer.push_str(&tool_use.input);
+                                .or_default();
+                            buffer.push_str(constraints.budget.take(&tool_use.input));
 
-                            // 如果是完整的工具调用，添加到列表
-                            if tool_use.stop {
+                            // 达到 max_tokens 时丢弃参数中不完整的尾部
+                            let truncated = constraints.budget.is_exhausted();
+                            if truncated && !tool_use.stop {
+                                *buffer = partial_json::repair(buffer);
+                            }
+
+                            // 如果是完整的工具调用（或已被截断），添加到列表
+                            if tool_use.stop || truncated {
                                 let input: serde_json::Value = if buffer.is_empty() {
                                     serde_json::json!({})
                                 } else {
-                                    serde_json::from_str(buffer)
-                                        .unwrap_or_else(|e| {
-                                            tracing::warn!(
-                                                "工具输入 JSON 解析失败: {}, tool_use_id: {}",
-                                                e, tool_use.tool_use_id
-                                            );
-                                            serde_json::json!({})
-                                        })
+                                    serde_json::from_str(buffer).unwrap_or_else(|e| {
+                                        tracing::warn!(
+                                            "工具输入 JSON 解析失败: {}, tool_use_id: {}",
+                                            e,
+                                            tool_use.tool_use_id
+                                        );
+                                        serde_json::json!({})
+                                    })
                                 };
 
                                 tool_uses.push(json!({
@@ -516,14 +698,16 @@ async fn handle_non_stream_request(
                                     "input": input
                                 }));
                             }
+                            if truncated {
+                                break;
+                            }
                         }
                         Event::ContextUsage(context_usage) => {
                             // 从上下文使用百分比计算实际的 input_tokens
-                            // 公式: percentage * 200000 / 100 = percentage * 2000
-                            let actual_input_tokens = (context_usage.context_usage_percentage
-                                * (CONTEXT_WINDOW_SIZE as f64)
-                                / 100.0)
-                                as i32;
+                            // 公式: percentage * context_window / 100
+                            let actual_input_tokens =
+                                (context_usage.context_usage_percentage * (context_window as f64)
+                                    / 100.0) as i32;
                             context_input_tokens = Some(actual_input_tokens);
                             // 上下文使用量达到 100% 时，设置 stop_reason 为 model_context_window_exceeded
                             if context_usage.context_usage_percentage >= 100.0 {
@@ -550,8 +734,15 @@ async fn handle_non_stream_request(
         }
     }
 
+    text_content.push_str(&constraints.stop_sequences.flush());
+
     // 确定 stop_reason
-    if has_tool_use && stop_reason == "end_turn" {
+    let stop_sequence = constraints.stop_sequences.matched();
+    if stop_sequence.is_some() {
+        stop_reason = "stop_sequence".to_string();
+    } else if constraints.budget.is_exhausted() {
+        stop_reason = "max_tokens".to_string();
+    } else if has_tool_use && stop_reason == "end_turn" {
         stop_reason = "tool_use".to_string();
     }
 
@@ -570,42 +761,90 @@ async fn handle_non_stream_request(
     // 估算输出 tokens
     let output_tokens = token::estimate_output_tokens(&content);
 
-    // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
-    let final_input_tokens = context_input_tokens.unwrap_or(input_tokens);
+    // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则等待后台估算值
+    let final_input_tokens = match context_input_tokens {
+        Some(tokens) => tokens,
+        None => input_tokens.wait().await,
+    };
+
+    // 校验 tool_choice（强制调用工具时模型必须调用，none 时不得调用）
+    if let Some(message) = constraints.tool_choice.finish(&stop_reason) {
+        tracing::warn!("{}", message);
+        usage.report_failure("tool_choice_violation", final_input_tokens, output_tokens);
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new("api_error", message)),
+        )
+            .into_response();
+    }
+
+    usage.report(final_input_tokens, output_tokens, &stop_reason);
+    if let Some(cache) = &cache {
+        cache.store(
+            &content,
+            &stop_reason,
+            stop_sequence,
+            final_input_tokens,
+            output_tokens,
+        );
+    }
 
     // 构建 Anthropic 响应
-    let response_body = json!({
-        "id": format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
+    let message_id = format!("msg_{}", Uuid::new_v4().to_string().replace('-', ""));
+    let response_body = build_message_body(
+        &message_id,
+        model,
+        content,
+        &stop_reason,
+        stop_sequence,
+        prompt_cache.usage_json(final_input_tokens, output_tokens),
+    );
+
+    (StatusCode::OK, Json(response_body)).into_response()
+}
+
+/// 构建 Anthropic 非流式响应体
+pub(super) fn build_message_body(
+    message_id: &str,
+    model: &str,
+    content: Vec<serde_json::Value>,
+    stop_reason: &str,
+    stop_sequence: Option<&str>,
+    usage: serde_json::Value,
+) -> serde_json::Value {
+    json!({
+        "id": message_id,
         "type": "message",
         "role": "assistant",
         "content": content,
         "model": model,
         "stop_reason": stop_reason,
-        "stop_sequence": null,
-        "usage": {
-            "input_tokens": final_input_tokens,
-            "output_tokens": output_tokens
-        }
-    });
-
-    (StatusCode::OK, Json(response_body)).into_response()
+        "stop_sequence": stop_sequence,
+        "usage": usage
+    })
 }
 
 /// 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
 ///
-/// - Opus 4.6：覆写为 adaptive 类型
+/// - 注册表中声明 `adaptiveThinking` 的模型（默认为 Opus 4.6）：覆写为 adaptive 类型
 /// - 其他模型：覆写为 enabled 类型
+/// - 模型未声明 thinking 变体时不覆写
 /// - budget_tokens 固定为 20000
-fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
+pub(super) fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
     let model_lower = payload.model.to_lowercase();
     if !model_lower.contains("thinking") {
         return;
     }
 
-    let is_opus_4_6 =
-        model_lower.contains("opus") && (model_lower.contains("4-6") || model_lower.contains("4.6"));
+    let registry = registry::registry();
+    let Some(entry) = registry.resolve(&payload.model) else {
+        return;
+    };
+    if !entry.thinking {
+        return;
+    }
 
-    let thinking_type = if is_opus_4_6 {
+    let thinking_type = if entry.adaptive_thinking {
         "adaptive"
     } else {
         "enabled"
@@ -621,8 +860,8 @@ fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
         thinking_type: thinking_type.to_string(),
         budget_tokens: 20000,
     });
-    
-    if is_opus_4_6 {
+
+    if entry.adaptive_thinking {
         payload.output_config = Some(OutputConfig {
             effort: "high".to_string(),
         });
@@ -646,7 +885,8 @@ pub async fn count_tokens(
         payload.system,
         payload.messages,
         payload.tools,
-    ) as i32;
+    )
+    .await as i32;
 
     Json(CountTokensResponse {
         input_tokens: total_tokens.max(1) as i32,
@@ -660,6 +900,8 @@ pub async fn count_tokens(
 /// - message_start 中的 input_tokens 是从 contextUsageEvent 计算的准确值
 pub async fn post_messages_cc(
     State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    headers: HeaderMap,
     JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
     tracing::info!(
@@ -670,91 +912,71 @@ pub async fn post_messages_cc(
         "Received POST /cc/v1/messages request"
     );
 
-    // 检查 KiroProvider 是否可用
-    let provider = match &state.kiro_provider {
-        Some(p) => p.clone(),
-        None => {
-            tracing::error!("KiroProvider 未配置");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(ErrorResponse::new(
-                    "service_unavailable",
-                    "Kiro API provider not configured",
-                )),
-            )
-                .into_response();
-        }
+    // 检查 KiroProvider、按模型名覆写 thinking 配置并做客户端 API Key 准入检查
+    let (provider, usage) = match admit_request(&state, client.as_deref(), &mut payload) {
+        Ok(admitted) => admitted,
+        Err(response) => return response,
     };
 
-    // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
-    override_thinking_from_model_name(&mut payload);
+    // 请求来源（用于匹配提示词改写规则）
+    let origin = RequestOrigin::new(Route::Cc, client.as_deref());
 
-    // 检查是否为 WebSearch 请求
-    if websearch::has_web_search_tool(&payload) {
-        tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
+    // Kiro 不支持的采样参数（在成功的响应上通过响应头告知客户端）
+    let ignored_params = check_sampling_params(&payload);
 
-        // 估算输入 tokens
-        let input_tokens = token::count_all_tokens(
+    // 检查是否为服务端工具（web_search / web_fetch）请求
+    if websearch::is_server_tool_request(&payload) {
+        tracing::info!("检测到服务端工具，路由到服务端工具循环处理");
+
+        // 在后台估算输入 tokens，与上游调用并发执行
+        let input_tokens = token::InputTokensTask::spawn(
             payload.model.clone(),
             payload.system.clone(),
             payload.messages.clone(),
             payload.tools.clone(),
-        ) as i32;
+        );
 
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+        let mut response = websearch::handle_websearch_request(
+            provider,
+            state.profile_arn.clone(),
+            &payload,
+            origin,
+            input_tokens,
+            usage,
+        )
+        .await;
+        apply_ignored_params(&ignored_params, &mut response);
+        return response;
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
+    // 下载 url 来源的图片和文档并转换请求
+    let conversion_result = match convert_payload(&mut payload, &origin, &usage).await {
         Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
+        Err(response) => return response,
     };
 
-    // 构建 Kiro 请求
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
-    };
-
-    let request_body = match serde_json::to_string(&kiro_request) {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
-    };
+    // 响应缓存（命中时直接回放，不调用上游）
+    let cache = CacheHandle::resolve(
+        &state,
+        &headers,
+        &payload,
+        &conversion_result.conversation_state,
+    );
+    if let Some(cached) = cache.as_ref().and_then(CacheHandle::lookup) {
+        let mut response = cache::replay(cached, &payload.model, payload.stream, usage);
+        apply_ignored_params(&ignored_params, &mut response);
+        return response;
+    }
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    // 估算 prompt caching 的缓存读取/写入 token
+    let prompt_cache = prompt_cache::evaluate(&state.prompt_cache, client.as_deref(), &payload);
 
-    // 估算输入 tokens
-    let input_tokens = token::count_all_tokens(
-        payload.model.clone(),
-        payload.system,
-        payload.messages,
-        payload.tools,
-    ) as i32;
+    // 上下文窗口预检、构建 Kiro 请求体，并在后台估算输入 tokens
+    let upstream =
+        match build_upstream(&state, &provider, conversion_result, &mut payload, &usage).await {
+            Ok(upstream) => upstream,
+            Err(response) => return response,
+        };
 
     // 检查是否启用了thinking
     let thinking_enabled = payload
@@ -763,20 +985,38 @@ pub async fn post_messages_cc(
         .map(|t| t.is_enabled())
         .unwrap_or(false);
 
-    if payload.stream {
-        // 流式响应（缓冲模式）
-        handle_stream_request_buffered(
+    let mut response = if payload.stream {
+        // 流式响应（缓冲模式，input_tokens 在上游响应后填入）
+        let ctx = BufferedStreamContext::new(&payload.model, 0, thinking_enabled)
+            .with_prompt_cache(prompt_cache)
+            .with_tool_choice(payload.tool_choice)
+            .with_stop_sequences(payload.stop_sequences)
+            .with_max_tokens(payload.max_tokens);
+        handle_stream_request_buffered(provider, &upstream.body, ctx, upstream.input_tokens, usage)
+            .await
+    } else {
+        // 非流式响应（复用现有逻辑，已经使用正确的 input_tokens）
+        handle_non_stream_request(
             provider,
-            &request_body,
+            &upstream.body,
             &payload.model,
-            input_tokens,
-            thinking_enabled,
+            upstream.input_tokens,
+            prompt_cache,
+            OutputConstraints {
+                tool_choice: ToolChoiceGuard::new(payload.tool_choice),
+                stop_sequences: StopSequenceMatcher::new(payload.stop_sequences),
+                budget: OutputBudget::new(Some(payload.max_tokens)),
+            },
+            usage,
+            cache,
         )
         .await
-    } else {
-        // 非流式响应（复用现有逻辑，已经使用正确的 input_tokens）
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
+    };
+    if let Some(report) = upstream.context_report {
+        report.apply(&mut response);
     }
+    apply_ignored_params(&ignored_params, &mut response);
+    response
 }
 
 /// 处理流式请求（缓冲版本）
@@ -784,23 +1024,20 @@ pub async fn post_messages_cc(
 /// 与 `handle_stream_request` 不同，此函数会缓冲所有事件直到流结束，
 /// 然后用从 contextUsageEvent 计算的正确 input_tokens 生成 message_start 事件。
 async fn handle_stream_request_buffered(
-    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    provider: Arc<KiroProvider>,
     request_body: &str,
-    model: &str,
-    estimated_input_tokens: i32,
-    thinking_enabled: bool,
+    mut ctx: BufferedStreamContext,
+    input_tokens: token::InputTokensTask,
+    mut usage: UsageReporter,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
+    let response = match call_upstream(&provider, request_body, true, &mut usage).await {
+        Ok(response) => response,
+        Err(response) => return response,
     };
-
-    // 创建缓冲流处理上下文
-    let ctx = BufferedStreamContext::new(model, estimated_input_tokens, thinking_enabled);
+    ctx.set_input_tokens(input_tokens.wait().await);
 
     // 创建缓冲 SSE 流
-    let stream = create_buffered_sse_stream(response, ctx);
+    let stream = create_buffered_sse_stream(response, ctx, usage);
 
     // 返回 SSE 响应
     Response::builder()
@@ -822,6 +1059,7 @@ async fn handle_stream_request_buffered(
 fn create_buffered_sse_stream(
     response: reqwest::Response,
     ctx: BufferedStreamContext,
+    usage: UsageReporter,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     let body_stream = response.bytes_stream();
 
@@ -832,8 +1070,9 @@ fn create_buffered_sse_stream(
             EventStreamDecoder::new(),
             false,
             interval(Duration::from_secs(PING_INTERVAL_SECS)),
+            usage,
         ),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, usage)| async move {
             if finished {
                 return None;
             }
@@ -848,7 +1087,7 @@ fn create_buffered_sse_stream(
                     _ = ping_interval.tick() => {
                         tracing::trace!("发送 ping 保活事件（缓冲模式）");
                         let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)));
+                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, usage)));
                     }
 
                     // 然后处理数据流
@@ -873,26 +1112,40 @@ fn create_buffered_sse_stream(
                                         }
                                     }
                                 }
+
+                                // 命中停止序列等停止条件时不再读取上游，直接返回所有事件
+                                if ctx.should_stop() {
+                                    let all_events = ctx.finish_and_get_all_events();
+                                    report_stream_usage(&usage, ctx.final_usage(), ctx.tool_choice_violation(), &ctx.stop_reason());
+                                    let bytes: Vec<Result<Bytes, Infallible>> = all_events
+                                        .into_iter()
+                                        .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                                        .collect();
+                                    return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage)));
+                                }
                                 // 继续读取下一个 chunk，不发送任何数据
                             }
                             Some(Err(e)) => {
                                 tracing::error!("读取响应流失败: {}", e);
                                 // 发生错误，完成处理并返回所有事件
                                 let all_events = ctx.finish_and_get_all_events();
+                                let (input_tokens, output_tokens) = ctx.final_usage();
+                                usage.report_failure("stream_error", input_tokens, output_tokens);
                                 let bytes: Vec<Result<Bytes, Infallible>> = all_events
                                     .into_iter()
                                     .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                     .collect();
-                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)));
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage)));
                             }
                             None => {
                                 // 流结束，完成处理并返回所有事件（已更正 input_tokens）
                                 let all_events = ctx.finish_and_get_all_events();
+                                report_stream_usage(&usage, ctx.final_usage(), ctx.tool_choice_violation(), &ctx.stop_reason());
                                 let bytes: Vec<Result<Bytes, Infallible>> = all_events
                                     .into_iter()
                                     .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                     .collect();
-                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)));
+                                return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage)));
                             }
                         }
                     }
@@ -10,8 +10,12 @@ use axum::{
     response::{IntoResponse, Json, Response},
 };
 
+use crate::apikey::{ApiKeyRejection, ApiKeyStore};
+use crate::batch::BatchStore;
+use crate::cache::{PromptCache, ResponseCache};
 use crate::common::auth;
 use crate::kiro::provider::KiroProvider;
+use crate::ledger::UsageLedger;
 
 use super::types::ErrorResponse;
 
@@ -25,6 +29,16 @@ pub struct AppState {
     pub kiro_provider: Option<Arc<KiroProvider>>,
     /// Profile ARN（可选，用于请求）
     pub profile_arn: Option<String>,
+    /// 客户端 API Key 存储（可选，用于多 Key 认证与配额）
+    pub api_keys: Option<Arc<ApiKeyStore>>,
+    /// 用量账本（可选，用于记录每个请求）
+    pub usage_ledger: Option<Arc<UsageLedger>>,
+    /// 非流式响应缓存（可选）
+    pub response_cache: Option<Arc<ResponseCache>>,
+    /// prompt caching 前缀哈希表（用于估算缓存 token）
+    pub prompt_cache: Arc<PromptCache>,
+    /// Message Batches 存储（可选）
+    pub batch_store: Option<Arc<BatchStore>>,
 }
 
 impl AppState {
@@ -34,6 +48,11 @@ impl AppState {
             api_key: api_key.into(),
             kiro_provider: None,
             profile_arn: None,
+            api_keys: None,
+            usage_ledger: None,
+            response_cache: None,
+            prompt_cache: Arc::new(PromptCache::new()),
+            batch_store: None,
         }
     }
 
@@ -48,21 +67,68 @@ impl AppState {
         self.profile_arn = Some(arn.into());
         self
     }
+
+    /// 设置客户端 API Key 存储
+    pub fn with_api_key_store(mut self, store: Arc<ApiKeyStore>) -> Self {
+        self.api_keys = Some(store);
+        self
+    }
+
+    /// 设置用量账本
+    pub fn with_usage_ledger(mut self, ledger: Arc<UsageLedger>) -> Self {
+        self.usage_ledger = Some(ledger);
+        self
+    }
+
+    /// 设置响应缓存
+    pub fn with_response_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
+    /// 设置 Message Batches 存储
+    pub fn with_batch_store(mut self, store: Arc<BatchStore>) -> Self {
+        self.batch_store = Some(store);
+        self
+    }
 }
 
 /// API Key 认证中间件
+///
+/// 主 API Key 直接放行；其他 Key 在客户端 Key 存储中查找，
+/// 认证通过后将 [`ClientKey`](crate::apikey::ClientKey) 写入请求扩展，供 handler 做配额检查与用量归属
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    match auth::extract_api_key(&request) {
-        Some(key) if auth::constant_time_eq(&key, &state.api_key) => next.run(request).await,
-        _ => {
-            let error = ErrorResponse::authentication_error();
-            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+    let Some(key) = auth::extract_api_key(&request) else {
+        return unauthorized(ErrorResponse::authentication_error());
+    };
+
+    if auth::constant_time_eq(&key, &state.api_key) {
+        return next.run(request).await;
+    }
+
+    if let Some(store) = &state.api_keys {
+        match store.authenticate(&key) {
+            Ok(client) => {
+                tracing::debug!(api_key_id = client.id, api_key_name = %client.name, "客户端 API Key 认证通过");
+                request.extensions_mut().insert(client);
+                return next.run(request).await;
+            }
+            Err(ApiKeyRejection::Invalid) => {}
+            Err(e) => {
+                return unauthorized(ErrorResponse::new("authentication_error", e.to_string()));
+            }
         }
     }
+
+    unauthorized(ErrorResponse::authentication_error())
+}
+
+fn unauthorized(error: ErrorResponse) -> Response {
+    (StatusCode::UNAUTHORIZED, Json(error)).into_response()
 }
 
 /// CORS 中间件层
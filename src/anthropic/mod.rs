@@ -8,6 +8,8 @@
 //! - `GET /v1/models` - 获取可用模型列表
 //! - `POST /v1/messages` - 创建消息（对话）
 //! - `POST /v1/messages/count_tokens` - 计算 token 数量
+//! - `POST /v1/chat/completions` - OpenAI Chat Completions 兼容端点（支持流式与 tool_calls）
+//! - `POST /v1/messages/batches` - 创建批处理（`GET` 列表、`/{id}` 查询与删除、`/{id}/cancel` 取消、`/{id}/results` 获取结果）
 //!
 //! ## Claude Code 兼容端点 (/cc/v1)
 //! - `POST /cc/v1/messages` - 创建消息（流式响应会等待 contextUsageEvent 后再发送 message_start，确保 input_tokens 准确）
@@ -22,12 +24,29 @@
 //! axum::serve(listener, app).await?;
 //! ```
 
+mod batches;
+mod cache;
+mod context_window;
 mod converter;
+mod document;
 mod handlers;
 mod middleware;
+mod openai;
+mod output_budget;
+mod partial_json;
+mod prompt_cache;
 mod router;
+mod stop_sequence;
 mod stream;
+mod tool_choice;
 pub mod types;
+mod usage;
+mod webfetch;
 mod websearch;
 
+pub use context_window::init_config as init_context_config;
+pub use converter::{RequestOrigin, preview_request};
+pub use document::{DocumentOptions, init_config as init_document_config};
+pub(crate) use document::render_document;
 pub use router::create_router_with_provider;
+pub use webfetch::{WebFetchOptions, init_config as init_web_fetch_config};
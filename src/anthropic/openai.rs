@@ -0,0 +1,1395 @@
+//! OpenAI Chat Completions 兼容层
+//!
+//! 将 `/v1/chat/completions` 请求翻译为 Anthropic `MessagesRequest`，
+//! 复用 `convert_request` → `KiroProvider` → `StreamContext` 的完整管线，
+//! 再把 `StreamContext` 产生的 Anthropic SSE 事件改写为 OpenAI `chat.completion.chunk`。
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    Extension, Json as JsonExtractor,
+    body::Body,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::time::interval;
+use uuid::Uuid;
+
+use crate::apikey::ClientKey;
+use crate::kiro::model::events::Event;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::KiroProvider;
+use crate::model::prompt_rules::Route;
+use crate::token;
+
+use super::converter::RequestOrigin;
+use super::handlers::{
+    admit_request, apply_ignored_params, build_upstream, call_upstream, check_sampling_params,
+    convert_payload, report_stream_usage,
+};
+use super::middleware::AppState;
+use super::stream::{SseEvent, StreamContext};
+use super::types::{ErrorResponse, Message, MessagesRequest, SystemMessage, Tool, ToolChoice};
+use super::usage::UsageReporter;
+
+/// 未指定 max_tokens 时的默认值
+const DEFAULT_MAX_TOKENS: i32 = 32000;
+
+/// Ping 注释间隔（25秒）
+const PING_INTERVAL_SECS: u64 = 25;
+
+// === 请求类型 ===
+
+/// Chat Completions 请求体
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    pub stream_options: Option<StreamOptions>,
+    pub max_tokens: Option<i32>,
+    /// 新版 OpenAI SDK 使用该字段替代 max_tokens
+    pub max_completion_tokens: Option<i32>,
+    pub tools: Option<Vec<ChatTool>>,
+    pub tool_choice: Option<Value>,
+    /// 停止序列，字符串或字符串数组
+    pub stop: Option<Value>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    /// OpenAI 的 user 字段，作为会话标识透传
+    pub user: Option<String>,
+}
+
+/// 流式选项
+#[derive(Debug, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// Chat 消息
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    /// 可以是 string、内容分片数组或 null（仅含 tool_calls 的 assistant 消息）
+    #[serde(default)]
+    pub content: Option<Value>,
+    pub tool_calls: Option<Vec<ChatToolCall>>,
+    pub tool_call_id: Option<String>,
+}
+
+/// assistant 消息中的工具调用
+#[derive(Debug, Deserialize)]
+pub struct ChatToolCall {
+    pub id: String,
+    pub function: ChatFunctionCall,
+}
+
+/// 工具调用的函数名与参数（参数为 JSON 字符串）
+#[derive(Debug, Deserialize)]
+pub struct ChatFunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+/// 工具定义（仅支持 function 类型）
+#[derive(Debug, Deserialize)]
+pub struct ChatTool {
+    #[serde(rename = "type", default)]
+    pub tool_type: String,
+    pub function: ChatFunction,
+}
+
+/// 函数定义
+#[derive(Debug, Deserialize)]
+pub struct ChatFunction {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub parameters: HashMap<String, Value>,
+}
+
+// === 请求转换 ===
+
+/// Chat Completions 请求无法转换（400，`param` 指向出错的字段）
+#[derive(Debug)]
+pub(crate) struct ChatRequestError {
+    param: String,
+    message: String,
+}
+
+impl IntoResponse for ChatRequestError {
+    fn into_response(self) -> Response {
+        error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            &self.message,
+            Some(&self.param),
+            None,
+        )
+    }
+}
+
+/// 将 Chat Completions 请求转换为 Anthropic MessagesRequest
+///
+/// - `system` / `developer` 消息提取为 system
+/// - assistant 的 `tool_calls` 转为 `tool_use` 块
+/// - 连续的 `tool` 消息合并为一条包含多个 `tool_result` 的 user 消息
+/// - 其他角色（如已废弃的 `function`）返回错误
+pub(crate) fn to_messages_request(
+    req: ChatCompletionRequest,
+) -> Result<MessagesRequest, ChatRequestError> {
+    let mut system = Vec::new();
+    let mut messages: Vec<Message> = Vec::new();
+
+    for (index, msg) in req.messages.into_iter().enumerate() {
+        match msg.role.as_str() {
+            "system" | "developer" => {
+                let text = content_to_text(msg.content.as_ref());
+                if !text.is_empty() {
+                    system.push(SystemMessage {
+                        text,
+                        cache_control: None,
+                    });
+                }
+            }
+            "assistant" => {
+                let mut blocks = Vec::new();
+                let text = content_to_text(msg.content.as_ref());
+                if !text.is_empty() {
+                    blocks.push(json!({"type": "text", "text": text}));
+                }
+                for call in msg.tool_calls.unwrap_or_default() {
+                    let input = if call.function.arguments.trim().is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(&call.function.arguments).unwrap_or_else(|e| {
+                            tracing::warn!("tool_calls 参数 JSON 解析失败: {}, id: {}", e, call.id);
+                            json!({})
+                        })
+                    };
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": call.id,
+                        "name": call.function.name,
+                        "input": input
+                    }));
+                }
+                messages.push(Message {
+                    role: "assistant".to_string(),
+                    content: Value::Array(blocks),
+                });
+            }
+            "tool" => {
+                let block = json!({
+                    "type": "tool_result",
+                    "tool_use_id": msg.tool_call_id.unwrap_or_default(),
+                    "content": content_to_text(msg.content.as_ref())
+                });
+                // 合并到紧邻的 tool_result 消息中
+                if let Some(last) = messages.last_mut()
+                    && last.role == "user"
+                    && is_tool_result_message(&last.content)
+                    && let Value::Array(arr) = &mut last.content
+                {
+                    arr.push(block);
+                    continue;
+                }
+                messages.push(Message {
+                    role: "user".to_string(),
+                    content: Value::Array(vec![block]),
+                });
+            }
+            "user" => {
+                messages.push(Message {
+                    role: "user".to_string(),
+                    content: convert_user_content(msg.content),
+                });
+            }
+            role => {
+                return Err(ChatRequestError {
+                    param: format!("messages.[{}].role", index),
+                    message: format!(
+                        "不支持的消息角色: {}（支持 system、developer、user、assistant、tool）",
+                        role
+                    ),
+                });
+            }
+        }
+    }
+
+    let tools = req.tools.map(|tools| {
+        tools
+            .into_iter()
+            .filter(|t| t.tool_type.is_empty() || t.tool_type == "function")
+            .map(|t| Tool {
+                tool_type: None,
+                name: t.function.name,
+                description: t.function.description,
+                input_schema: t.function.parameters,
+                max_uses: None,
+                allowed_domains: None,
+                blocked_domains: None,
+                user_location: None,
+                max_content_tokens: None,
+                citations: None,
+                cache_control: None,
+            })
+            .collect()
+    });
+
+    Ok(MessagesRequest {
+        model: req.model,
+        max_tokens: req
+            .max_completion_tokens
+            .or(req.max_tokens)
+            .unwrap_or(DEFAULT_MAX_TOKENS),
+        messages,
+        stream: req.stream,
+        system: if system.is_empty() {
+            None
+        } else {
+            Some(system)
+        },
+        tools,
+        tool_choice: req.tool_choice.map(convert_tool_choice),
+        stop_sequences: req.stop.and_then(convert_stop),
+        temperature: req.temperature,
+        top_p: req.top_p,
+        top_k: None,
+        thinking: None,
+        output_config: None,
+        metadata: req.user.map(|user_id| super::types::Metadata {
+            user_id: Some(user_id),
+        }),
+    })
+}
+
+/// 判断消息内容是否全部为 tool_result 块
+fn is_tool_result_message(content: &Value) -> bool {
+    content.as_array().is_some_and(|arr| {
+        !arr.is_empty()
+            && arr
+                .iter()
+                .all(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+    })
+}
+
+/// 提取消息中的纯文本（string 或 text 分片数组）
+fn content_to_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 转换 user 消息内容
+///
+/// `image_url` 分片支持 `data:<media_type>;base64,<data>` 与 http(s) URL（转为 url 来源，转换前下载）
+fn convert_user_content(content: Option<Value>) -> Value {
+    let parts = match content {
+        Some(Value::Array(parts)) => parts,
+        Some(Value::String(s)) => return Value::String(s),
+        _ => return Value::String(String::new()),
+    };
+
+    let mut blocks = Vec::new();
+    for part in parts {
+        match part.get("type").and_then(|t| t.as_str()) {
+            Some("text") => {
+                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
+                    blocks.push(json!({"type": "text", "text": text}));
+                }
+            }
+            Some("image_url") => {
+                let url = part
+                    .get("image_url")
+                    .and_then(|i| i.get("url").or(Some(i)))
+                    .and_then(|u| u.as_str())
+                    .unwrap_or_default();
+                match parse_data_url(url) {
+                    Some((media_type, data)) => blocks.push(json!({
+                        "type": "image",
+                        "source": {
+                            "type": "base64",
+                            "media_type": media_type,
+                            "data": data
+                        }
+                    })),
+                    None if url.starts_with("http://") || url.starts_with("https://") => blocks
+                        .push(json!({
+                            "type": "image",
+                            "source": {"type": "url", "url": url}
+                        })),
+                    None => tracing::warn!("不支持的 image_url 形式，已忽略"),
+                }
+            }
+            _ => {}
+        }
+    }
+    Value::Array(blocks)
+}
+
+/// 解析 `data:image/png;base64,xxx` 形式的 data URL
+fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("data:")?;
+    let (meta, data) = rest.split_once(',')?;
+    let media_type = meta.strip_suffix(";base64")?;
+    Some((media_type, data))
+}
+
+/// 转换 tool_choice
+///
+/// - `"auto"` → `{"type": "auto"}`
+/// - `"required"` → `{"type": "any"}`
+/// - `"none"` → `{"type": "none"}`
+/// - `{"type": "function", "function": {"name": ...}}` → `{"type": "tool", "name": ...}`
+fn convert_tool_choice(choice: Value) -> ToolChoice {
+    let auto = ToolChoice::Auto {
+        disable_parallel_tool_use: false,
+    };
+    match &choice {
+        Value::String(s) => match s.as_str() {
+            "required" => ToolChoice::Any {
+                disable_parallel_tool_use: false,
+            },
+            "none" => ToolChoice::None,
+            _ => auto,
+        },
+        Value::Object(_) => match choice.pointer("/function/name").and_then(|n| n.as_str()) {
+            Some(name) => ToolChoice::Tool {
+                name: name.to_string(),
+                disable_parallel_tool_use: false,
+            },
+            None => auto,
+        },
+        _ => auto,
+    }
+}
+
+/// 转换 stop（字符串或字符串数组）为 stop_sequences
+fn convert_stop(stop: Value) -> Option<Vec<String>> {
+    let sequences: Vec<String> = match stop {
+        Value::String(s) => vec![s],
+        Value::Array(items) => items
+            .into_iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if sequences.is_empty() {
+        None
+    } else {
+        Some(sequences)
+    }
+}
+
+/// 将 Anthropic stop_reason 映射为 OpenAI finish_reason
+fn map_finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "tool_use" => "tool_calls",
+        "max_tokens" | "model_context_window_exceeded" => "length",
+        _ => "stop",
+    }
+}
+
+// === 响应转换 ===
+
+/// Chat Completions 流处理上下文
+///
+/// 包装 `StreamContext`，把它输出的 Anthropic SSE 事件逐个改写为 OpenAI chunk。
+/// 非流式请求同样使用该上下文，最后通过 `aggregate_chunks` 合并为完整响应。
+pub struct ChatCompletionContext {
+    /// 内部流处理上下文（复用 thinking 解析与工具调用状态机）
+    inner: StreamContext,
+    /// chat completion ID
+    id: String,
+    /// 创建时间（Unix 秒）
+    created: i64,
+    /// 请求的模型名称
+    model: String,
+    /// 工具块索引映射 (content block index -> tool_calls index)
+    tool_call_indices: HashMap<i32, usize>,
+    /// 是否在末尾附加 usage chunk
+    include_usage: bool,
+}
+
+impl ChatCompletionContext {
+    /// 创建上下文
+    pub fn new(
+        model: impl Into<String>,
+        input_tokens: i32,
+        thinking_enabled: bool,
+        include_usage: bool,
+    ) -> Self {
+        let model = model.into();
+        Self {
+            inner: StreamContext::new_with_thinking(model.clone(), input_tokens, thinking_enabled),
+            id: format!("chatcmpl-{}", Uuid::new_v4().to_string().replace('-', "")),
+            created: chrono::Utc::now().timestamp(),
+            model,
+            tool_call_indices: HashMap::new(),
+            include_usage,
+        }
+    }
+
+    /// 设置请求的 tool_choice，用于校验上游返回的工具调用
+    pub fn with_tool_choice(mut self, tool_choice: Option<ToolChoice>) -> Self {
+        self.inner = self.inner.with_tool_choice(tool_choice);
+        self
+    }
+
+    /// 设置请求的 stop_sequences
+    pub fn with_stop_sequences(mut self, stop_sequences: Option<Vec<String>>) -> Self {
+        self.inner = self.inner.with_stop_sequences(stop_sequences);
+        self
+    }
+
+    /// 设置估算的 input_tokens（后台计数完成后填入）
+    pub fn set_input_tokens(&mut self, input_tokens: i32) {
+        self.inner.input_tokens = input_tokens;
+    }
+
+    /// 设置请求的 max_tokens
+    pub fn with_max_tokens(mut self, max_tokens: i32) -> Self {
+        self.inner = self.inner.with_max_tokens(max_tokens);
+        self
+    }
+
+    /// 是否应停止读取上游（已命中停止序列、达到 max_tokens 或违反 tool_choice）
+    pub fn should_stop(&self) -> bool {
+        self.inner.should_stop()
+    }
+
+    /// 违反 tool_choice 时的错误信息（流式请求已通过 error chunk 发送给客户端）
+    pub fn tool_choice_violation(&self) -> Option<&str> {
+        self.inner.tool_choice_violation()
+    }
+
+    /// 生成初始 chunk（role: assistant）
+    pub fn generate_initial_chunks(&mut self) -> Vec<Value> {
+        let events = self.inner.generate_initial_events();
+        self.convert_events(events)
+    }
+
+    /// 处理 Kiro 事件并转换为 chunk
+    pub fn process_kiro_event(&mut self, event: &Event) -> Vec<Value> {
+        let events = self.inner.process_kiro_event(event);
+        self.convert_events(events)
+    }
+
+    /// 生成最终 chunk（finish_reason 与可选的 usage）
+    pub fn generate_final_chunks(&mut self) -> Vec<Value> {
+        let events = self.inner.generate_final_events();
+        self.convert_events(events)
+    }
+
+    /// 最终的 (input_tokens, output_tokens)
+    pub fn final_usage(&self) -> (i32, i32) {
+        self.inner.final_usage()
+    }
+
+    /// 最终的 stop_reason
+    pub fn stop_reason(&self) -> String {
+        self.inner.stop_reason()
+    }
+
+    /// 构建一个 chunk
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason
+            }]
+        })
+    }
+
+    /// 将 Anthropic SSE 事件转换为 OpenAI chunk
+    fn convert_events(&mut self, events: Vec<SseEvent>) -> Vec<Value> {
+        let mut chunks = Vec::new();
+
+        for event in events {
+            let data = &event.data;
+            match event.event.as_str() {
+                "message_start" => {
+                    chunks.push(self.chunk(json!({"role": "assistant", "content": ""}), None));
+                }
+                "content_block_start" => {
+                    let block = &data["content_block"];
+                    if block["type"] != "tool_use" {
+                        continue;
+                    }
+                    let block_index = data["index"].as_i64().unwrap_or_default() as i32;
+                    let call_index = self.tool_call_indices.len();
+                    self.tool_call_indices.insert(block_index, call_index);
+                    chunks.push(self.chunk(
+                        json!({
+                            "tool_calls": [{
+                                "index": call_index,
+                                "id": block["id"],
+                                "type": "function",
+                                "function": {"name": block["name"], "arguments": ""}
+                            }]
+                        }),
+                        None,
+                    ));
+                }
+                "content_block_delta" => {
+                    let delta = &data["delta"];
+                    match delta["type"].as_str() {
+                        Some("text_delta") => {
+                            chunks.push(self.chunk(json!({"content": delta["text"]}), None));
+                        }
+                        Some("thinking_delta")
+                            if delta["thinking"].as_str().is_some_and(|t| !t.is_empty()) =>
+                        {
+                            chunks.push(
+                                self.chunk(json!({"reasoning_content": delta["thinking"]}), None),
+                            );
+                        }
+                        Some("input_json_delta") => {
+                            let block_index = data["index"].as_i64().unwrap_or_default() as i32;
+                            if let Some(&call_index) = self.tool_call_indices.get(&block_index) {
+                                chunks.push(self.chunk(
+                                    json!({
+                                        "tool_calls": [{
+                                            "index": call_index,
+                                            "function": {"arguments": delta["partial_json"]}
+                                        }]
+                                    }),
+                                    None,
+                                ));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                "message_delta" => {
+                    let stop_reason = data["delta"]["stop_reason"].as_str().unwrap_or("end_turn");
+                    chunks.push(self.chunk(json!({}), Some(map_finish_reason(stop_reason))));
+
+                    if self.include_usage {
+                        let prompt_tokens = data["usage"]["input_tokens"].as_i64().unwrap_or(0);
+                        let completion_tokens =
+                            data["usage"]["output_tokens"].as_i64().unwrap_or(0);
+                        chunks.push(json!({
+                            "id": self.id,
+                            "object": "chat.completion.chunk",
+                            "created": self.created,
+                            "model": self.model,
+                            "choices": [],
+                            "usage": {
+                                "prompt_tokens": prompt_tokens,
+                                "completion_tokens": completion_tokens,
+                                "total_tokens": prompt_tokens + completion_tokens
+                            }
+                        }));
+                    }
+                }
+                // 违反 tool_choice 时 StreamContext 以 error 事件中止输出
+                "error" => {
+                    let message = data["error"]["message"].as_str().unwrap_or_default();
+                    chunks.push(error_body("server_error", message, None, None));
+                }
+                // content_block_stop / message_stop 在 OpenAI 协议中没有对应的 chunk
+                _ => {}
+            }
+        }
+
+        chunks
+    }
+}
+
+/// 将 chunk 序列合并为非流式 `chat.completion` 响应
+///
+/// 要求 chunk 序列由启用 `include_usage` 的 `ChatCompletionContext` 产生
+pub(crate) fn aggregate_chunks(chunks: &[Value]) -> Value {
+    let mut id = Value::Null;
+    let mut created = Value::Null;
+    let mut model = Value::Null;
+    let mut content = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls: Vec<Value> = Vec::new();
+    let mut finish_reason = Value::Null;
+    let mut usage = Value::Null;
+
+    for chunk in chunks {
+        id = chunk["id"].clone();
+        created = chunk["created"].clone();
+        model = chunk["model"].clone();
+
+        if !chunk["usage"].is_null() {
+            usage = chunk["usage"].clone();
+        }
+
+        let Some(choice) = chunk["choices"].get(0) else {
+            continue;
+        };
+        if !choice["finish_reason"].is_null() {
+            finish_reason = choice["finish_reason"].clone();
+        }
+
+        let delta = &choice["delta"];
+        if let Some(text) = delta["content"].as_str() {
+            content.push_str(text);
+        }
+        if let Some(text) = delta["reasoning_content"].as_str() {
+            reasoning.push_str(text);
+        }
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index = call["index"].as_u64().unwrap_or_default() as usize;
+            // 索引可能乱序到达，先补齐占位再写入
+            if index >= tool_calls.len() {
+                tool_calls.resize(index + 1, Value::Null);
+            }
+            if tool_calls[index].is_null() {
+                tool_calls[index] = json!({
+                    "id": call["id"],
+                    "type": "function",
+                    "function": {"name": call["function"]["name"], "arguments": ""}
+                });
+            }
+            if let (Some(existing), Some(args)) = (
+                tool_calls[index]["function"]["arguments"].as_str(),
+                call["function"]["arguments"].as_str(),
+            ) {
+                let merged = format!("{}{}", existing, args);
+                tool_calls[index]["function"]["arguments"] = Value::String(merged);
+            }
+        }
+    }
+
+    tool_calls.retain(|call| !call.is_null());
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": if content.is_empty() && !tool_calls.is_empty() {
+            Value::Null
+        } else {
+            Value::String(content)
+        }
+    });
+    if !reasoning.is_empty() {
+        message["reasoning_content"] = Value::String(reasoning);
+    }
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason
+        }],
+        "usage": usage
+    })
+}
+
+/// 格式化为 OpenAI SSE 数据行
+fn to_sse_data(chunk: &Value) -> Bytes {
+    Bytes::from(format!(
+        "data: {}\n\n",
+        serde_json::to_string(chunk).unwrap_or_default()
+    ))
+}
+
+// === 错误响应 ===
+
+/// OpenAI 格式的错误体：`{"error": {"message", "type", "param", "code"}}`
+fn error_body(error_type: &str, message: &str, param: Option<&str>, code: Option<&str>) -> Value {
+    json!({
+        "error": {
+            "message": message,
+            "type": error_type,
+            "param": param,
+            "code": code
+        }
+    })
+}
+
+fn error_response(
+    status: StatusCode,
+    error_type: &str,
+    message: &str,
+    param: Option<&str>,
+    code: Option<&str>,
+) -> Response {
+    (status, Json(error_body(error_type, message, param, code))).into_response()
+}
+
+/// Anthropic 错误类型对应的 OpenAI (type, code)
+fn map_error_type(
+    status: StatusCode,
+    error_type: Option<&str>,
+) -> (&'static str, Option<&'static str>) {
+    match error_type {
+        Some("authentication_error") => ("invalid_request_error", Some("invalid_api_key")),
+        Some("rate_limit_error") => ("insufficient_quota", Some("insufficient_quota")),
+        Some("invalid_request_error" | "permission_error") => ("invalid_request_error", None),
+        _ if status.is_client_error() => ("invalid_request_error", None),
+        _ => ("server_error", None),
+    }
+}
+
+/// 把 `/v1/chat/completions` 的错误响应改写为 OpenAI 格式
+///
+/// 认证、准入、请求转换与上游调用都复用 Anthropic 端点的管线，错误体是 Anthropic 格式
+/// （`{"error": {"type", "message"}}`）；axum 的请求体解析错误为纯文本。作为路由的响应中间件统一改写，
+/// 已经是 OpenAI 格式（带 `param` 字段）的错误保持不变
+pub async fn map_error_response(response: Response) -> Response {
+    let status = response.status();
+    if status.is_success() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    let parsed: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    if parsed["error"].get("param").is_some() {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    let message = match parsed["error"]["message"].as_str() {
+        Some(message) => message.to_string(),
+        None => String::from_utf8_lossy(&bytes).trim().to_string(),
+    };
+    let (error_type, code) = map_error_type(status, parsed["error"]["type"].as_str());
+    error_response(status, error_type, &message, None, code)
+}
+
+// === Handler ===
+
+/// POST /v1/chat/completions
+///
+/// OpenAI Chat Completions 兼容端点，与 `/v1/messages` 共用请求管线
+/// （见 [`admit_request`]），错误响应由 [`map_error_response`] 改写为 OpenAI 格式
+pub async fn post_chat_completions(
+    State(state): State<AppState>,
+    client: Option<Extension<ClientKey>>,
+    JsonExtractor(payload): JsonExtractor<ChatCompletionRequest>,
+) -> Response {
+    tracing::info!(
+        model = %payload.model,
+        stream = %payload.stream,
+        message_count = %payload.messages.len(),
+        "Received POST /v1/chat/completions request"
+    );
+
+    let include_usage = payload
+        .stream_options
+        .as_ref()
+        .map(|o| o.include_usage)
+        .unwrap_or(false);
+
+    let mut payload = match to_messages_request(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("请求转换失败: {}", e.message);
+            return e.into_response();
+        }
+    };
+
+    // 检查 KiroProvider、按模型名覆写 thinking 配置并做客户端 API Key 准入检查
+    let (provider, usage) = match admit_request(&state, client.as_deref(), &mut payload) {
+        Ok(admitted) => admitted,
+        Err(response) => return response,
+    };
+
+    // Kiro 不支持的采样参数（在成功的响应上通过响应头告知客户端）
+    let ignored_params = check_sampling_params(&payload);
+
+    // 下载 url 来源的图片和文档并转换请求
+    let origin = RequestOrigin::new(Route::V1, client.as_deref());
+    let conversion_result = match convert_payload(&mut payload, &origin, &usage).await {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
+
+    // 上下文窗口预检、构建 Kiro 请求体，并在后台估算输入 tokens
+    let upstream =
+        match build_upstream(&state, &provider, conversion_result, &mut payload, &usage).await {
+            Ok(upstream) => upstream,
+            Err(response) => return response,
+        };
+
+    let thinking_enabled = payload
+        .thinking
+        .as_ref()
+        .map(|t| t.is_enabled())
+        .unwrap_or(false);
+
+    // input_tokens 在上游响应后填入
+    let ctx = ChatCompletionContext::new(
+        &payload.model,
+        0,
+        thinking_enabled,
+        include_usage || !payload.stream,
+    )
+    .with_tool_choice(payload.tool_choice)
+    .with_stop_sequences(payload.stop_sequences)
+    .with_max_tokens(payload.max_tokens);
+
+    let mut response = if payload.stream {
+        handle_chat_stream_request(provider, &upstream.body, ctx, upstream.input_tokens, usage)
+            .await
+    } else {
+        handle_chat_non_stream_request(provider, &upstream.body, ctx, upstream.input_tokens, usage)
+            .await
+    };
+    if let Some(report) = upstream.context_report {
+        report.apply(&mut response);
+    }
+    apply_ignored_params(&ignored_params, &mut response);
+    response
+}
+
+/// 处理流式 Chat Completions 请求
+async fn handle_chat_stream_request(
+    provider: Arc<KiroProvider>,
+    request_body: &str,
+    mut ctx: ChatCompletionContext,
+    input_tokens: token::InputTokensTask,
+    mut usage: UsageReporter,
+) -> Response {
+    let response = match call_upstream(&provider, request_body, true, &mut usage).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+    ctx.set_input_tokens(input_tokens.wait().await);
+
+    let initial_chunks = ctx.generate_initial_chunks();
+    let stream = create_chat_sse_stream(response, ctx, initial_chunks, usage);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::CONNECTION, "keep-alive")
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
+/// 创建 Chat Completions SSE 流
+///
+/// 以 `data: [DONE]` 结束；保活使用 SSE 注释行，避免干扰 OpenAI 客户端解析
+fn create_chat_sse_stream(
+    response: reqwest::Response,
+    ctx: ChatCompletionContext,
+    initial_chunks: Vec<Value>,
+    usage: UsageReporter,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let initial_stream = stream::iter(initial_chunks.into_iter().map(|c| Ok(to_sse_data(&c))));
+
+    let body_stream = response.bytes_stream();
+
+    let processing_stream = stream::unfold(
+        (
+            body_stream,
+            ctx,
+            EventStreamDecoder::new(),
+            false,
+            interval(Duration::from_secs(PING_INTERVAL_SECS)),
+            usage,
+        ),
+        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, usage)| async move {
+            if finished {
+                return None;
+            }
+
+            tokio::select! {
+                chunk_result = body_stream.next() => {
+                    match chunk_result {
+                        Some(Ok(chunk)) => {
+                            if let Err(e) = decoder.feed(&chunk) {
+                                tracing::warn!("缓冲区溢出: {}", e);
+                            }
+
+                            let mut chunks = Vec::new();
+                            for result in decoder.decode_iter() {
+                                match result {
+                                    Ok(frame) => {
+                                        if let Ok(event) = Event::from_frame(frame) {
+                                            chunks.extend(ctx.process_kiro_event(&event));
+                                        }
+                                    }
+                                    Err(e) => {
+                                        tracing::warn!("解码事件失败: {}", e);
+                                    }
+                                }
+                            }
+
+                            let mut bytes: Vec<Result<Bytes, Infallible>> =
+                                chunks.iter().map(|c| Ok(to_sse_data(c))).collect();
+
+                            // 命中停止序列或达到 max_tokens 时不再读取上游
+                            let finished = ctx.should_stop();
+                            if finished {
+                                bytes.extend(finish_chat_stream(&mut ctx, &usage, None));
+                            }
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, finished, ping_interval, usage)))
+                        }
+                        Some(Err(e)) => {
+                            tracing::error!("读取响应流失败: {}", e);
+                            let bytes = finish_chat_stream(&mut ctx, &usage, Some("stream_error"));
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage)))
+                        }
+                        None => {
+                            let bytes = finish_chat_stream(&mut ctx, &usage, None);
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, usage)))
+                        }
+                    }
+                }
+                _ = ping_interval.tick() => {
+                    tracing::trace!("发送 ping 保活注释");
+                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(Bytes::from(": ping\n\n"))];
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, usage)))
+                }
+            }
+        },
+    )
+    .flatten();
+
+    initial_stream.chain(processing_stream)
+}
+
+/// 生成流结束时的 chunk 与 `[DONE]` 标记
+///
+/// `error_type` 非空表示上游流中途失败
+fn finish_chat_stream(
+    ctx: &mut ChatCompletionContext,
+    usage: &UsageReporter,
+    error_type: Option<&str>,
+) -> Vec<Result<Bytes, Infallible>> {
+    let mut bytes: Vec<Result<Bytes, Infallible>> = ctx
+        .generate_final_chunks()
+        .iter()
+        .map(|c| Ok(to_sse_data(c)))
+        .collect();
+    match error_type {
+        Some(error_type) => {
+            let (input_tokens, output_tokens) = ctx.final_usage();
+            usage.report_failure(error_type, input_tokens, output_tokens);
+        }
+        None => report_stream_usage(
+            usage,
+            ctx.final_usage(),
+            ctx.tool_choice_violation(),
+            &ctx.stop_reason(),
+        ),
+    }
+    bytes.push(Ok(Bytes::from("data: [DONE]\n\n")));
+    bytes
+}
+
+/// 处理非流式 Chat Completions 请求
+async fn handle_chat_non_stream_request(
+    provider: Arc<KiroProvider>,
+    request_body: &str,
+    mut ctx: ChatCompletionContext,
+    input_tokens: token::InputTokensTask,
+    mut usage: UsageReporter,
+) -> Response {
+    let response = match call_upstream(&provider, request_body, false, &mut usage).await {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+    ctx.set_input_tokens(input_tokens.wait().await);
+
+    let body_bytes = match response.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("读取响应体失败: {}", e);
+            return usage.fail(
+                (
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("读取响应失败: {}", e),
+                    )),
+                )
+                    .into_response(),
+            );
+        }
+    };
+
+    let mut decoder = EventStreamDecoder::new();
+    if let Err(e) = decoder.feed(&body_bytes) {
+        tracing::warn!("缓冲区溢出: {}", e);
+    }
+
+    let mut chunks = ctx.generate_initial_chunks();
+    for result in decoder.decode_iter() {
+        match result {
+            Ok(frame) => {
+                if let Ok(event) = Event::from_frame(frame) {
+                    chunks.extend(ctx.process_kiro_event(&event));
+                }
+            }
+            Err(e) => {
+                tracing::warn!("解码事件失败: {}", e);
+            }
+        }
+    }
+    chunks.extend(ctx.generate_final_chunks());
+    let (input_tokens, output_tokens) = ctx.final_usage();
+
+    // 校验 tool_choice（none 时不得调用工具，指定工具时不得调用其他工具）
+    if let Some(message) = ctx.tool_choice_violation() {
+        tracing::warn!("{}", message);
+        usage.report_failure("tool_choice_violation", input_tokens, output_tokens);
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new("api_error", message)),
+        )
+            .into_response();
+    }
+    usage.report(input_tokens, output_tokens, &ctx.stop_reason());
+
+    (StatusCode::OK, Json(aggregate_chunks(&chunks))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::converter::convert_request;
+    use crate::kiro::model::events::ToolUseEvent;
+
+    fn parse_request(value: Value) -> ChatCompletionRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn text_event(text: &str) -> Event {
+        Event::AssistantResponse(serde_json::from_value(json!({ "content": text })).unwrap())
+    }
+
+    #[test]
+    fn test_to_messages_request_system_and_tool_calls() {
+        let req = parse_request(json!({
+            "model": "claude-sonnet-4-6",
+            "max_completion_tokens": 1024,
+            "messages": [
+                {"role": "system", "content": "You are helpful."},
+                {"role": "user", "content": "What's the weather?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\":\"Rome\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "sunny"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "rainy"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "description": "Get weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "tool_choice": "required"
+        }));
+
+        let converted = to_messages_request(req).unwrap();
+        assert_eq!(converted.max_tokens, 1024);
+        assert_eq!(
+            converted.system.as_ref().unwrap()[0].text,
+            "You are helpful."
+        );
+        assert_eq!(converted.messages.len(), 3);
+
+        let assistant = &converted.messages[1];
+        assert_eq!(assistant.role, "assistant");
+        assert_eq!(assistant.content[0]["type"], "tool_use");
+        assert_eq!(assistant.content[0]["input"]["city"], "Paris");
+
+        // 连续的 tool 消息合并为一条 user 消息
+        let results = converted.messages[2].content.as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1]["tool_use_id"], "call_2");
+
+        let tools = converted.tools.unwrap();
+        assert_eq!(tools[0].name, "get_weather");
+        assert!(tools[0].input_schema.contains_key("properties"));
+        assert!(matches!(
+            converted.tool_choice,
+            Some(ToolChoice::Any { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_messages_request_converts_to_kiro_state() {
+        let req = parse_request(json!({
+            "model": "claude-sonnet-4-6",
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "describe"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+            ]}]
+        }));
+
+        let converted = to_messages_request(req).unwrap();
+        assert_eq!(converted.max_tokens, DEFAULT_MAX_TOKENS);
+
+        let result = convert_request(&converted, &RequestOrigin::default()).unwrap();
+        let current = &result.conversation_state.current_message.user_input_message;
+        assert_eq!(current.content, "describe");
+        assert_eq!(current.images.len(), 1);
+        assert_eq!(current.images[0].format, "png");
+    }
+
+    #[test]
+    fn test_to_messages_request_rejects_unknown_role() {
+        let req = parse_request(json!({
+            "model": "claude-sonnet-4-6",
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "function", "name": "f", "content": "{}"}
+            ]
+        }));
+
+        let err = to_messages_request(req).unwrap_err();
+        assert_eq!(err.param, "messages.[1].role");
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    async fn response_json(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_map_error_response_uses_openai_shape() {
+        let anthropic = (
+            StatusCode::UNAUTHORIZED,
+            Json(ErrorResponse::authentication_error()),
+        )
+            .into_response();
+        let response = map_error_response(anthropic).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response_json(response).await,
+            json!({"error": {
+                "message": "Invalid API key",
+                "type": "invalid_request_error",
+                "param": null,
+                "code": "invalid_api_key"
+            }})
+        );
+
+        // 请求体解析失败等纯文本错误
+        let plain = (StatusCode::UNPROCESSABLE_ENTITY, "missing field `model`").into_response();
+        let body = response_json(map_error_response(plain).await).await;
+        assert_eq!(body["error"]["message"], "missing field `model`");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+
+        let upstream = (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new("api_error", "boom")),
+        )
+            .into_response();
+        let body = response_json(map_error_response(upstream).await).await;
+        assert_eq!(body["error"]["type"], "server_error");
+
+        // 已是 OpenAI 格式的错误保持不变
+        let role_error = ChatRequestError {
+            param: "messages.[0].role".to_string(),
+            message: "bad role".to_string(),
+        };
+        let body = response_json(map_error_response(role_error.into_response()).await).await;
+        assert_eq!(body["error"]["param"], "messages.[0].role");
+    }
+
+    #[test]
+    fn test_tool_choice_violation_ends_with_error_chunk() {
+        let mut ctx = ChatCompletionContext::new("claude-sonnet-4-6", 10, false, true)
+            .with_tool_choice(Some(ToolChoice::None));
+        let mut chunks = ctx.generate_initial_chunks();
+        chunks.extend(ctx.process_kiro_event(&Event::ToolUse(ToolUseEvent {
+            name: "get_weather".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: "{}".to_string(),
+            stop: true,
+        })));
+
+        assert!(ctx.should_stop());
+        assert!(ctx.tool_choice_violation().is_some());
+        let error = chunks.last().unwrap();
+        assert_eq!(error["error"]["type"], "server_error");
+        assert!(error["choices"].is_null());
+        assert!(ctx.generate_final_chunks().is_empty());
+    }
+
+    #[test]
+    fn test_convert_stop() {
+        assert_eq!(convert_stop(json!("END")), Some(vec!["END".to_string()]));
+        assert_eq!(
+            convert_stop(json!(["a", "b"])),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(convert_stop(json!([])), None);
+    }
+
+    #[test]
+    fn test_convert_tool_choice() {
+        assert_eq!(
+            convert_tool_choice(json!("auto")),
+            ToolChoice::Auto {
+                disable_parallel_tool_use: false
+            }
+        );
+        assert_eq!(convert_tool_choice(json!("none")), ToolChoice::None);
+        assert_eq!(
+            convert_tool_choice(json!({"type": "function", "function": {"name": "f"}})),
+            ToolChoice::Tool {
+                name: "f".to_string(),
+                disable_parallel_tool_use: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_stream_chunks_text_and_tool_calls() {
+        let mut ctx = ChatCompletionContext::new("claude-sonnet-4-6", 10, false, true);
+        let mut chunks = ctx.generate_initial_chunks();
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+
+        chunks.extend(ctx.process_kiro_event(&text_event("Hello")));
+        chunks.extend(ctx.process_kiro_event(&Event::ToolUse(ToolUseEvent {
+            name: "get_weather".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: "{\"city\":".to_string(),
+            stop: false,
+        })));
+        chunks.extend(ctx.process_kiro_event(&Event::ToolUse(ToolUseEvent {
+            name: "get_weather".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: "\"Paris\"}".to_string(),
+            stop: true,
+        })));
+        chunks.extend(ctx.generate_final_chunks());
+
+        assert!(
+            chunks
+                .iter()
+                .all(|c| c["object"] == "chat.completion.chunk")
+        );
+        assert!(
+            chunks
+                .iter()
+                .any(|c| c["choices"][0]["delta"]["content"] == "Hello")
+        );
+
+        let tool_start = chunks
+            .iter()
+            .find(|c| !c["choices"][0]["delta"]["tool_calls"][0]["id"].is_null())
+            .unwrap();
+        assert_eq!(
+            tool_start["choices"][0]["delta"]["tool_calls"][0]["index"],
+            0
+        );
+        assert_eq!(
+            tool_start["choices"][0]["delta"]["tool_calls"][0]["function"]["name"],
+            "get_weather"
+        );
+
+        let finish = chunks
+            .iter()
+            .find(|c| !c["choices"][0]["finish_reason"].is_null())
+            .unwrap();
+        assert_eq!(finish["choices"][0]["finish_reason"], "tool_calls");
+
+        let last = chunks.last().unwrap();
+        assert_eq!(last["choices"], json!([]));
+        assert_eq!(last["usage"]["prompt_tokens"], 10);
+
+        let aggregated = aggregate_chunks(&chunks);
+        let message = &aggregated["choices"][0]["message"];
+        assert_eq!(aggregated["object"], "chat.completion");
+        assert_eq!(message["content"], "Hello");
+        assert_eq!(message["tool_calls"][0]["id"], "tool_1");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(aggregated["choices"][0]["finish_reason"], "tool_calls");
+    }
+
+    #[test]
+    fn test_stream_chunks_thinking_as_reasoning_content() {
+        let mut ctx = ChatCompletionContext::new("claude-sonnet-4-6", 10, true, false);
+        let mut chunks = ctx.generate_initial_chunks();
+        chunks.extend(
+            ctx.process_kiro_event(&text_event("<thinking>\nlet me think</thinking>\n\nAnswer")),
+        );
+        chunks.extend(ctx.generate_final_chunks());
+
+        let aggregated = aggregate_chunks(&chunks);
+        let message = &aggregated["choices"][0]["message"];
+        assert_eq!(message["reasoning_content"], "let me think");
+        assert_eq!(message["content"], "Answer");
+        assert_eq!(aggregated["choices"][0]["finish_reason"], "stop");
+        // 未请求 include_usage 时不输出 usage chunk
+        assert!(chunks.iter().all(|c| c["usage"].is_null()));
+    }
+
+    #[test]
+    fn test_aggregate_chunks_out_of_order_tool_calls() {
+        let tool_chunk = |index: u64, id: &str, args: &str| {
+            json!({
+                "id": "chatcmpl-1",
+                "created": 0,
+                "model": "claude-sonnet-4-6",
+                "choices": [{
+                    "index": 0,
+                    "delta": {"tool_calls": [{
+                        "index": index,
+                        "id": id,
+                        "type": "function",
+                        "function": {"name": id, "arguments": args}
+                    }]},
+                    "finish_reason": null
+                }]
+            })
+        };
+        let chunks = vec![
+            tool_chunk(2, "tool_c", "{}"),
+            tool_chunk(0, "tool_a", "{\"x\":"),
+            tool_chunk(0, "tool_a", "1}"),
+        ];
+
+        let aggregated = aggregate_chunks(&chunks);
+        let tool_calls = aggregated["choices"][0]["message"]["tool_calls"]
+            .as_array()
+            .unwrap();
+        // 从未出现的索引 1 不会留下空占位
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0]["id"], "tool_a");
+        assert_eq!(tool_calls[0]["function"]["arguments"], "{\"x\":1}");
+        assert_eq!(tool_calls[1]["id"], "tool_c");
+    }
+}
@@ -0,0 +1,134 @@
+//! max_tokens 输出预算
+//!
+//! Kiro 不接受 max_tokens，输出不受限制。这里在输出到达时用 [`token::count_tokens`]
+//! 累计 token 数，超出预算时截断并标记耗尽，由调用方关闭内容块并返回
+//! `stop_reason: "max_tokens"`。
+
+use crate::token;
+
+/// 单个请求的输出 token 预算
+#[derive(Debug, Default)]
+pub(crate) struct OutputBudget {
+    max_tokens: Option<i32>,
+    used: i32,
+    /// 尚不足 1 个 token、暂未计入的文本
+    uncounted: String,
+    exhausted: bool,
+}
+
+impl OutputBudget {
+    /// 创建预算，`None` 表示不限制（仍会累计 token 数）
+    pub(crate) fn new(max_tokens: Option<i32>) -> Self {
+        Self {
+            max_tokens,
+            ..Self::default()
+        }
+    }
+
+    /// 已输出的 token 数
+    pub(crate) fn used(&self) -> i32 {
+        self.used
+    }
+
+    /// 预算是否已耗尽
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// 计入一段输出，返回预算内可以输出的前缀
+    ///
+    /// 超出预算时截断到最长的可容纳前缀并标记耗尽，耗尽后始终返回空串
+    pub(crate) fn take<'a>(&mut self, text: &'a str) -> &'a str {
+        if self.exhausted {
+            return "";
+        }
+
+        let tokens = self.count_with_uncounted(text);
+        let Some(max_tokens) = self.max_tokens else {
+            self.accept(text, tokens);
+            return text;
+        };
+        if self.used + tokens <= max_tokens {
+            self.accept(text, tokens);
+            return text;
+        }
+
+        // 二分查找可容纳的最长前缀（按字符边界）
+        let bounds: Vec<usize> = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(text.len()))
+            .collect();
+        let (mut lo, mut hi) = (0, bounds.len() - 1);
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if self.used + self.count_with_uncounted(&text[..bounds[mid]]) <= max_tokens {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+
+        let prefix = &text[..bounds[lo]];
+        tracing::debug!(max_tokens, "输出达到 max_tokens，截断响应");
+        self.used = max_tokens.max(self.used);
+        self.uncounted.clear();
+        self.exhausted = true;
+        prefix
+    }
+
+    fn count_with_uncounted(&self, text: &str) -> i32 {
+        if self.uncounted.is_empty() {
+            token::count_tokens(text) as i32
+        } else {
+            token::count_tokens(&format!("{}{}", self.uncounted, text)) as i32
+        }
+    }
+
+    fn accept(&mut self, text: &str, tokens: i32) {
+        if tokens > 0 {
+            self.used += tokens;
+            self.uncounted.clear();
+        } else {
+            self.uncounted.push_str(text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_budget_counts_tokens() {
+        let mut budget = OutputBudget::new(None);
+        let text = "hello world ".repeat(100);
+        assert_eq!(budget.take(&text), text);
+        assert!(budget.used() > 0);
+        assert!(!budget.is_exhausted());
+    }
+
+    #[test]
+    fn test_small_chunks_accumulate() {
+        let mut budget = OutputBudget::new(None);
+        for _ in 0..40 {
+            budget.take("a");
+        }
+        assert!(budget.used() > 0);
+    }
+
+    #[test]
+    fn test_truncates_at_max_tokens() {
+        let mut budget = OutputBudget::new(Some(10));
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(10);
+        let prefix = budget.take(&text);
+
+        assert!(!prefix.is_empty());
+        assert!(prefix.len() < text.len());
+        assert!(text.starts_with(prefix));
+        assert!(token::count_tokens(prefix) <= 10);
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.used(), 10);
+        assert_eq!(budget.take("more"), "");
+    }
+}
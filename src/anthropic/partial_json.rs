@@ -0,0 +1,193 @@
+//! 增量工具参数 JSON 处理
+//!
+//! 上游的 `ToolUseEvent.input` 是任意切分的 JSON 片段。这里逐字符扫描，只向客户端输出到
+//! 最近一个"安全点"（在此处截断后补上右括号即为合法 JSON）为止的内容，这样响应被
+//! max_tokens 等原因中途截断时，可以用 [`PartialJson::close`] 补全已输出的部分，
+//! 保证客户端拼接出的 `input` 始终能被解析。
+
+/// 容器类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    /// 对象；`in_value` 表示已读到冒号、正在读取值
+    Object {
+        in_value: bool,
+    },
+    Array,
+}
+
+/// 单个工具调用的参数 JSON 缓冲
+#[derive(Debug, Default)]
+pub(crate) struct PartialJson {
+    buffer: String,
+    /// 已输出的字节数
+    emitted: usize,
+    /// 已扫描的字节数
+    scanned: usize,
+    stack: Vec<Frame>,
+    in_string: bool,
+    escaped: bool,
+    string_is_key: bool,
+    in_scalar: bool,
+    /// 最近的安全点及当时的容器栈
+    safe: usize,
+    safe_stack: Vec<Frame>,
+}
+
+impl PartialJson {
+    /// 追加 JSON 片段，返回新增的可安全输出的部分
+    pub(crate) fn push(&mut self, fragment: &str) -> String {
+        self.buffer.push_str(fragment);
+        let start = self.scanned;
+        let chars: Vec<(usize, char)> = self.buffer[start..]
+            .char_indices()
+            .map(|(i, c)| (start + i, c))
+            .collect();
+        for (pos, c) in chars {
+            self.scan(pos, c);
+        }
+        self.scanned = self.buffer.len();
+
+        let delta = self.buffer[self.emitted..self.safe].to_string();
+        self.emitted = self.safe;
+        delta
+    }
+
+    /// 参数完整接收后调用，返回剩余未输出的部分
+    pub(crate) fn finish(&mut self) -> String {
+        let rest = self.buffer[self.emitted..].to_string();
+        self.emitted = self.buffer.len();
+        rest
+    }
+
+    /// 参数被截断时调用，返回补全已输出部分所需的右括号
+    ///
+    /// 尚未输出任何内容时返回 `{}`
+    pub(crate) fn close(&mut self) -> String {
+        if self.emitted == 0 {
+            return "{}".to_string();
+        }
+        self.safe_stack
+            .iter()
+            .rev()
+            .map(|frame| match frame {
+                Frame::Object { .. } => '}',
+                Frame::Array => ']',
+            })
+            .collect()
+    }
+
+    fn mark_safe(&mut self, pos: usize) {
+        self.safe = pos;
+        self.safe_stack.clone_from(&self.stack);
+    }
+
+    fn scan(&mut self, pos: usize, c: char) {
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+            } else if c == '\\' {
+                self.escaped = true;
+            } else if c == '"' {
+                self.in_string = false;
+                if !self.string_is_key {
+                    self.mark_safe(pos + 1);
+                }
+            }
+            return;
+        }
+
+        let is_delimiter =
+            matches!(c, ',' | '}' | ']' | ':' | '"' | '{' | '[') || c.is_whitespace();
+        if !is_delimiter {
+            // 数字、true/false/null 等标量，只有在遇到分隔符时才算完整
+            self.in_scalar = true;
+            return;
+        }
+        if self.in_scalar {
+            self.in_scalar = false;
+            self.mark_safe(pos);
+        }
+
+        match c {
+            '"' => {
+                self.in_string = true;
+                self.string_is_key =
+                    matches!(self.stack.last(), Some(Frame::Object { in_value: false }));
+            }
+            '{' => {
+                self.stack.push(Frame::Object { in_value: false });
+                self.mark_safe(pos + 1);
+            }
+            '[' => {
+                self.stack.push(Frame::Array);
+                self.mark_safe(pos + 1);
+            }
+            '}' | ']' => {
+                self.stack.pop();
+                self.mark_safe(pos + 1);
+            }
+            ':' => {
+                if let Some(Frame::Object { in_value }) = self.stack.last_mut() {
+                    *in_value = true;
+                }
+            }
+            ',' => {
+                if let Some(Frame::Object { in_value }) = self.stack.last_mut() {
+                    *in_value = false;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 将截断的 JSON 修复为合法 JSON（丢弃最后一个安全点之后的内容）
+pub(crate) fn repair(partial: &str) -> String {
+    let mut json = PartialJson::default();
+    let mut repaired = json.push(partial);
+    repaired.push_str(&json.close());
+    repaired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_valid(json: &str) -> serde_json::Value {
+        serde_json::from_str(json).unwrap_or_else(|e| panic!("invalid json {json:?}: {e}"))
+    }
+
+    #[test]
+    fn test_complete_json_passes_through() {
+        let mut json = PartialJson::default();
+        let mut output = json.push(r#"{"path": "/tmp/a", "#);
+        output.push_str(&json.push(r#""lines": [1, 2], "force": true}"#));
+        output.push_str(&json.finish());
+        assert_eq!(
+            output,
+            r#"{"path": "/tmp/a", "lines": [1, 2], "force": true}"#
+        );
+    }
+
+    #[test]
+    fn test_emitted_prefix_can_always_be_closed() {
+        let full = r#"{"a": "x\"y", "b": [1, {"c": null}, 23], "d": {"e": false}}"#;
+        for cut in 0..=full.len() {
+            let mut json = PartialJson::default();
+            let mut output = json.push(&full[..cut]);
+            output.push_str(&json.close());
+            assert_valid(&output);
+        }
+    }
+
+    #[test]
+    fn test_repair_drops_incomplete_tail() {
+        assert_eq!(
+            repair(r#"{"path": "/tmp/a", "content": "hel"#),
+            r#"{"path": "/tmp/a"}"#
+        );
+        assert_eq!(repair(r#"{"n": 12"#), "{}");
+        assert_eq!(repair(r#"{"items": [1, 2, "#), r#"{"items": [1, 2]}"#);
+        assert_eq!(repair(""), "{}");
+    }
+}
@@ -0,0 +1,307 @@
+//! Prompt caching 用量估算
+//!
+//! Kiro 上游没有提示词缓存的概念，这里按 Anthropic 的规则在本地模拟：
+//! 请求按 tools → system → messages 的顺序展开为内容块，对每个块位置计算前缀哈希。
+//! 带 `cache_control` 的块是缓存断点，断点（及其之前最多 20 个块）的前缀若已在
+//! [`PromptCache`] 中即为缓存读取，最后一个断点之前未命中的部分计为缓存写入。
+
+use chrono::Duration;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::apikey::ClientKey;
+use crate::cache::PromptCache;
+use crate::model::registry;
+use crate::token;
+
+use super::types::{CacheControl, MessagesRequest};
+
+/// 可缓存前缀的最小 token 数，低于此值的断点不生效
+const MIN_CACHEABLE_TOKENS: u64 = 1024;
+
+/// 断点向前查找命中的最大块数
+const LOOKBACK_BLOCKS: usize = 20;
+
+/// 本次请求的缓存 token 估算结果
+///
+/// 各字段为本地估算口径，通过 [`PromptCacheUsage::split`] 按比例折算到实际输入 token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PromptCacheUsage {
+    read_tokens: u64,
+    creation_tokens: u64,
+    total_tokens: u64,
+}
+
+impl PromptCacheUsage {
+    /// 将总输入 token 拆分为 (input_tokens, cache_creation_input_tokens, cache_read_input_tokens)
+    ///
+    /// 与 Anthropic 一致，返回的 `input_tokens` 不含缓存部分
+    pub(crate) fn split(&self, input_tokens: i32) -> (i32, i32, i32) {
+        if self.total_tokens == 0 || input_tokens <= 0 {
+            return (input_tokens, 0, 0);
+        }
+        let scale = input_tokens as f64 / self.total_tokens as f64;
+        let read = ((self.read_tokens as f64 * scale).round() as i32).min(input_tokens);
+        let creation =
+            ((self.creation_tokens as f64 * scale).round() as i32).min(input_tokens - read);
+        (input_tokens - read - creation, creation, read)
+    }
+
+    /// 生成 Anthropic 响应中的 `usage` 对象
+    pub(crate) fn usage_json(&self, input_tokens: i32, output_tokens: i32) -> serde_json::Value {
+        let (input_tokens, creation, read) = self.split(input_tokens);
+        json!({
+            "input_tokens": input_tokens,
+            "cache_creation_input_tokens": creation,
+            "cache_read_input_tokens": read,
+            "output_tokens": output_tokens
+        })
+    }
+}
+
+/// 展开后的单个内容块
+struct Segment {
+    /// 参与哈希的内容（已去除 `cache_control`）
+    value: serde_json::Value,
+    tokens: u64,
+    /// 断点 TTL（非断点为 None）
+    breakpoint: Option<Duration>,
+}
+
+/// 估算请求的缓存读取/写入 token，并更新前缀缓存
+///
+/// 缓存按客户端 API Key 与模型隔离
+pub(crate) fn evaluate(
+    cache: &PromptCache,
+    client: Option<&ClientKey>,
+    payload: &MessagesRequest,
+) -> PromptCacheUsage {
+    let segments = collect_segments(payload);
+    if segments.iter().all(|s| s.breakpoint.is_none()) {
+        return PromptCacheUsage::default();
+    }
+
+    let model = registry::registry()
+        .resolve(&payload.model)
+        .map(|entry| entry.id.clone())
+        .unwrap_or_else(|| payload.model.clone());
+    let scope = format!("{}:{}", client.map(|c| c.id).unwrap_or(0), model);
+
+    // 每个块位置的前缀哈希与累计 token
+    let mut hashes = Vec::with_capacity(segments.len());
+    let mut cumulative = Vec::with_capacity(segments.len());
+    let mut prev: Vec<u8> = Sha256::digest(scope.as_bytes()).to_vec();
+    let mut total = 0u64;
+    for segment in &segments {
+        let mut hasher = Sha256::new();
+        hasher.update(&prev);
+        hasher.update(serde_json::to_vec(&segment.value).unwrap_or_default());
+        prev = hasher.finalize().to_vec();
+        hashes.push(hex::encode(&prev));
+        total += segment.tokens;
+        cumulative.push(total);
+    }
+
+    let breakpoints: Vec<usize> = segments
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.breakpoint.is_some())
+        .map(|(i, _)| i)
+        .collect();
+
+    // 查找最长的已缓存前缀
+    let mut hit: Option<usize> = None;
+    for &bp in &breakpoints {
+        for pos in (bp.saturating_sub(LOOKBACK_BLOCKS)..=bp).rev() {
+            if hit.is_some_and(|h| h >= pos) {
+                break;
+            }
+            if cumulative[pos] >= MIN_CACHEABLE_TOKENS && cache.contains(&hashes[pos]) {
+                hit = Some(pos);
+                break;
+            }
+        }
+    }
+    let read_tokens = hit.map(|pos| cumulative[pos]).unwrap_or(0);
+
+    // 写入（或刷新）所有达到最小长度的断点
+    let mut cached_until = read_tokens;
+    for &bp in &breakpoints {
+        if cumulative[bp] < MIN_CACHEABLE_TOKENS {
+            continue;
+        }
+        let ttl = segments[bp]
+            .breakpoint
+            .unwrap_or_else(|| Duration::minutes(5));
+        cache.touch(&hashes[bp], ttl);
+        cached_until = cached_until.max(cumulative[bp]);
+    }
+    if let Some(pos) = hit {
+        cache.touch(&hashes[pos], Duration::minutes(5));
+    }
+
+    let usage = PromptCacheUsage {
+        read_tokens,
+        creation_tokens: cached_until - read_tokens,
+        total_tokens: total,
+    };
+    tracing::debug!(
+        read_tokens = usage.read_tokens,
+        creation_tokens = usage.creation_tokens,
+        total_tokens = usage.total_tokens,
+        "prompt caching 估算"
+    );
+    usage
+}
+
+fn breakpoint_ttl(cache_control: Option<&CacheControl>) -> Option<Duration> {
+    let cache_control = cache_control?;
+    match cache_control.ttl.as_deref() {
+        Some("1h") => Some(Duration::hours(1)),
+        _ => Some(Duration::minutes(5)),
+    }
+}
+
+/// 按 tools → system → messages 顺序展开请求
+fn collect_segments(payload: &MessagesRequest) -> Vec<Segment> {
+    let mut segments = Vec::new();
+
+    for tool in payload.tools.iter().flatten() {
+        segments.push(Segment {
+            value: json!({
+                "tool": tool.name,
+                "description": tool.description,
+                "input_schema": tool.input_schema,
+            }),
+            tokens: token::count_tool_tokens(tool),
+            breakpoint: breakpoint_ttl(tool.cache_control.as_ref()),
+        });
+    }
+
+    for system in payload.system.iter().flatten() {
+        segments.push(Segment {
+            value: json!({ "system": system.text }),
+            tokens: token::count_tokens(&system.text),
+            breakpoint: breakpoint_ttl(system.cache_control.as_ref()),
+        });
+    }
+
+    for message in &payload.messages {
+        match &message.content {
+            serde_json::Value::Array(blocks) => {
+                for block in blocks {
+                    let cache_control = block
+                        .get("cache_control")
+                        .and_then(|v| serde_json::from_value::<CacheControl>(v.clone()).ok());
+                    let mut value = block.clone();
+                    if let Some(obj) = value.as_object_mut() {
+                        obj.remove("cache_control");
+                    }
+                    segments.push(Segment {
+                        tokens: token::count_block_tokens(&value),
+                        value: json!({ "role": message.role, "block": value }),
+                        breakpoint: breakpoint_ttl(cache_control.as_ref()),
+                    });
+                }
+            }
+            content => {
+                let text = content.as_str().unwrap_or_default();
+                segments.push(Segment {
+                    value: json!({ "role": message.role, "text": text }),
+                    tokens: token::count_tokens(text),
+                    breakpoint: None,
+                });
+            }
+        }
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(messages: serde_json::Value) -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "system": [{
+                "type": "text",
+                "text": "You are a helpful assistant. ".repeat(400),
+                "cache_control": {"type": "ephemeral"}
+            }],
+            "messages": messages
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_no_breakpoints_reports_nothing() {
+        let cache = PromptCache::new();
+        let payload: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+
+        let usage = evaluate(&cache, None, &payload);
+        assert_eq!(usage, PromptCacheUsage::default());
+        assert_eq!(usage.split(100), (100, 0, 0));
+    }
+
+    #[test]
+    fn test_second_request_reads_cached_prefix() {
+        let cache = PromptCache::new();
+        let first = request(json!([{"role": "user", "content": "first question"}]));
+        let usage = evaluate(&cache, None, &first);
+        assert_eq!(usage.read_tokens, 0);
+        assert!(usage.creation_tokens >= MIN_CACHEABLE_TOKENS);
+
+        // 对话继续，system 前缀命中缓存
+        let second = request(json!([
+            {"role": "user", "content": "first question"},
+            {"role": "assistant", "content": "answer"},
+            {"role": "user", "content": [{"type": "text", "text": "follow up", "cache_control": {"type": "ephemeral"}}]}
+        ]));
+        let usage = evaluate(&cache, None, &second);
+        assert!(usage.read_tokens >= MIN_CACHEABLE_TOKENS);
+        assert!(usage.creation_tokens > 0);
+        assert!(usage.read_tokens + usage.creation_tokens <= usage.total_tokens);
+    }
+
+    #[test]
+    fn test_short_prefix_is_not_cached() {
+        let cache = PromptCache::new();
+        let payload: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "system": [{"type": "text", "text": "short", "cache_control": {"type": "ephemeral"}}],
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+
+        evaluate(&cache, None, &payload);
+        let usage = evaluate(&cache, None, &payload);
+        assert_eq!(usage.read_tokens, 0);
+        assert_eq!(usage.creation_tokens, 0);
+    }
+
+    #[test]
+    fn test_split_scales_to_actual_input_tokens() {
+        let usage = PromptCacheUsage {
+            read_tokens: 600,
+            creation_tokens: 200,
+            total_tokens: 1000,
+        };
+        assert_eq!(usage.split(2000), (400, 400, 1200));
+        assert_eq!(usage.split(0), (0, 0, 0));
+
+        let json = usage.usage_json(1000, 5);
+        assert_eq!(json["input_tokens"], 200);
+        assert_eq!(json["cache_creation_input_tokens"], 200);
+        assert_eq!(json["cache_read_input_tokens"], 600);
+        assert_eq!(json["output_tokens"], 5);
+    }
+}
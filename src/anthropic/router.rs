@@ -1,5 +1,7 @@
 //! Anthropic API 路由配置
 
+use std::sync::Arc;
+
 use axum::{
     Router,
     extract::DefaultBodyLimit,
@@ -7,11 +9,19 @@ use axum::{
     routing::{get, post},
 };
 
+use crate::apikey::ApiKeyStore;
+use crate::batch::BatchStore;
+use crate::cache::ResponseCache;
 use crate::kiro::provider::KiroProvider;
+use crate::ledger::UsageLedger;
 
 use super::{
+    batches::{
+        self, cancel_batch, create_batch, delete_batch, get_batch, get_batch_results, list_batches,
+    },
     handlers::{count_tokens, get_models, post_messages, post_messages_cc},
     middleware::{AppState, auth_middleware, cors_layer},
+    openai::{map_error_response, post_chat_completions},
 };
 
 /// 请求体最大大小限制 (50MB)
@@ -23,21 +33,33 @@ const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 /// - `GET /v1/models` - 获取可用模型列表
 /// - `POST /v1/messages` - 创建消息（对话）
 /// - `POST /v1/messages/count_tokens` - 计算 token 数量
+/// - `POST /v1/chat/completions` - OpenAI Chat Completions 兼容端点
+/// - `/v1/messages/batches` - Message Batches（创建、列表、查询、取消、删除、结果）
 ///
 /// # 认证
 /// 所有 `/v1` 路径需要 API Key 认证，支持：
 /// - `x-api-key` header
 /// - `Authorization: Bearer <token>` header
 ///
+/// 除主 API Key 外，还接受客户端 API Key 存储中的 Key（受模型权限与配额限制）
+///
 /// # 参数
 /// - `api_key`: API 密钥，用于验证客户端请求
 /// - `kiro_provider`: 可选的 KiroProvider，用于调用上游 API
+/// - `api_key_store`: 可选的客户端 API Key 存储
+/// - `usage_ledger`: 可选的用量账本
+/// - `response_cache`: 可选的非流式响应缓存
+/// - `batch_store`: 可选的 Message Batches 存储及后台并发数（同时配置 KiroProvider 时启动后台 worker）
 
 /// 创建带有 KiroProvider 的 Anthropic API 路由
 pub fn create_router_with_provider(
     api_key: impl Into<String>,
     kiro_provider: Option<KiroProvider>,
     profile_arn: Option<String>,
+    api_key_store: Option<Arc<ApiKeyStore>>,
+    usage_ledger: Option<Arc<UsageLedger>>,
+    response_cache: Option<Arc<ResponseCache>>,
+    batch_store: Option<(Arc<BatchStore>, usize)>,
 ) -> Router {
     let mut state = AppState::new(api_key);
     if let Some(provider) = kiro_provider {
@@ -46,17 +68,51 @@ pub fn create_router_with_provider(
     if let Some(arn) = profile_arn {
         state = state.with_profile_arn(arn);
     }
+    if let Some(store) = api_key_store {
+        state = state.with_api_key_store(store);
+    }
+    if let Some(ledger) = usage_ledger {
+        state = state.with_usage_ledger(ledger);
+    }
+    if let Some(cache) = response_cache {
+        state = state.with_response_cache(cache);
+    }
+    if let Some((store, concurrency)) = batch_store {
+        state = state.with_batch_store(store.clone());
+        if state.kiro_provider.is_some() {
+            batches::spawn_worker(state.clone(), store, concurrency);
+        }
+    }
 
     // 需要认证的 /v1 路由
     let v1_routes = Router::new()
         .route("/models", get(get_models))
         .route("/messages", post(post_messages))
         .route("/messages/count_tokens", post(count_tokens))
+        .route("/messages/batches", post(create_batch).get(list_batches))
+        .route(
+            "/messages/batches/{batch_id}",
+            get(get_batch).delete(delete_batch),
+        )
+        .route("/messages/batches/{batch_id}/cancel", post(cancel_batch))
+        .route(
+            "/messages/batches/{batch_id}/results",
+            get(get_batch_results),
+        )
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ));
 
+    // OpenAI 兼容端点：错误响应（包括认证失败）统一改写为 OpenAI 格式
+    let openai_routes = Router::new()
+        .route("/chat/completions", post(post_chat_completions))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .layer(middleware::map_response(map_error_response));
+
     // 需要认证的 /cc/v1 路由（Claude Code 兼容端点）
     // 与 /v1 的区别：流式响应会等待 contextUsageEvent 后再发送 message_start
     let cc_v1_routes = Router::new()
@@ -68,7 +124,7 @@ pub fn create_router_with_provider(
         ));
 
     Router::new()
-        .nest("/v1", v1_routes)
+        .nest("/v1", v1_routes.merge(openai_routes))
         .nest("/cc/v1", cc_v1_routes)
         .layer(cors_layer())
         .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
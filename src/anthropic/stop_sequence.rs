@@ -0,0 +1,124 @@
+//! stop_sequences 模拟
+//!
+//! Kiro 不支持停止序列，这里在输出文本中逐段扫描。为了识别跨 chunk 的停止序列，
+//! 每次只输出除末尾 `最长序列长度 - 1` 字节以外的内容，剩余部分暂存到下一段一起匹配。
+
+/// 停止序列匹配器
+#[derive(Debug, Default)]
+pub(crate) struct StopSequenceMatcher {
+    sequences: Vec<String>,
+    /// 尚未输出的尾部文本
+    pending: String,
+    /// 命中的停止序列
+    matched: Option<String>,
+    /// 暂存的最大字节数
+    holdback: usize,
+}
+
+impl StopSequenceMatcher {
+    pub(crate) fn new(sequences: Option<Vec<String>>) -> Self {
+        let sequences: Vec<String> = sequences
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect();
+        let holdback = sequences
+            .iter()
+            .map(|s| s.len().saturating_sub(1))
+            .max()
+            .unwrap_or(0);
+        Self {
+            sequences,
+            holdback,
+            ..Self::default()
+        }
+    }
+
+    /// 命中的停止序列
+    pub(crate) fn matched(&self) -> Option<&str> {
+        self.matched.as_deref()
+    }
+
+    /// 输入一段文本，返回可以立即输出的部分
+    ///
+    /// 命中后返回停止序列之前的内容，此后的输入全部丢弃
+    pub(crate) fn push(&mut self, text: &str) -> String {
+        if self.matched.is_some() {
+            return String::new();
+        }
+        if self.sequences.is_empty() {
+            return text.to_string();
+        }
+
+        self.pending.push_str(text);
+        let earliest = self
+            .sequences
+            .iter()
+            .filter_map(|s| self.pending.find(s.as_str()).map(|pos| (pos, s)))
+            .min_by_key(|(pos, _)| *pos);
+        if let Some((pos, sequence)) = earliest {
+            self.matched = Some(sequence.clone());
+            let output = self.pending[..pos].to_string();
+            self.pending.clear();
+            return output;
+        }
+
+        let mut split = self.pending.len().saturating_sub(self.holdback);
+        while !self.pending.is_char_boundary(split) {
+            split -= 1;
+        }
+        self.pending.drain(..split).collect()
+    }
+
+    /// 取出暂存的文本（输出结束或切换到其他内容块时调用）
+    pub(crate) fn flush(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(sequences: &[&str]) -> StopSequenceMatcher {
+        StopSequenceMatcher::new(Some(sequences.iter().map(|s| s.to_string()).collect()))
+    }
+
+    #[test]
+    fn test_passthrough_without_sequences() {
+        let mut m = StopSequenceMatcher::new(None);
+        assert_eq!(m.push("hello"), "hello");
+        assert_eq!(m.flush(), "");
+        assert_eq!(m.matched(), None);
+    }
+
+    #[test]
+    fn test_match_across_chunks() {
+        let mut m = matcher(&["\n\nHuman:"]);
+        let mut output = m.push("Sure, here it is.\n");
+        output.push_str(&m.push("\nHum"));
+        output.push_str(&m.push("an: next"));
+        assert_eq!(output, "Sure, here it is.");
+        assert_eq!(m.matched(), Some("\n\nHuman:"));
+
+        // 命中后丢弃后续输入
+        assert_eq!(m.push("more"), "");
+        assert_eq!(m.flush(), "");
+    }
+
+    #[test]
+    fn test_earliest_sequence_wins() {
+        let mut m = matcher(&["END", "STOP"]);
+        assert_eq!(m.push("a STOP b END"), "a ");
+        assert_eq!(m.matched(), Some("STOP"));
+    }
+
+    #[test]
+    fn test_holdback_respects_char_boundary() {
+        let mut m = matcher(&["停止!"]);
+        let mut output = m.push("你好世界");
+        output.push_str(&m.flush());
+        assert_eq!(output, "你好世界");
+        assert_eq!(m.matched(), None);
+    }
+}
@@ -8,12 +8,20 @@ use serde_json::json;
 use uuid::Uuid;
 
 use crate::kiro::model::events::Event;
+use crate::model::registry;
+
+use super::output_budget::OutputBudget;
+use super::partial_json::PartialJson;
+use super::prompt_cache::PromptCacheUsage;
+use super::stop_sequence::StopSequenceMatcher;
+use super::tool_choice::{ToolChoiceGuard, ToolUseDecision};
+use super::types::ToolChoice;
 
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
 ///
 /// UTF-8字符可能占用1-4个字节，直接按字节位置切片可能会切在多字节字符中间导致panic。
 /// 这个函数从目标位置向前搜索，找到最近的有效字符边界。
-fn find_char_boundary(s: &str, target: usize) -> usize {
+pub(super) fn find_char_boundary(s: &str, target: usize) -> usize {
     if target >= s.len() {
         return s.len();
     }
@@ -65,7 +73,7 @@ fn is_quote_char(buffer: &str, pos: usize) -> bool {
 /// # 返回值
 /// - `Some(pos)`: 真正的结束标签的起始位置
 /// - `None`: 没有找到真正的结束标签
-fn find_real_thinking_end_tag(buffer: &str) -> Option<usize> {
+pub(super) fn find_real_thinking_end_tag(buffer: &str) -> Option<usize> {
     const TAG: &str = "</thinking>";
     let mut search_start = 0;
 
@@ -112,7 +120,7 @@ fn find_real_thinking_end_tag(buffer: &str) -> Option<usize> {
 ///
 /// 约束：只有当 `</thinking>` 之后全部都是空白字符时才认为是结束标签，
 /// 以避免在 thinking 内容中提到 `</thinking>`（非结束标签）时误判。
-fn find_real_thinking_end_tag_at_buffer_end(buffer: &str) -> Option<usize> {
+pub(super) fn find_real_thinking_end_tag_at_buffer_end(buffer: &str) -> Option<usize> {
     const TAG: &str = "</thinking>";
     let mut search_start = 0;
 
@@ -145,7 +153,7 @@ fn find_real_thinking_end_tag_at_buffer_end(buffer: &str) -> Option<usize> {
 /// 查找真正的 thinking 开始标签（不被引用字符包裹）
 ///
 /// 与 `find_real_thinking_end_tag` 类似，跳过被引用字符包裹的开始标签。
-fn find_real_thinking_start_tag(buffer: &str) -> Option<usize> {
+pub(super) fn find_real_thinking_start_tag(buffer: &str) -> Option<usize> {
     const TAG: &str = "<thinking>";
     let mut search_start = 0;
 
@@ -235,6 +243,8 @@ pub struct SseStateManager {
     next_block_index: i32,
     /// 当前 stop_reason
     stop_reason: Option<String>,
+    /// 命中的停止序列
+    stop_sequence: Option<String>,
     /// 是否有工具调用
     has_tool_use: bool,
 }
@@ -254,6 +264,7 @@ impl SseStateManager {
             message_ended: false,
             next_block_index: 0,
             stop_reason: None,
+            stop_sequence: None,
             has_tool_use: false,
         }
     }
@@ -282,6 +293,12 @@ impl SseStateManager {
         self.stop_reason = Some(reason.into());
     }
 
+    /// 记录命中的停止序列（同时将 stop_reason 设为 stop_sequence）
+    pub fn set_stop_sequence(&mut self, sequence: impl Into<String>) {
+        self.stop_reason = Some("stop_sequence".to_string());
+        self.stop_sequence = Some(sequence.into());
+    }
+
     /// 检查是否存在非 thinking 类型的内容块（如 text 或 tool_use）
     fn has_non_thinking_blocks(&self) -> bool {
         self.active_blocks
@@ -400,11 +417,9 @@ impl SseStateManager {
     }
 
     /// 生成最终事件序列
-    pub fn generate_final_events(
-        &mut self,
-        input_tokens: i32,
-        output_tokens: i32,
-    ) -> Vec<SseEvent> {
+    ///
+    /// `usage` 为 message_delta 中的 usage 对象
+    pub fn generate_final_events(&mut self, usage: serde_json::Value) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
         // 关闭所有未关闭的块
@@ -430,12 +445,9 @@ impl SseStateManager {
                     "type": "message_delta",
                     "delta": {
                         "stop_reason": self.get_stop_reason(),
-                        "stop_sequence": null
+                        "stop_sequence": self.stop_sequence
                     },
-                    "usage": {
-                        "input_tokens": input_tokens,
-                        "output_tokens": output_tokens
-                    }
+                    "usage": usage
                 }),
             ));
         }
@@ -453,9 +465,6 @@ impl SseStateManager {
     }
 }
 
-/// 上下文窗口大小（200k tokens）
-const CONTEXT_WINDOW_SIZE: i32 = 200_000;
-
 /// 流处理上下文
 pub struct StreamContext {
     /// SSE 状态管理器
@@ -472,6 +481,8 @@ pub struct StreamContext {
     pub output_tokens: i32,
     /// 工具块索引映射 (tool_id -> block_index)
     pub tool_block_indices: HashMap<String, i32>,
+    /// 工具参数 JSON 缓冲 (tool_id -> 已接收的参数)
+    tool_inputs: HashMap<String, PartialJson>,
     /// thinking 是否启用
     pub thinking_enabled: bool,
     /// thinking 内容缓冲区
@@ -484,6 +495,16 @@ pub struct StreamContext {
     pub thinking_block_index: Option<i32>,
     /// 文本块索引（thinking 启用时动态分配）
     pub text_block_index: Option<i32>,
+    /// 模型上下文窗口大小（来自模型注册表）
+    pub context_window: i32,
+    /// prompt caching 估算结果
+    pub prompt_cache: PromptCacheUsage,
+    /// tool_choice 校验器
+    tool_choice: ToolChoiceGuard,
+    /// stop_sequences 匹配器
+    stop_sequences: StopSequenceMatcher,
+    /// max_tokens 输出预算
+    budget: OutputBudget,
     /// 是否需要剥离 thinking 内容开头的换行符
     /// 模型输出 `<thinking>\n` 时，`\n` 可能与标签在同一 chunk 或下一 chunk
     strip_thinking_leading_newline: bool,
@@ -496,24 +517,71 @@ impl StreamContext {
         input_tokens: i32,
         thinking_enabled: bool,
     ) -> Self {
+        let model = model.into();
+        let context_window = registry::registry()
+            .resolve(&model)
+            .map(|entry| entry.context_window)
+            .unwrap_or(registry::DEFAULT_CONTEXT_WINDOW);
         Self {
             state_manager: SseStateManager::new(),
-            model: model.into(),
+            model,
             message_id: format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
             input_tokens,
             context_input_tokens: None,
             output_tokens: 0,
             tool_block_indices: HashMap::new(),
+            tool_inputs: HashMap::new(),
             thinking_enabled,
             thinking_buffer: String::new(),
             in_thinking_block: false,
             thinking_extracted: false,
             thinking_block_index: None,
             text_block_index: None,
+            context_window,
+            prompt_cache: PromptCacheUsage::default(),
+            tool_choice: ToolChoiceGuard::default(),
+            stop_sequences: StopSequenceMatcher::default(),
+            budget: OutputBudget::default(),
             strip_thinking_leading_newline: false,
         }
     }
 
+    /// 设置 prompt caching 估算结果（用于 usage 中的缓存 token）
+    pub fn with_prompt_cache(mut self, usage: PromptCacheUsage) -> Self {
+        self.prompt_cache = usage;
+        self
+    }
+
+    /// 设置请求的 tool_choice，用于校验上游返回的工具调用
+    pub fn with_tool_choice(mut self, tool_choice: Option<ToolChoice>) -> Self {
+        self.tool_choice = ToolChoiceGuard::new(tool_choice);
+        self
+    }
+
+    /// 设置请求的 stop_sequences，在文本输出中模拟停止序列
+    pub fn with_stop_sequences(mut self, stop_sequences: Option<Vec<String>>) -> Self {
+        self.stop_sequences = StopSequenceMatcher::new(stop_sequences);
+        self
+    }
+
+    /// 设置请求的 max_tokens，输出达到上限时截断并返回 max_tokens
+    pub fn with_max_tokens(mut self, max_tokens: i32) -> Self {
+        self.budget = OutputBudget::new(Some(max_tokens));
+        self
+    }
+
+    /// 违反 tool_choice 时的错误信息（已通过 error 事件发送给客户端）
+    pub fn tool_choice_violation(&self) -> Option<&str> {
+        self.tool_choice.violation()
+    }
+
+    /// 是否应停止读取上游（命中停止序列、达到 max_tokens 或违反 tool_choice）
+    pub fn should_stop(&self) -> bool {
+        self.tool_choice.violation().is_some()
+            || self.stop_sequences.matched().is_some()
+            || self.budget.is_exhausted()
+    }
+
     /// 生成 message_start 事件
     pub fn create_message_start_event(&self) -> serde_json::Value {
         json!({
@@ -526,10 +594,7 @@ impl StreamContext {
                 "model": self.model,
                 "stop_reason": null,
                 "stop_sequence": null,
-                "usage": {
-                    "input_tokens": self.input_tokens,
-                    "output_tokens": 1
-                }
+                "usage": self.prompt_cache.usage_json(self.input_tokens, 1)
             }
         })
     }
@@ -576,13 +641,16 @@ impl StreamContext {
     /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
     pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
         match event {
+            // 已命中停止序列、达到 max_tokens 或因违反 tool_choice 中止时，只忽略后续内容事件，
+            // 上下文使用率等元数据事件仍需处理，以保证 usage 与 stop_reason 准确
+            Event::AssistantResponse(_) | Event::ToolUse(_) if self.should_stop() => Vec::new(),
             Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
             Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
             Event::ContextUsage(context_usage) => {
                 // 从上下文使用百分比计算实际的 input_tokens
-                // 公式: percentage * 200000 / 100 = percentage * 2000
+                // 公式: percentage * context_window / 100
                 let actual_input_tokens = (context_usage.context_usage_percentage
-                    * (CONTEXT_WINDOW_SIZE as f64)
+                    * (self.context_window as f64)
                     / 100.0) as i32;
                 self.context_input_tokens = Some(actual_input_tokens);
                 // 上下文使用量达到 100% 时，设置 stop_reason 为 model_context_window_exceeded
@@ -621,13 +689,16 @@ impl StreamContext {
 
     /// 处理助手响应事件
     fn process_assistant_response(&mut self, content: &str) -> Vec<SseEvent> {
+        // 计入输出预算，超出 max_tokens 的部分被截断
+        let content = self.budget.take(content);
+        self.output_tokens = self.budget.used();
+        if self.budget.is_exhausted() {
+            self.state_manager.set_stop_reason("max_tokens");
+        }
         if content.is_empty() {
             return Vec::new();
         }
 
-        // 估算 tokens
-        self.output_tokens += estimate_tokens(content);
-
         // 如果启用了thinking，需要处理thinking块
         if self.thinking_enabled {
             return self.process_content_with_thinking(content);
@@ -790,7 +861,31 @@ impl StreamContext {
     /// 当发生 tool_use 时，状态机会自动关闭当前文本块；后续文本会自动创建新的文本块继续输出。
     ///
     /// 返回值包含可能的 content_block_start 事件和 content_block_delta 事件。
+    ///
+    /// 配置了 stop_sequences 时，文本先经过匹配器：命中后只输出停止序列之前的内容
     fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
+        let text = self.stop_sequences.push(text);
+        if let Some(sequence) = self.stop_sequences.matched() {
+            tracing::debug!(stop_sequence = %sequence, "命中停止序列");
+            self.state_manager.set_stop_sequence(sequence);
+        }
+        if text.is_empty() {
+            return Vec::new();
+        }
+        self.emit_text_delta_events(&text)
+    }
+
+    /// 输出停止序列匹配器中暂存的文本
+    fn flush_stop_sequence_buffer(&mut self) -> Vec<SseEvent> {
+        let pending = self.stop_sequences.flush();
+        if pending.is_empty() {
+            return Vec::new();
+        }
+        self.emit_text_delta_events(&pending)
+    }
+
+    /// 直接发送 text_delta 事件（不经过停止序列匹配）
+    fn emit_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
         // 如果当前 text_block_index 指向的块已经被关闭（例如 tool_use 开始时自动 stop），
@@ -864,6 +959,19 @@ impl StreamContext {
         &mut self,
         tool_use: &crate::kiro::model::events::ToolUseEvent,
     ) -> Vec<SseEvent> {
+        match self
+            .tool_choice
+            .on_tool_use(&tool_use.tool_use_id, &tool_use.name)
+        {
+            ToolUseDecision::Accept => {}
+            ToolUseDecision::Skip => return Vec::new(),
+            ToolUseDecision::Reject => {
+                let message = self.tool_choice.violation().unwrap_or_default();
+                tracing::warn!("{}", message);
+                return vec![tool_choice_error_event(message)];
+            }
+        }
+
         let mut events = Vec::new();
 
         self.state_manager.set_has_tool_use(true);
@@ -920,6 +1028,12 @@ impl StreamContext {
             events.extend(self.create_text_delta_events(&buffered));
         }
 
+        // 输出停止序列匹配暂存的文本；若在此过程中命中停止序列，则不再输出工具调用
+        events.extend(self.flush_stop_sequence_buffer());
+        if self.stop_sequences.matched().is_some() {
+            return events;
+        }
+
         // 获取或分配块索引
         let block_index = if let Some(&idx) = self.tool_block_indices.get(&tool_use.tool_use_id) {
             idx
@@ -948,26 +1062,40 @@ impl StreamContext {
         events.extend(start_events);
 
         // 发送参数增量 (ToolUseEvent.input 是 String 类型)
-        if !tool_use.input.is_empty() {
-            self.output_tokens += (tool_use.input.len() as i32 + 3) / 4; // 估算 token
+        // 只输出到 JSON 的安全点，参数被 max_tokens 截断时补全右括号，保证客户端拼接结果合法
+        let input = self.budget.take(&tool_use.input);
+        self.output_tokens = self.budget.used();
+        let truncated = self.budget.is_exhausted();
+        let tool_input = self
+            .tool_inputs
+            .entry(tool_use.tool_use_id.clone())
+            .or_default();
+        let mut partial_json = tool_input.push(input);
+        if truncated {
+            partial_json.push_str(&tool_input.close());
+            self.state_manager.set_stop_reason("max_tokens");
+        } else if tool_use.stop {
+            partial_json.push_str(&tool_input.finish());
+        }
 
-            if let Some(delta_event) = self.state_manager.handle_content_block_delta(
+        if !partial_json.is_empty()
+            && let Some(delta_event) = self.state_manager.handle_content_block_delta(
                 block_index,
                 json!({
                     "type": "content_block_delta",
                     "index": block_index,
                     "delta": {
                         "type": "input_json_delta",
-                        "partial_json": tool_use.input
+                        "partial_json": partial_json
                     }
                 }),
-            ) {
-                events.push(delta_event);
-            }
+            )
+        {
+            events.push(delta_event);
         }
 
-        // 如果是完整的工具调用（stop=true），发送 content_block_stop
-        if tool_use.stop {
+        // 如果是完整的工具调用（stop=true）或已被截断，发送 content_block_stop
+        if tool_use.stop || truncated {
             if let Some(stop_event) = self.state_manager.handle_content_block_stop(block_index) {
                 events.push(stop_event);
             }
@@ -976,8 +1104,28 @@ impl StreamContext {
         events
     }
 
+    /// 最终的 (input_tokens, output_tokens)
+    ///
+    /// input_tokens 优先使用从 contextUsageEvent 计算的值
+    pub fn final_usage(&self) -> (i32, i32) {
+        (
+            self.context_input_tokens.unwrap_or(self.input_tokens),
+            self.output_tokens,
+        )
+    }
+
+    /// 最终的 stop_reason
+    pub fn stop_reason(&self) -> String {
+        self.state_manager.get_stop_reason()
+    }
+
     /// 生成最终事件序列
     pub fn generate_final_events(&mut self) -> Vec<SseEvent> {
+        // 已发送 error 事件，不再生成结束事件
+        if self.tool_choice.violation().is_some() {
+            return Vec::new();
+        }
+
         let mut events = Vec::new();
 
         // Flush thinking_buffer 中的剩余内容
@@ -1042,6 +1190,9 @@ impl StreamContext {
             self.thinking_buffer.clear();
         }
 
+        // 输出停止序列匹配暂存的文本
+        events.extend(self.flush_stop_sequence_buffer());
+
         // 如果整个流中只产生了 thinking 块，没有 text 也没有 tool_use，
         // 则设置 stop_reason 为 max_tokens（表示模型耗尽了 token 预算在思考上），
         // 并补发一套完整的 text 事件（内容为一个空格），确保 content 数组中有 text 块
@@ -1049,18 +1200,30 @@ impl StreamContext {
             && self.thinking_block_index.is_some()
             && !self.state_manager.has_non_thinking_blocks()
         {
-            self.state_manager.set_stop_reason("max_tokens");
-            events.extend(self.create_text_delta_events(" "));
+            if self.stop_sequences.matched().is_none() {
+                self.state_manager.set_stop_reason("max_tokens");
+            }
+            events.extend(self.emit_text_delta_events(" "));
+        }
+
+        // 强制调用工具但模型没有调用时，以 error 事件结束
+        if let Some(message) = self
+            .tool_choice
+            .finish(&self.state_manager.get_stop_reason())
+        {
+            tracing::warn!("{}", message);
+            events.push(tool_choice_error_event(message));
+            return events;
         }
 
         // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
         let final_input_tokens = self.context_input_tokens.unwrap_or(self.input_tokens);
 
         // 生成最终事件
-        events.extend(
-            self.state_manager
-                .generate_final_events(final_input_tokens, self.output_tokens),
-        );
+        let usage = self
+            .prompt_cache
+            .usage_json(final_input_tokens, self.output_tokens);
+        events.extend(self.state_manager.generate_final_events(usage));
         events
     }
 }
@@ -1103,6 +1266,36 @@ impl BufferedStreamContext {
         }
     }
 
+    /// 设置 prompt caching 估算结果
+    pub fn with_prompt_cache(mut self, usage: PromptCacheUsage) -> Self {
+        self.inner = self.inner.with_prompt_cache(usage);
+        self
+    }
+
+    /// 设置估算的 input_tokens（后台计数完成后填入）
+    pub fn set_input_tokens(&mut self, input_tokens: i32) {
+        self.inner.input_tokens = input_tokens;
+        self.estimated_input_tokens = input_tokens;
+    }
+
+    /// 设置请求的 tool_choice
+    pub fn with_tool_choice(mut self, tool_choice: Option<ToolChoice>) -> Self {
+        self.inner = self.inner.with_tool_choice(tool_choice);
+        self
+    }
+
+    /// 设置请求的 stop_sequences
+    pub fn with_stop_sequences(mut self, stop_sequences: Option<Vec<String>>) -> Self {
+        self.inner = self.inner.with_stop_sequences(stop_sequences);
+        self
+    }
+
+    /// 设置请求的 max_tokens
+    pub fn with_max_tokens(mut self, max_tokens: i32) -> Self {
+        self.inner = self.inner.with_max_tokens(max_tokens);
+        self
+    }
+
     /// 处理 Kiro 事件并缓冲结果
     ///
     /// 复用 StreamContext 的事件处理逻辑，但把结果缓存而不是立即发送。
@@ -1143,12 +1336,13 @@ impl BufferedStreamContext {
             .context_input_tokens
             .unwrap_or(self.estimated_input_tokens);
 
-        // 更正 message_start 事件中的 input_tokens
+        // 更正 message_start 事件中的 input_tokens（及按其折算的缓存 token）
+        let usage = self.inner.prompt_cache.usage_json(final_input_tokens, 1);
         for event in &mut self.event_buffer {
             if event.event == "message_start" {
                 if let Some(message) = event.data.get_mut("message") {
-                    if let Some(usage) = message.get_mut("usage") {
-                        usage["input_tokens"] = serde_json::json!(final_input_tokens);
+                    if message.get("usage").is_some() {
+                        message["usage"] = usage.clone();
                     }
                 }
             }
@@ -1156,27 +1350,40 @@ impl BufferedStreamContext {
 
         std::mem::take(&mut self.event_buffer)
     }
-}
 
-/// 简单的 token 估算
-fn estimate_tokens(text: &str) -> i32 {
-    let chars: Vec<char> = text.chars().collect();
-    let mut chinese_count = 0;
-    let mut other_count = 0;
+    /// 最终的 (input_tokens, output_tokens)
+    pub fn final_usage(&self) -> (i32, i32) {
+        self.inner.final_usage()
+    }
 
-    for c in &chars {
-        if *c >= '\u{4E00}' && *c <= '\u{9FFF}' {
-            chinese_count += 1;
-        } else {
-            other_count += 1;
-        }
+    /// 最终的 stop_reason
+    pub fn stop_reason(&self) -> String {
+        self.inner.stop_reason()
     }
 
-    // 中文约 1.5 字符/token，英文约 4 字符/token
-    let chinese_tokens = (chinese_count * 2 + 2) / 3;
-    let other_tokens = (other_count + 3) / 4;
+    /// 违反 tool_choice 时的错误信息
+    pub fn tool_choice_violation(&self) -> Option<&str> {
+        self.inner.tool_choice_violation()
+    }
 
-    (chinese_tokens + other_tokens).max(1)
+    /// 是否应停止读取上游
+    pub fn should_stop(&self) -> bool {
+        self.inner.should_stop()
+    }
+}
+
+/// 违反 tool_choice 时发送的 SSE error 事件
+fn tool_choice_error_event(message: &str) -> SseEvent {
+    SseEvent::new(
+        "error",
+        json!({
+            "type": "error",
+            "error": {
+                "type": "api_error",
+                "message": message
+            }
+        }),
+    )
 }
 
 #[cfg(test)]
@@ -1365,13 +1572,6 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_estimate_tokens() {
-        assert!(estimate_tokens("Hello") > 0);
-        assert!(estimate_tokens("你好") > 0);
-        assert!(estimate_tokens("Hello 你好") > 0);
-    }
-
     #[test]
     fn test_find_real_thinking_start_tag_basic() {
         // 基本情况：正常的开始标签
@@ -1644,7 +1844,12 @@ mod tests {
 
         let full_thinking: String = thinking_deltas
             .iter()
-            .filter(|e| !e.data["delta"]["thinking"].as_str().unwrap_or("").is_empty())
+            .filter(|e| {
+                !e.data["delta"]["thinking"]
+                    .as_str()
+                    .unwrap_or("")
+                    .is_empty()
+            })
             .map(|e| e.data["delta"]["thinking"].as_str().unwrap_or(""))
             .collect();
 
@@ -1657,14 +1862,11 @@ mod tests {
         let mut ctx = StreamContext::new_with_thinking("test-model", 1, true);
         let _initial_events = ctx.generate_initial_events();
 
-        let events =
-            ctx.process_assistant_response("<thinking>\nabc</thinking>\n\n你好");
+        let events = ctx.process_assistant_response("<thinking>\nabc</thinking>\n\n你好");
 
         let text_deltas: Vec<_> = events
             .iter()
-            .filter(|e| {
-                e.event == "content_block_delta" && e.data["delta"]["type"] == "text_delta"
-            })
+            .filter(|e| e.event == "content_block_delta" && e.data["delta"]["type"] == "text_delta")
             .collect();
 
         let full_text: String = text_deltas
@@ -1696,9 +1898,7 @@ mod tests {
     fn collect_text_content(events: &[SseEvent]) -> String {
         events
             .iter()
-            .filter(|e| {
-                e.event == "content_block_delta" && e.data["delta"]["type"] == "text_delta"
-            })
+            .filter(|e| e.event == "content_block_delta" && e.data["delta"]["type"] == "text_delta")
             .map(|e| e.data["delta"]["text"].as_str().unwrap_or(""))
             .collect()
     }
@@ -1717,7 +1917,11 @@ mod tests {
         all.extend(ctx.generate_final_events());
 
         let thinking = collect_thinking_content(&all);
-        assert_eq!(thinking, "abc", "thinking should be 'abc', got: {:?}", thinking);
+        assert_eq!(
+            thinking, "abc",
+            "thinking should be 'abc', got: {:?}",
+            thinking
+        );
 
         let text = collect_text_content(&all);
         assert_eq!(text, "你好", "text should be '你好', got: {:?}", text);
@@ -1735,7 +1939,11 @@ mod tests {
         all.extend(ctx.generate_final_events());
 
         let thinking = collect_thinking_content(&all);
-        assert_eq!(thinking, "abc", "thinking should be 'abc', got: {:?}", thinking);
+        assert_eq!(
+            thinking, "abc",
+            "thinking should be 'abc', got: {:?}",
+            thinking
+        );
 
         let text = collect_text_content(&all);
         assert_eq!(text, "你好", "text should be '你好', got: {:?}", text);
@@ -1755,7 +1963,11 @@ mod tests {
         all.extend(ctx.generate_final_events());
 
         let thinking = collect_thinking_content(&all);
-        assert_eq!(thinking, "abc", "thinking should be 'abc', got: {:?}", thinking);
+        assert_eq!(
+            thinking, "abc",
+            "thinking should be 'abc', got: {:?}",
+            thinking
+        );
 
         let text = collect_text_content(&all);
         assert_eq!(text, "text", "text should be 'text', got: {:?}", text);
@@ -1784,7 +1996,11 @@ mod tests {
         all.extend(ctx.generate_final_events());
 
         let thinking = collect_thinking_content(&all);
-        assert_eq!(thinking, "hello", "thinking should be 'hello', got: {:?}", thinking);
+        assert_eq!(
+            thinking, "hello",
+            "thinking should be 'hello', got: {:?}",
+            thinking
+        );
 
         let text = collect_text_content(&all);
         assert_eq!(text, "world", "text should be 'world', got: {:?}", text);
@@ -1874,12 +2090,14 @@ mod tests {
 
         let mut all_events = Vec::new();
         all_events.extend(ctx.process_assistant_response("<thinking>\nabc</thinking>"));
-        all_events.extend(ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
-            name: "test_tool".to_string(),
-            tool_use_id: "tool_1".to_string(),
-            input: "{}".to_string(),
-            stop: true,
-        }));
+        all_events.extend(
+            ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+                name: "test_tool".to_string(),
+                tool_use_id: "tool_1".to_string(),
+                input: "{}".to_string(),
+                stop: true,
+            }),
+        );
         all_events.extend(ctx.generate_final_events());
 
         let message_delta = all_events
@@ -1892,4 +2110,212 @@ mod tests {
             "stop_reason should be tool_use when tool_use is present"
         );
     }
+
+    #[test]
+    fn test_forced_tool_choice_rejects_other_tool() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_tool_choice(
+            Some(ToolChoice::Tool {
+                name: "extract".to_string(),
+                disable_parallel_tool_use: false,
+            }),
+        );
+        let _initial_events = ctx.generate_initial_events();
+
+        let events = ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+            name: "search".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: "{}".to_string(),
+            stop: true,
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "error");
+        assert_eq!(events[0].data["error"]["type"], "api_error");
+        assert!(ctx.tool_choice_violation().is_some());
+
+        // 中止后不再生成任何事件
+        let more =
+            Event::AssistantResponse(serde_json::from_value(json!({"content": "more"})).unwrap());
+        assert!(ctx.process_kiro_event(&more).is_empty());
+        assert!(ctx.generate_final_events().is_empty());
+    }
+
+    #[test]
+    fn test_required_tool_choice_without_tool_use_ends_with_error() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_tool_choice(
+            Some(ToolChoice::Any {
+                disable_parallel_tool_use: false,
+            }),
+        );
+        let _initial_events = ctx.generate_initial_events();
+        ctx.process_assistant_response("I would rather not.");
+
+        let events = ctx.generate_final_events();
+        assert_eq!(events.last().unwrap().event, "error");
+        assert!(!events.iter().any(|e| e.event == "message_delta"));
+    }
+
+    #[test]
+    fn test_disable_parallel_tool_use_drops_extra_calls() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_tool_choice(
+            Some(ToolChoice::Auto {
+                disable_parallel_tool_use: true,
+            }),
+        );
+        let _initial_events = ctx.generate_initial_events();
+
+        for id in ["tool_1", "tool_2"] {
+            ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+                name: "get_weather".to_string(),
+                tool_use_id: id.to_string(),
+                input: "{}".to_string(),
+                stop: true,
+            });
+        }
+        let events = ctx.generate_final_events();
+
+        assert_eq!(ctx.tool_block_indices.len(), 1);
+        assert!(ctx.tool_block_indices.contains_key("tool_1"));
+        assert_eq!(events.last().unwrap().event, "message_stop");
+    }
+
+    #[test]
+    fn test_stop_sequence_truncates_text_and_sets_stop_reason() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_stop_sequences(Some(vec!["###".to_string()]));
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = Vec::new();
+        for chunk in ["Answer: 42 #", "## trailing", " ignored"] {
+            let event = Event::AssistantResponse(
+                serde_json::from_value(json!({"content": chunk})).unwrap(),
+            );
+            all_events.extend(ctx.process_kiro_event(&event));
+        }
+        assert!(ctx.should_stop());
+        all_events.extend(ctx.generate_final_events());
+
+        assert_eq!(collect_text_content(&all_events), "Answer: 42 ");
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "stop_sequence");
+        assert_eq!(message_delta.data["delta"]["stop_sequence"], "###");
+    }
+
+    #[test]
+    fn test_context_usage_processed_after_stop() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_max_tokens(5);
+        let _initial_events = ctx.generate_initial_events();
+
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(5);
+        let event =
+            Event::AssistantResponse(serde_json::from_value(json!({"content": text})).unwrap());
+        ctx.process_kiro_event(&event);
+        assert!(ctx.should_stop());
+
+        // 停止后的内容事件被忽略，上下文使用率事件仍然更新 input_tokens
+        let more =
+            Event::AssistantResponse(serde_json::from_value(json!({"content": "more"})).unwrap());
+        assert!(ctx.process_kiro_event(&more).is_empty());
+        let usage = Event::ContextUsage(
+            serde_json::from_value(json!({"contextUsagePercentage": 1.0})).unwrap(),
+        );
+        assert!(ctx.process_kiro_event(&usage).is_empty());
+        assert_eq!(ctx.context_input_tokens, Some(ctx.context_window / 100));
+        assert_eq!(ctx.final_usage().1, 5);
+    }
+
+    #[test]
+    fn test_stop_sequence_holdback_flushed_before_tool_use() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_stop_sequences(Some(vec!["STOP".to_string()]));
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = ctx.process_assistant_response("Calling ST");
+        all_events.extend(
+            ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+                name: "test_tool".to_string(),
+                tool_use_id: "tool_1".to_string(),
+                input: "{}".to_string(),
+                stop: true,
+            }),
+        );
+        all_events.extend(ctx.generate_final_events());
+
+        assert_eq!(collect_text_content(&all_events), "Calling ST");
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "tool_use");
+        assert!(message_delta.data["delta"]["stop_sequence"].is_null());
+    }
+
+    #[test]
+    fn test_max_tokens_truncates_text() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_max_tokens(5);
+        let _initial_events = ctx.generate_initial_events();
+
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(5);
+        let event =
+            Event::AssistantResponse(serde_json::from_value(json!({"content": text})).unwrap());
+        let mut all_events = ctx.process_kiro_event(&event);
+        assert!(ctx.should_stop());
+        all_events.extend(ctx.generate_final_events());
+
+        let output = collect_text_content(&all_events);
+        assert!(!output.is_empty() && output.len() < text.len());
+        assert_eq!(ctx.final_usage().1, 5);
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "max_tokens");
+    }
+
+    #[test]
+    fn test_max_tokens_closes_truncated_tool_input() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_max_tokens(30);
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = Vec::new();
+        for (input, stop) in [
+            (r#"{"path": "/tmp/notes.txt", "#, false),
+            (
+                &*format!(r#""content": "{}"#, "lorem ipsum ".repeat(40)),
+                false,
+            ),
+            (r#""}"#, true),
+        ] {
+            all_events.extend(ctx.process_kiro_event(&Event::ToolUse(
+                crate::kiro::model::events::ToolUseEvent {
+                    name: "Write".to_string(),
+                    tool_use_id: "tool_1".to_string(),
+                    input: input.to_string(),
+                    stop,
+                },
+            )));
+        }
+        all_events.extend(ctx.generate_final_events());
+
+        let input: String = all_events
+            .iter()
+            .filter(|e| e.data["delta"]["type"] == "input_json_delta")
+            .map(|e| e.data["delta"]["partial_json"].as_str().unwrap())
+            .collect();
+        let input: serde_json::Value = serde_json::from_str(&input).unwrap();
+        assert_eq!(input, json!({"path": "/tmp/notes.txt"}));
+
+        let stops = all_events
+            .iter()
+            .filter(|e| e.event == "content_block_stop")
+            .count();
+        assert_eq!(stops, 2, "text block and tool block should both be closed");
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "max_tokens");
+    }
 }
@@ -0,0 +1,172 @@
+//! tool_choice 工具调用校验
+//!
+//! converter 已按 `tool_choice` 裁剪工具列表并追加系统指令，但 Kiro 并不保证遵守。
+//! 这里对上游返回的 `ToolUseEvent` 逐个校验：
+//! - `none`：出现任何工具调用即违规
+//! - `tool`：调用了其他工具即违规
+//! - `any` / `tool`：正常结束（`end_turn`）却没有任何工具调用即违规
+//! - `disable_parallel_tool_use`：第一个之后的工具调用被丢弃
+
+use std::collections::HashSet;
+
+use super::types::ToolChoice;
+
+/// 单个工具调用的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ToolUseDecision {
+    /// 正常输出
+    Accept,
+    /// 丢弃（超出并行调用限制）
+    Skip,
+    /// 违反 tool_choice，应中止响应
+    Reject,
+}
+
+/// 单个请求的工具调用校验器
+#[derive(Debug, Default)]
+pub(crate) struct ToolChoiceGuard {
+    choice: Option<ToolChoice>,
+    accepted: HashSet<String>,
+    skipped: HashSet<String>,
+    violation: Option<String>,
+}
+
+impl ToolChoiceGuard {
+    pub(crate) fn new(choice: Option<ToolChoice>) -> Self {
+        Self {
+            choice,
+            ..Self::default()
+        }
+    }
+
+    /// 校验一个工具调用事件
+    ///
+    /// 同一个 `tool_use_id` 的增量事件沿用首次判定的结果
+    pub(crate) fn on_tool_use(&mut self, tool_use_id: &str, name: &str) -> ToolUseDecision {
+        if self.violation.is_some() {
+            return ToolUseDecision::Reject;
+        }
+        if self.accepted.contains(tool_use_id) {
+            return ToolUseDecision::Accept;
+        }
+        if self.skipped.contains(tool_use_id) {
+            return ToolUseDecision::Skip;
+        }
+
+        match &self.choice {
+            Some(ToolChoice::None) => {
+                self.violation = Some(format!(
+                    "tool_choice 为 none，但模型调用了工具 {}",
+                    name
+                ));
+                return ToolUseDecision::Reject;
+            }
+            Some(ToolChoice::Tool { name: expected, .. }) if expected != name => {
+                self.violation = Some(format!(
+                    "tool_choice 要求调用工具 {}，但模型调用了工具 {}",
+                    expected, name
+                ));
+                return ToolUseDecision::Reject;
+            }
+            _ => {}
+        }
+
+        let disable_parallel = self
+            .choice
+            .as_ref()
+            .is_some_and(ToolChoice::disable_parallel_tool_use);
+        if disable_parallel && !self.accepted.is_empty() {
+            tracing::warn!(
+                tool_use_id = %tool_use_id,
+                tool = %name,
+                "disable_parallel_tool_use 已启用，丢弃多余的工具调用"
+            );
+            self.skipped.insert(tool_use_id.to_string());
+            return ToolUseDecision::Skip;
+        }
+
+        self.accepted.insert(tool_use_id.to_string());
+        ToolUseDecision::Accept
+    }
+
+    /// 响应结束时校验是否满足强制调用要求，返回违规信息
+    ///
+    /// 只在 `end_turn` 时检查：因 max_tokens 等原因截断的响应不视为违规
+    pub(crate) fn finish(&mut self, stop_reason: &str) -> Option<&str> {
+        let requires_tool_use = matches!(
+            self.choice,
+            Some(ToolChoice::Any { .. } | ToolChoice::Tool { .. })
+        );
+        if self.violation.is_none()
+            && requires_tool_use
+            && self.accepted.is_empty()
+            && stop_reason == "end_turn"
+        {
+            self.violation = Some("tool_choice 要求调用工具，但模型未调用任何工具".to_string());
+        }
+        self.violation.as_deref()
+    }
+
+    /// 已发生的违规信息
+    pub(crate) fn violation(&self) -> Option<&str> {
+        self.violation.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forced(name: &str, disable_parallel_tool_use: bool) -> ToolChoiceGuard {
+        ToolChoiceGuard::new(Some(ToolChoice::Tool {
+            name: name.to_string(),
+            disable_parallel_tool_use,
+        }))
+    }
+
+    #[test]
+    fn test_unconstrained_accepts_everything() {
+        let mut guard = ToolChoiceGuard::new(None);
+        assert_eq!(guard.on_tool_use("a", "x"), ToolUseDecision::Accept);
+        assert_eq!(guard.on_tool_use("b", "y"), ToolUseDecision::Accept);
+        assert_eq!(guard.finish("tool_use"), None);
+    }
+
+    #[test]
+    fn test_forced_tool_rejects_other_tools() {
+        let mut guard = forced("extract", false);
+        assert_eq!(guard.on_tool_use("a", "extract"), ToolUseDecision::Accept);
+        assert_eq!(guard.on_tool_use("a", "extract"), ToolUseDecision::Accept);
+        assert_eq!(guard.on_tool_use("b", "search"), ToolUseDecision::Reject);
+        assert!(guard.violation().unwrap().contains("search"));
+    }
+
+    #[test]
+    fn test_required_tool_use_missing() {
+        let mut guard = ToolChoiceGuard::new(Some(ToolChoice::Any {
+            disable_parallel_tool_use: false,
+        }));
+        assert!(guard.finish("end_turn").is_some());
+
+        // 截断的响应不视为违规
+        let mut guard = forced("extract", false);
+        assert_eq!(guard.finish("max_tokens"), None);
+    }
+
+    #[test]
+    fn test_none_rejects_any_tool_use() {
+        let mut guard = ToolChoiceGuard::new(Some(ToolChoice::None));
+        assert_eq!(guard.on_tool_use("a", "x"), ToolUseDecision::Reject);
+        assert!(guard.finish("tool_use").is_some());
+    }
+
+    #[test]
+    fn test_disable_parallel_skips_extra_calls() {
+        let mut guard = forced("extract", true);
+        assert_eq!(guard.on_tool_use("a", "extract"), ToolUseDecision::Accept);
+        assert_eq!(guard.on_tool_use("b", "extract"), ToolUseDecision::Skip);
+        assert_eq!(guard.on_tool_use("b", "extract"), ToolUseDecision::Skip);
+        assert_eq!(guard.on_tool_use("a", "extract"), ToolUseDecision::Accept);
+        assert_eq!(guard.finish("tool_use"), None);
+    }
+}
@@ -111,8 +111,51 @@ pub struct Metadata {
     pub user_id: Option<String>,
 }
 
+/// 工具选择策略（`tool_choice`）
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// 由模型决定是否调用工具
+    Auto {
+        #[serde(default)]
+        disable_parallel_tool_use: bool,
+    },
+    /// 必须调用至少一个工具
+    Any {
+        #[serde(default)]
+        disable_parallel_tool_use: bool,
+    },
+    /// 必须调用指定的工具
+    Tool {
+        name: String,
+        #[serde(default)]
+        disable_parallel_tool_use: bool,
+    },
+    /// 禁止调用工具
+    None,
+}
+
+impl ToolChoice {
+    /// 是否限制为最多一次工具调用
+    pub fn disable_parallel_tool_use(&self) -> bool {
+        match self {
+            ToolChoice::Auto {
+                disable_parallel_tool_use,
+            }
+            | ToolChoice::Any {
+                disable_parallel_tool_use,
+            }
+            | ToolChoice::Tool {
+                disable_parallel_tool_use,
+                ..
+            } => *disable_parallel_tool_use,
+            ToolChoice::None => false,
+        }
+    }
+}
+
 /// Messages 请求体
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MessagesRequest {
     pub model: String,
     pub max_tokens: i32,
@@ -122,7 +165,13 @@ pub struct MessagesRequest {
     #[serde(default, deserialize_with = "deserialize_system")]
     pub system: Option<Vec<SystemMessage>>,
     pub tools: Option<Vec<Tool>>,
-    pub tool_choice: Option<serde_json::Value>,
+    pub tool_choice: Option<ToolChoice>,
+    /// 自定义停止序列（Kiro 不支持，由代理在输出流中模拟）
+    pub stop_sequences: Option<Vec<String>>,
+    /// 采样参数（Kiro 不支持，仅接收）
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
     pub thinking: Option<Thinking>,
     pub output_config: Option<OutputConfig>,
     /// Claude Code 请求中的 metadata，包含 session 信息
@@ -152,6 +201,7 @@ where
         {
             Ok(Some(vec![SystemMessage {
                 text: value.to_string(),
+                cache_control: None,
             }]))
         }
 
@@ -196,10 +246,23 @@ pub struct Message {
     pub content: serde_json::Value,
 }
 
+/// Prompt caching 断点标记
+///
+/// 如 `{"type": "ephemeral", "ttl": "1h"}`，`ttl` 可选 `5m`（默认）或 `1h`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub cache_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+}
+
 /// 系统消息
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SystemMessage {
     pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 /// 工具定义
@@ -207,6 +270,7 @@ pub struct SystemMessage {
 /// 支持两种格式：
 /// 1. 普通工具：{ name, description, input_schema }
 /// 2. WebSearch 工具：{ type: "web_search_20250305", name: "web_search", max_uses: 8 }
+/// 3. WebFetch 工具：{ type: "web_fetch_20250910", name: "web_fetch", max_uses: 5 }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Tool {
     /// 工具类型，如 "web_search_20250305"（可选，仅 WebSearch 工具）
@@ -224,6 +288,49 @@ pub struct Tool {
     /// 最大使用次数（仅 WebSearch 工具）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_uses: Option<i32>,
+    /// 只保留这些域名的搜索结果（仅 WebSearch 工具）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_domains: Option<Vec<String>>,
+    /// 排除这些域名的搜索结果（仅 WebSearch 工具）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_domains: Option<Vec<String>>,
+    /// 用户大致位置，用于本地化搜索（仅 WebSearch 工具）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_location: Option<UserLocation>,
+    /// 单个页面写回对话的最大 token 数（仅 WebFetch 工具）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_content_tokens: Option<i32>,
+    /// 抓取结果是否启用引用（仅 WebFetch 工具）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citations: Option<CitationsConfig>,
+    /// Prompt caching 断点
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+/// WebFetch 工具的引用配置
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CitationsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// WebSearch 工具的用户位置
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct UserLocation {
+    /// 位置类型，目前只有 "approximate"
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub location_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// ISO 3166-1 alpha-2 国家代码
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// IANA 时区，如 "America/New_York"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
 }
 
 impl Tool {
@@ -233,6 +340,13 @@ impl Tool {
             .as_ref()
             .is_some_and(|t| t.starts_with("web_search"))
     }
+
+    /// 检查是否为 WebFetch 工具
+    pub fn is_web_fetch(&self) -> bool {
+        self.tool_type
+            .as_ref()
+            .is_some_and(|t| t.starts_with("web_fetch"))
+    }
 }
 
 /// 内容块
@@ -258,15 +372,34 @@ pub struct ContentBlock {
     pub is_error: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<ImageSource>,
+    /// 文档标题（仅 document 块）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// 文档上下文说明（仅 document 块）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
-/// 图片数据源
+/// 图片/文档数据源
+///
+/// - `base64`：`media_type` + `data`
+/// - `text`：纯文本文档，`data` 为原文
+/// - `content`：`content` 为文本内容块数组（仅 document）
+/// - `url`：`url` 为资源地址，转换前会被下载并改写为 `base64`/`text`
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ImageSource {
     #[serde(rename = "type")]
     pub source_type: String,
+    #[serde(default)]
     pub media_type: String,
+    #[serde(default)]
     pub data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<serde_json::Value>,
 }
 
 // === Count Tokens 端点类型 ===
@@ -0,0 +1,154 @@
+//! 客户端 API Key 准入、用量归属与请求账本
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Utc;
+
+use crate::apikey::{ApiKeyRejection, ApiKeyStore, ClientKey};
+use crate::kiro::provider::KiroProvider;
+use crate::ledger::{LedgerEntry, UsageLedger};
+use crate::metrics;
+
+use super::middleware::AppState;
+use super::types::ErrorResponse;
+
+/// 请求用量上报器
+///
+/// 由 [`UsageReporter::admit`] 在请求开始时创建，请求完成后通过 [`UsageReporter::report`]
+/// 把 token 使用量记到对应的客户端 API Key 上（使用主 API Key 的请求不做归属），
+/// 计入 `/metrics` 的 token 指标，并向用量账本写入一条请求记录
+#[derive(Clone)]
+pub(crate) struct UsageReporter {
+    api_key: Option<(Arc<ApiKeyStore>, u64)>,
+    client: Option<ClientKey>,
+    ledger: Option<Arc<UsageLedger>>,
+    model: String,
+    /// 指标使用的模型标签
+    model_label: String,
+    stream: bool,
+    credential_id: Option<u64>,
+    started_at: Instant,
+}
+
+impl UsageReporter {
+    /// 准入检查：校验客户端 Key 的模型权限与配额，并计入一次请求
+    ///
+    /// 失败时可通过 [`rejection_response`] 转换为错误响应
+    pub(crate) fn admit(
+        state: &AppState,
+        client: Option<&ClientKey>,
+        model: &str,
+        stream: bool,
+    ) -> Result<Self, ApiKeyRejection> {
+        let model_label = metrics::model_label(model);
+        metrics::set_request_model(&model_label);
+
+        let api_key = match (client, &state.api_keys) {
+            (Some(client), Some(store)) => {
+                if let Err(e) = store.admit(client.id, model) {
+                    tracing::warn!(api_key_id = client.id, api_key_name = %client.name, "客户端请求被拒绝: {}", e);
+                    return Err(e);
+                }
+                Some((store.clone(), client.id))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            api_key,
+            client: client.cloned(),
+            ledger: state.usage_ledger.clone(),
+            model: model.to_string(),
+            model_label,
+            stream,
+            credential_id: None,
+            started_at: Instant::now(),
+        })
+    }
+
+    /// 记录处理该请求的上游凭据
+    pub(crate) fn set_upstream(&mut self, response: &reqwest::Response) {
+        self.credential_id = KiroProvider::credential_id(response);
+    }
+
+    /// 上报请求完成后的 token 使用量
+    pub(crate) fn report(&self, input_tokens: i32, output_tokens: i32, stop_reason: &str) {
+        self.record_tokens(input_tokens, output_tokens);
+        self.record(input_tokens, output_tokens, Some(stop_reason), None);
+    }
+
+    /// 上报中途失败的请求（如上游流读取失败），已产生的 token 仍计入用量
+    pub(crate) fn report_failure(&self, error_type: &str, input_tokens: i32, output_tokens: i32) {
+        self.record_tokens(input_tokens, output_tokens);
+        self.record(input_tokens, output_tokens, None, Some(error_type));
+    }
+
+    /// 记录以错误响应结束的请求，并原样返回该响应
+    ///
+    /// 错误类型按响应状态码推断
+    pub(crate) fn fail(&self, response: Response) -> Response {
+        let error_type = match response.status() {
+            StatusCode::BAD_REQUEST => "invalid_request_error",
+            StatusCode::UNAUTHORIZED => "authentication_error",
+            StatusCode::FORBIDDEN => "permission_error",
+            StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+            _ => "api_error",
+        };
+        self.record(0, 0, None, Some(error_type));
+        response
+    }
+
+    fn record_tokens(&self, input_tokens: i32, output_tokens: i32) {
+        if let Some((store, id)) = &self.api_key {
+            store.record_tokens(*id, input_tokens.max(0) as u64, output_tokens.max(0) as u64);
+        }
+        metrics::record_tokens(&self.model_label, input_tokens, output_tokens);
+    }
+
+    /// 写入用量账本
+    fn record(
+        &self,
+        input_tokens: i32,
+        output_tokens: i32,
+        stop_reason: Option<&str>,
+        error_type: Option<&str>,
+    ) {
+        let Some(ledger) = &self.ledger else {
+            return;
+        };
+        ledger.record(&LedgerEntry {
+            timestamp: Utc::now(),
+            api_key_id: self.client.as_ref().map(|c| c.id),
+            api_key_name: self.client.as_ref().map(|c| c.name.clone()),
+            model: self.model.clone(),
+            credential_id: self.credential_id,
+            stream: self.stream,
+            input_tokens: input_tokens.max(0) as u64,
+            output_tokens: output_tokens.max(0) as u64,
+            stop_reason: stop_reason.map(str::to_string),
+            latency_ms: self.started_at.elapsed().as_millis() as u64,
+            error_type: error_type.map(str::to_string),
+        });
+    }
+}
+
+/// 将拒绝原因映射为 HTTP 响应
+pub(crate) fn rejection_response(rejection: &ApiKeyRejection) -> Response {
+    let (status, error_type) = match rejection {
+        ApiKeyRejection::Invalid | ApiKeyRejection::Disabled | ApiKeyRejection::Expired => {
+            (StatusCode::UNAUTHORIZED, "authentication_error")
+        }
+        ApiKeyRejection::ModelNotAllowed { .. } => (StatusCode::FORBIDDEN, "permission_error"),
+        ApiKeyRejection::QuotaExceeded(_) => (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error"),
+    };
+    (
+        status,
+        Json(ErrorResponse::new(error_type, rejection.to_string())),
+    )
+        .into_response()
+}
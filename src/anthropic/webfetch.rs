@@ -0,0 +1,497 @@
+//! WebFetch 工具处理模块
+//!
+//! 服务端 `web_fetch` 工具的抓取实现，由 [`super::websearch`] 的服务端工具循环调用：
+//! 模型通过 tool_use 给出 URL，这里下载页面并转为可读文本（HTML 转为 Markdown 风格的纯文本，
+//! PDF 提取文本），按 `max_content_tokens` 截断后以 `web_fetch_tool_result` 块返回给客户端，
+//! 同时作为 tool_result 写回对话。
+//!
+//! `allowed_domains` / `blocked_domains` 与 web_search 使用相同的域名规则，下载使用全局代理。
+//! 只允许访问公网地址，重定向的每一跳都会重新检查域名规则与目标地址。
+
+use std::sync::{Arc, LazyLock};
+
+use parking_lot::RwLock;
+use serde_json::json;
+
+use crate::common::io::blocking_io;
+use crate::http_client::{ProxyConfig, PublicUrlError, build_public_client, get_public_url};
+use crate::model::config::{TlsBackend, WebFetchConfig};
+use crate::search::DomainFilter;
+use crate::token;
+
+use super::document::{extract_pdf_pages, is_text_media_type, sniff_media_type};
+use super::types::Tool;
+use super::websearch::DEFAULT_MAX_USES;
+
+/// 暴露给模型的 web_fetch 工具描述
+const WEB_FETCH_TOOL_DESCRIPTION: &str = "Fetch the full content of a web page or PDF at the given URL and return it as readable text. Use it to read pages found by web search or URLs provided by the user, then answer based on the content and cite the URL.";
+
+/// URL 最大长度（与官方 API 一致）
+const MAX_URL_LENGTH: usize = 250;
+
+/// HTML 转文本时的行宽
+const TEXT_WIDTH: usize = 120;
+
+/// web_fetch 处理配置
+#[derive(Clone, Default)]
+pub struct WebFetchOptions {
+    /// 抓取限制
+    pub config: WebFetchConfig,
+    /// 下载使用的代理
+    pub proxy: Option<ProxyConfig>,
+    /// TLS 后端
+    pub tls_backend: TlsBackend,
+}
+
+static WEB_FETCH_OPTIONS: LazyLock<RwLock<Arc<WebFetchOptions>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(WebFetchOptions::default())));
+
+/// 初始化（或替换）web_fetch 配置
+///
+/// 应在应用启动和配置热重载时调用，未调用时使用默认限制
+pub fn init_config(options: WebFetchOptions) {
+    *WEB_FETCH_OPTIONS.write() = Arc::new(options);
+}
+
+fn options() -> Arc<WebFetchOptions> {
+    WEB_FETCH_OPTIONS.read().clone()
+}
+
+/// 模型看到的 web_fetch 工具（普通工具形式）
+pub(super) fn web_fetch_tool() -> Tool {
+    let input_schema = json!({
+        "type": "object",
+        "properties": {
+            "url": {"type": "string", "description": "The absolute http(s) URL to fetch"}
+        },
+        "required": ["url"]
+    });
+    Tool {
+        tool_type: None,
+        name: "web_fetch".to_string(),
+        description: WEB_FETCH_TOOL_DESCRIPTION.to_string(),
+        input_schema: serde_json::from_value(input_schema).unwrap_or_default(),
+        max_uses: None,
+        allowed_domains: None,
+        blocked_domains: None,
+        user_location: None,
+        max_content_tokens: None,
+        citations: None,
+        cache_control: None,
+    }
+}
+
+/// 请求中 web_fetch 工具的参数
+pub(super) struct WebFetchTool {
+    pub(super) max_uses: u32,
+    filter: DomainFilter,
+    max_content_tokens: usize,
+    citations: bool,
+}
+
+impl WebFetchTool {
+    pub(super) fn from_tool(tool: &Tool) -> Self {
+        Self {
+            max_uses: tool
+                .max_uses
+                .filter(|&n| n > 0)
+                .map(|n| n as u32)
+                .unwrap_or(DEFAULT_MAX_USES),
+            filter: DomainFilter::new(
+                tool.allowed_domains.as_deref(),
+                tool.blocked_domains.as_deref(),
+            ),
+            max_content_tokens: tool
+                .max_content_tokens
+                .filter(|&n| n > 0)
+                .map(|n| n as usize)
+                .unwrap_or(options().config.max_content_tokens),
+            citations: tool.citations.as_ref().is_some_and(|c| c.enabled),
+        }
+    }
+
+    /// 抓取 URL，所有失败都转换为官方 API 的错误码
+    pub(super) async fn fetch(&self, url: &str) -> FetchOutcome {
+        if url.len() > MAX_URL_LENGTH {
+            return FetchOutcome::Error("url_too_long");
+        }
+        let Ok(parsed) = reqwest::Url::parse(url) else {
+            return FetchOutcome::Error("invalid_input");
+        };
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return FetchOutcome::Error("invalid_input");
+        }
+
+        let options = options();
+        let client = match build_public_client(
+            options.proxy.as_ref(),
+            options.config.timeout_secs,
+            options.tls_backend,
+        ) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建 HTTP Client 失败: {}", e);
+                return FetchOutcome::Error("unavailable");
+            }
+        };
+
+        let (media_type, bytes) =
+            match download(&client, parsed, &self.filter, options.config.max_bytes).await {
+                Ok(resource) => resource,
+                Err(code) => return FetchOutcome::Error(code),
+            };
+
+        let Some((title, text)) = extract_text(&media_type, &bytes) else {
+            tracing::debug!(url = %url, media_type = %media_type, "web_fetch 不支持的内容类型");
+            return FetchOutcome::Error("unsupported_content_type");
+        };
+        let (text, truncated) = truncate_to_tokens(text, self.max_content_tokens);
+
+        FetchOutcome::Document(FetchedDocument {
+            url: url.to_string(),
+            title,
+            text,
+            truncated,
+            retrieved_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        })
+    }
+
+    /// 输出给客户端的 `web_fetch_tool_result` 块
+    pub(super) fn result_block(
+        &self,
+        tool_use_id: &str,
+        outcome: &FetchOutcome,
+    ) -> serde_json::Value {
+        let content = match outcome {
+            FetchOutcome::Document(doc) => {
+                let mut document = json!({
+                    "type": "document",
+                    "source": {"type": "text", "media_type": "text/plain", "data": doc.text}
+                });
+                if let Some(title) = &doc.title {
+                    document["title"] = json!(title);
+                }
+                if self.citations {
+                    document["citations"] = json!({"enabled": true});
+                }
+                json!({
+                    "type": "web_fetch_result",
+                    "url": doc.url,
+                    "content": document,
+                    "retrieved_at": doc.retrieved_at
+                })
+            }
+            FetchOutcome::Error(code) => json!({
+                "type": "web_fetch_tool_error",
+                "error_code": code
+            }),
+        };
+        json!({
+            "type": "web_fetch_tool_result",
+            "tool_use_id": tool_use_id,
+            "content": content
+        })
+    }
+}
+
+/// 抓取到的文档
+#[derive(Debug)]
+pub(super) struct FetchedDocument {
+    url: String,
+    title: Option<String>,
+    text: String,
+    /// 是否因 `max_content_tokens` 被截断
+    truncated: bool,
+    retrieved_at: String,
+}
+
+/// 单次抓取的结果
+#[derive(Debug)]
+pub(super) enum FetchOutcome {
+    Document(FetchedDocument),
+    /// 错误码（invalid_input / url_too_long / url_not_allowed / url_not_accessible /
+    /// too_many_requests / unsupported_content_type / max_uses_exceeded / unavailable）
+    Error(&'static str),
+}
+
+impl FetchOutcome {
+    /// 写回对话的 tool_result 块
+    pub(super) fn to_tool_result(&self, tool_use_id: &str) -> serde_json::Value {
+        match self {
+            FetchOutcome::Document(doc) => {
+                let mut text = format!("Content fetched from {}", doc.url);
+                if let Some(title) = &doc.title {
+                    text.push_str(&format!("\nTitle: {}", title));
+                }
+                if doc.truncated {
+                    text.push_str("\n(The content was truncated to fit the length limit.)");
+                }
+                text.push_str("\n\n");
+                text.push_str(&doc.text);
+                json!({"type": "tool_result", "tool_use_id": tool_use_id, "content": text})
+            }
+            FetchOutcome::Error(code) => json!({
+                "type": "tool_result",
+                "tool_use_id": tool_use_id,
+                "content": format!("Web fetch failed: {}", code),
+                "is_error": true
+            }),
+        }
+    }
+}
+
+/// 下载页面，返回 (media_type, 内容)；超过 `max_bytes` 的部分被丢弃
+///
+/// 每一跳重定向都要通过域名规则与公网地址检查
+async fn download(
+    client: &reqwest::Client,
+    url: reqwest::Url,
+    filter: &DomainFilter,
+    max_bytes: usize,
+) -> Result<(String, Vec<u8>), &'static str> {
+    let mut response = get_public_url(
+        client,
+        url.clone(),
+        Some("text/html,application/xhtml+xml,text/plain,application/pdf;q=0.9,*/*;q=0.8"),
+        |hop| filter.is_allowed(hop.as_str()),
+    )
+    .await
+    .map_err(|e| {
+        tracing::debug!(url = %url, "web_fetch 请求失败: {}", e);
+        match e {
+            PublicUrlError::NotAllowed(_) => "url_not_allowed",
+            PublicUrlError::Request(_) => "url_not_accessible",
+        }
+    })?;
+    let status = response.status();
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return Err("too_many_requests");
+    }
+    if !status.is_success() {
+        tracing::debug!(url = %url, status = %status, "web_fetch 返回错误状态");
+        return Err("url_not_accessible");
+    }
+
+    let media_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|_| "url_not_accessible")? {
+        let remaining = max_bytes - bytes.len();
+        if chunk.len() >= remaining {
+            bytes.extend_from_slice(&chunk[..remaining]);
+            tracing::debug!(url = %url, max_bytes, "web_fetch 内容超过上限，已截断");
+            break;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok((media_type, bytes))
+}
+
+/// 按内容类型提取 (标题, 正文)，不支持的类型返回 None
+fn extract_text(media_type: &str, bytes: &[u8]) -> Option<(Option<String>, String)> {
+    if media_type == "application/pdf" || sniff_media_type(bytes) == Some("application/pdf") {
+        let pages = extract_pdf_pages(bytes).ok()?;
+        let text = pages
+            .iter()
+            .map(|page| page.trim())
+            .filter(|page| !page.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        return Some((None, text));
+    }
+    if matches!(media_type, "text/html" | "application/xhtml+xml")
+        || (media_type.is_empty() && looks_like_html(bytes))
+    {
+        let html = String::from_utf8_lossy(bytes);
+        let title = html_title(&html);
+        let text =
+            blocking_io(|| html2text::config::plain().string_from_read(bytes, TEXT_WIDTH)).ok()?;
+        return Some((title, text.trim().to_string()));
+    }
+    if media_type.is_empty() || is_text_media_type(media_type) {
+        return Some((None, String::from_utf8_lossy(bytes).into_owned()));
+    }
+    None
+}
+
+fn looks_like_html(bytes: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]).to_ascii_lowercase();
+    let head = head.trim_start();
+    head.starts_with("<!doctype html") || head.starts_with("<html")
+}
+
+/// 提取 `<title>` 内容
+fn html_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = html[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'");
+    (!title.is_empty()).then_some(title)
+}
+
+/// 按 token 估算截断文本，返回 (文本, 是否截断)
+fn truncate_to_tokens(text: String, max_tokens: usize) -> (String, bool) {
+    let tokens = token::count_tokens(&text) as usize;
+    if tokens <= max_tokens {
+        return (text, false);
+    }
+    // 先按比例估算保留的字符数，再逐步收缩直到不超过上限
+    let mut keep = text.chars().count() * max_tokens / tokens;
+    loop {
+        let cut: String = text.chars().take(keep).collect();
+        if keep == 0 || token::count_tokens(&cut) as usize <= max_tokens {
+            return (cut, true);
+        }
+        keep = keep * 9 / 10;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(value: serde_json::Value) -> WebFetchTool {
+        WebFetchTool::from_tool(&serde_json::from_value(value).unwrap())
+    }
+
+    #[test]
+    fn test_from_tool_defaults_and_params() {
+        let fetch = tool(json!({"type": "web_fetch_20250910", "name": "web_fetch"}));
+        assert_eq!(fetch.max_uses, DEFAULT_MAX_USES);
+        assert_eq!(fetch.max_content_tokens, 50_000);
+        assert!(!fetch.citations);
+
+        let fetch = tool(json!({
+            "type": "web_fetch_20250910",
+            "name": "web_fetch",
+            "max_uses": 2,
+            "max_content_tokens": 1000,
+            "citations": {"enabled": true},
+            "blocked_domains": ["private.example.com"]
+        }));
+        assert_eq!(fetch.max_uses, 2);
+        assert_eq!(fetch.max_content_tokens, 1000);
+        assert!(fetch.citations);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_invalid_urls_without_network() {
+        let fetch = tool(json!({
+            "type": "web_fetch_20250910",
+            "name": "web_fetch",
+            "allowed_domains": ["docs.rs"]
+        }));
+
+        let long = format!("https://docs.rs/{}", "a".repeat(MAX_URL_LENGTH));
+        for (url, code) in [
+            ("not a url", "invalid_input"),
+            ("ftp://docs.rs/file", "invalid_input"),
+            ("https://example.com/", "url_not_allowed"),
+            (long.as_str(), "url_too_long"),
+        ] {
+            match fetch.fetch(url).await {
+                FetchOutcome::Error(actual) => assert_eq!(actual, code, "{}", url),
+                other => panic!("unexpected outcome for {}: {:?}", url, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_internal_addresses() {
+        let fetch = tool(json!({"type": "web_fetch_20250910", "name": "web_fetch"}));
+        for url in [
+            "http://127.0.0.1:8990/api/admin/config",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/",
+            "http://localhost/",
+        ] {
+            match fetch.fetch(url).await {
+                FetchOutcome::Error(actual) => assert_eq!(actual, "url_not_allowed", "{}", url),
+                other => panic!("unexpected outcome for {}: {:?}", url, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_extract_html_text_and_title() {
+        let html = br#"<!DOCTYPE html><html><head><title>
+            Rust &amp; Cargo</title><style>body { color: red }</style></head>
+            <body><h1>Heading</h1><p>Hello <b>world</b></p><script>alert(1)</script></body></html>"#;
+
+        let (title, text) = extract_text("text/html", html).unwrap();
+        assert_eq!(title.as_deref(), Some("Rust & Cargo"));
+        assert!(text.contains("# Heading"));
+        assert!(text.contains("Hello **world**"));
+        assert!(!text.contains("alert"));
+        assert!(!text.contains("color: red"));
+
+        // 未声明类型时根据内容识别
+        assert!(extract_text("", html).unwrap().0.is_some());
+        assert_eq!(extract_text("text/plain", b"plain").unwrap().1, "plain");
+        assert!(extract_text("image/png", b"\x89PNG").is_none());
+    }
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        let text = "word ".repeat(2000);
+        let (same, truncated) = truncate_to_tokens(text.clone(), 1_000_000);
+        assert_eq!(same, text);
+        assert!(!truncated);
+
+        let (cut, truncated) = truncate_to_tokens(text.clone(), 100);
+        assert!(truncated);
+        assert!(token::count_tokens(&cut) <= 100);
+        assert!(text.starts_with(&cut));
+    }
+
+    #[test]
+    fn test_result_blocks() {
+        let fetch = tool(json!({
+            "type": "web_fetch_20250910",
+            "name": "web_fetch",
+            "citations": {"enabled": true}
+        }));
+        let outcome = FetchOutcome::Document(FetchedDocument {
+            url: "https://example.com".to_string(),
+            title: Some("Example".to_string()),
+            text: "Body".to_string(),
+            truncated: true,
+            retrieved_at: "2026-01-01T00:00:00Z".to_string(),
+        });
+
+        let block = fetch.result_block("srvtoolu_1", &outcome);
+        assert_eq!(block["type"], "web_fetch_tool_result");
+        assert_eq!(block["tool_use_id"], "srvtoolu_1");
+        assert_eq!(block["content"]["type"], "web_fetch_result");
+        assert_eq!(block["content"]["content"]["source"]["data"], "Body");
+        assert_eq!(block["content"]["content"]["title"], "Example");
+        assert_eq!(block["content"]["content"]["citations"]["enabled"], true);
+
+        let result = outcome.to_tool_result("tooluse_1");
+        let text = result["content"].as_str().unwrap();
+        assert!(text.contains("Title: Example"));
+        assert!(text.contains("truncated"));
+        assert!(text.ends_with("Body"));
+
+        let error = FetchOutcome::Error("url_not_accessible");
+        let block = fetch.result_block("srvtoolu_2", &error);
+        assert_eq!(block["content"]["type"], "web_fetch_tool_error");
+        assert_eq!(block["content"]["error_code"], "url_not_accessible");
+        assert_eq!(error.to_tool_result("tooluse_2")["is_error"], true);
+    }
+}
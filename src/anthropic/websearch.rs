@@ -1,8 +1,18 @@
 //! WebSearch 工具处理模块
 //!
-//! 实现 Anthropic WebSearch 请求到 Kiro MCP 的转换和响应生成
-
+//! 实现服务端 web_search / web_fetch 工具的 agent loop：
+//! 1. 把服务端工具改写为普通工具交给模型，由模型通过 tool_use 决定搜索词或要抓取的 URL
+//! 2. 每次 web_search 调用通过配置的搜索后端执行搜索（受 `max_uses` 限制，见 [`crate::search`]），
+//!    结果按 `allowed_domains` / `blocked_domains` 过滤后以 `server_tool_use` /
+//!    `web_search_tool_result` 块输出给客户端，同时把结果作为 tool_result 写回对话；
+//!    web_fetch 调用由 [`super::webfetch`] 抓取页面，以 `web_fetch_tool_result` 块输出
+//! 3. 重复直到模型不再调用工具，输出最终回答，usage 中带
+//!    `server_tool_use.web_search_requests` / `web_fetch_requests`
+
+use std::collections::HashMap;
 use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{
     body::Body,
@@ -10,536 +20,1217 @@ use axum::{
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
-use futures::{Stream, stream};
-use serde::{Deserialize, Serialize};
+use futures::{Stream, StreamExt, stream};
 use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::time::interval;
 use uuid::Uuid;
 
-use super::stream::SseEvent;
-use super::types::{ErrorResponse, MessagesRequest};
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::KiroProvider;
+use crate::model::registry;
+use crate::search::{DomainFilter, SearchRequest, WebSearch, WebSearchResult};
+use crate::token;
+
+use super::converter::{RequestOrigin, convert_request};
+use super::handlers::{build_message_body, call_upstream};
+use super::output_budget::OutputBudget;
+use super::stop_sequence::StopSequenceMatcher;
+use super::stream::{
+    SseEvent, find_char_boundary, find_real_thinking_end_tag,
+    find_real_thinking_end_tag_at_buffer_end, find_real_thinking_start_tag,
+};
+use super::types::{ErrorResponse, Message, MessagesRequest, Tool, ToolChoice, UserLocation};
+use super::usage::UsageReporter;
+use super::webfetch::{FetchOutcome, WebFetchTool, web_fetch_tool};
 
-/// MCP 请求
-#[derive(Debug, Serialize)]
-pub struct McpRequest {
-    pub id: String,
-    pub jsonrpc: String,
-    pub method: String,
-    pub params: McpParams,
+/// 检查请求是否只包含服务端工具
+///
+/// 条件：tools 非空，且只包含 name 为 web_search 的工具和 web_fetch 服务端工具（各至多一个）
+pub fn is_server_tool_request(req: &MessagesRequest) -> bool {
+    req.tools.as_ref().is_some_and(|tools| {
+        let search = tools.iter().filter(|t| t.name == "web_search").count();
+        let fetch = tools.iter().filter(|t| t.is_web_fetch()).count();
+        !tools.is_empty() && search <= 1 && fetch <= 1 && search + fetch == tools.len()
+    })
 }
 
-/// MCP 请求参数
-#[derive(Debug, Serialize)]
-pub struct McpParams {
-    pub name: String,
-    pub arguments: McpArguments,
+/// 暴露给模型的 web_search 工具描述
+const WEB_SEARCH_TOOL_DESCRIPTION: &str = "Search the web for up-to-date information. Returns a list of results with title, URL, snippet and publish date. Call it again with a refined query if the results are not sufficient, then answer based on the results and cite the source URLs.";
+
+/// 未指定 `max_uses` 时的默认调用次数上限
+pub(super) const DEFAULT_MAX_USES: u32 = 5;
+
+/// Ping 事件间隔（25秒）
+const PING_INTERVAL_SECS: u64 = 25;
+
+/// 模型看到的 web_search 工具（普通工具形式）
+///
+/// 提供了 `user_location` 时在描述中告知模型用户所在地区
+fn web_search_tool(user_location: Option<&UserLocation>) -> Tool {
+    let input_schema = json!({
+        "type": "object",
+        "properties": {
+            "query": {"type": "string", "description": "The search query"}
+        },
+        "required": ["query"]
+    });
+    Tool {
+        tool_type: None,
+        name: "web_search".to_string(),
+        description: tool_description(user_location),
+        input_schema: serde_json::from_value(input_schema).unwrap_or_default(),
+        max_uses: None,
+        allowed_domains: None,
+        blocked_domains: None,
+        user_location: None,
+        max_content_tokens: None,
+        citations: None,
+        cache_control: None,
+    }
 }
 
-/// MCP 参数
-#[derive(Debug, Serialize)]
-pub struct McpArguments {
-    pub query: String,
+fn tool_description(user_location: Option<&UserLocation>) -> String {
+    let location: Vec<&str> = user_location
+        .map(|loc| {
+            [&loc.city, &loc.region, &loc.country]
+                .into_iter()
+                .filter_map(|part| part.as_deref())
+                .filter(|part| !part.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut description = WEB_SEARCH_TOOL_DESCRIPTION.to_string();
+    if !location.is_empty() {
+        description.push_str(&format!(
+            " The user is located in {}; prefer results relevant to this location when appropriate.",
+            location.join(", ")
+        ));
+    }
+    if let Some(timezone) = user_location.and_then(|loc| loc.timezone.as_deref()) {
+        description.push_str(&format!(" The user's timezone is {}.", timezone));
+    }
+    description
 }
 
-/// MCP 响应
-#[derive(Debug, Deserialize)]
-pub struct McpResponse {
-    pub error: Option<McpError>,
-    pub id: String,
-    pub jsonrpc: String,
-    pub result: Option<McpResult>,
+/// 生成 `server_tool_use` 块的 ID
+fn server_tool_use_id() -> String {
+    format!("srvtoolu_{}", Uuid::new_v4().to_string().replace('-', ""))
 }
 
-/// MCP 错误
-#[derive(Debug, Deserialize)]
-pub struct McpError {
-    pub code: Option<i32>,
-    pub message: Option<String>,
+/// 模型发起的一次工具调用
+#[derive(Debug, Clone)]
+struct TurnToolUse {
+    id: String,
+    name: String,
+    input: serde_json::Value,
 }
 
-/// MCP 结果
-#[derive(Debug, Deserialize)]
-pub struct McpResult {
-    pub content: Vec<McpContent>,
-    #[serde(rename = "isError")]
-    pub is_error: bool,
+/// 单轮上游响应
+#[derive(Debug, Default)]
+struct Turn {
+    /// 本轮输出的 thinking 内容（写回对话用）
+    thinking: String,
+    /// 本轮输出的正文（经过停止序列与 max_tokens 截断，写回对话用）
+    text: String,
+    tool_uses: Vec<TurnToolUse>,
+    tool_buffers: HashMap<String, String>,
+    context_input_tokens: Option<i32>,
+    /// 上游提前结束的原因（max_tokens / model_context_window_exceeded）
+    stop_reason: Option<&'static str>,
 }
 
-/// MCP 内容
-#[derive(Debug, Deserialize)]
-pub struct McpContent {
-    #[serde(rename = "type")]
-    pub content_type: String,
-    pub text: String,
+impl Turn {
+    /// 处理一个上游事件，返回模型输出的原始文本（由 [`LoopOutput`] 处理后输出）
+    fn apply(&mut self, event: Event, context_window: i32) -> Option<String> {
+        match event {
+            Event::AssistantResponse(resp) => {
+                if resp.content.is_empty() {
+                    return None;
+                }
+                return Some(resp.content);
+            }
+            Event::ToolUse(tool_use) => {
+                let buffer = self
+                    .tool_buffers
+                    .entry(tool_use.tool_use_id.clone())
+                    .or_default();
+                buffer.push_str(&tool_use.input);
+                if tool_use.stop {
+                    let input = serde_json::from_str(buffer).unwrap_or_else(|e| {
+                        tracing::warn!(
+                            "工具输入 JSON 解析失败: {}, tool_use_id: {}",
+                            e,
+                            tool_use.tool_use_id
+                        );
+                        json!({})
+                    });
+                    self.tool_uses.push(TurnToolUse {
+                        id: tool_use.tool_use_id,
+                        name: tool_use.name,
+                        input,
+                    });
+                }
+            }
+            Event::ContextUsage(context_usage) => {
+                self.context_input_tokens = Some(
+                    (context_usage.context_usage_percentage * (context_window as f64) / 100.0)
+                        as i32,
+                );
+                if context_usage.context_usage_percentage >= 100.0 {
+                    self.stop_reason = Some("model_context_window_exceeded");
+                }
+            }
+            Event::Exception { exception_type, .. }
+                if exception_type == "ContentLengthExceededException" =>
+            {
+                self.stop_reason = Some("max_tokens");
+            }
+            _ => {}
+        }
+        None
+    }
 }
 
-/// WebSearch 搜索结果
-#[derive(Debug, Deserialize)]
-pub struct WebSearchResults {
-    pub results: Vec<WebSearchResult>,
-    #[serde(rename = "totalResults")]
-    pub total_results: Option<i32>,
-    pub query: Option<String>,
-    pub error: Option<String>,
+/// agent loop 产生的输出
+#[derive(Debug)]
+enum LoopEvent {
+    /// 模型输出的 thinking 增量
+    Thinking(String),
+    /// 当前 thinking 块结束
+    ThinkingEnd,
+    /// 模型输出的文本增量
+    Text(String),
+    /// 发起一次服务端工具调用
+    ServerToolUse {
+        id: String,
+        name: &'static str,
+        input: serde_json::Value,
+    },
+    /// 服务端工具结果块（`web_search_tool_result` / `web_fetch_tool_result`）
+    ToolResult { block: serde_json::Value },
 }
 
-/// 单个搜索结果
-#[derive(Debug, Deserialize, Clone)]
-pub struct WebSearchResult {
-    pub title: String,
-    pub url: String,
-    pub snippet: Option<String>,
-    #[serde(rename = "publishedDate")]
-    pub published_date: Option<i64>,
-    pub id: Option<String>,
-    pub domain: Option<String>,
-    #[serde(rename = "maxVerbatimWordLimit")]
-    pub max_verbatim_word_limit: Option<i32>,
-    #[serde(rename = "publicDomain")]
-    pub public_domain: Option<bool>,
+/// 单轮文本中 thinking 标签的拆分阶段
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum ThinkingPhase {
+    /// 尚未遇到 `<thinking>`
+    #[default]
+    Detecting,
+    /// 位于 thinking 块内
+    Thinking,
+    /// thinking 已结束，剩余内容均为正文
+    Done,
 }
 
-/// 检查请求是否为纯 WebSearch 请求
+/// 把每轮上游文本拆分为 thinking 与正文
 ///
-/// 条件：tools 有且只有一个，且 name 为 web_search
-pub fn has_web_search_tool(req: &MessagesRequest) -> bool {
-    req.tools.as_ref().is_some_and(|tools| {
-        tools.len() == 1 && tools.first().is_some_and(|t| t.name == "web_search")
-    })
+/// 标签识别规则与 [`super::stream::StreamContext`] 一致。每轮结束时重置，
+/// 因此中间轮次（工具调用之前）的 thinking 也会输出为独立的 thinking 块
+#[derive(Debug, Default)]
+struct ThinkingSplitter {
+    enabled: bool,
+    phase: ThinkingPhase,
+    buffer: String,
+    /// 是否需要剥离 `<thinking>` 后紧跟的换行符（可能跨 chunk）
+    strip_leading_newline: bool,
 }
 
-/// 从消息中提取搜索查询
-///
-/// 读取 messages 的第一条消息的第一个内容块
-/// 并去除 "Perform a web search for the query: " 前缀
-pub fn extract_search_query(req: &MessagesRequest) -> Option<String> {
-    // 获取第一条消息
-    let first_msg = req.messages.first()?;
-
-    // 提取文本内容
-    let text = match &first_msg.content {
-        serde_json::Value::String(s) => s.clone(),
-        serde_json::Value::Array(arr) => {
-            // 获取第一个内容块
-            let first_block = arr.first()?;
-            if first_block.get("type")?.as_str()? == "text" {
-                first_block.get("text")?.as_str()?.to_string()
-            } else {
-                return None;
+impl ThinkingSplitter {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+
+    /// 输入一段文本，返回可以立即输出的 thinking / 正文片段
+    fn push(&mut self, text: &str) -> Vec<LoopEvent> {
+        if !self.enabled {
+            return vec![LoopEvent::Text(text.to_string())];
+        }
+        self.buffer.push_str(text);
+
+        let mut events = Vec::new();
+        loop {
+            match self.phase {
+                ThinkingPhase::Detecting => {
+                    let Some(start) = find_real_thinking_start_tag(&self.buffer) else {
+                        // 保留可能是部分 `<thinking>` 标签的尾部，前导空白也暂不输出
+                        let target = self.buffer.len().saturating_sub("<thinking>".len());
+                        let safe_len = find_char_boundary(&self.buffer, target);
+                        if !self.buffer[..safe_len].trim().is_empty() {
+                            events.push(LoopEvent::Text(self.buffer.drain(..safe_len).collect()));
+                        }
+                        break;
+                    };
+                    let before: String = self.buffer.drain(..start).collect();
+                    if !before.trim().is_empty() {
+                        events.push(LoopEvent::Text(before));
+                    }
+                    self.buffer.drain(.."<thinking>".len());
+                    self.phase = ThinkingPhase::Thinking;
+                    self.strip_leading_newline = true;
+                }
+                ThinkingPhase::Thinking => {
+                    if self.strip_leading_newline && !self.buffer.is_empty() {
+                        if self.buffer.starts_with('\n') {
+                            self.buffer.remove(0);
+                        }
+                        self.strip_leading_newline = false;
+                    }
+                    let Some(end) = find_real_thinking_end_tag(&self.buffer) else {
+                        // 保留可能是部分 `</thinking>\n\n` 的尾部
+                        let target = self.buffer.len().saturating_sub("</thinking>\n\n".len());
+                        let safe_len = find_char_boundary(&self.buffer, target);
+                        if safe_len > 0 {
+                            events
+                                .push(LoopEvent::Thinking(self.buffer.drain(..safe_len).collect()));
+                        }
+                        break;
+                    };
+                    let thinking: String = self.buffer.drain(..end).collect();
+                    if !thinking.is_empty() {
+                        events.push(LoopEvent::Thinking(thinking));
+                    }
+                    events.push(LoopEvent::ThinkingEnd);
+                    self.buffer.drain(.."</thinking>\n\n".len());
+                    self.phase = ThinkingPhase::Done;
+                }
+                ThinkingPhase::Done => {
+                    if !self.buffer.is_empty() {
+                        events.push(LoopEvent::Text(std::mem::take(&mut self.buffer)));
+                    }
+                    break;
+                }
             }
         }
-        _ => return None,
-    };
+        events
+    }
 
-    // 去除前缀 "Perform a web search for the query: "
-    const PREFIX: &str = "Perform a web search for the query: ";
-    let query = if text.starts_with(PREFIX) {
-        text[PREFIX.len()..].to_string()
-    } else {
-        text
-    };
+    /// 本轮输出结束（工具调用或响应结束），输出暂存内容并关闭未结束的 thinking 块
+    fn finish(&mut self) -> Vec<LoopEvent> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let phase = std::mem::take(&mut self.phase);
+        let mut events = Vec::new();
+        match phase {
+            ThinkingPhase::Detecting if !buffer.trim().is_empty() => {
+                events.push(LoopEvent::Text(buffer));
+            }
+            ThinkingPhase::Detecting => {}
+            ThinkingPhase::Thinking => {
+                // `</thinking>` 后面没有 `\n\n`（紧跟 tool_use 或流结束）时在这里识别
+                let (thinking, rest) = match find_real_thinking_end_tag_at_buffer_end(&buffer) {
+                    Some(end) => (
+                        &buffer[..end],
+                        buffer[end + "</thinking>".len()..].trim_start(),
+                    ),
+                    None => (buffer.as_str(), ""),
+                };
+                if !thinking.is_empty() {
+                    events.push(LoopEvent::Thinking(thinking.to_string()));
+                }
+                events.push(LoopEvent::ThinkingEnd);
+                if !rest.is_empty() {
+                    events.push(LoopEvent::Text(rest.to_string()));
+                }
+            }
+            ThinkingPhase::Done if !buffer.is_empty() => events.push(LoopEvent::Text(buffer)),
+            ThinkingPhase::Done => {}
+        }
+        self.strip_leading_newline = false;
+        events
+    }
+}
 
-    if query.is_empty() { None } else { Some(query) }
+/// agent loop 的输出处理：thinking 拆分、停止序列与 max_tokens 预算
+///
+/// 与普通请求（[`super::stream::StreamContext`] / 非流式处理）使用相同的匹配器与预算，
+/// 停止序列与预算跨轮次累计
+#[derive(Debug)]
+struct LoopOutput {
+    thinking: ThinkingSplitter,
+    stop_sequences: StopSequenceMatcher,
+    budget: OutputBudget,
 }
 
-/// 生成22位大小写字母和数字的随机字符串
-fn generate_random_id_22() -> String {
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-    (0..22)
-        .map(|_| {
-            let idx = fastrand::usize(..CHARSET.len());
-            CHARSET[idx] as char
-        })
-        .collect()
+impl LoopOutput {
+    fn new(payload: &MessagesRequest) -> Self {
+        let thinking_enabled = payload.thinking.as_ref().is_some_and(|t| t.is_enabled());
+        Self {
+            thinking: ThinkingSplitter::new(thinking_enabled),
+            stop_sequences: StopSequenceMatcher::new(payload.stop_sequences.clone()),
+            budget: OutputBudget::new(Some(payload.max_tokens)),
+        }
+    }
+
+    /// 已命中停止序列或达到 max_tokens，不再读取上游输出
+    fn should_stop(&self) -> bool {
+        self.stop_sequences.matched().is_some() || self.budget.is_exhausted()
+    }
+
+    /// 输出一段模型文本：先计入预算，再拆分 thinking，正文经过停止序列匹配
+    fn text(&mut self, text: &str, turn: &mut Turn, emit: &mut impl FnMut(LoopEvent)) {
+        let text = self.budget.take(text);
+        if text.is_empty() {
+            return;
+        }
+        for event in self.thinking.push(text) {
+            self.emit(event, turn, emit);
+        }
+    }
+
+    /// 计入工具调用参数的 token 数
+    fn tool_use(&mut self, tool_use: &TurnToolUse) {
+        self.budget.take(&tool_use.input.to_string());
+    }
+
+    /// 本轮输出结束：关闭 thinking 并输出停止序列匹配器暂存的文本
+    fn end_turn(&mut self, turn: &mut Turn, emit: &mut impl FnMut(LoopEvent)) {
+        for event in self.thinking.finish() {
+            self.emit(event, turn, emit);
+        }
+        let pending = self.stop_sequences.flush();
+        if !pending.is_empty() {
+            turn.text.push_str(&pending);
+            emit(LoopEvent::Text(pending));
+        }
+    }
+
+    fn emit(&mut self, event: LoopEvent, turn: &mut Turn, emit: &mut impl FnMut(LoopEvent)) {
+        match event {
+            LoopEvent::Text(text) => {
+                let text = self.stop_sequences.push(&text);
+                if let Some(sequence) = self.stop_sequences.matched() {
+                    tracing::debug!(stop_sequence = %sequence, "命中停止序列");
+                }
+                if !text.is_empty() {
+                    turn.text.push_str(&text);
+                    emit(LoopEvent::Text(text));
+                }
+            }
+            LoopEvent::Thinking(thinking) => {
+                turn.thinking.push_str(&thinking);
+                emit(LoopEvent::Thinking(thinking));
+            }
+            event => emit(event),
+        }
+    }
+
+    /// 输出被截断时的 stop_reason（stop_sequence 优先于 max_tokens）
+    fn stop(&self, outcome: &mut LoopOutcome) -> bool {
+        if let Some(sequence) = self.stop_sequences.matched() {
+            outcome.stop_reason = "stop_sequence".to_string();
+            outcome.stop_sequence = Some(sequence.to_string());
+            true
+        } else if self.budget.is_exhausted() {
+            outcome.stop_reason = "max_tokens".to_string();
+            true
+        } else {
+            false
+        }
+    }
 }
 
-/// 生成8位小写字母和数字的随机字符串
-fn generate_random_id_8() -> String {
-    const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
-    (0..8)
-        .map(|_| {
-            let idx = fastrand::usize(..CHARSET.len());
-            CHARSET[idx] as char
+/// agent loop 的最终结果
+#[derive(Debug, Default)]
+struct LoopOutcome {
+    stop_reason: String,
+    /// 命中的停止序列
+    stop_sequence: Option<String>,
+    input_tokens: i32,
+    output_tokens: i32,
+    search_requests: u32,
+    fetch_requests: u32,
+    /// 中途失败的错误信息
+    error: Option<String>,
+}
+
+impl LoopOutcome {
+    fn usage_json(&self) -> serde_json::Value {
+        json!({
+            "input_tokens": self.input_tokens,
+            "output_tokens": self.output_tokens,
+            "cache_creation_input_tokens": 0,
+            "cache_read_input_tokens": 0,
+            "server_tool_use": {
+                "web_search_requests": self.search_requests,
+                "web_fetch_requests": self.fetch_requests
+            }
         })
-        .collect()
+    }
 }
 
-/// 创建 MCP 请求
-///
-/// ID 格式: web_search_tooluse_{22位随机}_{毫秒时间戳}_{8位随机}
-pub fn create_mcp_request(query: &str) -> (String, McpRequest) {
-    let random_22 = generate_random_id_22();
-    let timestamp = chrono::Utc::now().timestamp_millis();
-    let random_8 = generate_random_id_8();
-
-    let request_id = format!(
-        "web_search_tooluse_{}_{}_{}",
-        random_22, timestamp, random_8
-    );
+/// 单次搜索的结果
+enum SearchOutcome {
+    Results(Vec<WebSearchResult>),
+    /// 错误码（max_uses_exceeded / invalid_tool_input / unavailable）
+    Error(&'static str),
+}
 
-    // tool_use_id 使用相同格式
-    let tool_use_id = format!(
-        "srvtoolu_{}",
-        Uuid::new_v4().to_string().replace('-', "")[..32].to_string()
-    );
+impl SearchOutcome {
+    /// 输出给客户端的 `web_search_tool_result.content`
+    fn to_block_content(&self) -> serde_json::Value {
+        match self {
+            SearchOutcome::Results(results) => results
+                .iter()
+                .map(|r| {
+                    json!({
+                        "type": "web_search_result",
+                        "title": r.title,
+                        "url": r.url,
+                        "encrypted_content": r.snippet.clone().unwrap_or_default(),
+                        "page_age": page_age(r)
+                    })
+                })
+                .collect(),
+            SearchOutcome::Error(code) => json!({
+                "type": "web_search_tool_result_error",
+                "error_code": code
+            }),
+        }
+    }
 
-    let request = McpRequest {
-        id: request_id,
-        jsonrpc: "2.0".to_string(),
-        method: "tools/call".to_string(),
-        params: McpParams {
-            name: "web_search".to_string(),
-            arguments: McpArguments {
-                query: query.to_string(),
-            },
-        },
-    };
+    /// 输出给客户端的 `web_search_tool_result` 块
+    fn to_result_block(&self) -> serde_json::Value {
+        // 官方 API 的 web_search_tool_result 没有 tool_use_id 字段
+        json!({
+            "type": "web_search_tool_result",
+            "content": self.to_block_content()
+        })
+    }
 
-    (tool_use_id, request)
+    /// 写回对话的 tool_result 块
+    fn to_tool_result(&self, tool_use_id: &str, query: &str) -> serde_json::Value {
+        match self {
+            SearchOutcome::Results(results) => {
+                let mut text = format!("Search results for \"{}\":\n", query);
+                if results.is_empty() {
+                    text.push_str("\nNo results found.\n");
+                }
+                for (i, r) in results.iter().enumerate() {
+                    text.push_str(&format!("\n{}. {}\n   URL: {}\n", i + 1, r.title, r.url));
+                    if let Some(age) = page_age(r) {
+                        text.push_str(&format!("   Published: {}\n", age));
+                    }
+                    if let Some(snippet) = &r.snippet {
+                        text.push_str(&format!("   {}\n", snippet));
+                    }
+                }
+                json!({"type": "tool_result", "tool_use_id": tool_use_id, "content": text})
+            }
+            SearchOutcome::Error(code) => json!({
+                "type": "tool_result",
+                "tool_use_id": tool_use_id,
+                "content": format!("Web search failed: {}", code),
+                "is_error": true
+            }),
+        }
+    }
 }
 
-/// 解析 MCP 响应中的搜索结果
-pub fn parse_search_results(mcp_response: &McpResponse) -> Option<WebSearchResults> {
-    let result = mcp_response.result.as_ref()?;
-    let content = result.content.first()?;
+fn page_age(result: &WebSearchResult) -> Option<String> {
+    result.published_date.and_then(|ms| {
+        chrono::DateTime::from_timestamp_millis(ms).map(|dt| dt.format("%B %-d, %Y").to_string())
+    })
+}
 
-    if content.content_type != "text" {
-        return None;
-    }
+/// 请求中 web_search 工具的参数
+struct SearchTool {
+    max_uses: u32,
+    /// 搜索后端链
+    backends: WebSearch,
+    /// allowed_domains / blocked_domains 过滤
+    filter: DomainFilter,
+    user_location: Option<UserLocation>,
+}
 
-    serde_json::from_str(&content.text).ok()
+/// 服务端工具 agent loop
+struct WebSearchLoop {
+    provider: Arc<KiroProvider>,
+    profile_arn: Option<String>,
+    /// 发往上游的请求（服务端工具已改写为普通工具，并追加了每轮的工具结果）
+    request: MessagesRequest,
+    /// 请求来源（用于匹配提示词改写规则）
+    origin: RequestOrigin,
+    context_window: i32,
+    search: Option<SearchTool>,
+    fetch: Option<WebFetchTool>,
+    output: LoopOutput,
 }
 
-/// 生成 WebSearch SSE 响应流
-pub fn create_websearch_sse_stream(
-    model: String,
-    query: String,
-    tool_use_id: String,
-    search_results: Option<WebSearchResults>,
-    input_tokens: i32,
-) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    let events =
-        generate_websearch_events(&model, &query, &tool_use_id, search_results, input_tokens);
+impl WebSearchLoop {
+    fn new(
+        provider: Arc<KiroProvider>,
+        profile_arn: Option<String>,
+        payload: &MessagesRequest,
+        origin: RequestOrigin,
+    ) -> Self {
+        let tools = payload.tools.iter().flatten();
+        let search = tools
+            .clone()
+            .find(|t| t.name == "web_search")
+            .map(|tool| SearchTool {
+                max_uses: tool
+                    .max_uses
+                    .filter(|&n| n > 0)
+                    .map(|n| n as u32)
+                    .unwrap_or(DEFAULT_MAX_USES),
+                backends: WebSearch::from_config(provider.clone()),
+                filter: DomainFilter::new(
+                    tool.allowed_domains.as_deref(),
+                    tool.blocked_domains.as_deref(),
+                ),
+                user_location: tool.user_location.clone(),
+            });
+        let fetch = tools
+            .clone()
+            .find(|t| t.is_web_fetch())
+            .map(WebFetchTool::from_tool);
+
+        let mut request = payload.clone();
+        let mut model_tools = Vec::new();
+        if let Some(search) = &search {
+            model_tools.push(web_search_tool(search.user_location.as_ref()));
+        }
+        if fetch.is_some() {
+            model_tools.push(web_fetch_tool());
+        }
+        request.tools = Some(model_tools);
+
+        let context_window = registry::registry()
+            .resolve(&payload.model)
+            .map(|entry| entry.context_window)
+            .unwrap_or(registry::DEFAULT_CONTEXT_WINDOW);
+
+        Self {
+            provider,
+            profile_arn,
+            request,
+            origin,
+            context_window,
+            search,
+            fetch,
+            output: LoopOutput::new(payload),
+        }
+    }
 
-    stream::iter(
-        events
-            .into_iter()
-            .map(|e| Ok(Bytes::from(e.to_sse_string()))),
-    )
-}
+    /// 转换当前对话为 Kiro 请求体
+    fn request_body(&self) -> anyhow::Result<String> {
+        let conversion = convert_request(&self.request, &self.origin)?;
+        let kiro_request = KiroRequest {
+            conversation_state: conversion.conversation_state,
+            profile_arn: self.profile_arn.clone(),
+        };
+        Ok(serde_json::to_string(&kiro_request)?)
+    }
 
-/// 生成 WebSearch SSE 事件序列
-fn generate_websearch_events(
-    model: &str,
-    query: &str,
-    tool_use_id: &str,
-    search_results: Option<WebSearchResults>,
-    input_tokens: i32,
-) -> Vec<SseEvent> {
-    let mut events = Vec::new();
-    let message_id = format!(
-        "msg_{}",
-        Uuid::new_v4().to_string().replace('-', "")[..24].to_string()
-    );
+    async fn send(&self, body: &str) -> anyhow::Result<reqwest::Response> {
+        tracing::debug!("Kiro request body: {}", body);
+        if self.request.stream {
+            self.provider.call_api_stream(body).await
+        } else {
+            self.provider.call_api(body).await
+        }
+    }
 
-    // 1. message_start
-    events.push(SseEvent::new(
-        "message_start",
-        json!({
-            "type": "message_start",
-            "message": {
-                "id": message_id,
-                "type": "message",
-                "role": "assistant",
-                "model": model,
-                "content": [],
-                "stop_reason": null,
-                "usage": {
-                    "input_tokens": input_tokens,
-                    "output_tokens": 0,
-                    "cache_creation_input_tokens": 0,
-                    "cache_read_input_tokens": 0
+    /// 读取一轮上游响应，文本增量通过 `emit` 实时输出
+    ///
+    /// 命中停止序列或达到 max_tokens 后不再读取剩余响应
+    async fn read_turn(
+        &mut self,
+        response: reqwest::Response,
+        emit: &mut impl FnMut(LoopEvent),
+    ) -> anyhow::Result<Turn> {
+        let mut turn = Turn::default();
+        let mut decoder = EventStreamDecoder::new();
+        let mut body = response.bytes_stream();
+        'read: while let Some(chunk) = body.next().await {
+            if let Err(e) = decoder.feed(&chunk?) {
+                tracing::warn!("缓冲区溢出: {}", e);
+            }
+            for result in decoder.decode_iter() {
+                match result {
+                    Ok(frame) => {
+                        if let Ok(event) = Event::from_frame(frame)
+                            && let Some(text) = turn.apply(event, self.context_window)
+                        {
+                            self.output.text(&text, &mut turn, emit);
+                            if self.output.should_stop() {
+                                break 'read;
+                            }
+                        }
+                    }
+                    Err(e) => tracing::warn!("解码事件失败: {}", e),
                 }
             }
-        }),
-    ));
+        }
+        self.output.end_turn(&mut turn, emit);
+        Ok(turn)
+    }
 
-    // 2. content_block_start (text - 搜索决策说明, index 0)
-    let decision_text = format!("I'll search for \"{}\".", query);
-    events.push(SseEvent::new(
-        "content_block_start",
-        json!({
-            "type": "content_block_start",
-            "index": 0,
-            "content_block": {
-                "type": "text",
-                "text": ""
+    /// 所有服务端工具的调用次数上限之和
+    fn max_uses(&self) -> u32 {
+        self.search.as_ref().map_or(0, |s| s.max_uses)
+            + self.fetch.as_ref().map_or(0, |f| f.max_uses)
+    }
+
+    /// 执行一次 web_search 调用，返回写回对话的 tool_result
+    async fn run_search(
+        &self,
+        tool_use: &TurnToolUse,
+        outcome: &mut LoopOutcome,
+        emit: &mut impl FnMut(LoopEvent),
+    ) -> serde_json::Value {
+        let query = tool_use.input["query"].as_str().unwrap_or_default().trim();
+        emit(LoopEvent::ServerToolUse {
+            id: server_tool_use_id(),
+            name: "web_search",
+            input: json!({"query": query}),
+        });
+
+        let result = match &self.search {
+            Some(search) if tool_use.name == "web_search" && !query.is_empty() => {
+                if outcome.search_requests >= search.max_uses {
+                    SearchOutcome::Error("max_uses_exceeded")
+                } else {
+                    outcome.search_requests += 1;
+                    tracing::info!(query = %query, "执行 WebSearch 搜索");
+                    let request = SearchRequest {
+                        query,
+                        user_location: search.user_location.as_ref(),
+                    };
+                    match search.backends.search(&request, &search.filter).await {
+                        Ok(results) => SearchOutcome::Results(results),
+                        Err(e) => {
+                            tracing::warn!(query = %query, "所有搜索后端均失败: {}", e);
+                            SearchOutcome::Error("unavailable")
+                        }
+                    }
+                }
             }
-        }),
-    ));
+            _ => SearchOutcome::Error("invalid_tool_input"),
+        };
+        emit(LoopEvent::ToolResult {
+            block: result.to_result_block(),
+        });
+        result.to_tool_result(&tool_use.id, query)
+    }
 
-    events.push(SseEvent::new(
-        "content_block_delta",
-        json!({
-            "type": "content_block_delta",
-            "index": 0,
-            "delta": {
-                "type": "text_delta",
-                "text": decision_text
+    /// 执行一次 web_fetch 调用，返回写回对话的 tool_result
+    async fn run_fetch(
+        &self,
+        fetch: &WebFetchTool,
+        tool_use: &TurnToolUse,
+        outcome: &mut LoopOutcome,
+        emit: &mut impl FnMut(LoopEvent),
+    ) -> serde_json::Value {
+        let url = tool_use.input["url"].as_str().unwrap_or_default().trim();
+        let id = server_tool_use_id();
+        emit(LoopEvent::ServerToolUse {
+            id: id.clone(),
+            name: "web_fetch",
+            input: json!({"url": url}),
+        });
+
+        let result = if url.is_empty() {
+            FetchOutcome::Error("invalid_input")
+        } else if outcome.fetch_requests >= fetch.max_uses {
+            FetchOutcome::Error("max_uses_exceeded")
+        } else {
+            outcome.fetch_requests += 1;
+            tracing::info!(url = %url, "执行 WebFetch 抓取");
+            fetch.fetch(url).await
+        };
+        emit(LoopEvent::ToolResult {
+            block: fetch.result_block(&id, &result),
+        });
+        result.to_tool_result(&tool_use.id)
+    }
+
+    /// 运行 agent loop，直到模型给出最终回答
+    ///
+    /// `first` 为第一轮的上游响应（由调用方发起，以便在出错时直接返回 HTTP 错误）
+    async fn run(
+        mut self,
+        first: reqwest::Response,
+        mut emit: impl FnMut(LoopEvent),
+    ) -> LoopOutcome {
+        let mut outcome = LoopOutcome {
+            stop_reason: "end_turn".to_string(),
+            ..LoopOutcome::default()
+        };
+        // 每次工具调用后模型还需要一轮来读取结果，额外留一轮处理超出 max_uses 的调用
+        let max_turns = self.max_uses() + 2;
+        let mut response = first;
+
+        for turn_no in 1..=max_turns {
+            // 与读取本轮响应并发估算输入 tokens
+            let estimated_input = token::InputTokensTask::spawn(
+                self.request.model.clone(),
+                self.request.system.clone(),
+                self.request.messages.clone(),
+                self.request.tools.clone(),
+            );
+            let turn = match self.read_turn(response, &mut emit).await {
+                Ok(turn) => turn,
+                Err(e) => {
+                    tracing::error!("读取响应流失败: {}", e);
+                    outcome.error = Some(format!("读取响应失败: {}", e));
+                    return outcome;
+                }
+            };
+            outcome.input_tokens += match turn.context_input_tokens {
+                Some(tokens) => tokens,
+                None => estimated_input.wait().await,
+            };
+            for tool_use in &turn.tool_uses {
+                self.output.tool_use(tool_use);
             }
-        }),
-    ));
+            outcome.output_tokens = self.output.budget.used();
 
-    events.push(SseEvent::new(
-        "content_block_stop",
-        json!({
-            "type": "content_block_stop",
-            "index": 0
-        }),
-    ));
+            if self.output.stop(&mut outcome) {
+                return outcome;
+            }
+            if let Some(reason) = turn.stop_reason {
+                outcome.stop_reason = reason.to_string();
+                return outcome;
+            }
+            if turn.tool_uses.is_empty() {
+                return outcome;
+            }
 
-    // 3. content_block_start (server_tool_use, index 1)
-    // server_tool_use 是服务端工具，input 在 content_block_start 中一次性完整发送，
-    // 不像客户端 tool_use 需要通过 input_json_delta 增量传输。
-    events.push(SseEvent::new(
-        "content_block_start",
-        json!({
-            "type": "content_block_start",
-            "index": 1,
-            "content_block": {
-                "id": tool_use_id,
-                "type": "server_tool_use",
-                "name": "web_search",
-                "input": {"query": query}
+            // 执行本轮的工具调用
+            let mut tool_results = Vec::new();
+            for tool_use in &turn.tool_uses {
+                let result = match &self.fetch {
+                    Some(fetch) if tool_use.name == "web_fetch" => {
+                        self.run_fetch(fetch, tool_use, &mut outcome, &mut emit)
+                            .await
+                    }
+                    _ => self.run_search(tool_use, &mut outcome, &mut emit).await,
+                };
+                tool_results.push(result);
             }
-        }),
-    ));
 
-    // 4. content_block_stop (server_tool_use)
-    events.push(SseEvent::new(
-        "content_block_stop",
-        json!({
-            "type": "content_block_stop",
-            "index": 1
-        }),
-    ));
+            // 把本轮的工具调用与工具结果写回对话
+            let mut assistant_content = Vec::new();
+            if !turn.thinking.is_empty() {
+                assistant_content.push(json!({"type": "thinking", "thinking": turn.thinking}));
+            }
+            if !turn.text.is_empty() {
+                assistant_content.push(json!({"type": "text", "text": turn.text}));
+            }
+            assistant_content.extend(turn.tool_uses.iter().map(
+                |t| json!({"type": "tool_use", "id": t.id, "name": t.name, "input": t.input}),
+            ));
+            self.request.messages.push(Message {
+                role: "assistant".to_string(),
+                content: serde_json::Value::Array(assistant_content),
+            });
+            self.request.messages.push(Message {
+                role: "user".to_string(),
+                content: serde_json::Value::Array(tool_results),
+            });
 
-    // 5. content_block_start (web_search_tool_result, index 2)
-    // 官方 API 的 web_search_tool_result 没有 tool_use_id 字段
-    let search_content = if let Some(ref results) = search_results {
-        results
-            .results
-            .iter()
-            .map(|r| {
-                let page_age = r.published_date.and_then(|ms| {
-                    chrono::DateTime::from_timestamp_millis(ms)
-                        .map(|dt| dt.format("%B %-d, %Y").to_string())
+            if turn_no == max_turns {
+                break;
+            }
+            // 强制调用工具的 tool_choice 只约束第一轮，之后由模型决定继续搜索还是作答
+            if let Some(choice @ (ToolChoice::Any { .. } | ToolChoice::Tool { .. })) =
+                &self.request.tool_choice
+            {
+                let disable_parallel_tool_use = choice.disable_parallel_tool_use();
+                self.request.tool_choice = Some(ToolChoice::Auto {
+                    disable_parallel_tool_use,
                 });
-                json!({
-                    "type": "web_search_result",
-                    "title": r.title,
-                    "url": r.url,
-                    "encrypted_content": r.snippet.clone().unwrap_or_default(),
-                    "page_age": page_age
-                })
-            })
-            .collect::<Vec<_>>()
-    } else {
-        vec![]
-    };
-
-    events.push(SseEvent::new(
-        "content_block_start",
-        json!({
-            "type": "content_block_start",
-            "index": 2,
-            "content_block": {
-                "type": "web_search_tool_result",
-                "content": search_content
             }
-        }),
-    ));
 
-    // 6. content_block_stop (web_search_tool_result)
-    events.push(SseEvent::new(
-        "content_block_stop",
-        json!({
-            "type": "content_block_stop",
-            "index": 2
-        }),
-    ));
+            let next = match self.request_body() {
+                Ok(body) => self.send(&body).await,
+                Err(e) => Err(e),
+            };
+            response = match next {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::error!("WebSearch 后续轮次请求失败: {}", e);
+                    outcome.error = Some(e.to_string());
+                    return outcome;
+                }
+            };
+        }
 
-    // 7. content_block_start (text, index 3)
-    events.push(SseEvent::new(
-        "content_block_start",
-        json!({
-            "type": "content_block_start",
-            "index": 3,
-            "content_block": {
-                "type": "text",
-                "text": ""
-            }
-        }),
-    ));
+        // 达到轮次上限，模型仍在调用工具：与官方 API 一致返回 pause_turn，由客户端决定是否继续
+        tracing::warn!(max_turns, "WebSearch 达到轮次上限");
+        outcome.stop_reason = "pause_turn".to_string();
+        outcome
+    }
+}
 
-    // 8. content_block_delta (text_delta) - 生成搜索结果摘要
-    let summary = generate_search_summary(query, &search_results);
+/// 流式输出的内容块状态
+#[derive(Debug, Default)]
+struct SseRenderer {
+    next_index: i32,
+    /// 当前打开的 text 块
+    text_index: Option<i32>,
+    /// 当前打开的 thinking 块
+    thinking_index: Option<i32>,
+}
+
+impl SseRenderer {
+    fn message_start(model: &str, input_tokens: i32) -> SseEvent {
+        let message_id = format!("msg_{}", &Uuid::new_v4().to_string().replace('-', "")[..24]);
+        SseEvent::new(
+            "message_start",
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "model": model,
+                    "content": [],
+                    "stop_reason": null,
+                    "usage": {
+                        "input_tokens": input_tokens,
+                        "output_tokens": 0,
+                        "cache_creation_input_tokens": 0,
+                        "cache_read_input_tokens": 0
+                    }
+                }
+            }),
+        )
+    }
+
+    fn render(&mut self, event: LoopEvent) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        match event {
+            LoopEvent::Thinking(thinking) => {
+                let index = match self.thinking_index {
+                    Some(index) => index,
+                    None => {
+                        events.extend(self.close_text());
+                        let index = self.next_block_index();
+                        self.thinking_index = Some(index);
+                        events.push(SseEvent::new(
+                            "content_block_start",
+                            json!({
+                                "type": "content_block_start",
+                                "index": index,
+                                "content_block": {"type": "thinking", "thinking": ""}
+                            }),
+                        ));
+                        index
+                    }
+                };
+                events.push(thinking_delta(index, &thinking));
+            }
+            LoopEvent::ThinkingEnd => events.extend(self.close_thinking()),
+            LoopEvent::Text(text) => {
+                let index = match self.text_index {
+                    Some(index) => index,
+                    None => {
+                        let index = self.next_block_index();
+                        self.text_index = Some(index);
+                        events.push(SseEvent::new(
+                            "content_block_start",
+                            json!({
+                                "type": "content_block_start",
+                                "index": index,
+                                "content_block": {"type": "text", "text": ""}
+                            }),
+                        ));
+                        index
+                    }
+                };
+                events.push(SseEvent::new(
+                    "content_block_delta",
+                    json!({
+                        "type": "content_block_delta",
+                        "index": index,
+                        "delta": {"type": "text_delta", "text": text}
+                    }),
+                ));
+            }
+            LoopEvent::ServerToolUse { id, name, input } => {
+                events.extend(self.close_text());
+                // server_tool_use 是服务端工具，input 在 content_block_start 中一次性完整发送，
+                // 不像客户端 tool_use 需要通过 input_json_delta 增量传输。
+                events.extend(self.whole_block(json!({
+                    "id": id,
+                    "type": "server_tool_use",
+                    "name": name,
+                    "input": input
+                })));
+            }
+            LoopEvent::ToolResult { block } => {
+                events.extend(self.whole_block(block));
+            }
+        }
+        events
+    }
 
-    // 分块发送文本
-    let chunk_size = 100;
-    for chunk in summary.chars().collect::<Vec<_>>().chunks(chunk_size) {
-        let text: String = chunk.iter().collect();
+    /// 结束消息：关闭未关闭的块并发送 message_delta / message_stop（失败时发送 error 事件）
+    fn finish(&mut self, outcome: &LoopOutcome) -> Vec<SseEvent> {
+        let mut events = self.close_thinking();
+        events.extend(self.close_text());
+        if let Some(message) = &outcome.error {
+            events.push(SseEvent::new(
+                "error",
+                json!({
+                    "type": "error",
+                    "error": {"type": "api_error", "message": message}
+                }),
+            ));
+            return events;
+        }
         events.push(SseEvent::new(
-            "content_block_delta",
+            "message_delta",
             json!({
-                "type": "content_block_delta",
-                "index": 3,
+                "type": "message_delta",
                 "delta": {
-                    "type": "text_delta",
-                    "text": text
-                }
+                    "stop_reason": outcome.stop_reason,
+                    "stop_sequence": outcome.stop_sequence
+                },
+                "usage": outcome.usage_json()
             }),
         ));
+        events.push(SseEvent::new(
+            "message_stop",
+            json!({"type": "message_stop"}),
+        ));
+        events
     }
 
-    // 9. content_block_stop (text)
-    events.push(SseEvent::new(
-        "content_block_stop",
-        json!({
-            "type": "content_block_stop",
-            "index": 3
-        }),
-    ));
+    fn next_block_index(&mut self) -> i32 {
+        let index = self.next_index;
+        self.next_index += 1;
+        index
+    }
 
-    // 10. message_delta
-    // 官方 API 的 message_delta.delta 中没有 stop_sequence 字段
-    let output_tokens = (summary.len() as i32 + 3) / 4; // 简单估算
-    events.push(SseEvent::new(
-        "message_delta",
-        json!({
-            "type": "message_delta",
-            "delta": {
-                "stop_reason": "end_turn"
-            },
-            "usage": {
-                "output_tokens": output_tokens,
-                "server_tool_use": {
-                    "web_search_requests": 1
-                }
-            }
-        }),
-    ));
+    /// 关闭 thinking 块：与普通流式响应一致，先发送空的 thinking_delta 再发送 content_block_stop
+    fn close_thinking(&mut self) -> Vec<SseEvent> {
+        match self.thinking_index.take() {
+            Some(index) => vec![
+                thinking_delta(index, ""),
+                SseEvent::new(
+                    "content_block_stop",
+                    json!({"type": "content_block_stop", "index": index}),
+                ),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    fn close_text(&mut self) -> Vec<SseEvent> {
+        match self.text_index.take() {
+            Some(index) => vec![SseEvent::new(
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": index}),
+            )],
+            None => Vec::new(),
+        }
+    }
+
+    /// 一次性输出完整的内容块（start + stop）
+    fn whole_block(&mut self, content_block: serde_json::Value) -> Vec<SseEvent> {
+        let index = self.next_block_index();
+        vec![
+            SseEvent::new(
+                "content_block_start",
+                json!({
+                    "type": "content_block_start",
+                    "index": index,
+                    "content_block": content_block
+                }),
+            ),
+            SseEvent::new(
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": index}),
+            ),
+        ]
+    }
+}
 
-    // 11. message_stop
-    events.push(SseEvent::new(
-        "message_stop",
+fn thinking_delta(index: i32, thinking: &str) -> SseEvent {
+    SseEvent::new(
+        "content_block_delta",
         json!({
-            "type": "message_stop"
+            "type": "content_block_delta",
+            "index": index,
+            "delta": {"type": "thinking_delta", "thinking": thinking}
         }),
-    ));
-
-    events
+    )
 }
 
-/// 生成搜索结果摘要
-fn generate_search_summary(query: &str, results: &Option<WebSearchResults>) -> String {
-    let mut summary = format!("Here are the search results for \"{}\":\n\n", query);
-
-    if let Some(results) = results {
-        for (i, result) in results.results.iter().enumerate() {
-            summary.push_str(&format!("{}. **{}**\n", i + 1, result.title));
-            if let Some(ref snippet) = result.snippet {
-                // 截断过长的摘要（安全处理 UTF-8 多字节字符）
-                let truncated = match snippet.char_indices().nth(200) {
-                    Some((idx, _)) => format!("{}...", &snippet[..idx]),
-                    None => snippet.clone(),
-                };
-                summary.push_str(&format!("   {}\n", truncated));
+/// 把 agent loop 的输出收集为非流式响应的 content 数组
+fn collect_content(content: &mut Vec<serde_json::Value>, event: LoopEvent) {
+    match event {
+        LoopEvent::Thinking(thinking) => {
+            if let Some(last) = content.last_mut()
+                && last["type"] == "thinking"
+            {
+                let merged = format!(
+                    "{}{}",
+                    last["thinking"].as_str().unwrap_or_default(),
+                    thinking
+                );
+                last["thinking"] = json!(merged);
+            } else {
+                content.push(json!({"type": "thinking", "thinking": thinking}));
             }
-            summary.push_str(&format!("   Source: {}\n\n", result.url));
         }
-    } else {
-        summary.push_str("No results found.\n");
+        LoopEvent::ThinkingEnd => {}
+        LoopEvent::Text(text) => {
+            if let Some(last) = content.last_mut()
+                && last["type"] == "text"
+            {
+                let merged = format!("{}{}", last["text"].as_str().unwrap_or_default(), text);
+                last["text"] = json!(merged);
+            } else {
+                content.push(json!({"type": "text", "text": text}));
+            }
+        }
+        LoopEvent::ServerToolUse { id, name, input } => content.push(json!({
+            "type": "server_tool_use",
+            "id": id,
+            "name": name,
+            "input": input
+        })),
+        LoopEvent::ToolResult { block } => content.push(block),
     }
-
-    summary.push_str("\nPlease note that these are web search results and may not be fully accurate or up-to-date.");
-
-    summary
 }
 
-/// 处理 WebSearch 请求
+/// 处理服务端工具（web_search / web_fetch）请求
+///
+/// 流式与非流式请求都会运行完整的 agent loop
 pub async fn handle_websearch_request(
-    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
+    provider: Arc<KiroProvider>,
+    profile_arn: Option<String>,
     payload: &MessagesRequest,
-    input_tokens: i32,
+    origin: RequestOrigin,
+    input_tokens: token::InputTokensTask,
+    mut usage: UsageReporter,
 ) -> Response {
-    // 1. 提取搜索查询
-    let query = match extract_search_query(payload) {
-        Some(q) => q,
-        None => {
+    let search_loop = WebSearchLoop::new(provider, profile_arn, payload, origin);
+    tracing::info!(
+        web_search = search_loop.search.is_some(),
+        web_fetch = search_loop.fetch.is_some(),
+        max_uses = search_loop.max_uses(),
+        "处理服务端工具请求"
+    );
+
+    // 第一轮在返回响应前发起，失败时可以直接返回 HTTP 错误
+    let body = match search_loop.request_body() {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("请求转换失败: {}", e);
+            return usage.fail(
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new("invalid_request_error", e.to_string())),
+                )
+                    .into_response(),
+            );
+        }
+    };
+    let first = match call_upstream(&search_loop.provider, &body, payload.stream, &mut usage).await
+    {
+        Ok(response) => response,
+        Err(response) => return response,
+    };
+
+    let model = payload.model.clone();
+    if !payload.stream {
+        let mut content = Vec::new();
+        let outcome = search_loop
+            .run(first, |event| collect_content(&mut content, event))
+            .await;
+        if let Some(message) = &outcome.error {
+            usage.report_failure("stream_error", outcome.input_tokens, outcome.output_tokens);
             return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(
-                    "invalid_request_error",
-                    "无法从消息中提取搜索查询",
-                )),
+                StatusCode::BAD_GATEWAY,
+                Json(ErrorResponse::new("api_error", message.clone())),
             )
                 .into_response();
         }
-    };
+        usage.report(
+            outcome.input_tokens,
+            outcome.output_tokens,
+            &outcome.stop_reason,
+        );
+        let message_id = format!("msg_{}", Uuid::new_v4().to_string().replace('-', ""));
+        let body = build_message_body(
+            &message_id,
+            &model,
+            content,
+            &outcome.stop_reason,
+            outcome.stop_sequence.as_deref(),
+            outcome.usage_json(),
+        );
+        return (StatusCode::OK, Json(body)).into_response();
+    }
 
-    tracing::info!(query = %query, "处理 WebSearch 请求");
+    let (tx, rx) = mpsc::unbounded_channel::<Bytes>();
+    tokio::spawn(async move {
+        let send = |event: SseEvent| {
+            let _ = tx.send(Bytes::from(event.to_sse_string()));
+        };
+        send(SseRenderer::message_start(
+            &model,
+            input_tokens.wait().await,
+        ));
 
-    // 2. 创建 MCP 请求
-    let (tool_use_id, mcp_request) = create_mcp_request(&query);
+        let mut renderer = SseRenderer::default();
+        let outcome = search_loop
+            .run(first, |event| {
+                renderer.render(event).into_iter().for_each(send)
+            })
+            .await;
+        renderer.finish(&outcome).into_iter().for_each(send);
 
-    // 3. 调用 Kiro MCP API
-    let search_results = match call_mcp_api(&provider, &mcp_request).await {
-        Ok(response) => parse_search_results(&response),
-        Err(e) => {
-            tracing::warn!("MCP API 调用失败: {}", e);
-            None
+        match &outcome.error {
+            Some(_) => {
+                usage.report_failure("stream_error", outcome.input_tokens, outcome.output_tokens)
+            }
+            None => usage.report(
+                outcome.input_tokens,
+                outcome.output_tokens,
+                &outcome.stop_reason,
+            ),
         }
-    };
-
-    // 4. 生成 SSE 响应
-    let model = payload.model.clone();
-    let stream =
-        create_websearch_sse_stream(model, query, tool_use_id, search_results, input_tokens);
+    });
 
     Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "text/event-stream")
         .header(header::CACHE_CONTROL, "no-cache")
         .header(header::CONNECTION, "keep-alive")
-        .body(Body::from_stream(stream))
+        .body(Body::from_stream(create_sse_stream(rx)))
         .unwrap()
 }
 
-/// 调用 Kiro MCP API
-async fn call_mcp_api(
-    provider: &crate::kiro::provider::KiroProvider,
-    request: &McpRequest,
-) -> anyhow::Result<McpResponse> {
-    let request_body = serde_json::to_string(request)?;
-
-    tracing::debug!("MCP request: {}", request_body);
-
-    let response = provider.call_mcp(&request_body).await?;
-
-    let body = response.text().await?;
-    tracing::debug!("MCP response: {}", body);
-
-    let mcp_response: McpResponse = serde_json::from_str(&body)?;
-
-    if let Some(ref error) = mcp_response.error {
-        anyhow::bail!(
-            "MCP error: {} - {}",
-            error.code.unwrap_or(-1),
-            error.message.as_deref().unwrap_or("Unknown error")
-        );
-    }
-
-    Ok(mcp_response)
+/// 把 agent loop 的输出转为 SSE 流，同时每25秒发送 ping 保活（搜索期间可能长时间没有输出）
+fn create_sse_stream(
+    rx: mpsc::UnboundedReceiver<Bytes>,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let mut ping_interval = interval(Duration::from_secs(PING_INTERVAL_SECS));
+    ping_interval.reset();
+    stream::unfold(
+        (rx, ping_interval),
+        |(mut rx, mut ping_interval)| async move {
+            tokio::select! {
+                bytes = rx.recv() => bytes.map(|b| (Ok(b), (rx, ping_interval))),
+                _ = ping_interval.tick() => {
+                    tracing::trace!("发送 ping 保活事件");
+                    let ping = Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n");
+                    Some((Ok(ping), (rx, ping_interval)))
+                }
+            }
+        },
+    )
 }
 
 #[cfg(test)]
@@ -565,14 +1256,24 @@ mod tests {
                 description: String::new(),
                 input_schema: Default::default(),
                 max_uses: Some(8),
+                allowed_domains: None,
+                blocked_domains: None,
+                user_location: None,
+                max_content_tokens: None,
+                citations: None,
+                cache_control: None,
             }]),
             tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
             thinking: None,
             output_config: None,
             metadata: None,
         };
 
-        assert!(has_web_search_tool(&req));
+        assert!(is_server_tool_request(&req));
     }
 
     #[test]
@@ -595,6 +1296,12 @@ mod tests {
                     description: String::new(),
                     input_schema: Default::default(),
                     max_uses: Some(8),
+                    allowed_domains: None,
+                    blocked_domains: None,
+                    user_location: None,
+                    max_content_tokens: None,
+                    citations: None,
+                    cache_control: None,
                 },
                 Tool {
                     tool_type: None,
@@ -602,156 +1309,372 @@ mod tests {
                     description: "Other tool".to_string(),
                     input_schema: Default::default(),
                     max_uses: None,
+                    allowed_domains: None,
+                    blocked_domains: None,
+                    user_location: None,
+                    max_content_tokens: None,
+                    citations: None,
+                    cache_control: None,
                 },
             ]),
             tool_choice: None,
+            stop_sequences: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
             thinking: None,
             output_config: None,
             metadata: None,
         };
 
         // 多个工具时不应该被识别为纯 websearch 请求
-        assert!(!has_web_search_tool(&req));
+        assert!(!is_server_tool_request(&req));
     }
 
     #[test]
-    fn test_extract_search_query_with_prefix() {
-        use crate::anthropic::types::Message;
-
-        let req = MessagesRequest {
-            model: "claude-sonnet-4".to_string(),
-            max_tokens: 1024,
-            messages: vec![Message {
-                role: "user".to_string(),
-                content: serde_json::json!([{
-                    "type": "text",
-                    "text": "Perform a web search for the query: rust latest version 2026"
-                }]),
-            }],
-            stream: true,
-            system: None,
-            tools: None,
-            tool_choice: None,
-            thinking: None,
-            output_config: None,
-            metadata: None,
+    fn test_is_server_tool_request_with_web_fetch() {
+        let request = |tools: serde_json::Value| -> MessagesRequest {
+            serde_json::from_value(json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 1024,
+                "messages": [{"role": "user", "content": "test"}],
+                "tools": tools
+            }))
+            .unwrap()
         };
+        let search = json!({"type": "web_search_20250305", "name": "web_search"});
+        let fetch = json!({"type": "web_fetch_20250910", "name": "web_fetch"});
+        let client_fetch = json!({"name": "web_fetch", "input_schema": {"type": "object"}});
+
+        assert!(is_server_tool_request(&request(json!([fetch]))));
+        assert!(is_server_tool_request(&request(json!([search, fetch]))));
+        // 同名的客户端工具不是服务端工具
+        assert!(!is_server_tool_request(&request(json!([client_fetch]))));
+        assert!(!is_server_tool_request(&request(json!([fetch, fetch]))));
+        assert!(!is_server_tool_request(&request(json!([]))));
+    }
 
-        let query = extract_search_query(&req);
-        // 前缀应该被去除
-        assert_eq!(query, Some("rust latest version 2026".to_string()));
+    fn tool_use_event(input: &str, stop: bool) -> Event {
+        Event::ToolUse(crate::kiro::model::events::ToolUseEvent {
+            name: "web_search".to_string(),
+            tool_use_id: "tooluse_1".to_string(),
+            input: input.to_string(),
+            stop,
+        })
     }
 
     #[test]
-    fn test_extract_search_query_plain_text() {
-        use crate::anthropic::types::Message;
+    fn test_turn_collects_text_and_tool_calls() {
+        let mut turn = Turn::default();
+        let text = Event::AssistantResponse(
+            serde_json::from_value(json!({"content": "Let me search."})).unwrap(),
+        );
+        assert_eq!(
+            turn.apply(text, 200_000),
+            Some("Let me search.".to_string())
+        );
+        assert_eq!(
+            turn.apply(tool_use_event(r#"{"query": "ru"#, false), 200_000),
+            None
+        );
+        assert!(turn.tool_uses.is_empty());
+        turn.apply(tool_use_event(r#"st 2026"}"#, true), 200_000);
 
-        let req = MessagesRequest {
-            model: "claude-sonnet-4".to_string(),
-            max_tokens: 1024,
-            messages: vec![Message {
-                role: "user".to_string(),
-                content: serde_json::json!("What is the weather today?"),
-            }],
-            stream: true,
-            system: None,
-            tools: None,
-            tool_choice: None,
-            thinking: None,
-            output_config: None,
-            metadata: None,
-        };
+        assert_eq!(turn.tool_uses.len(), 1);
+        assert_eq!(turn.tool_uses[0].input["query"], "rust 2026");
+    }
 
-        let query = extract_search_query(&req);
-        assert_eq!(query, Some("What is the weather today?".to_string()));
+    fn web_search_request(extra: serde_json::Value) -> MessagesRequest {
+        let mut request = json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "test"}],
+            "tools": [{"type": "web_search_20250305", "name": "web_search"}]
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    /// 依次输入文本片段并结束本轮，返回收集到的 content 与本轮记录
+    fn run_output(output: &mut LoopOutput, chunks: &[&str]) -> (Vec<serde_json::Value>, Turn) {
+        let mut content = Vec::new();
+        let mut turn = Turn::default();
+        let mut emit = |event| collect_content(&mut content, event);
+        for chunk in chunks {
+            output.text(chunk, &mut turn, &mut emit);
+            if output.should_stop() {
+                break;
+            }
+        }
+        output.end_turn(&mut turn, &mut emit);
+        (content, turn)
     }
 
     #[test]
-    fn test_create_mcp_request() {
-        let (tool_use_id, request) = create_mcp_request("test query");
+    fn test_loop_output_stop_sequences() {
+        let request = web_search_request(json!({"stop_sequences": ["END"]}));
+        let mut output = LoopOutput::new(&request);
+        let (content, turn) = run_output(&mut output, &["Answer: 42 E", "ND and more", "ignored"]);
+
+        assert_eq!(
+            content,
+            vec![json!({"type": "text", "text": "Answer: 42 "})]
+        );
+        assert_eq!(turn.text, "Answer: 42 ");
+        let mut outcome = LoopOutcome::default();
+        assert!(output.stop(&mut outcome));
+        assert_eq!(outcome.stop_reason, "stop_sequence");
+        assert_eq!(outcome.stop_sequence.as_deref(), Some("END"));
+    }
 
-        assert!(tool_use_id.starts_with("srvtoolu_"));
-        assert_eq!(request.jsonrpc, "2.0");
-        assert_eq!(request.method, "tools/call");
-        assert_eq!(request.params.name, "web_search");
-        assert_eq!(request.params.arguments.query, "test query");
+    #[test]
+    fn test_loop_output_max_tokens_spans_turns() {
+        let request = web_search_request(json!({"max_tokens": 8}));
+        let mut output = LoopOutput::new(&request);
+        let (_, first) = run_output(&mut output, &["Let me search."]);
+        assert!(!output.should_stop());
+        assert_eq!(first.text, "Let me search.");
+
+        let long = "word ".repeat(100);
+        let (content, second) = run_output(&mut output, &[&long]);
+        assert!(output.should_stop());
+        assert!(second.text.len() < long.len());
+        assert_eq!(content[0]["text"], second.text);
+        assert_eq!(output.budget.used(), 8);
+
+        let mut outcome = LoopOutcome::default();
+        assert!(output.stop(&mut outcome));
+        assert_eq!(outcome.stop_reason, "max_tokens");
+        assert_eq!(outcome.stop_sequence, None);
+    }
 
-        // 验证 ID 格式: web_search_tooluse_{22位}_{时间戳}_{8位}
-        assert!(request.id.starts_with("web_search_tooluse_"));
+    #[test]
+    fn test_loop_output_splits_thinking_per_turn() {
+        let request = web_search_request(json!({"thinking": {"type": "enabled"}}));
+        let mut output = LoopOutput::new(&request);
+
+        // 工具调用前的 thinking：`</thinking>` 后紧跟 tool_use，没有 `\n\n`
+        let (content, turn) = run_output(
+            &mut output,
+            &["\n\n<think", "ing>\nneed to search</thinking>"],
+        );
+        assert_eq!(
+            content,
+            vec![json!({"type": "thinking", "thinking": "need to search"})]
+        );
+        assert_eq!(turn.thinking, "need to search");
+        assert!(turn.text.is_empty());
+
+        // 下一轮重新识别 thinking
+        let (content, turn) = run_output(
+            &mut output,
+            &[
+                "<thinking>results look good</thinking>\n",
+                "\nThe answer is 42.",
+            ],
+        );
+        assert_eq!(
+            content,
+            vec![
+                json!({"type": "thinking", "thinking": "results look good"}),
+                json!({"type": "text", "text": "The answer is 42."}),
+            ]
+        );
+        assert_eq!(turn.text, "The answer is 42.");
     }
 
     #[test]
-    fn test_mcp_request_id_format() {
-        let (_, request) = create_mcp_request("test");
-
-        // 格式: web_search_tooluse_{22位}_{毫秒时间戳}_{8位}
-        let id = &request.id;
-        assert!(id.starts_with("web_search_tooluse_"));
-
-        let suffix = &id["web_search_tooluse_".len()..];
-        let parts: Vec<&str> = suffix.split('_').collect();
-        assert_eq!(parts.len(), 3, "应该有3个部分: 22位随机_时间戳_8位随机");
-
-        // 第一部分: 22位大小写字母和数字
-        assert_eq!(parts[0].len(), 22);
-        assert!(parts[0].chars().all(|c| c.is_ascii_alphanumeric()));
-
-        // 第二部分: 毫秒时间戳
-        assert!(parts[1].parse::<i64>().is_ok());
-
-        // 第三部分: 8位小写字母和数字
-        assert_eq!(parts[2].len(), 8);
-        assert!(
-            parts[2]
-                .chars()
-                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    fn test_loop_output_without_thinking_keeps_tags_as_text() {
+        let request = web_search_request(json!({}));
+        let mut output = LoopOutput::new(&request);
+        let (content, _) = run_output(&mut output, &["<thinking>x</thinking>\n\nok"]);
+        assert_eq!(
+            content,
+            vec![json!({"type": "text", "text": "<thinking>x</thinking>\n\nok"})]
         );
     }
 
     #[test]
-    fn test_parse_search_results() {
-        let response = McpResponse {
-            error: None,
-            id: "test_id".to_string(),
-            jsonrpc: "2.0".to_string(),
-            result: Some(McpResult {
-                content: vec![McpContent {
-                    content_type: "text".to_string(),
-                    text: r#"{"results":[{"title":"Test","url":"https://example.com","snippet":"Test snippet"}],"totalResults":1}"#.to_string(),
-                }],
-                is_error: false,
-            }),
-        };
+    fn test_sse_renderer_block_sequence() {
+        let mut renderer = SseRenderer::default();
+        let mut events = Vec::new();
+        events.extend(renderer.render(LoopEvent::Text("I'll search.".to_string())));
+        events.extend(renderer.render(LoopEvent::ServerToolUse {
+            id: "srvtoolu_1".to_string(),
+            name: "web_search",
+            input: json!({"query": "rust"}),
+        }));
+        events.extend(renderer.render(LoopEvent::ToolResult {
+            block: SearchOutcome::Error("unavailable").to_result_block(),
+        }));
+        events.extend(renderer.render(LoopEvent::Text("Answer".to_string())));
+        events.extend(renderer.finish(&LoopOutcome {
+            stop_reason: "end_turn".to_string(),
+            input_tokens: 10,
+            output_tokens: 5,
+            search_requests: 1,
+            ..LoopOutcome::default()
+        }));
+
+        let starts: Vec<(i64, String)> = events
+            .iter()
+            .filter(|e| e.event == "content_block_start")
+            .map(|e| {
+                (
+                    e.data["index"].as_i64().unwrap(),
+                    e.data["content_block"]["type"]
+                        .as_str()
+                        .unwrap()
+                        .to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                (0, "text".to_string()),
+                (1, "server_tool_use".to_string()),
+                (2, "web_search_tool_result".to_string()),
+                (3, "text".to_string()),
+            ]
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| e.event == "content_block_stop")
+                .count(),
+            4
+        );
 
-        let results = parse_search_results(&response);
-        assert!(results.is_some());
-        let results = results.unwrap();
-        assert_eq!(results.results.len(), 1);
-        assert_eq!(results.results[0].title, "Test");
+        let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "end_turn");
+        assert_eq!(
+            delta.data["delta"]["stop_sequence"],
+            serde_json::Value::Null
+        );
+        assert_eq!(
+            delta.data["usage"]["server_tool_use"]["web_search_requests"],
+            1
+        );
+        assert_eq!(events.last().unwrap().event, "message_stop");
     }
 
     #[test]
-    fn test_generate_search_summary() {
-        let results = WebSearchResults {
-            results: vec![WebSearchResult {
-                title: "Test Result".to_string(),
-                url: "https://example.com".to_string(),
-                snippet: Some("This is a test snippet".to_string()),
-                published_date: None,
-                id: None,
-                domain: None,
-                max_verbatim_word_limit: None,
-                public_domain: None,
-            }],
-            total_results: Some(1),
-            query: Some("test".to_string()),
-            error: None,
-        };
+    fn test_sse_renderer_thinking_block_and_stop_sequence() {
+        let mut renderer = SseRenderer::default();
+        let mut events = Vec::new();
+        events.extend(renderer.render(LoopEvent::Thinking("plan".to_string())));
+        events.extend(renderer.render(LoopEvent::ThinkingEnd));
+        events.extend(renderer.render(LoopEvent::Text("Answer".to_string())));
+        events.extend(renderer.finish(&LoopOutcome {
+            stop_reason: "stop_sequence".to_string(),
+            stop_sequence: Some("END".to_string()),
+            ..LoopOutcome::default()
+        }));
+
+        let kinds: Vec<(&str, Option<&str>)> = events
+            .iter()
+            .map(|e| {
+                let detail = e.data["content_block"]["type"]
+                    .as_str()
+                    .or(e.data["delta"]["type"].as_str());
+                (e.event.as_str(), detail)
+            })
+            .collect();
+        assert_eq!(
+            kinds[..6],
+            [
+                ("content_block_start", Some("thinking")),
+                ("content_block_delta", Some("thinking_delta")),
+                ("content_block_delta", Some("thinking_delta")),
+                ("content_block_stop", None),
+                ("content_block_start", Some("text")),
+                ("content_block_delta", Some("text_delta")),
+            ]
+        );
+        assert_eq!(events[2].data["delta"]["thinking"], "");
+        assert_eq!(events[4].data["index"], 1);
+
+        let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "stop_sequence");
+        assert_eq!(delta.data["delta"]["stop_sequence"], "END");
+    }
+
+    #[test]
+    fn test_sse_renderer_error_ends_without_message_stop() {
+        let mut renderer = SseRenderer::default();
+        renderer.render(LoopEvent::Text("partial".to_string()));
+        let events = renderer.finish(&LoopOutcome {
+            stop_reason: "end_turn".to_string(),
+            error: Some("boom".to_string()),
+            ..LoopOutcome::default()
+        });
+        assert_eq!(events[0].event, "content_block_stop");
+        assert_eq!(events.last().unwrap().event, "error");
+    }
 
-        let summary = generate_search_summary("test", &Some(results));
+    #[test]
+    fn test_collect_content_merges_text() {
+        let mut content = Vec::new();
+        collect_content(&mut content, LoopEvent::Text("Hel".to_string()));
+        collect_content(&mut content, LoopEvent::Text("lo".to_string()));
+        collect_content(
+            &mut content,
+            LoopEvent::ServerToolUse {
+                id: "srvtoolu_1".to_string(),
+                name: "web_search",
+                input: json!({"query": "q"}),
+            },
+        );
+        collect_content(&mut content, LoopEvent::Text("Done".to_string()));
+
+        assert_eq!(content.len(), 3);
+        assert_eq!(content[0]["text"], "Hello");
+        assert_eq!(content[1]["type"], "server_tool_use");
+        assert_eq!(content[2]["text"], "Done");
+    }
 
-        assert!(summary.contains("Test Result"));
-        assert!(summary.contains("https://example.com"));
-        assert!(summary.contains("This is a test snippet"));
+    #[test]
+    fn test_search_outcome_tool_result() {
+        let results = SearchOutcome::Results(vec![WebSearchResult {
+            title: "Rust 1.90".to_string(),
+            url: "https://blog.rust-lang.org".to_string(),
+            snippet: Some("Release notes".to_string()),
+            published_date: Some(0),
+            id: None,
+            domain: None,
+            max_verbatim_word_limit: None,
+            public_domain: None,
+        }]);
+        let block = results.to_tool_result("tooluse_1", "rust");
+        let text = block["content"].as_str().unwrap();
+        assert_eq!(block["tool_use_id"], "tooluse_1");
+        assert!(text.contains("Rust 1.90"));
+        assert!(text.contains("https://blog.rust-lang.org"));
+        assert!(text.contains("January 1, 1970"));
+        assert_eq!(results.to_block_content()[0]["type"], "web_search_result");
+
+        let error = SearchOutcome::Error("max_uses_exceeded");
+        assert_eq!(error.to_tool_result("tooluse_2", "rust")["is_error"], true);
+        assert_eq!(error.to_block_content()["error_code"], "max_uses_exceeded");
+    }
+
+    #[test]
+    fn test_tool_description_includes_user_location() {
+        assert_eq!(tool_description(None), WEB_SEARCH_TOOL_DESCRIPTION);
+
+        let location = UserLocation {
+            location_type: Some("approximate".to_string()),
+            city: Some("San Francisco".to_string()),
+            country: Some("US".to_string()),
+            timezone: Some("America/Los_Angeles".to_string()),
+            ..Default::default()
+        };
+        let description = tool_description(Some(&location));
+        assert!(description.contains("located in San Francisco, US"));
+        assert!(description.contains("America/Los_Angeles"));
     }
 }
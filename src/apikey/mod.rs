@@ -0,0 +1,11 @@
+//! 客户端 API Key 管理模块
+//!
+//! 在 `config.json` 的主 `apiKey` 之外，支持为不同客户端签发独立的 API Key，
+//! 每个 Key 可配置名称、启用状态、允许的模型、按日/按月的请求数与 token 配额以及过期时间。
+//!
+//! Key 列表持久化在 `api_keys.json`（默认位于凭据文件同目录，可通过 `--api-keys` 指定），
+//! 使用量统计随 Key 一并保存，按 debounce 策略落盘。
+
+mod store;
+
+pub use store::{ApiKeyEntry, ApiKeyRejection, ApiKeyStore, ApiKeyUsage, ClientKey};
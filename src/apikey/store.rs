@@ -0,0 +1,646 @@
+//! API Key 存储与配额检查
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::common::auth;
+use crate::model::registry;
+
+/// 使用量统计落盘的 debounce 间隔
+const USAGE_SAVE_DEBOUNCE: Duration = Duration::from_secs(30);
+
+/// 生成的 API Key 前缀
+const KEY_PREFIX: &str = "sk-kiro-";
+
+/// 单个客户端 API Key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyEntry {
+    /// 唯一 ID（由存储分配）
+    #[serde(default)]
+    pub id: u64,
+
+    /// 名称（用于区分客户端）
+    pub name: String,
+
+    /// API Key 明文
+    pub key: String,
+
+    /// 是否启用
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 允许访问的模型（为空表示不限制；匹配请求模型名或注册表解析后的模型 ID）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_models: Vec<String>,
+
+    /// 每日请求数上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_request_limit: Option<u64>,
+
+    /// 每月请求数上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_request_limit: Option<u64>,
+
+    /// 每日 token 上限（输入 + 输出）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_token_limit: Option<u64>,
+
+    /// 每月 token 上限（输入 + 输出）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_token_limit: Option<u64>,
+
+    /// 过期时间（RFC3339 格式）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+
+    /// 创建时间（RFC3339 格式）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+
+    /// 使用量统计
+    #[serde(default)]
+    pub usage: ApiKeyUsage,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// API Key 使用量统计
+///
+/// 日/月计数按 UTC 自然日、自然月重置
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyUsage {
+    /// 日计数所属日期（YYYY-MM-DD）
+    #[serde(default)]
+    pub day: String,
+    /// 月计数所属月份（YYYY-MM）
+    #[serde(default)]
+    pub month: String,
+    /// 当日请求数
+    #[serde(default)]
+    pub daily_requests: u64,
+    /// 当日 tokens
+    #[serde(default)]
+    pub daily_tokens: u64,
+    /// 当月请求数
+    #[serde(default)]
+    pub monthly_requests: u64,
+    /// 当月 tokens
+    #[serde(default)]
+    pub monthly_tokens: u64,
+    /// 累计请求数
+    #[serde(default)]
+    pub total_requests: u64,
+    /// 累计输入 tokens
+    #[serde(default)]
+    pub total_input_tokens: u64,
+    /// 累计输出 tokens
+    #[serde(default)]
+    pub total_output_tokens: u64,
+    /// 最后使用时间（RFC3339 格式）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_at: Option<String>,
+}
+
+impl ApiKeyUsage {
+    /// 跨日/跨月时重置对应计数
+    fn roll(&mut self, now: DateTime<Utc>) {
+        let day = now.format("%Y-%m-%d").to_string();
+        if self.day != day {
+            self.day = day;
+            self.daily_requests = 0;
+            self.daily_tokens = 0;
+        }
+        let month = now.format("%Y-%m").to_string();
+        if self.month != month {
+            self.month = month;
+            self.monthly_requests = 0;
+            self.monthly_tokens = 0;
+        }
+    }
+}
+
+impl ApiKeyEntry {
+    /// 生成新的随机 API Key
+    pub fn generate_key() -> String {
+        format!("{}{}", KEY_PREFIX, Uuid::new_v4().simple())
+    }
+
+    /// 脱敏后的 Key（用于列表展示）
+    pub fn masked_key(&self) -> String {
+        let chars: Vec<char> = self.key.chars().collect();
+        if chars.len() <= 12 {
+            return "*".repeat(chars.len());
+        }
+        let head: String = chars[..8].iter().collect();
+        let tail: String = chars[chars.len() - 4..].iter().collect();
+        format!("{}****{}", head, tail)
+    }
+
+    /// 是否已过期（无法解析的过期时间视为已过期）
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        match &self.expires_at {
+            Some(expires_at) => DateTime::parse_from_rfc3339(expires_at)
+                .map(|t| t <= now)
+                .unwrap_or(true),
+            None => false,
+        }
+    }
+
+    /// 是否允许访问指定模型
+    pub fn allows_model(&self, model: &str) -> bool {
+        if self.allowed_models.is_empty() {
+            return true;
+        }
+        let resolved = registry::registry().resolve(model).map(|m| m.id.clone());
+        self.allowed_models.iter().any(|allowed| {
+            allowed.eq_ignore_ascii_case(model)
+                || resolved
+                    .as_deref()
+                    .is_some_and(|id| allowed.eq_ignore_ascii_case(id))
+        })
+    }
+
+    /// 检查 Key 当前是否可用（启用且未过期）
+    fn check_active(&self, now: DateTime<Utc>) -> Result<(), ApiKeyRejection> {
+        if !self.enabled {
+            return Err(ApiKeyRejection::Disabled);
+        }
+        if self.is_expired(now) {
+            return Err(ApiKeyRejection::Expired);
+        }
+        Ok(())
+    }
+
+    /// 检查配额（调用前需先 roll 使用量）
+    fn check_quota(&self) -> Result<(), ApiKeyRejection> {
+        let checks = [
+            (
+                self.daily_request_limit,
+                self.usage.daily_requests,
+                "每日请求数",
+            ),
+            (
+                self.monthly_request_limit,
+                self.usage.monthly_requests,
+                "每月请求数",
+            ),
+            (
+                self.daily_token_limit,
+                self.usage.daily_tokens,
+                "每日 token",
+            ),
+            (
+                self.monthly_token_limit,
+                self.usage.monthly_tokens,
+                "每月 token",
+            ),
+        ];
+        for (limit, used, label) in checks {
+            if let Some(limit) = limit
+                && used >= limit
+            {
+                return Err(ApiKeyRejection::QuotaExceeded(format!(
+                    "{}配额已用尽（{}/{}）",
+                    label, used, limit
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 通过认证的客户端标识（由认证中间件写入请求扩展）
+#[derive(Debug, Clone)]
+pub struct ClientKey {
+    /// API Key ID
+    pub id: u64,
+    /// API Key 名称
+    pub name: String,
+}
+
+/// API Key 拒绝原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiKeyRejection {
+    /// Key 不存在
+    Invalid,
+    /// Key 已禁用
+    Disabled,
+    /// Key 已过期
+    Expired,
+    /// 不允许访问该模型
+    ModelNotAllowed { model: String },
+    /// 配额已用尽
+    QuotaExceeded(String),
+}
+
+impl std::fmt::Display for ApiKeyRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiKeyRejection::Invalid => write!(f, "Invalid API key"),
+            ApiKeyRejection::Disabled => write!(f, "API Key 已被禁用"),
+            ApiKeyRejection::Expired => write!(f, "API Key 已过期"),
+            ApiKeyRejection::ModelNotAllowed { model } => {
+                write!(f, "API Key 无权访问模型: {}", model)
+            }
+            ApiKeyRejection::QuotaExceeded(msg) => write!(f, "API Key {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ApiKeyRejection {}
+
+/// API Key 存储
+///
+/// 管理所有客户端 Key，负责认证、模型权限与配额检查以及使用量统计
+pub struct ApiKeyStore {
+    entries: Mutex<Vec<ApiKeyEntry>>,
+    path: Option<PathBuf>,
+    /// 使用量是否有未落盘的变更
+    usage_dirty: AtomicBool,
+    /// 上次落盘时间
+    last_save_at: Mutex<Option<Instant>>,
+}
+
+impl ApiKeyStore {
+    /// 使用给定的 Key 列表创建存储
+    pub fn new(entries: Vec<ApiKeyEntry>, path: Option<PathBuf>) -> Self {
+        Self {
+            entries: Mutex::new(entries),
+            path,
+            usage_dirty: AtomicBool::new(false),
+            last_save_at: Mutex::new(None),
+        }
+    }
+
+    /// 从文件加载（文件不存在时返回空存储）
+    pub fn load(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) if content.trim().is_empty() => Vec::new(),
+            Ok(content) => {
+                let mut entries: Vec<ApiKeyEntry> = serde_json::from_str(&content)
+                    .with_context(|| format!("解析 API Key 文件失败: {:?}", path))?;
+                // 补齐缺失的 ID
+                let max_id = entries.iter().map(|e| e.id).max().unwrap_or(0);
+                for (id, entry) in (max_id + 1..).zip(entries.iter_mut().filter(|e| e.id == 0)) {
+                    entry.id = id;
+                }
+                entries
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("读取 API Key 文件失败: {:?}", path));
+            }
+        };
+        Ok(Self::new(entries, Some(path)))
+    }
+
+    /// Key 数量
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    /// 认证 API Key
+    ///
+    /// 对所有 Key 执行常量时间比较，避免泄露匹配位置
+    pub fn authenticate(&self, key: &str) -> Result<ClientKey, ApiKeyRejection> {
+        let now = Utc::now();
+        let entries = self.entries.lock();
+        let mut matched = None;
+        for entry in entries.iter() {
+            if auth::constant_time_eq(key, &entry.key) {
+                matched = Some(entry);
+            }
+        }
+        let entry = matched.ok_or(ApiKeyRejection::Invalid)?;
+        entry.check_active(now)?;
+        Ok(ClientKey {
+            id: entry.id,
+            name: entry.name.clone(),
+        })
+    }
+
+    /// 准入检查：校验模型权限与配额，通过后计入一次请求
+    pub fn admit(&self, id: u64, model: &str) -> Result<(), ApiKeyRejection> {
+        let now = Utc::now();
+        {
+            let mut entries = self.entries.lock();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or(ApiKeyRejection::Invalid)?;
+            entry.check_active(now)?;
+            if !entry.allows_model(model) {
+                return Err(ApiKeyRejection::ModelNotAllowed {
+                    model: model.to_string(),
+                });
+            }
+            entry.usage.roll(now);
+            entry.check_quota()?;
+
+            let usage = &mut entry.usage;
+            usage.daily_requests += 1;
+            usage.monthly_requests += 1;
+            usage.total_requests += 1;
+            usage.last_used_at = Some(now.to_rfc3339());
+        }
+        self.save_usage_debounced();
+        Ok(())
+    }
+
+    /// 记录请求完成后的 token 使用量
+    pub fn record_tokens(&self, id: u64, input_tokens: u64, output_tokens: u64) {
+        let now = Utc::now();
+        {
+            let mut entries = self.entries.lock();
+            let Some(entry) = entries.iter_mut().find(|e| e.id == id) else {
+                return;
+            };
+            entry.usage.roll(now);
+            let usage = &mut entry.usage;
+            let total = input_tokens + output_tokens;
+            usage.daily_tokens += total;
+            usage.monthly_tokens += total;
+            usage.total_input_tokens += input_tokens;
+            usage.total_output_tokens += output_tokens;
+        }
+        self.save_usage_debounced();
+    }
+
+    /// 获取所有 Key（按 ID 排序）
+    pub fn list(&self) -> Vec<ApiKeyEntry> {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().clone();
+        // 展示前先按当前日期重置过期的日/月计数
+        for entry in entries.iter_mut() {
+            entry.usage.roll(now);
+        }
+        entries.sort_by_key(|e| e.id);
+        entries
+    }
+
+    /// 添加 Key
+    ///
+    /// 自动分配 ID 与创建时间；`key` 为空时自动生成
+    pub fn add(&self, mut entry: ApiKeyEntry) -> anyhow::Result<ApiKeyEntry> {
+        if entry.name.trim().is_empty() {
+            bail!("API Key 名称不能为空");
+        }
+        if entry.key.trim().is_empty() {
+            entry.key = ApiKeyEntry::generate_key();
+        }
+        validate_expires_at(&entry.expires_at)?;
+
+        {
+            let mut entries = self.entries.lock();
+            if entries.iter().any(|e| e.key == entry.key) {
+                bail!("API Key 已存在");
+            }
+            entry.id = entries.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+            entry.created_at = Some(Utc::now().to_rfc3339());
+            entry.usage = ApiKeyUsage::default();
+            entries.push(entry.clone());
+        }
+        self.persist()?;
+        Ok(entry)
+    }
+
+    /// 修改 Key
+    pub fn update(&self, id: u64, f: impl FnOnce(&mut ApiKeyEntry)) -> anyhow::Result<ApiKeyEntry> {
+        let updated = {
+            let mut entries = self.entries.lock();
+            let entry = match entries.iter_mut().find(|e| e.id == id) {
+                Some(e) => e,
+                None => bail!("API Key 不存在: {}", id),
+            };
+            let mut candidate = entry.clone();
+            f(&mut candidate);
+            if candidate.name.trim().is_empty() {
+                bail!("API Key 名称不能为空");
+            }
+            validate_expires_at(&candidate.expires_at)?;
+            *entry = candidate.clone();
+            candidate
+        };
+        self.persist()?;
+        Ok(updated)
+    }
+
+    /// 删除 Key
+    pub fn delete(&self, id: u64) -> anyhow::Result<()> {
+        {
+            let mut entries = self.entries.lock();
+            let before = entries.len();
+            entries.retain(|e| e.id != id);
+            if entries.len() == before {
+                bail!("API Key 不存在: {}", id);
+            }
+        }
+        self.persist()
+    }
+
+    // ============ 持久化 ============
+
+    /// 将 Key 列表写入文件
+    fn persist(&self) -> anyhow::Result<()> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let json = {
+            let entries = self.entries.lock();
+            serde_json::to_string_pretty(&*entries).context("序列化 API Key 失败")?
+        };
+
+        // 写入文件（在 Tokio 多线程 runtime 内使用 block_in_place 避免阻塞 worker）
+        let in_multi_thread_runtime = tokio::runtime::Handle::try_current()
+            .is_ok_and(|h| h.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread);
+        if in_multi_thread_runtime {
+            tokio::task::block_in_place(|| std::fs::write(path, &json))
+        } else {
+            std::fs::write(path, &json)
+        }
+        .with_context(|| format!("写入 API Key 文件失败: {:?}", path))?;
+
+        *self.last_save_at.lock() = Some(Instant::now());
+        self.usage_dirty.store(false, Ordering::Relaxed);
+        tracing::debug!("已保存 API Key 文件: {:?}", path);
+        Ok(())
+    }
+
+    /// 标记使用量已更新，并按 debounce 策略决定是否立即落盘
+    fn save_usage_debounced(&self) {
+        self.usage_dirty.store(true, Ordering::Relaxed);
+
+        let should_flush = {
+            let last = *self.last_save_at.lock();
+            match last {
+                Some(last_saved_at) => last_saved_at.elapsed() >= USAGE_SAVE_DEBOUNCE,
+                None => true,
+            }
+        };
+
+        if should_flush && let Err(e) = self.persist() {
+            tracing::warn!("保存 API Key 使用量失败: {}", e);
+        }
+    }
+}
+
+impl Drop for ApiKeyStore {
+    fn drop(&mut self) {
+        if self.usage_dirty.load(Ordering::Relaxed)
+            && let Err(e) = self.persist()
+        {
+            tracing::warn!("保存 API Key 使用量失败: {}", e);
+        }
+    }
+}
+
+/// 校验过期时间格式
+fn validate_expires_at(expires_at: &Option<String>) -> anyhow::Result<()> {
+    if let Some(value) = expires_at
+        && DateTime::parse_from_rfc3339(value).is_err()
+    {
+        bail!("过期时间格式无效（需要 RFC3339）: {}", value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, key: &str) -> ApiKeyEntry {
+        ApiKeyEntry {
+            id: 0,
+            name: name.to_string(),
+            key: key.to_string(),
+            enabled: true,
+            allowed_models: Vec::new(),
+            daily_request_limit: None,
+            monthly_request_limit: None,
+            daily_token_limit: None,
+            monthly_token_limit: None,
+            expires_at: None,
+            created_at: None,
+            usage: ApiKeyUsage::default(),
+        }
+    }
+
+    #[test]
+    fn test_authenticate_rejects_disabled_and_expired() {
+        let store = ApiKeyStore::new(Vec::new(), None);
+        let active = store.add(entry("active", "sk-active")).unwrap();
+        let disabled = store.add(entry("disabled", "sk-disabled")).unwrap();
+        store.update(disabled.id, |e| e.enabled = false).unwrap();
+        let expired = store.add(entry("expired", "sk-expired")).unwrap();
+        store
+            .update(expired.id, |e| {
+                e.expires_at = Some("2000-01-01T00:00:00Z".to_string())
+            })
+            .unwrap();
+
+        assert_eq!(store.authenticate("sk-active").unwrap().id, active.id);
+        assert_eq!(
+            store.authenticate("sk-disabled").unwrap_err(),
+            ApiKeyRejection::Disabled
+        );
+        assert_eq!(
+            store.authenticate("sk-expired").unwrap_err(),
+            ApiKeyRejection::Expired
+        );
+        assert_eq!(
+            store.authenticate("sk-unknown").unwrap_err(),
+            ApiKeyRejection::Invalid
+        );
+    }
+
+    #[test]
+    fn test_admit_enforces_quotas_and_models() {
+        let store = ApiKeyStore::new(Vec::new(), None);
+        let mut limited = entry("limited", "sk-limited");
+        limited.daily_request_limit = Some(2);
+        limited.monthly_token_limit = Some(100);
+        limited.allowed_models = vec!["claude-sonnet-4-5-20250929".to_string()];
+        let limited = store.add(limited).unwrap();
+
+        // 通过注册表解析的模型 ID 匹配
+        assert!(store.admit(limited.id, "claude-sonnet-4-5").is_ok());
+        assert!(matches!(
+            store.admit(limited.id, "claude-opus-4-6"),
+            Err(ApiKeyRejection::ModelNotAllowed { .. })
+        ));
+
+        // token 配额
+        store.record_tokens(limited.id, 80, 30);
+        assert!(matches!(
+            store.admit(limited.id, "claude-sonnet-4-5"),
+            Err(ApiKeyRejection::QuotaExceeded(_))
+        ));
+
+        let usage = &store.list()[0].usage;
+        assert_eq!(usage.daily_requests, 1);
+        assert_eq!(usage.total_input_tokens, 80);
+        assert_eq!(usage.total_output_tokens, 30);
+        assert_eq!(usage.monthly_tokens, 110);
+    }
+
+    #[test]
+    fn test_usage_rolls_over_by_day_and_month() {
+        let mut usage = ApiKeyUsage {
+            day: "2026-01-31".to_string(),
+            month: "2026-01".to_string(),
+            daily_requests: 5,
+            daily_tokens: 50,
+            monthly_requests: 9,
+            monthly_tokens: 90,
+            total_requests: 9,
+            ..Default::default()
+        };
+
+        let next_day = DateTime::parse_from_rfc3339("2026-02-01T08:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        usage.roll(next_day);
+
+        assert_eq!(usage.day, "2026-02-01");
+        assert_eq!(usage.month, "2026-02");
+        assert_eq!(usage.daily_requests, 0);
+        assert_eq!(usage.monthly_tokens, 0);
+        assert_eq!(usage.total_requests, 9);
+    }
+
+    #[test]
+    fn test_persist_and_reload() {
+        let path = std::env::temp_dir().join(format!("kiro_api_keys_{}.json", Uuid::new_v4()));
+        {
+            let store = ApiKeyStore::load(&path).unwrap();
+            assert_eq!(store.len(), 0);
+            let created = store.add(entry("ci", "")).unwrap();
+            assert!(created.key.starts_with(KEY_PREFIX));
+            store.admit(created.id, "claude-sonnet-4-5").unwrap();
+            // Drop 时落盘使用量
+        }
+
+        let store = ApiKeyStore::load(&path).unwrap();
+        let entries = store.list();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "ci");
+        assert_eq!(entries[0].usage.total_requests, 1);
+        assert!(entries[0].masked_key().contains("****"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,9 @@
+//! Message Batches 任务队列模块
+//!
+//! `/v1/messages/batches` 创建的批处理持久化在 `batches/<batch_id>/` 下（默认位于凭据文件同目录），
+//! 由后台 worker 按 `batches.concurrency` 限制的并发逐个执行，结果追加写入 `results.jsonl`。
+//! 重启后未结束的批处理会从尚无结果的请求继续执行；已结束的批处理超过 `batches.retentionDays` 后删除。
+
+mod store;
+
+pub use store::{BatchError, BatchRequest, BatchResult, BatchStore, MessageBatch};
@@ -0,0 +1,818 @@
+//! 批处理持久化存储与待执行队列
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use crate::apikey::ClientKey;
+use crate::common::io::blocking_io;
+
+/// 批处理元数据文件名
+const BATCH_FILE: &str = "batch.json";
+
+/// 请求列表文件名
+const REQUESTS_FILE: &str = "requests.jsonl";
+
+/// 执行结果文件名
+const RESULTS_FILE: &str = "results.jsonl";
+
+/// 批处理有效期（小时），超过后仍未执行的请求标记为 expired
+const EXPIRY_HOURS: i64 = 24;
+
+/// 批处理状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessingStatus {
+    InProgress,
+    Canceling,
+    Ended,
+}
+
+/// 各状态的请求数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+impl RequestCounts {
+    /// 一个请求从 processing 转为结果状态
+    fn settle(&mut self, result: &BatchResult) {
+        self.processing = self.processing.saturating_sub(1);
+        match result {
+            BatchResult::Succeeded { .. } => self.succeeded += 1,
+            BatchResult::Errored { .. } => self.errored += 1,
+            BatchResult::Canceled => self.canceled += 1,
+            BatchResult::Expired => self.expired += 1,
+        }
+    }
+}
+
+/// 单个请求的执行结果
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchResult {
+    /// 成功，`message` 为 Messages API 的响应体
+    Succeeded {
+        message: Value,
+    },
+    /// 失败，`error` 为 `{"type": "error", "error": {...}}`
+    Errored {
+        error: Value,
+    },
+    Canceled,
+    Expired,
+}
+
+/// 批处理中的单个请求
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    /// Messages API 请求参数
+    pub params: Value,
+}
+
+/// 结果文件中的一行
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchResultLine {
+    pub custom_id: String,
+    pub result: BatchResult,
+}
+
+/// 批处理对象（与 Message Batches API 的 `message_batch` 一致）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageBatch {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub processing_status: ProcessingStatus,
+    pub request_counts: RequestCounts,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub archived_at: Option<DateTime<Utc>>,
+    pub cancel_initiated_at: Option<DateTime<Utc>>,
+    pub results_url: Option<String>,
+}
+
+/// 持久化的批处理元数据（附带创建者，不返回给客户端）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BatchRecord {
+    #[serde(flatten)]
+    batch: MessageBatch,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_key_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_key_name: Option<String>,
+}
+
+impl BatchRecord {
+    /// 主 API Key 可以访问全部批处理，客户端 Key 只能访问自己创建的
+    fn visible_to(&self, client: Option<&ClientKey>) -> bool {
+        client.is_none_or(|c| self.api_key_id == Some(c.id))
+    }
+}
+
+/// 批处理操作错误
+#[derive(Debug)]
+pub enum BatchError {
+    /// 批处理不存在（或不属于当前客户端）
+    NotFound,
+    /// 批处理尚未结束
+    NotEnded,
+    /// 读写存储失败
+    Storage(anyhow::Error),
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchError::NotFound => write!(f, "批处理不存在"),
+            BatchError::NotEnded => write!(f, "批处理尚未结束"),
+            BatchError::Storage(e) => write!(f, "批处理存储错误: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+impl From<anyhow::Error> for BatchError {
+    fn from(e: anyhow::Error) -> Self {
+        BatchError::Storage(e)
+    }
+}
+
+/// 交给后台执行的单个请求
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub batch_id: String,
+    /// 创建批处理的客户端（用于配额与用量归属）
+    pub client: Option<ClientKey>,
+    pub request: BatchRequest,
+}
+
+/// 内存中的批处理状态
+struct BatchEntry {
+    record: BatchRecord,
+    /// 尚未开始执行的请求
+    pending: VecDeque<BatchRequest>,
+    /// 正在执行的请求数
+    running: usize,
+    /// 串行化该批处理的状态修改与磁盘写入，见 [`BatchStore::update`]
+    write_lock: Arc<Mutex<()>>,
+}
+
+/// 批处理存储
+///
+/// 每个批处理一个目录：`batch.json`（元数据）、`requests.jsonl`（请求）、`results.jsonl`（结果，追加写入）。
+/// 启动时未结束批处理中尚无结果的请求会重新入队，因此执行中的请求在重启后会被重新执行。
+pub struct BatchStore {
+    dir: PathBuf,
+    /// 已结束批处理的保留天数（0 表示永久保留）
+    retention_days: u32,
+    batches: Mutex<HashMap<String, BatchEntry>>,
+    /// 有新的待执行请求或请求执行完成时通知后台 worker
+    notify: Notify,
+}
+
+impl BatchStore {
+    /// 打开存储目录并恢复未结束的批处理
+    pub fn open(dir: impl Into<PathBuf>, retention_days: u32) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        let mut batches = HashMap::new();
+
+        if dir.exists() {
+            let entries =
+                fs::read_dir(&dir).with_context(|| format!("读取批处理目录失败: {:?}", dir))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.is_dir() {
+                    continue;
+                }
+                match load_batch(&path) {
+                    Ok(mut batch) => {
+                        // 重启前已在取消中的批处理，剩余请求直接标记为 canceled
+                        if batch.record.batch.processing_status == ProcessingStatus::Canceling {
+                            let lines = drain_pending(&mut batch, BatchResult::Canceled);
+                            append_results(&path, &lines)?;
+                        }
+                        finish_if_done(&mut batch, Utc::now());
+                        save_record(&path, &batch.record)?;
+                        batches.insert(batch.record.batch.id.clone(), batch);
+                    }
+                    Err(e) => tracing::warn!("加载批处理失败 {:?}: {}", path, e),
+                }
+            }
+        }
+
+        let store = Self {
+            dir,
+            retention_days,
+            batches: Mutex::new(batches),
+            notify: Notify::new(),
+        };
+        store.prune(Utc::now());
+        Ok(store)
+    }
+
+    /// 待执行（含执行中）的请求数量
+    pub fn pending_count(&self) -> usize {
+        self.batches
+            .lock()
+            .values()
+            .map(|b| b.pending.len() + b.running)
+            .sum()
+    }
+
+    /// 创建批处理并加入执行队列
+    pub fn create(
+        &self,
+        requests: Vec<BatchRequest>,
+        client: Option<&ClientKey>,
+    ) -> anyhow::Result<MessageBatch> {
+        let now = Utc::now();
+        let id = format!("msgbatch_{}", uuid::Uuid::new_v4().simple());
+        let record = BatchRecord {
+            batch: MessageBatch {
+                id: id.clone(),
+                object_type: "message_batch".to_string(),
+                processing_status: ProcessingStatus::InProgress,
+                request_counts: RequestCounts {
+                    processing: requests.len() as u64,
+                    ..Default::default()
+                },
+                ended_at: None,
+                created_at: now,
+                expires_at: now + Duration::hours(EXPIRY_HOURS),
+                archived_at: None,
+                cancel_initiated_at: None,
+                results_url: None,
+            },
+            api_key_id: client.map(|c| c.id),
+            api_key_name: client.map(|c| c.name.clone()),
+        };
+
+        let path = self.dir.join(&id);
+        blocking_io(|| -> anyhow::Result<()> {
+            fs::create_dir_all(&path).with_context(|| format!("创建批处理目录失败: {:?}", path))?;
+            let file = File::create(path.join(REQUESTS_FILE))
+                .with_context(|| format!("写入批处理请求失败: {:?}", path))?;
+            let mut writer = BufWriter::new(file);
+            for request in &requests {
+                serde_json::to_writer(&mut writer, request).context("序列化批处理请求失败")?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+            save_record(&path, &record)
+        })?;
+
+        let batch = record.batch.clone();
+        self.batches.lock().insert(
+            id,
+            BatchEntry {
+                record,
+                pending: requests.into(),
+                running: 0,
+                write_lock: Arc::default(),
+            },
+        );
+        self.prune(now);
+        self.notify.notify_one();
+        Ok(batch)
+    }
+
+    /// 列出当前客户端可见的批处理（按创建时间倒序）
+    pub fn list(&self, client: Option<&ClientKey>) -> Vec<MessageBatch> {
+        let mut batches: Vec<MessageBatch> = self
+            .batches
+            .lock()
+            .values()
+            .filter(|b| b.record.visible_to(client))
+            .map(|b| b.record.batch.clone())
+            .collect();
+        batches.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        batches
+    }
+
+    /// 获取单个批处理
+    pub fn get(&self, id: &str, client: Option<&ClientKey>) -> Result<MessageBatch, BatchError> {
+        self.batches
+            .lock()
+            .get(id)
+            .filter(|b| b.record.visible_to(client))
+            .map(|b| b.record.batch.clone())
+            .ok_or(BatchError::NotFound)
+    }
+
+    /// 取消批处理：未执行的请求标记为 canceled，执行中的请求完成后批处理结束
+    pub fn cancel(&self, id: &str, client: Option<&ClientKey>) -> Result<MessageBatch, BatchError> {
+        let mut canceled = false;
+        let batch = self.update(id, |batch| {
+            if !batch.record.visible_to(client) {
+                return Err(BatchError::NotFound);
+            }
+            if batch.record.batch.processing_status != ProcessingStatus::InProgress {
+                return Ok(None);
+            }
+            let now = Utc::now();
+            batch.record.batch.processing_status = ProcessingStatus::Canceling;
+            batch.record.batch.cancel_initiated_at = Some(now);
+            let lines = drain_pending(batch, BatchResult::Canceled);
+            finish_if_done(batch, now);
+            canceled = true;
+            Ok(Some(lines))
+        })?;
+        if canceled {
+            tracing::info!(batch_id = %id, "批处理已取消");
+        }
+        Ok(batch)
+    }
+
+    /// 删除已结束的批处理及其结果
+    ///
+    /// 先在锁内从内存中移除，再在锁外删除目录；删除失败时放回内存
+    pub fn delete(&self, id: &str, client: Option<&ClientKey>) -> Result<(), BatchError> {
+        let batch = {
+            let mut batches = self.batches.lock();
+            let batch = batches
+                .get(id)
+                .filter(|b| b.record.visible_to(client))
+                .ok_or(BatchError::NotFound)?;
+            if batch.record.batch.processing_status != ProcessingStatus::Ended {
+                return Err(BatchError::NotEnded);
+            }
+            batches.remove(id).ok_or(BatchError::NotFound)?
+        };
+
+        let path = self.dir.join(id);
+        let result = blocking_io(|| fs::remove_dir_all(&path))
+            .with_context(|| format!("删除批处理目录失败: {:?}", path));
+        if result.is_err() {
+            self.batches.lock().insert(id.to_string(), batch);
+        }
+        Ok(result?)
+    }
+
+    /// 读取已结束批处理的结果（JSONL）
+    pub fn results(&self, id: &str, client: Option<&ClientKey>) -> Result<Vec<u8>, BatchError> {
+        let batch = self.get(id, client)?;
+        if batch.processing_status != ProcessingStatus::Ended {
+            return Err(BatchError::NotEnded);
+        }
+
+        let path = self.dir.join(id).join(RESULTS_FILE);
+        match blocking_io(|| fs::read(&path)) {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(anyhow::Error::new(e)
+                .context(format!("读取批处理结果失败: {:?}", path))
+                .into()),
+        }
+    }
+
+    /// 取出下一个待执行的请求（先创建的批处理优先）
+    ///
+    /// 已过期批处理的剩余请求在此标记为 expired
+    pub fn next_job(&self) -> Option<BatchJob> {
+        let now = Utc::now();
+        let is_expired =
+            |batch: &BatchEntry| batch.record.batch.expires_at <= now && !batch.pending.is_empty();
+
+        let expired_ids: Vec<String> = self
+            .batches
+            .lock()
+            .iter()
+            .filter(|(_, batch)| is_expired(batch))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired_ids {
+            let expired = self.update(&id, |batch| {
+                if !is_expired(batch) {
+                    return Ok(None);
+                }
+                let lines = drain_pending(batch, BatchResult::Expired);
+                finish_if_done(batch, now);
+                Ok(Some(lines))
+            });
+            match expired {
+                Ok(_) => tracing::warn!(batch_id = %id, "批处理已过期，剩余请求标记为 expired"),
+                Err(e) => tracing::warn!(batch_id = %id, "保存过期批处理失败: {}", e),
+            }
+        }
+
+        let mut batches = self.batches.lock();
+        let batch = batches
+            .values_mut()
+            .filter(|b| !b.pending.is_empty())
+            .min_by(|a, b| {
+                a.record
+                    .batch
+                    .created_at
+                    .cmp(&b.record.batch.created_at)
+                    .then(a.record.batch.id.cmp(&b.record.batch.id))
+            })?;
+        let request = batch.pending.pop_front()?;
+        batch.running += 1;
+
+        let client = match (batch.record.api_key_id, &batch.record.api_key_name) {
+            (Some(id), Some(name)) => Some(ClientKey {
+                id,
+                name: name.clone(),
+            }),
+            _ => None,
+        };
+        Some(BatchJob {
+            batch_id: batch.record.batch.id.clone(),
+            client,
+            request,
+        })
+    }
+
+    /// 记录请求的执行结果
+    pub fn complete(&self, batch_id: &str, custom_id: String, result: BatchResult) {
+        let saved = self.update(batch_id, |batch| {
+            batch.running = batch.running.saturating_sub(1);
+            batch.record.batch.request_counts.settle(&result);
+            finish_if_done(batch, Utc::now());
+            Ok(Some(vec![BatchResultLine { custom_id, result }]))
+        });
+        match saved {
+            Ok(batch) if batch.processing_status == ProcessingStatus::Ended => {
+                tracing::info!(
+                    batch_id = %batch_id,
+                    counts = ?batch.request_counts,
+                    "批处理已结束"
+                );
+            }
+            Ok(_) | Err(BatchError::NotFound) => {}
+            Err(e) => tracing::warn!(batch_id = %batch_id, "写入批处理结果失败: {}", e),
+        }
+        self.notify.notify_one();
+    }
+
+    /// 修改单个批处理的内存状态并持久化，返回修改后的批处理
+    ///
+    /// `update` 在全局锁内执行，返回需要追加的结果行（`None` 表示没有修改、无需写盘）；
+    /// 结果与元数据在全局锁外写入，不阻塞其他批处理的查询与调度。
+    /// 同一批处理的修改与写入由其写锁串行化，保证磁盘上的元数据与结果文件一致。
+    fn update(
+        &self,
+        id: &str,
+        update: impl FnOnce(&mut BatchEntry) -> Result<Option<Vec<BatchResultLine>>, BatchError>,
+    ) -> Result<MessageBatch, BatchError> {
+        let write_lock = self
+            .batches
+            .lock()
+            .get(id)
+            .map(|b| b.write_lock.clone())
+            .ok_or(BatchError::NotFound)?;
+
+        blocking_io(|| {
+            let _guard = write_lock.lock();
+            let (lines, record) = {
+                let mut batches = self.batches.lock();
+                let batch = batches.get_mut(id).ok_or(BatchError::NotFound)?;
+                match update(batch)? {
+                    Some(lines) => (lines, batch.record.clone()),
+                    None => return Ok(batch.record.batch.clone()),
+                }
+            };
+
+            let path = self.dir.join(id);
+            append_results(&path, &lines)?;
+            save_record(&path, &record)?;
+            Ok(record.batch)
+        })
+    }
+
+    /// 等待队列变化（新批处理或请求完成）
+    pub async fn changed(&self) {
+        self.notify.notified().await
+    }
+
+    /// 删除超过保留天数的已结束批处理
+    ///
+    /// 在锁内取出过期批处理，锁外删除目录，删除失败的放回内存等待下次清理
+    fn prune(&self, now: DateTime<Utc>) {
+        if self.retention_days == 0 {
+            return;
+        }
+        let cutoff = now - Duration::days(self.retention_days as i64);
+
+        let expired: Vec<(String, BatchEntry)> = self
+            .batches
+            .lock()
+            .extract_if(|_, batch| batch.record.batch.ended_at.is_some_and(|t| t < cutoff))
+            .collect();
+        for (id, batch) in expired {
+            let path = self.dir.join(&id);
+            match blocking_io(|| fs::remove_dir_all(&path)) {
+                Ok(()) => tracing::info!("已删除过期批处理: {}", id),
+                Err(e) => {
+                    tracing::warn!("删除过期批处理失败 {}: {}", id, e);
+                    self.batches.lock().insert(id, batch);
+                }
+            }
+        }
+    }
+}
+
+/// 从目录加载批处理，已有结果的请求不再入队
+fn load_batch(path: &Path) -> anyhow::Result<BatchEntry> {
+    let content = fs::read_to_string(path.join(BATCH_FILE))
+        .with_context(|| format!("读取批处理元数据失败: {:?}", path))?;
+    let mut record: BatchRecord = serde_json::from_str(&content).context("解析批处理元数据失败")?;
+
+    let mut pending = VecDeque::new();
+    if record.batch.processing_status != ProcessingStatus::Ended {
+        let mut counts = RequestCounts::default();
+        let mut done = HashSet::new();
+        for line in read_lines(&path.join(RESULTS_FILE))? {
+            let line: BatchResultLine =
+                serde_json::from_str(&line).context("解析批处理结果失败")?;
+            counts.settle(&line.result);
+            done.insert(line.custom_id);
+        }
+        for line in read_lines(&path.join(REQUESTS_FILE))? {
+            let request: BatchRequest =
+                serde_json::from_str(&line).context("解析批处理请求失败")?;
+            if !done.contains(&request.custom_id) {
+                pending.push_back(request);
+            }
+        }
+        counts.processing = pending.len() as u64;
+        record.batch.request_counts = counts;
+    }
+
+    Ok(BatchEntry {
+        record,
+        pending,
+        running: 0,
+        write_lock: Arc::default(),
+    })
+}
+
+/// 读取 JSONL 文件的非空行（文件不存在时为空）
+fn read_lines(path: &Path) -> anyhow::Result<Vec<String>> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("读取文件失败: {:?}", path)),
+    };
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("读取文件失败: {:?}", path))?;
+        if !line.trim().is_empty() {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+/// 将未执行的请求全部以指定结果结束，返回需要追加的结果行
+fn drain_pending(batch: &mut BatchEntry, result: BatchResult) -> Vec<BatchResultLine> {
+    let lines: Vec<BatchResultLine> = batch
+        .pending
+        .drain(..)
+        .map(|request| BatchResultLine {
+            custom_id: request.custom_id,
+            result: result.clone(),
+        })
+        .collect();
+    for line in &lines {
+        batch.record.batch.request_counts.settle(&line.result);
+    }
+    lines
+}
+
+/// 没有待执行和执行中的请求时结束批处理
+fn finish_if_done(batch: &mut BatchEntry, now: DateTime<Utc>) {
+    let info = &mut batch.record.batch;
+    if info.processing_status == ProcessingStatus::Ended
+        || !batch.pending.is_empty()
+        || batch.running > 0
+    {
+        return;
+    }
+    info.processing_status = ProcessingStatus::Ended;
+    info.ended_at = Some(now);
+    info.results_url = Some(format!("/v1/messages/batches/{}/results", info.id));
+}
+
+fn append_results(path: &Path, lines: &[BatchResultLine]) -> anyhow::Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let mut content = String::new();
+    for line in lines {
+        content.push_str(&serde_json::to_string(line).context("序列化批处理结果失败")?);
+        content.push('\n');
+    }
+    let file_path = path.join(RESULTS_FILE);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file_path)
+        .and_then(|mut f| f.write_all(content.as_bytes()))
+        .with_context(|| format!("写入批处理结果失败: {:?}", file_path))
+}
+
+/// 原子写入元数据（先写临时文件再重命名）
+fn save_record(path: &Path, record: &BatchRecord) -> anyhow::Result<()> {
+    let content = serde_json::to_vec_pretty(record).context("序列化批处理元数据失败")?;
+    let tmp = path.join(format!("{}.tmp", BATCH_FILE));
+    fs::write(&tmp, content).with_context(|| format!("写入批处理元数据失败: {:?}", tmp))?;
+    fs::rename(&tmp, path.join(BATCH_FILE))
+        .with_context(|| format!("写入批处理元数据失败: {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn requests(ids: &[&str]) -> Vec<BatchRequest> {
+        ids.iter()
+            .map(|id| BatchRequest {
+                custom_id: id.to_string(),
+                params: json!({"model": "claude-sonnet-4-5", "max_tokens": 16, "messages": []}),
+            })
+            .collect()
+    }
+
+    fn succeeded() -> BatchResult {
+        BatchResult::Succeeded {
+            message: json!({"type": "message"}),
+        }
+    }
+
+    #[test]
+    fn test_batch_runs_to_end_and_serves_results() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let store = BatchStore::open(dir, 0).unwrap();
+        let batch = store.create(requests(&["a", "b"]), None).unwrap();
+        assert_eq!(batch.processing_status, ProcessingStatus::InProgress);
+        assert_eq!(batch.request_counts.processing, 2);
+        assert!(matches!(
+            store.results(&batch.id, None),
+            Err(BatchError::NotEnded)
+        ));
+
+        let first = store.next_job().unwrap();
+        let second = store.next_job().unwrap();
+        assert!(store.next_job().is_none());
+        assert_eq!(first.request.custom_id, "a");
+
+        store.complete(&batch.id, second.request.custom_id, succeeded());
+        let error = json!({"type": "error", "error": {"type": "api_error", "message": "x"}});
+        store.complete(
+            &batch.id,
+            first.request.custom_id,
+            BatchResult::Errored { error },
+        );
+
+        let batch = store.get(&batch.id, None).unwrap();
+        assert_eq!(batch.processing_status, ProcessingStatus::Ended);
+        assert_eq!(batch.request_counts.succeeded, 1);
+        assert_eq!(batch.request_counts.errored, 1);
+        assert_eq!(batch.request_counts.processing, 0);
+        assert_eq!(
+            batch.results_url.as_deref(),
+            Some(format!("/v1/messages/batches/{}/results", batch.id).as_str())
+        );
+
+        let results = String::from_utf8(store.results(&batch.id, None).unwrap()).unwrap();
+        let lines: Vec<BatchResultLine> = results
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0].custom_id, "b");
+        assert_eq!(lines[0].result, succeeded());
+        assert!(results.contains(r#""type":"errored""#));
+
+        store.delete(&batch.id, None).unwrap();
+        assert!(!dir.join(&batch.id).exists());
+    }
+
+    #[test]
+    fn test_concurrent_complete_persists_consistent_state() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let ids: Vec<String> = (0..32).map(|i| i.to_string()).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let store = BatchStore::open(dir, 0).unwrap();
+        let batch = store.create(requests(&ids), None).unwrap();
+
+        let jobs: Vec<BatchJob> = std::iter::from_fn(|| store.next_job()).collect();
+        assert_eq!(jobs.len(), ids.len());
+        std::thread::scope(|scope| {
+            for job in jobs {
+                let store = &store;
+                scope.spawn(move || {
+                    store.complete(&job.batch_id, job.request.custom_id, succeeded())
+                });
+            }
+        });
+
+        // 磁盘上的元数据是最后一次修改后的状态，结果文件没有遗漏
+        let record: BatchRecord = serde_json::from_str(
+            &fs::read_to_string(dir.join(&batch.id).join(BATCH_FILE)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(record.batch, store.get(&batch.id, None).unwrap());
+        assert_eq!(record.batch.processing_status, ProcessingStatus::Ended);
+        assert_eq!(record.batch.request_counts.succeeded, 32);
+        let results = store.results(&batch.id, None).unwrap();
+        assert_eq!(results.iter().filter(|b| **b == b'\n').count(), 32);
+    }
+
+    #[test]
+    fn test_reopen_requeues_unfinished_requests() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let id = {
+            let store = BatchStore::open(dir, 0).unwrap();
+            let batch = store.create(requests(&["a", "b", "c"]), None).unwrap();
+            let job = store.next_job().unwrap();
+            store.complete(&batch.id, job.request.custom_id, succeeded());
+            // "b" 执行中时进程退出
+            store.next_job().unwrap();
+            batch.id
+        };
+
+        let store = BatchStore::open(dir, 0).unwrap();
+        let batch = store.get(&id, None).unwrap();
+        assert_eq!(batch.processing_status, ProcessingStatus::InProgress);
+        assert_eq!(batch.request_counts.succeeded, 1);
+        assert_eq!(batch.request_counts.processing, 2);
+        assert_eq!(store.pending_count(), 2);
+        assert_eq!(store.next_job().unwrap().request.custom_id, "b");
+    }
+
+    #[test]
+    fn test_cancel_marks_pending_as_canceled() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let store = BatchStore::open(dir, 0).unwrap();
+        let batch = store.create(requests(&["a", "b", "c"]), None).unwrap();
+        let job = store.next_job().unwrap();
+
+        let canceled = store.cancel(&batch.id, None).unwrap();
+        assert_eq!(canceled.processing_status, ProcessingStatus::Canceling);
+        assert_eq!(canceled.request_counts.canceled, 2);
+        assert_eq!(canceled.request_counts.processing, 1);
+        assert!(canceled.cancel_initiated_at.is_some());
+        assert!(store.next_job().is_none());
+
+        // 执行中的请求完成后批处理结束
+        store.complete(&batch.id, job.request.custom_id, succeeded());
+        let batch = store.get(&batch.id, None).unwrap();
+        assert_eq!(batch.processing_status, ProcessingStatus::Ended);
+        assert_eq!(batch.request_counts.succeeded, 1);
+    }
+
+    #[test]
+    fn test_client_only_sees_own_batches() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let store = BatchStore::open(dir, 0).unwrap();
+        let alice = ClientKey {
+            id: 1,
+            name: "alice".to_string(),
+        };
+        let bob = ClientKey {
+            id: 2,
+            name: "bob".to_string(),
+        };
+        let batch = store.create(requests(&["a"]), Some(&alice)).unwrap();
+
+        assert_eq!(store.list(Some(&alice)).len(), 1);
+        assert!(store.list(Some(&bob)).is_empty());
+        assert_eq!(store.list(None).len(), 1);
+        assert!(matches!(
+            store.get(&batch.id, Some(&bob)),
+            Err(BatchError::NotFound)
+        ));
+        assert!(matches!(
+            store.cancel(&batch.id, Some(&bob)),
+            Err(BatchError::NotFound)
+        ));
+
+        // 执行时沿用创建者的身份
+        let job = store.next_job().unwrap();
+        assert_eq!(job.client.map(|c| c.id), Some(1));
+    }
+}
@@ -0,0 +1,108 @@
+//! 简单的 LRU 索引
+
+use std::collections::{BTreeMap, HashMap};
+
+/// 按访问顺序淘汰的 LRU 表
+///
+/// `order` 以单调递增的访问序号为键，最小序号即最久未使用的条目
+pub(super) struct Lru<V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (V, u64)>,
+    order: BTreeMap<u64, String>,
+}
+
+impl<V> Lru<V> {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// 读取条目并标记为最近使用
+    pub(super) fn get(&mut self, key: &str) -> Option<&V> {
+        let tick = self.next_tick();
+        let (_, last) = self.entries.get_mut(key)?;
+        self.order.remove(last);
+        *last = tick;
+        self.order.insert(tick, key.to_string());
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    /// 插入条目，返回因超出容量被淘汰的 key
+    pub(super) fn insert(&mut self, key: String, value: V) -> Vec<String> {
+        let tick = self.next_tick();
+        if let Some((_, last)) = self.entries.insert(key.clone(), (value, tick)) {
+            self.order.remove(&last);
+        }
+        self.order.insert(tick, key);
+
+        let mut evicted = Vec::new();
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            evicted.push(oldest);
+        }
+        evicted
+    }
+
+    pub(super) fn remove(&mut self, key: &str) -> Option<V> {
+        let (value, tick) = self.entries.remove(key)?;
+        self.order.remove(&tick);
+        Some(value)
+    }
+
+    pub(super) fn keys(&self) -> impl Iterator<Item = &String> {
+        self.order.values()
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut lru = Lru::new(2);
+        assert!(lru.insert("a".to_string(), 1).is_empty());
+        assert!(lru.insert("b".to_string(), 2).is_empty());
+
+        // 访问 a 后，b 成为最久未使用
+        assert_eq!(lru.get("a"), Some(&1));
+        assert_eq!(lru.insert("c".to_string(), 3), vec!["b".to_string()]);
+
+        assert_eq!(lru.len(), 2);
+        assert!(lru.get("b").is_none());
+        assert_eq!(lru.get("c"), Some(&3));
+    }
+
+    #[test]
+    fn test_reinsert_replaces_value() {
+        let mut lru = Lru::new(2);
+        lru.insert("a".to_string(), 1);
+        lru.insert("a".to_string(), 2);
+
+        assert_eq!(lru.len(), 1);
+        assert_eq!(lru.remove("a"), Some(2));
+        assert_eq!(lru.len(), 0);
+    }
+}
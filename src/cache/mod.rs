@@ -0,0 +1,19 @@
+//! 缓存模块
+//!
+//! 非流式响应缓存以转换后的 `ConversationState`（排除会话 ID 与延续 ID）加模型名的 SHA-256 作为 key，
+//! 缓存非流式请求的完整响应。相同请求再次到达时直接返回缓存内容，流式请求命中时
+//! 将缓存内容合成为 SSE 事件流回放。
+//!
+//! 支持进程内 LRU（`memory`）与磁盘文件（`disk`）两种存储，条目在 `ttlSecs` 后过期，
+//! 超过 `maxEntries` 时按 LRU 淘汰。客户端可通过 `cache-control: no-cache`（不读缓存）
+//! 或 `no-store`（不读也不写）跳过缓存。
+//!
+//! 另外提供 [`PromptCache`]：记录带 `cache_control` 断点的提示词前缀哈希，
+//! 用于在响应中报告 `cache_read_input_tokens` / `cache_creation_input_tokens`。
+
+mod lru;
+mod prompt;
+mod store;
+
+pub use prompt::PromptCache;
+pub use store::{CacheStats, CachedResponse, ResponseCache};
@@ -0,0 +1,72 @@
+//! 提示词前缀缓存（模拟 Anthropic prompt caching）
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+
+use super::lru::Lru;
+
+/// 最多记录的前缀哈希数
+const MAX_PREFIXES: usize = 100_000;
+
+/// 前缀哈希表
+///
+/// 只记录带 `cache_control` 断点的请求前缀哈希及其过期时间，不保存内容。
+/// 用于估算 `cache_read_input_tokens` / `cache_creation_input_tokens`
+pub struct PromptCache {
+    prefixes: Mutex<Lru<DateTime<Utc>>>,
+}
+
+impl Default for PromptCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PromptCache {
+    pub fn new() -> Self {
+        Self {
+            prefixes: Mutex::new(Lru::new(MAX_PREFIXES)),
+        }
+    }
+
+    /// 前缀是否仍在缓存中（过期条目会被移除）
+    pub fn contains(&self, hash: &str) -> bool {
+        let mut prefixes = self.prefixes.lock();
+        match prefixes.get(hash) {
+            Some(expires_at) if *expires_at > Utc::now() => true,
+            Some(_) => {
+                prefixes.remove(hash);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 写入或刷新前缀，有效期取现有值与 `now + ttl` 中较晚者
+    pub fn touch(&self, hash: &str, ttl: Duration) {
+        let mut prefixes = self.prefixes.lock();
+        let expires_at = Utc::now() + ttl;
+        let expires_at = match prefixes.get(hash) {
+            Some(existing) if *existing > expires_at => *existing,
+            _ => expires_at,
+        };
+        prefixes.insert(hash.to_string(), expires_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touch_and_expire() {
+        let cache = PromptCache::new();
+        assert!(!cache.contains("a"));
+
+        cache.touch("a", Duration::minutes(5));
+        assert!(cache.contains("a"));
+
+        cache.touch("b", Duration::seconds(-1));
+        assert!(!cache.contains("b"));
+    }
+}
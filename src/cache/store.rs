@@ -0,0 +1,422 @@
+//! 响应缓存存储
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::common::io::blocking_io;
+use crate::kiro::model::requests::conversation::ConversationState;
+use crate::model::config::{CacheStorage, ResponseCacheConfig};
+
+use super::lru::Lru;
+
+/// 磁盘缓存文件扩展名
+const FILE_SUFFIX: &str = ".json";
+
+/// 缓存的非流式响应
+///
+/// 只保存与请求无关的部分（消息 ID 在返回时重新生成）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedResponse {
+    /// Anthropic 格式的内容块
+    pub content: Vec<serde_json::Value>,
+    pub stop_reason: String,
+    /// 命中的停止序列（stop_reason 为 stop_sequence 时）
+    #[serde(default)]
+    pub stop_sequence: Option<String>,
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+    /// 写入缓存的时间
+    pub created_at: DateTime<Utc>,
+}
+
+/// 缓存统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub storage: CacheStorage,
+    pub entries: usize,
+    pub max_entries: usize,
+    pub ttl_secs: u64,
+    /// 命中次数
+    pub hits: u64,
+    /// 未命中次数（含过期）
+    pub misses: u64,
+    /// 写入次数
+    pub stores: u64,
+    /// LRU 淘汰次数
+    pub evictions: u64,
+    /// 客户端通过 `cache-control` 跳过缓存的次数
+    pub bypasses: u64,
+}
+
+enum Storage {
+    Memory(Mutex<Lru<CachedResponse>>),
+    /// 磁盘存储：内容写入 `<dir>/<key>.json`，内存中只保留 LRU 索引
+    Disk {
+        dir: PathBuf,
+        index: Mutex<Lru<()>>,
+    },
+}
+
+/// 内容寻址的响应缓存
+pub struct ResponseCache {
+    storage: Storage,
+    storage_kind: CacheStorage,
+    ttl: Duration,
+    max_entries: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stores: AtomicU64,
+    evictions: AtomicU64,
+    bypasses: AtomicU64,
+}
+
+impl ResponseCache {
+    /// 根据配置创建缓存
+    ///
+    /// `default_dir` 为未配置 `dir` 时的磁盘缓存目录；磁盘存储会在启动时加载已有条目的索引
+    pub fn new(config: &ResponseCacheConfig, default_dir: PathBuf) -> Self {
+        let max_entries = config.max_entries.max(1);
+        let ttl = Duration::seconds(config.ttl_secs.min(i64::MAX as u64) as i64);
+
+        let storage = match config.storage {
+            CacheStorage::Memory => Storage::Memory(Mutex::new(Lru::new(max_entries))),
+            CacheStorage::Disk => {
+                let dir = config
+                    .dir
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .unwrap_or(default_dir);
+                let index = load_disk_index(&dir, max_entries, ttl);
+                Storage::Disk {
+                    dir,
+                    index: Mutex::new(index),
+                }
+            }
+        };
+
+        Self {
+            storage,
+            storage_kind: config.storage,
+            ttl,
+            max_entries,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            stores: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            bypasses: AtomicU64::new(0),
+        }
+    }
+
+    /// 计算缓存 key
+    ///
+    /// 对转换后的 `ConversationState`（清除每次请求随机生成的会话 ID 与延续 ID）、
+    /// 客户端请求的模型名，以及影响输出但不在 `ConversationState` 中的参数（`params`）做 SHA-256
+    pub fn key(model: &str, state: &ConversationState, params: &serde_json::Value) -> String {
+        let mut state = state.clone();
+        state.conversation_id.clear();
+        state.agent_continuation_id = None;
+
+        let mut hasher = Sha256::new();
+        hasher.update(model.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(serde_json::to_vec(&state).unwrap_or_default());
+        hasher.update([0u8]);
+        hasher.update(serde_json::to_vec(params).unwrap_or_default());
+        hex::encode(hasher.finalize())
+    }
+
+    /// 查询缓存，过期条目视为未命中并删除
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let cached = match &self.storage {
+            Storage::Memory(lru) => lru.lock().get(key).cloned(),
+            Storage::Disk { dir, index } => {
+                let mut index = index.lock();
+                if index.get(key).is_some() {
+                    let path = entry_path(dir, key);
+                    let loaded = blocking_io(|| fs::read(&path))
+                        .ok()
+                        .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+                    if loaded.is_none() {
+                        tracing::warn!("读取响应缓存失败，已移除: {}", path.display());
+                        index.remove(key);
+                        remove_file(&path);
+                    }
+                    loaded
+                } else {
+                    None
+                }
+            }
+        };
+
+        match cached {
+            Some(response) if !self.is_expired(&response) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(response)
+            }
+            Some(_) => {
+                self.remove(key);
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// 写入缓存
+    pub fn put(&self, key: &str, response: CachedResponse) {
+        let evicted = match &self.storage {
+            Storage::Memory(lru) => lru.lock().insert(key.to_string(), response),
+            Storage::Disk { dir, index } => {
+                let mut index = index.lock();
+                let path = entry_path(dir, key);
+                let written = blocking_io(|| -> anyhow::Result<()> {
+                    fs::create_dir_all(dir)?;
+                    fs::write(&path, serde_json::to_vec(&response)?)?;
+                    Ok(())
+                });
+                if let Err(e) = written {
+                    tracing::warn!("写入响应缓存失败: {}: {}", path.display(), e);
+                    return;
+                }
+                let evicted = index.insert(key.to_string(), ());
+                for key in &evicted {
+                    remove_file(&entry_path(dir, key));
+                }
+                evicted
+            }
+        };
+
+        self.stores.fetch_add(1, Ordering::Relaxed);
+        self.evictions
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+    }
+
+    /// 记录一次客户端跳过缓存
+    pub fn record_bypass(&self) {
+        self.bypasses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 清空缓存，返回清除的条目数
+    pub fn clear(&self) -> usize {
+        match &self.storage {
+            Storage::Memory(lru) => {
+                let mut lru = lru.lock();
+                let count = lru.len();
+                lru.clear();
+                count
+            }
+            Storage::Disk { dir, index } => {
+                let mut index = index.lock();
+                let count = index.len();
+                for key in index.keys() {
+                    remove_file(&entry_path(dir, key));
+                }
+                index.clear();
+                count
+            }
+        }
+    }
+
+    /// 获取缓存统计
+    pub fn stats(&self) -> CacheStats {
+        let entries = match &self.storage {
+            Storage::Memory(lru) => lru.lock().len(),
+            Storage::Disk { index, .. } => index.lock().len(),
+        };
+        CacheStats {
+            storage: self.storage_kind,
+            entries,
+            max_entries: self.max_entries,
+            ttl_secs: self.ttl.num_seconds() as u64,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            stores: self.stores.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            bypasses: self.bypasses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_expired(&self, response: &CachedResponse) -> bool {
+        Utc::now() - response.created_at >= self.ttl
+    }
+
+    fn remove(&self, key: &str) {
+        match &self.storage {
+            Storage::Memory(lru) => {
+                lru.lock().remove(key);
+            }
+            Storage::Disk { dir, index } => {
+                index.lock().remove(key);
+                remove_file(&entry_path(dir, key));
+            }
+        }
+    }
+}
+
+fn entry_path(dir: &std::path::Path, key: &str) -> PathBuf {
+    dir.join(format!("{}{}", key, FILE_SUFFIX))
+}
+
+fn remove_file(path: &std::path::Path) {
+    if let Err(e) = blocking_io(|| fs::remove_file(path))
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        tracing::warn!("删除响应缓存文件失败: {}: {}", path.display(), e);
+    }
+}
+
+/// 扫描磁盘缓存目录，按修改时间重建 LRU 索引并删除已过期的文件
+fn load_disk_index(dir: &std::path::Path, max_entries: usize, ttl: Duration) -> Lru<()> {
+    let mut index = Lru::new(max_entries);
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return index;
+    };
+
+    let now = SystemTime::now();
+    let ttl = ttl.to_std().unwrap_or_default();
+    let mut files: Vec<(SystemTime, String, PathBuf)> = Vec::new();
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let Some(key) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(FILE_SUFFIX))
+        else {
+            continue;
+        };
+        let Ok(modified) = entry.metadata().and_then(|m| m.modified()) else {
+            continue;
+        };
+        if now.duration_since(modified).unwrap_or_default() >= ttl {
+            remove_file(&path);
+            continue;
+        }
+        files.push((modified, key.to_string(), path));
+    }
+
+    // 按修改时间从旧到新插入，超出容量的旧文件直接删除
+    files.sort_by_key(|(modified, _, _)| *modified);
+    for (_, key, _) in files {
+        for evicted in index.insert(key, ()) {
+            remove_file(&entry_path(dir, &evicted));
+        }
+    }
+
+    if index.len() > 0 {
+        tracing::info!("已加载 {} 条磁盘响应缓存: {}", index.len(), dir.display());
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(text: &str) -> CachedResponse {
+        CachedResponse {
+            content: vec![serde_json::json!({"type": "text", "text": text})],
+            stop_reason: "end_turn".to_string(),
+            stop_sequence: None,
+            input_tokens: 10,
+            output_tokens: 2,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn config(storage: CacheStorage, max_entries: usize) -> ResponseCacheConfig {
+        ResponseCacheConfig {
+            enabled: true,
+            ttl_secs: 60,
+            max_entries,
+            storage,
+            dir: None,
+        }
+    }
+
+    #[test]
+    fn test_key_ignores_conversation_ids() {
+        let a = ConversationState::new("conv-a").with_agent_continuation_id("cont-a");
+        let b = ConversationState::new("conv-b").with_agent_continuation_id("cont-b");
+
+        let params = serde_json::json!({});
+
+        assert_eq!(
+            ResponseCache::key("claude-sonnet-4-5", &a, &params),
+            ResponseCache::key("claude-sonnet-4-5", &b, &params)
+        );
+        assert_ne!(
+            ResponseCache::key("claude-sonnet-4-5", &a, &params),
+            ResponseCache::key("claude-opus-4-6", &a, &params)
+        );
+        assert_ne!(
+            ResponseCache::key("claude-sonnet-4-5", &a, &params),
+            ResponseCache::key(
+                "claude-sonnet-4-5",
+                &a,
+                &serde_json::json!({"stop_sequences": ["END"]})
+            )
+        );
+    }
+
+    #[test]
+    fn test_memory_hit_miss_and_eviction() {
+        let cache = ResponseCache::new(&config(CacheStorage::Memory, 1), PathBuf::new());
+
+        assert!(cache.get("a").is_none());
+        cache.put("a", response("first"));
+        let cached = cache.get("a").expect("应命中缓存");
+        assert_eq!(cached.content, response("first").content);
+        cache.put("b", response("second"));
+        assert!(cache.get("a").is_none());
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.stores, 2);
+        assert_eq!(stats.evictions, 1);
+    }
+
+    #[test]
+    fn test_expired_entry_is_miss() {
+        let cache = ResponseCache::new(&config(CacheStorage::Memory, 10), PathBuf::new());
+        let mut stale = response("stale");
+        stale.created_at = Utc::now() - Duration::seconds(120);
+        cache.put("a", stale);
+
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn test_disk_storage_survives_restart() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let mut config = config(CacheStorage::Disk, 10);
+        config.dir = Some(dir.to_string_lossy().to_string());
+
+        let cache = ResponseCache::new(&config, PathBuf::new());
+        cache.put("a", response("persisted"));
+        drop(cache);
+
+        let cache = ResponseCache::new(&config, PathBuf::new());
+        assert_eq!(cache.stats().entries, 1);
+        let cached = cache.get("a").expect("缓存应在重启后保留");
+        assert_eq!(cached.content, response("persisted").content);
+
+        assert_eq!(cache.clear(), 1);
+        assert!(cache.get("a").is_none());
+    }
+}
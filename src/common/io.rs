@@ -0,0 +1,12 @@
+//! 公共文件 IO 工具函数
+
+/// 在 Tokio 多线程 runtime 内使用 block_in_place 执行文件 IO，避免阻塞 worker
+pub fn blocking_io<T>(f: impl FnOnce() -> T) -> T {
+    let in_multi_thread_runtime = tokio::runtime::Handle::try_current()
+        .is_ok_and(|h| h.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread);
+    if in_multi_thread_runtime {
+        tokio::task::block_in_place(f)
+    } else {
+        f()
+    }
+}
@@ -1,12 +1,22 @@
 //! HTTP Client 构建模块
 //!
-//! 提供统一的 HTTP Client 构建功能，支持代理配置
+//! 提供统一的 HTTP Client 构建功能，支持代理配置。
+//!
+//! 下载用户或模型提供的 URL（web_fetch、图片/文档 URL 来源）时使用 [`build_public_client`]
+//! 与 [`get_public_url`]：只允许访问公网地址，并在每一跳重定向时重新检查，防止借助服务端访问
+//! 内网服务或云厂商元数据地址（SSRF）。
 
-use reqwest::{Client, Proxy};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, ClientBuilder, Proxy, Response, Url, header, redirect};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::model::config::TlsBackend;
 
+/// 下载不可信 URL 时最多跟随的重定向次数
+const MAX_REDIRECTS: usize = 10;
+
 /// 代理配置
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct ProxyConfig {
@@ -49,6 +59,32 @@ pub fn build_client(
     timeout_secs: u64,
     tls_backend: TlsBackend,
 ) -> anyhow::Result<Client> {
+    Ok(client_builder(proxy, timeout_secs, tls_backend)?.build()?)
+}
+
+/// 构建下载不可信 URL 的 HTTP Client
+///
+/// 不自动跟随重定向（由 [`get_public_url`] 逐跳检查后跟随）；未使用代理时 DNS 解析结果
+/// 必须全部为公网地址，避免检查与连接之间的 DNS 重绑定。使用代理时目标地址由代理解析，
+/// 只能依赖 [`check_public_url`] 的预先检查。
+pub fn build_public_client(
+    proxy: Option<&ProxyConfig>,
+    timeout_secs: u64,
+    tls_backend: TlsBackend,
+) -> anyhow::Result<Client> {
+    let mut builder =
+        client_builder(proxy, timeout_secs, tls_backend)?.redirect(redirect::Policy::none());
+    if proxy.is_none() {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+    Ok(builder.build()?)
+}
+
+fn client_builder(
+    proxy: Option<&ProxyConfig>,
+    timeout_secs: u64,
+    tls_backend: TlsBackend,
+) -> anyhow::Result<ClientBuilder> {
     let mut builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
 
     if tls_backend == TlsBackend::Rustls {
@@ -67,7 +103,177 @@ pub fn build_client(
         tracing::debug!("HTTP Client 使用代理: {}", proxy_config.url);
     }
 
-    Ok(builder.build()?)
+    Ok(builder)
+}
+
+/// 下载不可信 URL 失败的原因
+#[derive(Debug)]
+pub enum PublicUrlError {
+    /// URL 不允许访问（协议不支持、未通过调用方的规则或指向非公网地址）
+    NotAllowed(String),
+    /// 域名解析或请求失败
+    Request(String),
+}
+
+impl std::fmt::Display for PublicUrlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublicUrlError::NotAllowed(msg) | PublicUrlError::Request(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PublicUrlError {}
+
+/// 以 GET 请求下载不可信 URL，手动跟随重定向
+///
+/// 每一跳（包括第一跳）都先经过 `allow`（如域名规则）与 [`check_public_url`] 检查，
+/// `client` 需由 [`build_public_client`] 构建
+pub async fn get_public_url(
+    client: &Client,
+    mut url: Url,
+    accept: Option<&str>,
+    allow: impl Fn(&Url) -> bool,
+) -> Result<Response, PublicUrlError> {
+    for _ in 0..=MAX_REDIRECTS {
+        if !allow(&url) {
+            return Err(PublicUrlError::NotAllowed(format!("不允许访问 {}", url)));
+        }
+        check_public_url(&url).await?;
+
+        let mut request = client.get(url.clone());
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PublicUrlError::Request(format!("请求 {} 失败: {}", url, e)))?;
+
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|v| v.to_str().ok());
+        let Some(location) = location.filter(|_| response.status().is_redirection()) else {
+            return Ok(response);
+        };
+        let next = url.join(location).map_err(|e| {
+            PublicUrlError::Request(format!("{} 的重定向地址无效 {}: {}", url, location, e))
+        })?;
+        tracing::debug!(from = %url, to = %next, "跟随重定向");
+        url = next;
+    }
+    Err(PublicUrlError::Request(format!(
+        "重定向次数超过 {} 次",
+        MAX_REDIRECTS
+    )))
+}
+
+/// 检查 URL 是否为 http/https 且主机只解析到公网地址
+pub async fn check_public_url(url: &Url) -> Result<(), PublicUrlError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(PublicUrlError::NotAllowed(format!(
+            "仅支持 http/https URL: {}",
+            url
+        )));
+    }
+    let Some(host) = url.host_str() else {
+        return Err(PublicUrlError::NotAllowed(format!("URL 缺少主机: {}", url)));
+    };
+    // IPv6 字面量带方括号
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let Ok(ip) = host.parse::<IpAddr>() else {
+        let port = url.port_or_known_default().unwrap_or(80);
+        return resolve_public(host, port).await.map(|_| ());
+    };
+    if !is_public_ip(ip) {
+        return Err(PublicUrlError::NotAllowed(format!(
+            "不允许访问非公网地址: {}",
+            url
+        )));
+    }
+    Ok(())
+}
+
+/// 解析域名，任一地址不是公网地址时拒绝
+async fn resolve_public(host: &str, port: u16) -> Result<Vec<SocketAddr>, PublicUrlError> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| PublicUrlError::Request(format!("解析域名 {} 失败: {}", host, e)))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(PublicUrlError::Request(format!(
+            "域名 {} 没有解析结果",
+            host
+        )));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        return Err(PublicUrlError::NotAllowed(format!(
+            "域名 {} 解析到非公网地址 {}",
+            host,
+            addr.ip()
+        )));
+    }
+    Ok(addrs)
+}
+
+/// 只返回公网地址的 DNS 解析器
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// 是否为公网可路由地址
+///
+/// 拒绝本网络、回环、私有、链路本地（含 169.254.169.254 元数据地址）、运营商级 NAT、
+/// 文档、基准测试、组播与保留地址；内嵌 IPv4 的 IPv6 地址按内嵌地址判断
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(a == 0
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        // 100.64.0.0/10 运营商级 NAT
+        || (a == 100 && (b & 0xc0) == 64)
+        // 192.0.0.0/24 IETF 协议分配
+        || (a == 192 && b == 0 && c == 0)
+        || ip.is_documentation()
+        // 198.18.0.0/15 基准测试
+        || (a == 198 && (b & 0xfe) == 18)
+        // 组播、保留与广播
+        || a >= 224)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(v4);
+    }
+    let segments = ip.segments();
+    let embedded = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    // 64:ff9b::/96 NAT64
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        return is_public_ipv4(embedded(segments[6], segments[7]));
+    }
+    // 2002::/16 6to4
+    if segments[0] == 0x2002 {
+        return is_public_ipv4(embedded(segments[1], segments[2]));
+    }
+    // 只允许全局单播 2000::/3，排除其中的 Teredo（2001::/32）与文档地址（2001:db8::/32）
+    (segments[0] & 0xe000) == 0x2000
+        && !(segments[0] == 0x2001 && matches!(segments[1], 0 | 0x0db8))
 }
 
 #[cfg(test)]
@@ -102,4 +308,89 @@ mod tests {
         let client = build_client(Some(&config), 30, TlsBackend::Rustls);
         assert!(client.is_ok());
     }
+
+    #[test]
+    fn test_is_public_ip() {
+        for ip in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "172.31.255.255",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "192.0.0.170",
+            "192.0.2.1",
+            "198.18.0.1",
+            "224.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a00:1",
+            "2002:c0a8:101::1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "fec0::1",
+            "ff02::1",
+            "2001:db8::1",
+            "2001::1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "8.8.8.8",
+            "1.1.1.1",
+            "172.32.0.1",
+            "100.128.0.1",
+            "::ffff:8.8.8.8",
+            "2606:4700::1111",
+            "2002:808:808::1",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_public_url() {
+        for url in [
+            "http://127.0.0.1:8080/admin",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/",
+            "http://[::ffff:10.0.0.1]/",
+            "http://localhost/",
+            "file:///etc/passwd",
+        ] {
+            let result = check_public_url(&url.parse().unwrap()).await;
+            assert!(
+                matches!(result, Err(PublicUrlError::NotAllowed(_))),
+                "{}: {:?}",
+                url,
+                result
+            );
+        }
+        assert!(
+            check_public_url(&"https://8.8.8.8/".parse().unwrap())
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_public_url_checks_first_hop_without_network() {
+        let client = build_public_client(None, 5, TlsBackend::Rustls).unwrap();
+
+        let result =
+            get_public_url(&client, "http://10.0.0.1/".parse().unwrap(), None, |_| true).await;
+        assert!(matches!(result, Err(PublicUrlError::NotAllowed(_))));
+
+        let result = get_public_url(&client, "https://8.8.8.8/".parse().unwrap(), None, |_| {
+            false
+        })
+        .await;
+        assert!(matches!(result, Err(PublicUrlError::NotAllowed(_))));
+    }
 }
@@ -11,7 +11,7 @@ use crate::http_client::ProxyConfig;
 use crate::model::config::Config;
 
 /// Kiro OAuth 凭证
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct KiroCredentials {
     /// 凭据唯一标识符（自增 ID）
@@ -96,6 +96,32 @@ pub struct KiroCredentials {
     /// 凭据是否被禁用（默认为 false）
     #[serde(default)]
     pub disabled: bool,
+
+    /// 禁用原因（由程序写入，重启后据此恢复自动禁用状态；缺失时视为手动禁用）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_reason: Option<DisabledReason>,
+
+    /// 禁用时间（RFC3339 格式）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_at: Option<String>,
+
+    /// 计划自动重新启用的时间（RFC3339 格式）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reenable_at: Option<String>,
+}
+
+/// 凭据禁用原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DisabledReason {
+    /// Admin API 手动禁用
+    Manual,
+    /// 连续失败达到阈值后自动禁用
+    TooManyFailures,
+    /// 额度已用尽（如 MONTHLY_REQUEST_COUNT）
+    QuotaExceeded,
+    /// refreshToken 已永久失效（被吊销或过期），需要重新登录获取
+    InvalidRefreshToken,
 }
 
 /// 判断是否为零（用于跳过序列化）
@@ -339,6 +365,9 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            disabled_reason: None,
+            disabled_at: None,
+            reenable_at: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -457,6 +486,9 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            disabled_reason: None,
+            disabled_at: None,
+            reenable_at: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -487,6 +519,9 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            disabled_reason: None,
+            disabled_at: None,
+            reenable_at: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -599,6 +634,9 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            disabled_reason: None,
+            disabled_at: None,
+            reenable_at: None,
         };
 
         let json = original.to_pretty_json().unwrap();
@@ -144,6 +144,17 @@ impl UsageLimitsResponse {
         self.usage_breakdown_list.first()
     }
 
+    /// 是否有激活的免费试用或奖励额度
+    pub fn has_active_bonus(&self) -> bool {
+        self.primary_breakdown().is_some_and(|breakdown| {
+            breakdown
+                .free_trial_info
+                .as_ref()
+                .is_some_and(FreeTrialInfo::is_active)
+                || breakdown.bonuses.iter().any(Bonus::is_active)
+        })
+    }
+
     /// 获取总使用限额（精确值）
     ///
     /// 累加基础额度、激活的免费试用额度和激活的奖励额度
@@ -8,7 +8,7 @@ use reqwest::Client;
 use reqwest::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use uuid::Uuid;
 
@@ -16,6 +16,7 @@ use crate::http_client::{ProxyConfig, build_client};
 use crate::kiro::machine_id;
 use crate::kiro::model::credentials::KiroCredentials;
 use crate::kiro::token_manager::{CallContext, MultiTokenManager};
+use crate::metrics;
 use crate::model::config::TlsBackend;
 use parking_lot::Mutex;
 
@@ -25,53 +26,51 @@ const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
 /// 总重试次数硬上限（避免无限重试）
 const MAX_TOTAL_RETRIES: usize = 9;
 
+/// 附加在上游响应 extensions 中的凭据 ID（用于用量归属）
+#[derive(Debug, Clone, Copy)]
+struct UpstreamCredential(u64);
+
 /// Kiro API Provider
 ///
 /// 核心组件，负责与 Kiro API 通信
 /// 支持多凭据故障转移和重试机制
 pub struct KiroProvider {
+    /// Token 管理器（同时提供全局代理与 TLS 后端配置，热重载后立即生效）
     token_manager: Arc<MultiTokenManager>,
-    /// 全局代理配置（用于凭据无自定义代理时的回退）
-    global_proxy: Option<ProxyConfig>,
-    /// Client 缓存：key = effective proxy config, value = reqwest::Client
+    /// Client 缓存：key = (effective proxy config, TLS 后端), value = reqwest::Client
     /// 不同代理配置的凭据使用不同的 Client，共享相同代理的凭据复用 Client
-    client_cache: Mutex<HashMap<Option<ProxyConfig>, Client>>,
-    /// TLS 后端配置
-    tls_backend: TlsBackend,
+    client_cache: Mutex<HashMap<(Option<ProxyConfig>, TlsBackend), Client>>,
 }
 
 impl KiroProvider {
     /// 创建新的 KiroProvider 实例
+    ///
+    /// 凭据无自定义代理时回退到 token_manager 中的全局代理
     pub fn new(token_manager: Arc<MultiTokenManager>) -> Self {
-        Self::with_proxy(token_manager, None)
-    }
-
-    /// 创建带代理配置的 KiroProvider 实例
-    pub fn with_proxy(token_manager: Arc<MultiTokenManager>, proxy: Option<ProxyConfig>) -> Self {
+        let proxy = token_manager.proxy();
         let tls_backend = token_manager.config().tls_backend;
         // 预热：构建全局代理对应的 Client
         let initial_client = build_client(proxy.as_ref(), 720, tls_backend)
             .expect("创建 HTTP 客户端失败");
         let mut cache = HashMap::new();
-        cache.insert(proxy.clone(), initial_client);
+        cache.insert((proxy, tls_backend), initial_client);
 
         Self {
             token_manager,
-            global_proxy: proxy,
             client_cache: Mutex::new(cache),
-            tls_backend,
         }
     }
 
     /// 根据凭据的代理配置获取（或创建并缓存）对应的 reqwest::Client
     fn client_for(&self, credentials: &KiroCredentials) -> anyhow::Result<Client> {
-        let effective = credentials.effective_proxy(self.global_proxy.as_ref());
+        let effective = credentials.effective_proxy(self.token_manager.proxy().as_ref());
+        let key = (effective, self.token_manager.config().tls_backend);
         let mut cache = self.client_cache.lock();
-        if let Some(client) = cache.get(&effective) {
+        if let Some(client) = cache.get(&key) {
             return Ok(client.clone());
         }
-        let client = build_client(effective.as_ref(), 720, self.tls_backend)?;
-        cache.insert(effective, client.clone());
+        let client = build_client(key.0.as_ref(), 720, key.1)?;
+        cache.insert(key, client.clone());
         Ok(client)
     }
 
@@ -105,7 +104,7 @@ impl KiroProvider {
     fn base_url_for(&self, credentials: &KiroCredentials) -> String {
         format!(
             "https://q.{}.amazonaws.com/generateAssistantResponse",
-            credentials.effective_api_region(self.token_manager.config())
+            credentials.effective_api_region(&self.token_manager.config())
         )
     }
 
@@ -113,7 +112,7 @@ impl KiroProvider {
     fn mcp_url_for(&self, credentials: &KiroCredentials) -> String {
         format!(
             "https://q.{}.amazonaws.com/mcp",
-            credentials.effective_api_region(self.token_manager.config())
+            credentials.effective_api_region(&self.token_manager.config())
         )
     }
 
@@ -121,7 +120,7 @@ impl KiroProvider {
     fn base_domain_for(&self, credentials: &KiroCredentials) -> String {
         format!(
             "q.{}.amazonaws.com",
-            credentials.effective_api_region(self.token_manager.config())
+            credentials.effective_api_region(&self.token_manager.config())
         )
     }
 
@@ -149,7 +148,7 @@ impl KiroProvider {
     fn build_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
-        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, config)
+        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, &config)
             .ok_or_else(|| anyhow::anyhow!("无法生成 machine_id，请检查凭证配置"))?;
 
         let kiro_version = &config.kiro_version;
@@ -201,7 +200,7 @@ impl KiroProvider {
     fn build_mcp_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
-        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, config)
+        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, &config)
             .ok_or_else(|| anyhow::anyhow!("无法生成 machine_id，请检查凭证配置"))?;
 
         let kiro_version = &config.kiro_version;
@@ -276,6 +275,16 @@ impl KiroProvider {
         self.call_api_with_retry(request_body, true).await
     }
 
+    /// 获取处理该响应的凭据 ID
+    ///
+    /// 仅对 `call_api` / `call_api_stream` 返回的响应有效
+    pub fn credential_id(response: &reqwest::Response) -> Option<u64> {
+        response
+            .extensions()
+            .get::<UpstreamCredential>()
+            .map(|c| c.0)
+    }
+
     /// 发送 MCP API 请求
     ///
     /// 用于 WebSearch 等工具调用
@@ -409,13 +418,27 @@ impl KiroProvider {
         }))
     }
 
-    /// 内部方法：带重试逻辑的 API 调用
-    ///
+    /// 内部方法：带重试逻辑的 API 调用（记录总耗时指标）
+    async fn call_api_with_retry(
+        &self,
+        request_body: &str,
+        is_stream: bool,
+    ) -> anyhow::Result<reqwest::Response> {
+        let started = Instant::now();
+        let result = self.send_api_with_retry(request_body, is_stream).await;
+        metrics::record_upstream_duration(
+            metrics::api_label(is_stream),
+            result.is_ok(),
+            started.elapsed(),
+        );
+        result
+    }
+
     /// 重试策略：
     /// - 每个凭据最多重试 MAX_RETRIES_PER_CREDENTIAL 次
     /// - 总重试次数 = min(凭据数量 × 每凭据重试次数, MAX_TOTAL_RETRIES)
     /// - 硬上限 9 次，避免无限重试
-    async fn call_api_with_retry(
+    async fn send_api_with_retry(
         &self,
         request_body: &str,
         is_stream: bool,
@@ -424,11 +447,16 @@ impl KiroProvider {
         let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
         let mut last_error: Option<anyhow::Error> = None;
         let api_type = if is_stream { "流式" } else { "非流式" };
+        let api_label = metrics::api_label(is_stream);
 
         // 尝试从请求体中提取模型信息
         let model = Self::extract_model_from_request(request_body);
 
         for attempt in 0..max_retries {
+            if attempt > 0 {
+                metrics::record_upstream_retry(api_label);
+            }
+
             // 获取调用上下文（绑定 index、credentials、token）
             let ctx = match self.token_manager.acquire_context(model.as_deref()).await {
                 Ok(c) => c,
@@ -448,7 +476,8 @@ impl KiroProvider {
             };
 
             // 发送请求
-            let response = match self
+            let sent_at = Instant::now();
+            let mut response = match self
                 .client_for(&ctx.credentials)?
                 .post(&url)
                 .headers(headers)
@@ -458,6 +487,7 @@ impl KiroProvider {
             {
                 Ok(resp) => resp,
                 Err(e) => {
+                    metrics::record_upstream_error(api_label);
                     tracing::warn!(
                         "API 请求发送失败（尝试 {}/{}）: {}",
                         attempt + 1,
@@ -475,10 +505,14 @@ impl KiroProvider {
             };
 
             let status = response.status();
+            metrics::record_upstream_response(api_label, status.as_u16(), sent_at.elapsed());
 
             // 成功响应
             if status.is_success() {
                 self.token_manager.report_success(ctx.id);
+                response
+                    .extensions_mut()
+                    .insert(UpstreamCredential(ctx.id));
                 return Ok(response);
             }
 
@@ -5,24 +5,27 @@
 
 use anyhow::bail;
 use chrono::{DateTime, Duration, Utc};
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio::sync::Mutex as TokioMutex;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration as StdDuration, Instant};
 
 use crate::http_client::{ProxyConfig, build_client};
 use crate::kiro::machine_id;
-use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::credentials::{DisabledReason, KiroCredentials};
 use crate::kiro::model::token_refresh::{
     IdcRefreshRequest, IdcRefreshResponse, RefreshRequest, RefreshResponse,
 };
 use crate::kiro::model::usage_limits::UsageLimitsResponse;
-use crate::model::config::Config;
+use crate::metrics;
+use crate::model::config::{Config, TokenRefreshConfig, is_valid_load_balancing_mode};
 
 /// Token 管理器
 ///
@@ -134,13 +137,32 @@ pub(crate) fn validate_refresh_token(credentials: &KiroCredentials) -> anyhow::R
     Ok(())
 }
 
+/// refreshToken 已永久失效（被吊销、过期或格式无效），重试无意义
+///
+/// 作为 `anyhow::Error` 的来源返回，通过 [`is_invalid_refresh_token`] 识别
+#[derive(Debug)]
+pub(crate) struct InvalidRefreshToken(String);
+
+impl fmt::Display for InvalidRefreshToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidRefreshToken {}
+
+/// 判断刷新错误是否表示 refreshToken 已永久失效
+pub(crate) fn is_invalid_refresh_token(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<InvalidRefreshToken>().is_some()
+}
+
 /// 刷新 Token
 pub(crate) async fn refresh_token(
     credentials: &KiroCredentials,
     config: &Config,
     proxy: Option<&ProxyConfig>,
 ) -> anyhow::Result<KiroCredentials> {
-    validate_refresh_token(credentials)?;
+    validate_refresh_token(credentials).map_err(|e| InvalidRefreshToken(e.to_string()))?;
 
     // 根据 auth_method 选择刷新方式
     // 如果未指定 auth_method，根据是否有 clientId/clientSecret 自动判断
@@ -152,14 +174,16 @@ pub(crate) async fn refresh_token(
         }
     });
 
-    if auth_method.eq_ignore_ascii_case("idc")
+    let result = if auth_method.eq_ignore_ascii_case("idc")
         || auth_method.eq_ignore_ascii_case("builder-id")
         || auth_method.eq_ignore_ascii_case("iam")
     {
         refresh_idc_token(credentials, config, proxy).await
     } else {
         refresh_social_token(credentials, config, proxy).await
-    }
+    };
+    metrics::record_token_refresh(result.is_ok());
+    result
 }
 
 /// 刷新 Social Token
@@ -210,7 +234,11 @@ async fn refresh_social_token(
             500..=599 => "服务器错误，AWS OAuth 服务暂时不可用",
             _ => "Token 刷新失败",
         };
-        bail!("{}: {} {}", error_msg, status, body_text);
+        let message = format!("{}: {} {}", error_msg, status, body_text);
+        if status.as_u16() == 401 {
+            return Err(InvalidRefreshToken(message).into());
+        }
+        bail!(message);
     }
 
     let data: RefreshResponse = response.json().await?;
@@ -292,7 +320,13 @@ async fn refresh_idc_token(
             500..=599 => "服务器错误，AWS OIDC 服务暂时不可用",
             _ => "IdC Token 刷新失败",
         };
-        bail!("{}: {} {}", error_msg, status, body_text);
+        let message = format!("{}: {} {}", error_msg, status, body_text);
+        // OIDC 对已吊销/过期的 refreshToken 返回 400 invalid_grant
+        if status.as_u16() == 401 || (status.as_u16() == 400 && body_text.contains("invalid_grant"))
+        {
+            return Err(InvalidRefreshToken(message).into());
+        }
+        bail!(message);
     }
 
     let data: IdcRefreshResponse = response.json().await?;
@@ -394,27 +428,148 @@ struct CredentialEntry {
     id: u64,
     /// 凭据信息
     credentials: KiroCredentials,
+    /// 凭据文件中最近一次读取/写入的 refreshToken
+    ///
+    /// 单凭据格式不回写，刷新后内存中的 refreshToken 会与文件不同，热重载以此判断文件是否被修改
+    file_refresh_token: Option<String>,
     /// API 调用连续失败次数
     failure_count: u32,
     /// 是否已禁用
     disabled: bool,
     /// 禁用原因（用于区分手动禁用 vs 自动禁用，便于自愈）
     disabled_reason: Option<DisabledReason>,
+    /// 禁用时间
+    disabled_at: Option<DateTime<Utc>>,
+    /// 计划自动重新启用的时间（额度重置时间或冷却期结束时间）
+    reenable_at: Option<DateTime<Utc>>,
     /// API 调用成功次数
     success_count: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
     last_used_at: Option<String>,
+    /// 最近一次 Token 刷新结果
+    last_refresh: Option<RefreshOutcome>,
+    /// 计划的下一次后台刷新时间（None 表示尚未排期）
+    next_refresh_at: Option<DateTime<Utc>>,
+    /// 最近一次后台校验时间（仅连续失败禁用的凭据使用）
+    last_validated_at: Option<Instant>,
+    /// 最近一次查询到的使用额度（`quota` 模式轮询或 Admin API 查询余额时更新）
+    quota: Option<QuotaStatus>,
+}
+
+impl CredentialEntry {
+    /// 从凭据创建条目，恢复持久化的禁用原因与自动启用时间
+    fn new(id: u64, credentials: KiroCredentials) -> Self {
+        let parse_time = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|t| t.with_timezone(&Utc))
+        };
+        let disabled = credentials.disabled;
+        Self {
+            id,
+            disabled,
+            disabled_reason: disabled.then(|| {
+                credentials
+                    .disabled_reason
+                    .unwrap_or(DisabledReason::Manual)
+            }),
+            disabled_at: parse_time(&credentials.disabled_at).filter(|_| disabled),
+            reenable_at: parse_time(&credentials.reenable_at).filter(|_| disabled),
+            file_refresh_token: credentials.refresh_token.clone(),
+            credentials,
+            failure_count: 0,
+            success_count: 0,
+            last_used_at: None,
+            last_refresh: None,
+            next_refresh_at: None,
+            last_validated_at: None,
+            quota: None,
+        }
+    }
+
+    /// 禁用凭据并记录原因、时间与计划的自动重新启用时间
+    fn disable(&mut self, reason: DisabledReason, reenable_at: Option<DateTime<Utc>>) {
+        self.disabled = true;
+        self.disabled_reason = Some(reason);
+        self.disabled_at = Some(Utc::now());
+        self.reenable_at = reenable_at;
+    }
+
+    /// 重新启用凭据，清除禁用信息并重置失败计数
+    fn enable(&mut self) {
+        self.disabled = false;
+        self.disabled_reason = None;
+        self.disabled_at = None;
+        self.reenable_at = None;
+        self.failure_count = 0;
+    }
+}
+
+/// 单次 Token 刷新结果
+#[derive(Debug, Clone)]
+struct RefreshOutcome {
+    /// 刷新时间
+    at: DateTime<Utc>,
+    /// 失败原因（None 表示成功）
+    error: Option<String>,
 }
 
-/// 禁用原因
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum DisabledReason {
-    /// Admin API 手动禁用
-    Manual,
-    /// 连续失败达到阈值后自动禁用
-    TooManyFailures,
-    /// 额度已用尽（如 MONTHLY_REQUEST_COUNT）
-    QuotaExceeded,
+/// 凭据使用额度
+#[derive(Debug, Clone)]
+struct QuotaStatus {
+    /// 剩余额度（含激活的免费试用与奖励额度）
+    remaining: f64,
+    /// 总额度（含激活的免费试用与奖励额度）
+    usage_limit: f64,
+    /// 是否有激活的免费试用或奖励额度
+    has_bonus: bool,
+    /// 额度下次重置时间
+    next_reset_at: Option<DateTime<Utc>>,
+    /// 查询时间
+    fetched_at: DateTime<Utc>,
+}
+
+impl QuotaStatus {
+    fn from_usage(usage: &UsageLimitsResponse, now: DateTime<Utc>) -> Self {
+        let usage_limit = usage.usage_limit();
+        Self {
+            remaining: (usage_limit - usage.current_usage()).max(0.0),
+            usage_limit,
+            has_bonus: usage.has_active_bonus(),
+            next_reset_at: usage
+                .next_date_reset
+                .and_then(|ts| DateTime::from_timestamp(ts as i64, 0)),
+            fetched_at: now,
+        }
+    }
+
+    /// 剩余额度是否已低于保留阈值（总额度未知时视为否）
+    fn is_near_limit(&self, reserve_percent: f64) -> bool {
+        self.usage_limit > 0.0 && self.remaining <= self.usage_limit * reserve_percent / 100.0
+    }
+
+    /// 额度是否充足（总额度已知且高于保留阈值）
+    fn has_headroom(&self, reserve_percent: f64) -> bool {
+        self.usage_limit > 0.0 && !self.is_near_limit(reserve_percent)
+    }
+}
+
+/// `quota` 模式下的凭据排序：有激活奖励/免费试用的优先（通常会过期），其次剩余额度多的，
+/// 尚未查询到额度的排在最后，平局按优先级
+fn compare_by_quota(a: &CredentialEntry, b: &CredentialEntry) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let by_quota = match (&a.quota, &b.quota) {
+        (Some(qa), Some(qb)) => qb
+            .has_bonus
+            .cmp(&qa.has_bonus)
+            .then(qb.remaining.total_cmp(&qa.remaining)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    };
+    by_quota.then(a.credentials.priority.cmp(&b.credentials.priority))
 }
 
 /// 统计数据持久化条目
@@ -438,6 +593,12 @@ pub struct CredentialEntrySnapshot {
     pub priority: u32,
     /// 是否被禁用
     pub disabled: bool,
+    /// 禁用原因
+    pub disabled_reason: Option<DisabledReason>,
+    /// 禁用时间（RFC3339 格式）
+    pub disabled_at: Option<String>,
+    /// 计划自动重新启用的时间（RFC3339 格式）
+    pub reenable_at: Option<String>,
     /// 连续失败次数
     pub failure_count: u32,
     /// 认证方式
@@ -459,6 +620,12 @@ pub struct CredentialEntrySnapshot {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 最近一次 Token 刷新时间（RFC3339 格式）
+    pub last_refresh_at: Option<String>,
+    /// 最近一次 Token 刷新失败原因（刷新成功时为 None）
+    pub last_refresh_error: Option<String>,
+    /// 计划的下一次后台刷新时间（RFC3339 格式）
+    pub next_refresh_at: Option<String>,
 }
 
 /// 凭据管理器状态快照
@@ -475,13 +642,31 @@ pub struct ManagerSnapshot {
     pub available: usize,
 }
 
+/// 凭据热重载的差异（凭据 ID 列表）
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialsDiff {
+    pub added: Vec<u64>,
+    pub updated: Vec<u64>,
+    pub removed: Vec<u64>,
+}
+
+impl CredentialsDiff {
+    /// 是否没有任何变化
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
 /// 多凭据 Token 管理器
 ///
 /// 支持多个凭据的管理，实现固定优先级 + 故障转移策略
 /// 故障统计基于 API 调用结果，而非 Token 刷新结果
 pub struct MultiTokenManager {
-    config: Config,
-    proxy: Option<ProxyConfig>,
+    /// 应用配置（热重载时整体替换）
+    config: RwLock<Arc<Config>>,
+    /// 全局代理配置（热重载时替换）
+    proxy: RwLock<Option<ProxyConfig>>,
     /// 凭据条目列表
     entries: Mutex<Vec<CredentialEntry>>,
     /// 当前活动凭据 ID
@@ -504,6 +689,32 @@ pub struct MultiTokenManager {
 const MAX_FAILURES_PER_CREDENTIAL: u32 = 3;
 /// 统计数据持久化防抖间隔
 const STATS_SAVE_DEBOUNCE: StdDuration = StdDuration::from_secs(30);
+/// 后台刷新调度器的检查间隔
+const REFRESH_SCHEDULER_TICK: StdDuration = StdDuration::from_secs(30);
+/// 额度轮询任务的检查间隔（实际查询间隔由 `quotaBalancing.pollIntervalSecs` 决定）
+const QUOTA_POLLER_TICK: StdDuration = StdDuration::from_secs(30);
+
+/// 计算凭据的下一次后台刷新时间
+///
+/// 在过期前 `leadSecs` 秒基础上再随机提前至多 `jitterSecs` 秒，避免多个凭据同时刷新；
+/// 没有（或无法解析）过期时间时立即刷新
+fn schedule_refresh_at(
+    credentials: &KiroCredentials,
+    config: &TokenRefreshConfig,
+    now: DateTime<Utc>,
+) -> DateTime<Utc> {
+    let Some(expires_at) = credentials
+        .expires_at
+        .as_deref()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+    else {
+        return now;
+    };
+    let jitter = fastrand::u64(0..=config.jitter_secs);
+    let lead = config.lead_secs.saturating_add(jitter);
+    let at = expires_at.with_timezone(&Utc) - Duration::seconds(lead as i64);
+    at.max(now)
+}
 
 /// API 调用上下文
 ///
@@ -561,19 +772,8 @@ impl MultiTokenManager {
                         has_new_machine_ids = true;
                     }
                 }
-                CredentialEntry {
-                    id,
-                    credentials: cred.clone(),
-                    failure_count: 0,
-                    disabled: cred.disabled, // 从配置文件读取 disabled 状态
-                    disabled_reason: if cred.disabled {
-                        Some(DisabledReason::Manual)
-                    } else {
-                        None
-                    },
-                    success_count: 0,
-                    last_used_at: None,
-                }
+                // 从配置文件读取 disabled 状态
+                CredentialEntry::new(id, cred)
             })
             .collect();
 
@@ -598,8 +798,8 @@ impl MultiTokenManager {
 
         let load_balancing_mode = config.load_balancing_mode.clone();
         let manager = Self {
-            config,
-            proxy,
+            config: RwLock::new(Arc::new(config)),
+            proxy: RwLock::new(proxy),
             entries: Mutex::new(entries),
             current_id: Mutex::new(initial_id),
             refresh_lock: TokioMutex::new(()),
@@ -625,9 +825,26 @@ impl MultiTokenManager {
         Ok(manager)
     }
 
-    /// 获取配置的引用
-    pub fn config(&self) -> &Config {
-        &self.config
+    /// 获取当前配置
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().clone()
+    }
+
+    /// 获取当前全局代理配置
+    pub fn proxy(&self) -> Option<ProxyConfig> {
+        self.proxy.read().clone()
+    }
+
+    /// 替换运行时配置与全局代理（配置热重载）
+    ///
+    /// 配置文件中的负载均衡模式发生变化时同步到运行时
+    pub fn apply_config(&self, config: Config, proxy: Option<ProxyConfig>) {
+        let previous_mode = self.config.read().load_balancing_mode.clone();
+        if config.load_balancing_mode != previous_mode {
+            *self.load_balancing_mode.lock() = config.load_balancing_mode.clone();
+        }
+        *self.config.write() = Arc::new(config);
+        *self.proxy.write() = proxy;
     }
 
     /// 获取当前活动凭据的克隆
@@ -658,7 +875,13 @@ impl MultiTokenManager {
     ///
     /// # 参数
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
-    fn select_next_credential(&self, model: Option<&str>) -> Option<(u64, KiroCredentials)> {
+    /// - `exclude`: 需要跳过的凭据 ID（如本次请求中已尝试失败的凭据）
+    fn select_next_credential(
+        &self,
+        model: Option<&str>,
+        exclude: &HashSet<u64>,
+    ) -> Option<(u64, KiroCredentials)> {
+        let reserve_percent = self.config().quota_balancing.reserve_percent;
         let entries = self.entries.lock();
 
         // 检查是否是 opus 模型
@@ -670,7 +893,7 @@ impl MultiTokenManager {
         let available: Vec<_> = entries
             .iter()
             .filter(|e| {
-                if e.disabled {
+                if e.disabled || exclude.contains(&e.id) {
                     return false;
                 }
                 // 如果是 opus 模型，需要检查订阅等级
@@ -698,6 +921,21 @@ impl MultiTokenManager {
 
                 Some((entry.id, entry.credentials.clone()))
             }
+            "quota" => {
+                // 剩余额度低于保留阈值的凭据仅在没有其他可用凭据时使用
+                let has_headroom = |e: &&&CredentialEntry| {
+                    !e.quota
+                        .as_ref()
+                        .is_some_and(|q| q.is_near_limit(reserve_percent))
+                };
+                let entry = available
+                    .iter()
+                    .filter(has_headroom)
+                    .min_by(|a, b| compare_by_quota(a, b))
+                    .or_else(|| available.iter().min_by(|a, b| compare_by_quota(a, b)))?;
+
+                Some((entry.id, entry.credentials.clone()))
+            }
             _ => {
                 // priority 模式（默认）：选择优先级最高的
                 let entry = available.iter().min_by_key(|e| e.credentials.priority)?;
@@ -718,10 +956,11 @@ impl MultiTokenManager {
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
     pub async fn acquire_context(&self, model: Option<&str>) -> anyhow::Result<CallContext> {
         let total = self.total_count();
-        let mut tried_count = 0;
+        // 本次请求中 Token 获取失败的凭据，重试时不再选择
+        let mut tried = HashSet::new();
 
         loop {
-            if tried_count >= total {
+            if tried.len() >= total {
                 anyhow::bail!(
                     "所有凭据均无法获取有效 Token（可用: {}/{}）",
                     self.available_count(),
@@ -730,26 +969,29 @@ impl MultiTokenManager {
             }
 
             let (id, credentials) = {
-                let is_balanced = self.load_balancing_mode.lock().as_str() == "balanced";
+                let select_per_request = matches!(
+                    self.load_balancing_mode.lock().as_str(),
+                    "balanced" | "quota"
+                );
 
-                // balanced 模式：每次请求都轮询选择，不固定 current_id
+                // balanced / quota 模式：每次请求都重新选择，不固定 current_id
                 // priority 模式：优先使用 current_id 指向的凭据
-                let current_hit = if is_balanced {
+                let current_hit = if select_per_request {
                     None
                 } else {
                     let entries = self.entries.lock();
                     let current_id = *self.current_id.lock();
                     entries
                         .iter()
-                        .find(|e| e.id == current_id && !e.disabled)
+                        .find(|e| e.id == current_id && !e.disabled && !tried.contains(&e.id))
                         .map(|e| (e.id, e.credentials.clone()))
                 };
 
                 if let Some(hit) = current_hit {
                     hit
                 } else {
-                    // 当前凭据不可用或 balanced 模式，根据负载均衡策略选择
-                    let mut best = self.select_next_credential(model);
+                    // 当前凭据不可用或 balanced / quota 模式，根据负载均衡策略选择
+                    let mut best = self.select_next_credential(model, &tried);
 
                     // 没有可用凭据：如果是"自动禁用导致全灭"，做一次类似重启的自愈
                     if best.is_none() {
@@ -762,13 +1004,11 @@ impl MultiTokenManager {
                             );
                             for e in entries.iter_mut() {
                                 if e.disabled_reason == Some(DisabledReason::TooManyFailures) {
-                                    e.disabled = false;
-                                    e.disabled_reason = None;
-                                    e.failure_count = 0;
+                                    e.enable();
                                 }
                             }
                             drop(entries);
-                            best = self.select_next_credential(model);
+                            best = self.select_next_credential(model, &tried);
                         }
                     }
 
@@ -777,6 +1017,12 @@ impl MultiTokenManager {
                         let mut current_id = self.current_id.lock();
                         *current_id = new_id;
                         (new_id, new_creds)
+                    } else if !tried.is_empty() {
+                        anyhow::bail!(
+                            "所有凭据均无法获取有效 Token（可用: {}/{}）",
+                            self.available_count(),
+                            total
+                        );
                     } else {
                         let entries = self.entries.lock();
                         // 注意：必须在 bail! 之前计算 available_count，
@@ -798,7 +1044,7 @@ impl MultiTokenManager {
 
                     // Token 刷新失败，切换到下一个优先级的凭据（不计入失败次数）
                     self.switch_to_next_by_priority();
-                    tried_count += 1;
+                    tried.insert(id);
                 }
             }
         }
@@ -816,6 +1062,7 @@ impl MultiTokenManager {
             .min_by_key(|e| e.credentials.priority)
         {
             *current_id = entry.id;
+            metrics::record_credential_failover("token_refresh_failed");
             tracing::info!(
                 "已切换到凭据 #{}（优先级 {}）",
                 entry.id,
@@ -881,28 +1128,7 @@ impl MultiTokenManager {
 
             if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
                 // 确实需要刷新
-                let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await?;
-
-                if is_token_expired(&new_creds) {
-                    anyhow::bail!("刷新后的 Token 仍然无效或已过期");
-                }
-
-                // 更新凭据
-                {
-                    let mut entries = self.entries.lock();
-                    if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
-                        entry.credentials = new_creds.clone();
-                    }
-                }
-
-                // 回写凭据到文件（仅多凭据格式），失败只记录警告
-                if let Err(e) = self.persist_credentials() {
-                    tracing::warn!("Token 刷新后持久化失败（不影响本次请求）: {}", e);
-                }
-
-                new_creds
+                self.refresh_entry(id, &current_creds).await?
             } else {
                 // 其他请求已经完成刷新，直接使用新凭据
                 tracing::debug!("Token 已被其他请求刷新，跳过刷新");
@@ -924,6 +1150,321 @@ impl MultiTokenManager {
         })
     }
 
+    /// 刷新指定凭据的 Token 并更新条目（调用方需持有 `refresh_lock`）
+    ///
+    /// 记录刷新结果并重新排期后台刷新；refreshToken 永久失效时以
+    /// [`DisabledReason::InvalidRefreshToken`] 禁用该凭据，不参与自愈
+    async fn refresh_entry(
+        &self,
+        id: u64,
+        credentials: &KiroCredentials,
+    ) -> anyhow::Result<KiroCredentials> {
+        let config = self.config();
+        let effective_proxy = credentials.effective_proxy(self.proxy().as_ref());
+        let result = refresh_token(credentials, &config, effective_proxy.as_ref())
+            .await
+            .and_then(|new_creds| {
+                if is_token_expired(&new_creds) {
+                    bail!("刷新后的 Token 仍然无效或已过期");
+                }
+                Ok(new_creds)
+            });
+        let invalid = result.as_ref().is_err_and(is_invalid_refresh_token);
+
+        {
+            let mut entries = self.entries.lock();
+            if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                let now = Utc::now();
+                entry.last_refresh = Some(RefreshOutcome {
+                    at: now,
+                    error: result.as_ref().err().map(|e| e.to_string()),
+                });
+                match &result {
+                    Ok(new_creds) => {
+                        entry.credentials = new_creds.clone();
+                        entry.next_refresh_at =
+                            Some(schedule_refresh_at(new_creds, &config.token_refresh, now));
+                    }
+                    Err(_) if invalid => {
+                        entry.disable(DisabledReason::InvalidRefreshToken, None);
+                        entry.next_refresh_at = None;
+                    }
+                    Err(_) => {
+                        let retry = Duration::seconds(config.token_refresh.retry_secs as i64);
+                        entry.next_refresh_at = Some(now + retry);
+                    }
+                }
+            }
+        }
+
+        match &result {
+            Ok(_) => {
+                // 回写凭据到文件（仅多凭据格式），失败只记录警告
+                if let Err(e) = self.persist_credentials() {
+                    tracing::warn!("Token 刷新后持久化失败（不影响本次请求）: {}", e);
+                }
+            }
+            Err(e) if invalid => {
+                tracing::error!("凭据 #{} 的 refreshToken 已失效，已被禁用: {}", id, e);
+                if let Err(e) = self.persist_credentials() {
+                    tracing::warn!("禁用凭据后持久化失败: {}", e);
+                }
+            }
+            Err(_) => {}
+        }
+
+        result
+    }
+
+    /// 启动后台 Token 刷新调度器
+    ///
+    /// 每 30 秒检查一次：重新启用到达计划时间的凭据（见 [`Self::reenable_due_credentials`]），
+    /// 并执行后台刷新（`tokenRefresh.enabled` 为 false 时跳过，见 [`Self::run_refresh_cycle`]）
+    pub fn spawn_refresh_scheduler(self: &Arc<Self>) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_SCHEDULER_TICK);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                manager.reenable_due_credentials(Utc::now());
+                manager.run_refresh_cycle().await;
+            }
+        });
+    }
+
+    /// 执行一轮后台刷新
+    ///
+    /// - 启用的凭据在过期前（带随机抖动）主动刷新，失败后按 `retrySecs` 重试
+    /// - 因连续失败被自动禁用的凭据每隔 `validateIntervalSecs` 校验一次，校验成功则重新启用
+    /// - 因额度用尽被禁用但尚不知道重置时间的凭据同样周期性查询额度，以获取重置时间
+    async fn run_refresh_cycle(&self) {
+        let config = self.config();
+        let refresh_config = &config.token_refresh;
+        if !refresh_config.enabled {
+            return;
+        }
+
+        let validate_interval = StdDuration::from_secs(refresh_config.validate_interval_secs);
+        let now = Utc::now();
+        let (due, to_validate) = {
+            let mut entries = self.entries.lock();
+            let mut due = Vec::new();
+            let mut to_validate = Vec::new();
+            for entry in entries.iter_mut() {
+                if !entry.disabled {
+                    entry.last_validated_at = None;
+                    let at = *entry.next_refresh_at.get_or_insert_with(|| {
+                        schedule_refresh_at(&entry.credentials, refresh_config, now)
+                    });
+                    if at <= now {
+                        due.push(entry.id);
+                    }
+                } else if entry.disabled_reason == Some(DisabledReason::TooManyFailures)
+                    || (entry.disabled_reason == Some(DisabledReason::QuotaExceeded)
+                        && entry.reenable_at.is_none())
+                {
+                    // 从首次观察到禁用开始计时
+                    let last = entry.last_validated_at.get_or_insert_with(Instant::now);
+                    if last.elapsed() >= validate_interval {
+                        *last = Instant::now();
+                        to_validate.push(entry.id);
+                    }
+                }
+            }
+            (due, to_validate)
+        };
+
+        for id in due {
+            let _guard = self.refresh_lock.lock().await;
+            // 获取锁后重新检查：凭据可能已被请求路径刷新、禁用或删除
+            let credentials = {
+                let entries = self.entries.lock();
+                entries
+                    .iter()
+                    .find(|e| {
+                        e.id == id
+                            && !e.disabled
+                            && e.next_refresh_at.is_some_and(|at| at <= Utc::now())
+                    })
+                    .map(|e| e.credentials.clone())
+            };
+            let Some(credentials) = credentials else {
+                continue;
+            };
+            match self.refresh_entry(id, &credentials).await {
+                Ok(_) => tracing::info!("后台刷新凭据 #{} Token 成功", id),
+                Err(e) => tracing::warn!("后台刷新凭据 #{} Token 失败: {}", id, e),
+            }
+        }
+
+        for id in to_validate {
+            match self.get_usage_limits_for(id).await {
+                Ok(_) => {
+                    let enabled = {
+                        let mut entries = self.entries.lock();
+                        // 校验期间状态可能已变化（如 refreshToken 失效、被手动启用或禁用）
+                        match entries.iter_mut().find(|e| e.id == id) {
+                            Some(entry)
+                                if entry.disabled_reason
+                                    == Some(DisabledReason::TooManyFailures) =>
+                            {
+                                entry.enable();
+                                true
+                            }
+                            _ => false,
+                        }
+                    };
+                    if enabled {
+                        tracing::info!("凭据 #{} 后台校验成功，已重新启用", id);
+                        if let Err(e) = self.persist_credentials() {
+                            tracing::warn!("重新启用凭据后持久化失败: {}", e);
+                        }
+                    }
+                }
+                Err(e) => tracing::debug!("凭据 #{} 后台校验失败，保持禁用: {}", id, e),
+            }
+        }
+    }
+
+    /// 启动额度轮询任务（仅在 `quota` 负载均衡模式下查询），见 [`Self::run_quota_cycle`]
+    pub fn spawn_quota_poller(self: &Arc<Self>) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(QUOTA_POLLER_TICK);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                manager.run_quota_cycle().await;
+            }
+        });
+    }
+
+    /// 执行一轮额度轮询
+    ///
+    /// - 启用的凭据每隔 `pollIntervalSecs` 查询一次使用额度，用于 `quota` 模式选择凭据
+    /// - 因额度用尽被禁用的凭据在额度重置时间（`nextDateReset`）之后查询，额度恢复则重新启用
+    async fn run_quota_cycle(&self) {
+        if self.get_load_balancing_mode() != "quota" {
+            return;
+        }
+
+        let poll_interval =
+            Duration::seconds(self.config().quota_balancing.poll_interval_secs as i64);
+        let now = Utc::now();
+        let due: Vec<u64> = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .filter(|e| {
+                    let stale = e
+                        .quota
+                        .as_ref()
+                        .is_none_or(|q| q.fetched_at + poll_interval <= now);
+                    if !e.disabled {
+                        stale
+                    } else if e.disabled_reason == Some(DisabledReason::QuotaExceeded) {
+                        let reset_passed = e
+                            .quota
+                            .as_ref()
+                            .and_then(|q| q.next_reset_at)
+                            .is_none_or(|at| at <= now);
+                        stale && reset_passed
+                    } else {
+                        false
+                    }
+                })
+                .map(|e| e.id)
+                .collect()
+        };
+
+        for id in due {
+            if let Err(e) = self.get_usage_limits_for(id).await {
+                tracing::warn!("查询凭据 #{} 使用额度失败: {}", id, e);
+            }
+        }
+    }
+
+    /// 记录查询到的使用额度
+    ///
+    /// 因额度用尽被禁用的凭据，额度已恢复（如跨过 `nextDateReset`）时重新启用
+    /// 额度仍未恢复时，以额度重置时间作为计划的自动启用时间
+    fn record_quota(&self, id: u64, usage: &UsageLimitsResponse) {
+        let config = self.config();
+        let status = QuotaStatus::from_usage(usage, Utc::now());
+        let changed = {
+            let mut entries = self.entries.lock();
+            let Some(entry) = entries.iter_mut().find(|e| e.id == id) else {
+                return;
+            };
+            let mut changed = false;
+            if entry.disabled_reason == Some(DisabledReason::QuotaExceeded) {
+                if status.has_headroom(config.quota_balancing.reserve_percent) {
+                    entry.enable();
+                    changed = true;
+                    tracing::info!(
+                        "凭据 #{} 额度已恢复（剩余 {:.2}/{:.2}），已重新启用",
+                        id,
+                        status.remaining,
+                        status.usage_limit
+                    );
+                } else if config.auto_reenable.enabled
+                    && status.next_reset_at.is_some()
+                    && entry.reenable_at != status.next_reset_at
+                {
+                    entry.reenable_at = status.next_reset_at;
+                    changed = true;
+                    tracing::info!(
+                        "凭据 #{} 额度已用尽，计划于 {} 额度重置后自动启用",
+                        id,
+                        status.next_reset_at.unwrap().to_rfc3339()
+                    );
+                }
+            }
+            entry.quota = Some(status);
+            changed
+        };
+        if changed && let Err(e) = self.persist_credentials() {
+            tracing::warn!("更新凭据禁用状态后持久化失败: {}", e);
+        }
+    }
+
+    /// 重新启用已到达计划时间（`reenableAt`）的自动禁用凭据，返回被启用的凭据 ID
+    ///
+    /// 手动禁用与 refreshToken 失效的凭据没有计划时间，不会被自动启用
+    fn reenable_due_credentials(&self, now: DateTime<Utc>) -> Vec<u64> {
+        if !self.config().auto_reenable.enabled {
+            return Vec::new();
+        }
+
+        let reenabled: Vec<u64> = {
+            let mut entries = self.entries.lock();
+            entries
+                .iter_mut()
+                .filter(|e| e.disabled && e.reenable_at.is_some_and(|at| at <= now))
+                .map(|e| {
+                    tracing::info!(
+                        "凭据 #{} 已到达计划启用时间（禁用原因: {:?}），已自动重新启用",
+                        e.id,
+                        e.disabled_reason
+                    );
+                    e.enable();
+                    e.id
+                })
+                .collect()
+        };
+
+        if !reenabled.is_empty() {
+            if let Err(e) = self.persist_credentials() {
+                tracing::warn!("自动重新启用凭据后持久化失败: {}", e);
+            }
+            if self.load_balancing_mode.lock().as_str() == "priority" {
+                self.select_highest_priority();
+            }
+        }
+        reenabled
+    }
+
     /// 将凭据列表回写到源文件
     ///
     /// 仅在以下条件满足时回写：
@@ -949,14 +1490,18 @@ impl MultiTokenManager {
 
         // 收集所有凭据
         let credentials: Vec<KiroCredentials> = {
-            let entries = self.entries.lock();
+            let mut entries = self.entries.lock();
             entries
-                .iter()
+                .iter_mut()
                 .map(|e| {
+                    // 同步 disabled 状态到凭据对象（热重载以此判断文件中的 disabled 是否被修改）
+                    e.credentials.disabled = e.disabled;
+                    e.credentials.disabled_reason = e.disabled_reason;
+                    e.credentials.disabled_at = e.disabled_at.map(|t| t.to_rfc3339());
+                    e.credentials.reenable_at = e.reenable_at.map(|t| t.to_rfc3339());
+                    e.file_refresh_token = e.credentials.refresh_token.clone();
                     let mut cred = e.credentials.clone();
                     cred.canonicalize_auth_method();
-                    // 同步 disabled 状态到凭据对象
-                    cred.disabled = e.disabled;
                     cred
                 })
                 .collect()
@@ -1087,6 +1632,7 @@ impl MultiTokenManager {
                 entry.failure_count = 0;
                 entry.success_count += 1;
                 entry.last_used_at = Some(Utc::now().to_rfc3339());
+                metrics::record_credential_result(id, "success");
                 tracing::debug!(
                     "凭据 #{} API 调用成功（累计 {} 次）",
                     id,
@@ -1105,6 +1651,8 @@ impl MultiTokenManager {
     /// # Arguments
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_failure(&self, id: u64) -> bool {
+        let auto_reenable = self.config().auto_reenable.clone();
+        let mut newly_disabled = false;
         let result = {
             let mut entries = self.entries.lock();
             let mut current_id = self.current_id.lock();
@@ -1117,6 +1665,7 @@ impl MultiTokenManager {
             entry.failure_count += 1;
             entry.last_used_at = Some(Utc::now().to_rfc3339());
             let failure_count = entry.failure_count;
+            metrics::record_credential_result(id, "failure");
 
             tracing::warn!(
                 "凭据 #{} API 调用失败（{}/{}）",
@@ -1125,10 +1674,25 @@ impl MultiTokenManager {
                 MAX_FAILURES_PER_CREDENTIAL
             );
 
-            if failure_count >= MAX_FAILURES_PER_CREDENTIAL {
-                entry.disabled = true;
-                entry.disabled_reason = Some(DisabledReason::TooManyFailures);
-                tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count);
+            // 已禁用的凭据（如进行中的请求晚到的失败）不覆盖原有禁用原因
+            if failure_count >= MAX_FAILURES_PER_CREDENTIAL && !entry.disabled {
+                let reenable_at =
+                    (auto_reenable.enabled && auto_reenable.failure_cooldown_secs > 0).then(|| {
+                        Utc::now() + Duration::seconds(auto_reenable.failure_cooldown_secs as i64)
+                    });
+                entry.disable(DisabledReason::TooManyFailures, reenable_at);
+                newly_disabled = true;
+                match reenable_at {
+                    Some(at) => tracing::error!(
+                        "凭据 #{} 已连续失败 {} 次，已被禁用，计划于 {} 自动启用",
+                        id,
+                        failure_count,
+                        at.to_rfc3339()
+                    ),
+                    None => {
+                        tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count)
+                    }
+                }
 
                 // 切换到优先级最高的可用凭据
                 if let Some(next) = entries
@@ -1137,6 +1701,7 @@ impl MultiTokenManager {
                     .min_by_key(|e| e.credentials.priority)
                 {
                     *current_id = next.id;
+                    metrics::record_credential_failover("too_many_failures");
                     tracing::info!(
                         "已切换到凭据 #{}（优先级 {}）",
                         next.id,
@@ -1149,6 +1714,9 @@ impl MultiTokenManager {
 
             entries.iter().any(|e| !e.disabled)
         };
+        if newly_disabled && let Err(e) = self.persist_credentials() {
+            tracing::warn!("禁用凭据后持久化失败: {}", e);
+        }
         self.save_stats_debounced();
         result
     }
@@ -1157,9 +1725,12 @@ impl MultiTokenManager {
     ///
     /// 用于处理 402 Payment Required 且 reason 为 `MONTHLY_REQUEST_COUNT` 的场景：
     /// - 立即禁用该凭据（不等待连续失败阈值）
+    /// - 以最近一次查询额度得到的 `nextDateReset` 作为计划的自动启用时间；
+    ///   尚未查询过时由后台调度器查询额度获取
     /// - 切换到下一个可用凭据继续重试
     /// - 返回是否还有可用凭据
     pub fn report_quota_exhausted(&self, id: u64) -> bool {
+        let auto_reenable = self.config().auto_reenable.enabled;
         let result = {
             let mut entries = self.entries.lock();
             let mut current_id = self.current_id.lock();
@@ -1173,13 +1744,28 @@ impl MultiTokenManager {
                 return entries.iter().any(|e| !e.disabled);
             }
 
-            entry.disabled = true;
-            entry.disabled_reason = Some(DisabledReason::QuotaExceeded);
-            entry.last_used_at = Some(Utc::now().to_rfc3339());
+            let now = Utc::now();
+            let reenable_at = entry
+                .quota
+                .as_ref()
+                .and_then(|q| q.next_reset_at)
+                .filter(|at| auto_reenable && *at > now);
+            entry.disable(DisabledReason::QuotaExceeded, reenable_at);
+            entry.last_used_at = Some(now.to_rfc3339());
             // 设为阈值，便于在管理面板中直观看到该凭据已不可用
             entry.failure_count = MAX_FAILURES_PER_CREDENTIAL;
+            metrics::record_credential_result(id, "quota_exhausted");
 
-            tracing::error!("凭据 #{} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用", id);
+            match reenable_at {
+                Some(at) => tracing::error!(
+                    "凭据 #{} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用，计划于 {} 额度重置后自动启用",
+                    id,
+                    at.to_rfc3339()
+                ),
+                None => {
+                    tracing::error!("凭据 #{} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用", id)
+                }
+            }
 
             // 切换到优先级最高的可用凭据
             if let Some(next) = entries
@@ -1188,6 +1774,7 @@ impl MultiTokenManager {
                 .min_by_key(|e| e.credentials.priority)
             {
                 *current_id = next.id;
+                metrics::record_credential_failover("quota_exhausted");
                 tracing::info!(
                     "已切换到凭据 #{}（优先级 {}）",
                     next.id,
@@ -1199,6 +1786,9 @@ impl MultiTokenManager {
                 false
             }
         };
+        if let Err(e) = self.persist_credentials() {
+            tracing::warn!("禁用凭据后持久化失败: {}", e);
+        }
         self.save_stats_debounced();
         result
     }
@@ -1232,10 +1822,10 @@ impl MultiTokenManager {
     /// 获取使用额度信息
     pub async fn get_usage_limits(&self) -> anyhow::Result<UsageLimitsResponse> {
         let ctx = self.acquire_context(None).await?;
-        let effective_proxy = ctx.credentials.effective_proxy(self.proxy.as_ref());
+        let effective_proxy = ctx.credentials.effective_proxy(self.proxy().as_ref());
         get_usage_limits(
             &ctx.credentials,
-            &self.config,
+            &self.config(),
             &ctx.token,
             effective_proxy.as_ref(),
         )
@@ -1259,6 +1849,9 @@ impl MultiTokenManager {
                     id: e.id,
                     priority: e.credentials.priority,
                     disabled: e.disabled,
+                    disabled_reason: e.disabled_reason,
+                    disabled_at: e.disabled_at.map(|t| t.to_rfc3339()),
+                    reenable_at: e.reenable_at.map(|t| t.to_rfc3339()),
                     failure_count: e.failure_count,
                     auth_method: e.credentials.auth_method.as_deref().map(|m| {
                         if m.eq_ignore_ascii_case("builder-id") || m.eq_ignore_ascii_case("iam") {
@@ -1275,6 +1868,9 @@ impl MultiTokenManager {
                     last_used_at: e.last_used_at.clone(),
                     has_proxy: e.credentials.proxy_url.is_some(),
                     proxy_url: e.credentials.proxy_url.clone(),
+                    last_refresh_at: e.last_refresh.as_ref().map(|r| r.at.to_rfc3339()),
+                    last_refresh_error: e.last_refresh.as_ref().and_then(|r| r.error.clone()),
+                    next_refresh_at: e.next_refresh_at.map(|at| at.to_rfc3339()),
                 })
                 .collect(),
             current_id,
@@ -1291,13 +1887,11 @@ impl MultiTokenManager {
                 .iter_mut()
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
-            entry.disabled = disabled;
             if !disabled {
                 // 启用时重置失败计数
-                entry.failure_count = 0;
-                entry.disabled_reason = None;
+                entry.enable();
             } else {
-                entry.disabled_reason = Some(DisabledReason::Manual);
+                entry.disable(DisabledReason::Manual, None);
             }
         }
         // 持久化更改
@@ -1333,9 +1927,7 @@ impl MultiTokenManager {
                 .iter_mut()
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
-            entry.failure_count = 0;
-            entry.disabled = false;
-            entry.disabled_reason = None;
+            entry.enable();
         }
         // 持久化更改
         self.persist_credentials()?;
@@ -1368,20 +1960,8 @@ impl MultiTokenManager {
             };
 
             if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await?;
-                {
-                    let mut entries = self.entries.lock();
-                    if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
-                        entry.credentials = new_creds.clone();
-                    }
-                }
-                // 持久化失败只记录警告，不影响本次请求
-                if let Err(e) = self.persist_credentials() {
-                    tracing::warn!("Token 刷新后持久化失败（不影响本次请求）: {}", e);
-                }
-                new_creds
+                self.refresh_entry(id, &current_creds)
+                    .await?
                     .access_token
                     .ok_or_else(|| anyhow::anyhow!("刷新后无 access_token"))?
             } else {
@@ -1404,8 +1984,15 @@ impl MultiTokenManager {
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
         };
 
-        let effective_proxy = credentials.effective_proxy(self.proxy.as_ref());
-        let usage_limits = get_usage_limits(&credentials, &self.config, &token, effective_proxy.as_ref()).await?;
+        let effective_proxy = credentials.effective_proxy(self.proxy().as_ref());
+        let usage_limits = get_usage_limits(
+            &credentials,
+            &self.config(),
+            &token,
+            effective_proxy.as_ref(),
+        )
+        .await?;
+        self.record_quota(id, &usage_limits);
 
         // 更新订阅等级到凭据（仅在发生变化时持久化）
         if let Some(subscription_title) = usage_limits.subscription_title() {
@@ -1414,8 +2001,7 @@ impl MultiTokenManager {
                 if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
                     let old_title = entry.credentials.subscription_title.clone();
                     if old_title.as_deref() != Some(subscription_title) {
-                        entry.credentials.subscription_title =
-                            Some(subscription_title.to_string());
+                        entry.credentials.subscription_title = Some(subscription_title.to_string());
                         tracing::info!(
                             "凭据 #{} 订阅等级已更新: {:?} -> {}",
                             id,
@@ -1481,9 +2067,9 @@ impl MultiTokenManager {
         }
 
         // 3. 尝试刷新 Token 验证凭据有效性
-        let effective_proxy = new_cred.effective_proxy(self.proxy.as_ref());
+        let effective_proxy = new_cred.effective_proxy(self.proxy().as_ref());
         let mut validated_cred =
-            refresh_token(&new_cred, &self.config, effective_proxy.as_ref()).await?;
+            refresh_token(&new_cred, &self.config(), effective_proxy.as_ref()).await?;
 
         // 4. 分配新 ID
         let new_id = {
@@ -1514,15 +2100,12 @@ impl MultiTokenManager {
 
         {
             let mut entries = self.entries.lock();
-            entries.push(CredentialEntry {
-                id: new_id,
-                credentials: validated_cred,
-                failure_count: 0,
-                disabled: false,
-                disabled_reason: None,
-                success_count: 0,
-                last_used_at: None,
+            let mut entry = CredentialEntry::new(new_id, validated_cred);
+            entry.last_refresh = Some(RefreshOutcome {
+                at: Utc::now(),
+                error: None,
             });
+            entries.push(entry);
         }
 
         // 6. 持久化
@@ -1598,6 +2181,139 @@ impl MultiTokenManager {
         Ok(())
     }
 
+    /// 按凭据文件的新内容增量更新凭据（热重载）
+    ///
+    /// 按 ID 对比：新增的凭据加入列表，文件中已不存在的凭据移除，其余凭据原地更新，
+    /// 失败计数、调用统计与运行时禁用状态保持不变；进行中的请求持有各自的 [`CallContext`]，不受影响。
+    ///
+    /// - 文件中的 refreshToken 与上次读取/写入的值相同时保留内存中（可能已刷新）的 Token 与过期时间
+    /// - 仅当文件中的 `disabled` 与上次读取/写入的值不同时才应用
+    /// - 没有 ID 的凭据按 refreshToken 匹配已有凭据，单凭据格式下按位置匹配；
+    ///   都匹配不上时视为新凭据，分配新 ID 并回写文件
+    pub fn reload_credentials(
+        &self,
+        mut credentials: Vec<KiroCredentials>,
+    ) -> anyhow::Result<CredentialsDiff> {
+        let mut seen_ids = validate_credential_ids(&credentials)?;
+
+        let config = self.config();
+        let mut diff = CredentialsDiff::default();
+        let mut needs_persist = false;
+        let mut needs_reselect = false;
+        {
+            let mut entries = self.entries.lock();
+            let current_id = *self.current_id.lock();
+
+            for (index, cred) in credentials.iter_mut().enumerate() {
+                if cred.id.is_some() {
+                    continue;
+                }
+                let unclaimed = |e: &&CredentialEntry| !seen_ids.contains(&e.id);
+                let matched = entries
+                    .iter()
+                    .filter(unclaimed)
+                    .find(|e| {
+                        cred.refresh_token.is_some()
+                            && (cred.refresh_token == e.file_refresh_token
+                                || cred.refresh_token == e.credentials.refresh_token)
+                    })
+                    .or_else(|| {
+                        (!self.is_multiple_format)
+                            .then(|| entries.get(index))
+                            .flatten()
+                            .filter(|e| unclaimed(e))
+                    })
+                    .map(|e| e.id);
+                if let Some(id) = matched {
+                    cred.id = Some(id);
+                    seen_ids.insert(id);
+                }
+            }
+            let mut next_id = entries
+                .iter()
+                .map(|e| e.id)
+                .chain(seen_ids.iter().copied())
+                .max()
+                .unwrap_or(0)
+                + 1;
+
+            entries.retain(|e| {
+                let keep = seen_ids.contains(&e.id);
+                if !keep {
+                    diff.removed.push(e.id);
+                    needs_reselect |= e.id == current_id;
+                }
+                keep
+            });
+
+            for mut cred in credentials {
+                cred.canonicalize_auth_method();
+                let id = match cred.id {
+                    Some(id) => id,
+                    None => {
+                        let id = next_id;
+                        next_id += 1;
+                        cred.id = Some(id);
+                        needs_persist = true;
+                        id
+                    }
+                };
+
+                match entries.iter_mut().find(|e| e.id == id) {
+                    Some(entry) => {
+                        let file_refresh_token = cred.refresh_token.clone();
+                        let merged = merge_reloaded_credentials(
+                            &entry.credentials,
+                            entry.file_refresh_token.as_deref(),
+                            cred,
+                        );
+                        entry.file_refresh_token = file_refresh_token;
+                        if merged == entry.credentials {
+                            continue;
+                        }
+                        if merged.disabled != entry.credentials.disabled {
+                            if merged.disabled {
+                                entry.disable(DisabledReason::Manual, None);
+                            } else {
+                                entry.enable();
+                            }
+                        }
+                        needs_reselect |= merged.priority != entry.credentials.priority
+                            || merged.disabled != entry.credentials.disabled;
+                        if merged.expires_at != entry.credentials.expires_at {
+                            entry.next_refresh_at = None;
+                        }
+                        entry.credentials = merged;
+                        diff.updated.push(id);
+                    }
+                    None => {
+                        if cred.machine_id.is_none() {
+                            cred.machine_id = machine_id::generate_from_credentials(&cred, &config);
+                            needs_persist |= cred.machine_id.is_some();
+                        }
+                        entries.push(CredentialEntry::new(id, cred));
+                        diff.added.push(id);
+                        needs_reselect = true;
+                    }
+                }
+            }
+        }
+
+        if needs_reselect {
+            self.select_highest_priority();
+        }
+        if self.entries.lock().is_empty() {
+            *self.current_id.lock() = 0;
+        }
+        if needs_persist && let Err(e) = self.persist_credentials() {
+            tracing::warn!("热重载后回写凭据失败: {}", e);
+        }
+        if !diff.removed.is_empty() {
+            self.save_stats();
+        }
+        Ok(diff)
+    }
+
     /// 获取负载均衡模式（Admin API）
     pub fn get_load_balancing_mode(&self) -> String {
         self.load_balancing_mode.lock().clone()
@@ -1606,7 +2322,7 @@ impl MultiTokenManager {
     fn persist_load_balancing_mode(&self, mode: &str) -> anyhow::Result<()> {
         use anyhow::Context;
 
-        let config_path = match self.config.config_path() {
+        let config_path = match self.config().config_path() {
             Some(path) => path.to_path_buf(),
             None => {
                 tracing::warn!("配置文件路径未知，负载均衡模式仅在当前进程生效: {}", mode);
@@ -1627,7 +2343,7 @@ impl MultiTokenManager {
     /// 设置负载均衡模式（Admin API）
     pub fn set_load_balancing_mode(&self, mode: String) -> anyhow::Result<()> {
         // 验证模式值
-        if mode != "priority" && mode != "balanced" {
+        if !is_valid_load_balancing_mode(&mode) {
             anyhow::bail!("无效的负载均衡模式: {}", mode);
         }
 
@@ -1648,6 +2364,51 @@ impl MultiTokenManager {
     }
 }
 
+/// 校验凭据文件中显式指定的 ID 没有重复，返回出现过的 ID 集合
+///
+/// 热重载时在应用任何修改之前调用
+pub fn validate_credential_ids(credentials: &[KiroCredentials]) -> anyhow::Result<HashSet<u64>> {
+    let mut seen_ids = HashSet::new();
+    let duplicate_ids: Vec<u64> = credentials
+        .iter()
+        .filter_map(|c| c.id)
+        .filter(|id| !seen_ids.insert(*id))
+        .collect();
+    if !duplicate_ids.is_empty() {
+        anyhow::bail!("检测到重复的凭据 ID: {:?}", duplicate_ids);
+    }
+    Ok(seen_ids)
+}
+
+/// 合并热重载读取的凭据与内存中的凭据
+///
+/// 文件中的 refreshToken 与上次读取/写入的值相同（未被修改）时，文件中的 Token 可能早于内存中
+/// 刷新后的值（如单凭据格式不回写轮换后的 refreshToken），此时保留内存中的 Token；
+/// 刷新过程中补全的字段在文件中缺失时也保留内存值
+fn merge_reloaded_credentials(
+    existing: &KiroCredentials,
+    file_refresh_token: Option<&str>,
+    mut incoming: KiroCredentials,
+) -> KiroCredentials {
+    if incoming.refresh_token == existing.refresh_token
+        || incoming.refresh_token.as_deref() == file_refresh_token
+    {
+        incoming.refresh_token = existing.refresh_token.clone();
+        incoming.access_token = existing.access_token.clone();
+        incoming.expires_at = existing.expires_at.clone();
+    }
+    if incoming.profile_arn.is_none() {
+        incoming.profile_arn = existing.profile_arn.clone();
+    }
+    if incoming.machine_id.is_none() {
+        incoming.machine_id = existing.machine_id.clone();
+    }
+    if incoming.subscription_title.is_none() {
+        incoming.subscription_title = existing.subscription_title.clone();
+    }
+    incoming
+}
+
 impl Drop for MultiTokenManager {
     fn drop(&mut self) {
         if self.stats_dirty.load(Ordering::Relaxed) {
@@ -1870,23 +2631,142 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reload_credentials_keeps_refreshed_token_and_stats() {
+        let cred = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("r1".to_string()),
+            access_token: Some("old".to_string()),
+            ..Default::default()
+        };
+
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred.clone()], None, None, false)
+                .unwrap();
+        manager.report_success(1);
+        // 模拟运行中刷新了 accessToken
+        manager.entries.lock()[0].credentials.access_token = Some("refreshed".to_string());
+
+        // refreshToken 未变化：保留刷新后的 accessToken，仅应用优先级
+        let mut reloaded = cred.clone();
+        reloaded.priority = 3;
+        let diff = manager.reload_credentials(vec![reloaded]).unwrap();
+        assert_eq!(diff.updated, [1]);
+        let entries = manager.entries.lock();
+        assert_eq!(entries[0].credentials.priority, 3);
+        assert_eq!(
+            entries[0].credentials.access_token,
+            Some("refreshed".to_string())
+        );
+        assert_eq!(entries[0].success_count, 1);
+        drop(entries);
+
+        // refreshToken 变化：使用文件中的 Token
+        let mut replaced = cred;
+        replaced.refresh_token = Some("r2".to_string());
+        manager.reload_credentials(vec![replaced]).unwrap();
+        assert_eq!(
+            manager.entries.lock()[0].credentials.access_token,
+            Some("old".to_string())
+        );
+    }
+
+    #[test]
+    fn test_reload_credentials_single_format_matches_by_position() {
+        let cred = KiroCredentials {
+            refresh_token: Some("r1".to_string()),
+            access_token: Some("old".to_string()),
+            ..Default::default()
+        };
+
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred.clone()], None, None, false)
+                .unwrap();
+        manager.report_success(1);
+        // 模拟运行中刷新时轮换了 refreshToken（单凭据格式不回写文件）
+        {
+            let mut entries = manager.entries.lock();
+            entries[0].credentials.refresh_token = Some("r1-rotated".to_string());
+            entries[0].credentials.access_token = Some("refreshed".to_string());
+        }
+
+        // 文件中的 refreshToken 未被修改：同一凭据原地更新，保留轮换后的 Token 与统计
+        let mut reloaded = cred.clone();
+        reloaded.priority = 2;
+        let diff = manager.reload_credentials(vec![reloaded]).unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.updated, [1]);
+        let entries = manager.entries.lock();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, 1);
+        assert_eq!(entries[0].credentials.priority, 2);
+        assert_eq!(
+            entries[0].credentials.refresh_token.as_deref(),
+            Some("r1-rotated")
+        );
+        assert_eq!(
+            entries[0].credentials.access_token.as_deref(),
+            Some("refreshed")
+        );
+        assert_eq!(entries[0].success_count, 1);
+        drop(entries);
+
+        // 文件中换成了新的 refreshToken：仍是同一凭据，使用文件中的 Token
+        let replaced = KiroCredentials {
+            refresh_token: Some("r2".to_string()),
+            ..cred
+        };
+        let diff = manager.reload_credentials(vec![replaced]).unwrap();
+        assert_eq!(diff.updated, [1]);
+        let entries = manager.entries.lock();
+        assert_eq!(entries[0].credentials.refresh_token.as_deref(), Some("r2"));
+        assert_eq!(entries[0].credentials.access_token.as_deref(), Some("old"));
+        assert_eq!(entries[0].success_count, 1);
+    }
+
+    #[test]
+    fn test_reload_credentials_only_applies_changed_disabled_flag() {
+        let cred = KiroCredentials {
+            id: Some(1),
+            refresh_token: Some("r1".to_string()),
+            ..Default::default()
+        };
+
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred.clone()], None, None, false)
+                .unwrap();
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        assert_eq!(manager.available_count(), 0);
+
+        // 文件中的 disabled 未变化：不会解除运行时的自动禁用
+        assert!(
+            manager
+                .reload_credentials(vec![cred.clone()])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(manager.available_count(), 0);
+
+        // 文件中手动禁用后再启用：凭据恢复可用
+        let mut disabled = cred.clone();
+        disabled.disabled = true;
+        manager.reload_credentials(vec![disabled]).unwrap();
+        manager.reload_credentials(vec![cred]).unwrap();
+        assert_eq!(manager.available_count(), 1);
+    }
+
     #[test]
     fn test_set_load_balancing_mode_persists_to_config_file() {
-        let config_path = std::env::temp_dir().join(format!(
-            "kiro-load-balancing-{}.json",
-            uuid::Uuid::new_v4()
-        ));
+        let config_path =
+            std::env::temp_dir().join(format!("kiro-load-balancing-{}.json", uuid::Uuid::new_v4()));
         std::fs::write(&config_path, r#"{"loadBalancingMode":"priority"}"#).unwrap();
 
         let config = Config::load(&config_path).unwrap();
-        let manager = MultiTokenManager::new(
-            config,
-            vec![KiroCredentials::default()],
-            None,
-            None,
-            false,
-        )
-        .unwrap();
+        let manager =
+            MultiTokenManager::new(config, vec![KiroCredentials::default()], None, None, false)
+                .unwrap();
 
         manager
             .set_load_balancing_mode("balanced".to_string())
@@ -1928,6 +2808,32 @@ mod tests {
         assert_eq!(manager.available_count(), 2);
     }
 
+    #[tokio::test]
+    async fn test_acquire_context_skips_tried_credentials_in_quota_mode() {
+        // 凭据 #1 优先级更高但没有 accessToken，获取 Token 失败（不会被禁用）
+        let cred1 = KiroCredentials {
+            priority: 0,
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            priority: 1,
+            access_token: Some("t2".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred1, cred2], None, None, false)
+                .unwrap();
+        *manager.load_balancing_mode.lock() = "quota".to_string();
+
+        // 重试时排除已失败的凭据，而不是反复选择同一个
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.id, 2);
+        assert_eq!(ctx.token, "t2");
+        assert_eq!(manager.available_count(), 2);
+    }
+
     #[test]
     fn test_multi_token_manager_report_quota_exhausted() {
         let config = Config::default();
@@ -1960,7 +2866,12 @@ mod tests {
         manager.report_quota_exhausted(2);
         assert_eq!(manager.available_count(), 0);
 
-        let err = manager.acquire_context(None).await.err().unwrap().to_string();
+        let err = manager
+            .acquire_context(None)
+            .await
+            .err()
+            .unwrap()
+            .to_string();
         assert!(
             err.contains("所有凭据均已禁用"),
             "错误应提示所有凭据禁用，实际: {}",
@@ -1969,6 +2880,320 @@ mod tests {
         assert_eq!(manager.available_count(), 0);
     }
 
+    #[tokio::test]
+    async fn test_invalid_refresh_token_disables_credential_without_auto_recovery() {
+        let cred = |token: &str| KiroCredentials {
+            refresh_token: Some(token.to_string()),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![cred("short-1"), cred("short-2")],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        // refreshToken 被截断：刷新失败后永久禁用，而不是计入失败次数
+        assert!(manager.acquire_context(None).await.is_err());
+        assert_eq!(manager.available_count(), 0);
+
+        let snapshot = manager.snapshot();
+        for entry in &snapshot.entries {
+            assert_eq!(
+                entry.disabled_reason,
+                Some(DisabledReason::InvalidRefreshToken)
+            );
+            assert_eq!(entry.failure_count, 0);
+            assert!(entry.last_refresh_at.is_some());
+            assert!(
+                entry
+                    .last_refresh_error
+                    .as_deref()
+                    .unwrap()
+                    .contains("截断")
+            );
+            assert!(entry.next_refresh_at.is_none());
+        }
+
+        // 不参与"全部自动禁用"的自愈
+        let err = manager
+            .acquire_context(None)
+            .await
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("所有凭据均已禁用"), "实际: {}", err);
+        assert_eq!(manager.available_count(), 0);
+    }
+
+    #[test]
+    fn test_schedule_refresh_at_applies_lead_and_jitter() {
+        let config = TokenRefreshConfig::default();
+        let now = Utc::now();
+        let expires_at = now + Duration::hours(1);
+        let cred = KiroCredentials {
+            expires_at: Some(expires_at.to_rfc3339()),
+            ..Default::default()
+        };
+
+        for _ in 0..20 {
+            let at = schedule_refresh_at(&cred, &config, now);
+            assert!(at <= expires_at - Duration::seconds(config.lead_secs as i64));
+            assert!(
+                at >= expires_at
+                    - Duration::seconds((config.lead_secs + config.jitter_secs) as i64)
+            );
+        }
+
+        // 即将过期或没有过期时间：立即刷新
+        let soon = KiroCredentials {
+            expires_at: Some((now + Duration::minutes(5)).to_rfc3339()),
+            ..Default::default()
+        };
+        assert_eq!(schedule_refresh_at(&soon, &config, now), now);
+        assert_eq!(
+            schedule_refresh_at(&KiroCredentials::default(), &config, now),
+            now
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_cycle_schedules_without_refreshing_valid_tokens() {
+        let cred = KiroCredentials {
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            access_token: Some("token".to_string()),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![cred.clone(), cred],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(2);
+        }
+
+        manager.run_refresh_cycle().await;
+
+        let snapshot = manager.snapshot();
+        let active = snapshot.entries.iter().find(|e| e.id == 1).unwrap();
+        assert!(active.next_refresh_at.is_some());
+        assert!(active.last_refresh_at.is_none());
+        // 自动禁用的凭据需等待 validateIntervalSecs 后才校验
+        let disabled = snapshot.entries.iter().find(|e| e.id == 2).unwrap();
+        assert_eq!(
+            disabled.disabled_reason,
+            Some(DisabledReason::TooManyFailures)
+        );
+        assert!(disabled.next_refresh_at.is_none());
+    }
+
+    fn quota(remaining: f64, has_bonus: bool) -> QuotaStatus {
+        QuotaStatus {
+            remaining,
+            usage_limit: 100.0,
+            has_bonus,
+            next_reset_at: None,
+            fetched_at: Utc::now(),
+        }
+    }
+
+    fn set_quota(manager: &MultiTokenManager, id: u64, status: Option<QuotaStatus>) {
+        let mut entries = manager.entries.lock();
+        entries.iter_mut().find(|e| e.id == id).unwrap().quota = status;
+    }
+
+    #[test]
+    fn test_quota_mode_prefers_bonus_then_remaining_and_skips_near_limit() {
+        let creds = (0..4)
+            .map(|priority| KiroCredentials {
+                priority,
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+        *manager.load_balancing_mode.lock() = "quota".to_string();
+        let selected = || {
+            manager
+                .select_next_credential(None, &HashSet::new())
+                .unwrap()
+                .0
+        };
+
+        // 尚未查询到额度：按优先级
+        assert_eq!(selected(), 1);
+
+        set_quota(&manager, 1, Some(quota(10.0, false)));
+        set_quota(&manager, 2, Some(quota(50.0, false)));
+        set_quota(&manager, 3, Some(quota(5.0, true)));
+        assert_eq!(selected(), 3, "有激活奖励额度的凭据优先");
+
+        // 低于保留阈值（默认 2%）后不再分配，选择剩余最多的凭据
+        set_quota(&manager, 3, Some(quota(1.0, true)));
+        assert_eq!(selected(), 2);
+
+        // 全部接近上限时仍选择剩余最多的凭据，避免直接拒绝请求
+        set_quota(&manager, 1, Some(quota(1.5, false)));
+        set_quota(&manager, 2, Some(quota(0.5, false)));
+        set_quota(&manager, 4, Some(quota(0.0, false)));
+        assert_eq!(selected(), 3);
+    }
+
+    #[test]
+    fn test_record_quota_reenables_quota_exceeded_after_reset() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        manager.report_quota_exhausted(1);
+        manager.report_failure(2);
+        manager.report_failure(2);
+        manager.report_failure(2);
+
+        let usage = |current: f64| -> UsageLimitsResponse {
+            serde_json::from_value(serde_json::json!({
+                "nextDateReset": 1767225600.0,
+                "usageBreakdownList": [{
+                    "currentUsageWithPrecision": current,
+                    "usageLimitWithPrecision": 50.0
+                }]
+            }))
+            .unwrap()
+        };
+
+        // 尚未重置：保持禁用，并记录下次重置时间
+        manager.record_quota(1, &usage(50.0));
+        assert_eq!(manager.available_count(), 0);
+        assert!(
+            manager.entries.lock()[0]
+                .quota
+                .as_ref()
+                .unwrap()
+                .next_reset_at
+                .is_some()
+        );
+
+        // 额度已恢复：仅重新启用因额度用尽禁用的凭据
+        manager.record_quota(1, &usage(0.0));
+        manager.record_quota(2, &usage(0.0));
+        let snapshot = manager.snapshot();
+        assert!(!snapshot.entries[0].disabled);
+        assert_eq!(snapshot.entries[0].failure_count, 0);
+        assert_eq!(
+            snapshot.entries[1].disabled_reason,
+            Some(DisabledReason::TooManyFailures)
+        );
+    }
+
+    #[test]
+    fn test_quota_exhausted_credential_reenabled_after_reset() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let now = Utc::now();
+        let reset_at = DateTime::from_timestamp((now + Duration::hours(1)).timestamp(), 0).unwrap();
+        let usage: UsageLimitsResponse = serde_json::from_value(serde_json::json!({
+            "nextDateReset": reset_at.timestamp() as f64,
+            "usageBreakdownList": [{
+                "currentUsageWithPrecision": 10.0,
+                "usageLimitWithPrecision": 50.0
+            }]
+        }))
+        .unwrap();
+
+        // 以最近一次查询额度得到的 nextDateReset 作为计划启用时间
+        manager.record_quota(1, &usage);
+        manager.report_quota_exhausted(1);
+        // 手动禁用的凭据没有计划启用时间
+        manager.set_disabled(2, true).unwrap();
+        let snapshot = manager.snapshot();
+        assert_eq!(
+            snapshot.entries[0].reenable_at.as_deref(),
+            Some(reset_at.to_rfc3339().as_str())
+        );
+        assert!(snapshot.entries[0].disabled_at.is_some());
+        assert!(snapshot.entries[1].reenable_at.is_none());
+
+        assert!(manager.reenable_due_credentials(now).is_empty());
+        assert_eq!(
+            manager.reenable_due_credentials(reset_at + Duration::seconds(1)),
+            vec![1]
+        );
+
+        let snapshot = manager.snapshot();
+        assert!(!snapshot.entries[0].disabled);
+        assert!(snapshot.entries[0].disabled_reason.is_none());
+        assert_eq!(snapshot.entries[0].failure_count, 0);
+        assert_eq!(
+            snapshot.entries[1].disabled_reason,
+            Some(DisabledReason::Manual)
+        );
+    }
+
+    #[test]
+    fn test_disabled_reason_and_reenable_time_persist_across_restart() {
+        use crate::kiro::model::credentials::CredentialsConfig;
+
+        let dir = std::env::temp_dir().join(format!("kiro-disabled-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.json");
+        std::fs::write(&path, r#"[{"id": 1}, {"id": 2}]"#).unwrap();
+
+        let load = || {
+            let credentials = CredentialsConfig::load(path.to_str().unwrap())
+                .unwrap()
+                .into_sorted_credentials();
+            MultiTokenManager::new(
+                Config::default(),
+                credentials,
+                None,
+                Some(path.clone()),
+                true,
+            )
+            .unwrap()
+        };
+
+        let manager = load();
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        let before = manager.snapshot().entries[0].clone();
+        assert!(
+            before.reenable_at.is_some(),
+            "连续失败禁用应计划冷却期后启用"
+        );
+        drop(manager);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains(r#""disabledReason": "tooManyFailures""#));
+
+        // 重启后恢复禁用原因与计划启用时间，而不是视为手动禁用
+        let restored = load().snapshot().entries[0].clone();
+        assert!(restored.disabled);
+        assert_eq!(
+            restored.disabled_reason,
+            Some(DisabledReason::TooManyFailures)
+        );
+        assert_eq!(restored.disabled_at, before.disabled_at);
+        assert_eq!(restored.reenable_at, before.reenable_at);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     // ============ 凭据级 Region 优先级测试 ============
 
     #[test]
@@ -0,0 +1,12 @@
+//! 请求用量账本模块
+//!
+//! 每个经过 `/v1/messages`、`/cc/v1/messages` 与 `/v1/chat/completions` 的请求在完成后
+//! 写入一条记录（时间、客户端 Key、模型、凭据、输入/输出 token、stop_reason、耗时、错误类型）。
+//!
+//! 记录以 JSONL 追加写入 `usage/usage-YYYY-MM-DD.jsonl`（默认位于凭据文件同目录），
+//! 按 UTC 自然日轮转，超过 `usageRetentionDays` 的文件会在轮转时删除。
+//! 可通过 Admin API `GET /api/admin/usage` 按模型、凭据或日期聚合查询。
+
+mod store;
+
+pub use store::{GroupBy, LedgerEntry, UsageLedger, UsageReport};
@@ -0,0 +1,421 @@
+//! 用量账本存储与聚合查询
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::common::io::blocking_io;
+
+/// 账本文件名前缀
+const FILE_PREFIX: &str = "usage-";
+
+/// 账本文件扩展名
+const FILE_SUFFIX: &str = ".jsonl";
+
+/// 单条请求记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntry {
+    /// 请求完成时间
+    pub timestamp: DateTime<Utc>,
+
+    /// 客户端 API Key ID（使用主 API Key 时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_id: Option<u64>,
+
+    /// 客户端 API Key 名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_name: Option<String>,
+
+    /// 请求的模型名
+    pub model: String,
+
+    /// 实际处理请求的凭据 ID（未到达上游时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+
+    /// 是否为流式请求
+    #[serde(default)]
+    pub stream: bool,
+
+    /// 输入 token 数（估算或来自 contextUsageEvent）
+    #[serde(default)]
+    pub input_tokens: u64,
+
+    /// 输出 token 数（估算）
+    #[serde(default)]
+    pub output_tokens: u64,
+
+    /// stop_reason（请求失败时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+
+    /// 请求耗时（毫秒，流式请求统计到流结束）
+    #[serde(default)]
+    pub latency_ms: u64,
+
+    /// 错误类型（成功时为空）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_type: Option<String>,
+}
+
+/// 聚合维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Model,
+    Credential,
+    Day,
+}
+
+impl GroupBy {
+    /// 从查询参数解析
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "model" => Some(Self::Model),
+            "credential" => Some(Self::Credential),
+            "day" => Some(Self::Day),
+            _ => None,
+        }
+    }
+
+    fn key_of(&self, entry: &LedgerEntry) -> String {
+        match self {
+            Self::Model => entry.model.clone(),
+            Self::Credential => entry
+                .credential_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            Self::Day => entry.timestamp.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+/// 聚合结果中的一组统计
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageBucket {
+    /// 分组键（模型名 / 凭据 ID / 日期）
+    pub key: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 平均耗时（毫秒）
+    pub avg_latency_ms: u64,
+    #[serde(skip)]
+    total_latency_ms: u64,
+}
+
+impl UsageBucket {
+    fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, entry: &LedgerEntry) {
+        self.requests += 1;
+        if entry.error_type.is_some() {
+            self.errors += 1;
+        }
+        self.input_tokens += entry.input_tokens;
+        self.output_tokens += entry.output_tokens;
+        self.total_latency_ms += entry.latency_ms;
+        self.avg_latency_ms = self.total_latency_ms / self.requests;
+    }
+}
+
+/// 聚合查询结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub group_by: GroupBy,
+    /// 区间内全部请求的汇总
+    pub total: UsageBucket,
+    /// 按维度分组的统计（按日期升序，其余维度按请求数降序）
+    pub buckets: Vec<UsageBucket>,
+}
+
+/// 用量账本
+///
+/// 追加写入按日轮转的 JSONL 文件，查询时按日期范围扫描对应文件
+pub struct UsageLedger {
+    dir: PathBuf,
+    /// 文件保留天数（0 表示永久保留）
+    retention_days: u32,
+    /// 当前写入的文件及其日期
+    writer: Mutex<Option<(NaiveDate, File)>>,
+}
+
+impl UsageLedger {
+    /// 创建账本（目录在首次写入时创建）
+    pub fn new(dir: impl Into<PathBuf>, retention_days: u32) -> Self {
+        Self {
+            dir: dir.into(),
+            retention_days,
+            writer: Mutex::new(None),
+        }
+    }
+
+    /// 追加一条记录，失败时仅记录日志
+    pub fn record(&self, entry: &LedgerEntry) {
+        if let Err(e) = blocking_io(|| self.append(entry)) {
+            tracing::warn!("写入用量账本失败: {}", e);
+        }
+    }
+
+    fn append(&self, entry: &LedgerEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(entry).context("序列化用量记录失败")?;
+        line.push('\n');
+
+        let day = entry.timestamp.date_naive();
+        let mut writer = self.writer.lock();
+        let file = match writer.as_mut() {
+            Some((current, file)) if *current == day => file,
+            _ => {
+                fs::create_dir_all(&self.dir)
+                    .with_context(|| format!("创建用量账本目录失败: {:?}", self.dir))?;
+                let path = self.file_path(day);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("打开用量账本文件失败: {:?}", path))?;
+                self.prune(day);
+                &mut writer.insert((day, file)).1
+            }
+        };
+
+        file.write_all(line.as_bytes())
+            .context("写入用量账本文件失败")?;
+        Ok(())
+    }
+
+    fn file_path(&self, day: NaiveDate) -> PathBuf {
+        self.dir.join(format!(
+            "{}{}{}",
+            FILE_PREFIX,
+            day.format("%Y-%m-%d"),
+            FILE_SUFFIX
+        ))
+    }
+
+    /// 删除超过保留天数的账本文件
+    fn prune(&self, today: NaiveDate) {
+        if self.retention_days == 0 {
+            return;
+        }
+        let cutoff = today - Duration::days(self.retention_days as i64);
+
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for file in dir.flatten() {
+            let name = file.file_name();
+            let Some(day) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix(FILE_PREFIX))
+                .and_then(|n| n.strip_suffix(FILE_SUFFIX))
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            else {
+                continue;
+            };
+            if day < cutoff {
+                match fs::remove_file(file.path()) {
+                    Ok(()) => tracing::info!("已删除过期用量账本: {:?}", name),
+                    Err(e) => tracing::warn!("删除过期用量账本失败: {:?}: {}", name, e),
+                }
+            }
+        }
+    }
+
+    /// 按时间范围（闭区间）聚合查询
+    pub fn query(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        group_by: GroupBy,
+    ) -> anyhow::Result<UsageReport> {
+        let mut total = UsageBucket::new("total");
+        let mut buckets: HashMap<String, UsageBucket> = HashMap::new();
+
+        let mut day = from.date_naive();
+        let last_day = to.date_naive();
+        while day <= last_day {
+            let path = self.file_path(day);
+            let file = match File::open(&path) {
+                Ok(f) => Some(f),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("读取用量账本失败: {:?}", path));
+                }
+            };
+
+            for line in file.into_iter().flat_map(|f| BufReader::new(f).lines()) {
+                let line = line.with_context(|| format!("读取用量账本失败: {:?}", path))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let entry: LedgerEntry = match serde_json::from_str(&line) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        tracing::debug!("跳过无法解析的用量记录: {}", e);
+                        continue;
+                    }
+                };
+                if entry.timestamp < from || entry.timestamp > to {
+                    continue;
+                }
+
+                total.add(&entry);
+                let key = group_by.key_of(&entry);
+                buckets
+                    .entry(key.clone())
+                    .or_insert_with(|| UsageBucket::new(key))
+                    .add(&entry);
+            }
+
+            day = match day.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        let mut buckets: Vec<UsageBucket> = buckets.into_values().collect();
+        if group_by == GroupBy::Day {
+            // 补齐没有请求的日期，便于前端绘制连续的图表
+            let mut day = from.date_naive();
+            while day <= last_day {
+                let key = day.format("%Y-%m-%d").to_string();
+                if !buckets.iter().any(|b| b.key == key) {
+                    buckets.push(UsageBucket::new(key));
+                }
+                day = match day.succ_opt() {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+            buckets.sort_by(|a, b| a.key.cmp(&b.key));
+        } else {
+            buckets.sort_by(|a, b| b.requests.cmp(&a.requests).then(a.key.cmp(&b.key)));
+        }
+
+        Ok(UsageReport {
+            from,
+            to,
+            group_by,
+            total,
+            buckets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(ts: DateTime<Utc>, model: &str, credential_id: u64, error: bool) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: ts,
+            api_key_id: None,
+            api_key_name: None,
+            model: model.to_string(),
+            credential_id: Some(credential_id),
+            stream: true,
+            input_tokens: 100,
+            output_tokens: 20,
+            stop_reason: (!error).then(|| "end_turn".to_string()),
+            latency_ms: 1000,
+            error_type: error.then(|| "api_error".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_record_rotates_by_day_and_queries_by_model() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let ledger = UsageLedger::new(dir, 0);
+        let day1 = Utc.with_ymd_and_hms(2026, 3, 1, 23, 0, 0).unwrap();
+        let day2 = Utc.with_ymd_and_hms(2026, 3, 2, 1, 0, 0).unwrap();
+
+        ledger.record(&entry(day1, "claude-sonnet-4-5", 1, false));
+        ledger.record(&entry(day2, "claude-sonnet-4-5", 2, true));
+        ledger.record(&entry(day2, "claude-opus-4-6", 1, false));
+
+        assert!(dir.join("usage-2026-03-01.jsonl").exists());
+        assert!(dir.join("usage-2026-03-02.jsonl").exists());
+
+        let report = ledger
+            .query(day1 - Duration::hours(1), day2, GroupBy::Model)
+            .unwrap();
+        assert_eq!(report.total.requests, 3);
+        assert_eq!(report.total.errors, 1);
+        assert_eq!(report.total.input_tokens, 300);
+        assert_eq!(report.buckets[0].key, "claude-sonnet-4-5");
+        assert_eq!(report.buckets[0].requests, 2);
+        assert_eq!(report.buckets[1].key, "claude-opus-4-6");
+
+        // 时间范围过滤
+        let report = ledger
+            .query(day2 - Duration::minutes(1), day2, GroupBy::Credential)
+            .unwrap();
+        assert_eq!(report.total.requests, 2);
+        assert_eq!(report.buckets.len(), 2);
+    }
+
+    #[test]
+    fn test_query_by_day_fills_empty_days() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let ledger = UsageLedger::new(dir, 0);
+        let ts = Utc.with_ymd_and_hms(2026, 3, 3, 12, 0, 0).unwrap();
+        ledger.record(&entry(ts, "claude-sonnet-4-5", 1, false));
+
+        let from = Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2026, 3, 4, 23, 59, 59).unwrap();
+        let report = ledger.query(from, to, GroupBy::Day).unwrap();
+        let keys: Vec<&str> = report.buckets.iter().map(|b| b.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            ["2026-03-01", "2026-03-02", "2026-03-03", "2026-03-04"]
+        );
+        assert_eq!(report.buckets[2].requests, 1);
+        assert_eq!(report.buckets[2].avg_latency_ms, 1000);
+    }
+
+    #[test]
+    fn test_prune_removes_expired_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("usage-2026-01-01.jsonl"), "").unwrap();
+        fs::write(dir.join("unrelated.txt"), "").unwrap();
+
+        let ledger = UsageLedger::new(dir, 30);
+        let ts = Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap();
+        ledger.record(&entry(ts, "claude-sonnet-4-5", 1, false));
+
+        assert!(!dir.join("usage-2026-01-01.jsonl").exists());
+        assert!(dir.join("unrelated.txt").exists());
+        assert!(dir.join("usage-2026-03-01.jsonl").exists());
+    }
+
+    #[test]
+    fn test_group_by_parse() {
+        assert_eq!(GroupBy::parse("model"), Some(GroupBy::Model));
+        assert_eq!(GroupBy::parse("Credential"), Some(GroupBy::Credential));
+        assert_eq!(GroupBy::parse("day"), Some(GroupBy::Day));
+        assert_eq!(GroupBy::parse("week"), None);
+    }
+}
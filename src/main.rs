@@ -1,18 +1,30 @@
 mod admin;
 mod admin_ui;
 mod anthropic;
+mod apikey;
+mod batch;
+mod cache;
 mod common;
 mod http_client;
 mod kiro;
+mod ledger;
+mod metrics;
 mod model;
+mod reload;
+mod search;
 pub mod token;
 
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use apikey::ApiKeyStore;
+use batch::BatchStore;
+use cache::ResponseCache;
 use clap::Parser;
 use kiro::model::credentials::{CredentialsConfig, KiroCredentials};
 use kiro::provider::KiroProvider;
 use kiro::token_manager::MultiTokenManager;
+use ledger::UsageLedger;
 use model::arg::Args;
 use model::config::Config;
 
@@ -47,6 +59,61 @@ async fn main() {
         std::process::exit(1);
     });
 
+    // 加载客户端 API Key（默认位于凭证文件同目录）
+    let api_keys_path = args.api_keys.map(PathBuf::from).unwrap_or_else(|| {
+        Path::new(&credentials_path)
+            .parent()
+            .map(|d| d.join("api_keys.json"))
+            .unwrap_or_else(|| PathBuf::from("api_keys.json"))
+    });
+    let api_key_store = ApiKeyStore::load(&api_keys_path).unwrap_or_else(|e| {
+        tracing::error!("加载客户端 API Key 失败: {}", e);
+        std::process::exit(1);
+    });
+    let api_key_store = Arc::new(api_key_store);
+    tracing::info!("已加载 {} 个客户端 API Key", api_key_store.len());
+
+    // 用量账本（位于凭证文件同目录的 usage/ 下）
+    let usage_ledger_dir = Path::new(&credentials_path)
+        .parent()
+        .map(|d| d.join("usage"))
+        .unwrap_or_else(|| PathBuf::from("usage"));
+    let usage_ledger = Arc::new(UsageLedger::new(
+        usage_ledger_dir,
+        config.usage_retention_days,
+    ));
+
+    // 非流式响应缓存（磁盘存储默认位于凭证文件同目录的 cache/ 下）
+    let response_cache = config.response_cache.enabled.then(|| {
+        let default_dir = Path::new(&credentials_path)
+            .parent()
+            .map(|d| d.join("cache"))
+            .unwrap_or_else(|| PathBuf::from("cache"));
+        let cache = ResponseCache::new(&config.response_cache, default_dir);
+        tracing::info!(
+            "已启用响应缓存: storage={:?}, ttl={}s, maxEntries={}",
+            config.response_cache.storage,
+            config.response_cache.ttl_secs,
+            config.response_cache.max_entries
+        );
+        Arc::new(cache)
+    });
+
+    // Message Batches 存储（位于凭证文件同目录的 batches/ 下）
+    let batch_dir = Path::new(&credentials_path)
+        .parent()
+        .map(|d| d.join("batches"))
+        .unwrap_or_else(|| PathBuf::from("batches"));
+    let batch_store =
+        BatchStore::open(batch_dir, config.batches.retention_days).unwrap_or_else(|e| {
+            tracing::error!("加载批处理失败: {}", e);
+            std::process::exit(1);
+        });
+    let batch_store = Arc::new(batch_store);
+    if batch_store.pending_count() > 0 {
+        tracing::info!("恢复 {} 个未完成的批处理请求", batch_store.pending_count());
+    }
+
     // 判断是否为多凭据格式（用于刷新后回写）
     let is_multiple_format = credentials_config.is_multiple();
 
@@ -65,13 +132,7 @@ async fn main() {
     });
 
     // 构建代理配置
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
-        }
-        proxy
-    });
+    let proxy_config = reload::proxy_config(&config);
 
     if proxy_config.is_some() {
         tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
@@ -82,7 +143,7 @@ async fn main() {
         config.clone(),
         credentials_list,
         proxy_config.clone(),
-        Some(credentials_path.into()),
+        Some(credentials_path.clone().into()),
         is_multiple_format,
     )
     .unwrap_or_else(|e| {
@@ -90,22 +151,34 @@ async fn main() {
         std::process::exit(1);
     });
     let token_manager = Arc::new(token_manager);
-    let kiro_provider = KiroProvider::with_proxy(token_manager.clone(), proxy_config.clone());
-
-    // 初始化 count_tokens 配置
-    token::init_config(token::CountTokensConfig {
-        api_url: config.count_tokens_api_url.clone(),
-        api_key: config.count_tokens_api_key.clone(),
-        auth_type: config.count_tokens_auth_type.clone(),
-        proxy: proxy_config,
-        tls_backend: config.tls_backend,
-    });
+    // 后台主动刷新 Token（过期前带抖动刷新，并周期性校验自动禁用的凭据）
+    token_manager.spawn_refresh_scheduler();
+    // quota 负载均衡模式的额度轮询（其他模式下空转）
+    token_manager.spawn_quota_poller();
+    let kiro_provider = KiroProvider::new(token_manager.clone());
+
+    // 配置与凭据热重载（文件变化、SIGHUP、Admin API）
+    let reloader = Arc::new(reload::ConfigReloader::new(
+        &config_path,
+        &credentials_path,
+        token_manager.clone(),
+    ));
+    reloader.spawn_watcher();
+    reloader.spawn_signal_handler();
+
+    // 初始化 count_tokens、document、web_search、web_fetch 与模型注册表等运行时配置
+    reload::init_runtime_config(&config, proxy_config);
+    tracing::info!("已加载 {} 个模型定义", config.models.len());
 
     // 构建 Anthropic API 路由（从第一个凭据获取 profile_arn）
     let anthropic_app = anthropic::create_router_with_provider(
         &api_key,
         Some(kiro_provider),
         first_credentials.profile_arn.clone(),
+        Some(api_key_store.clone()),
+        Some(usage_ledger.clone()),
+        response_cache.clone(),
+        Some((batch_store, config.batches.concurrency)),
     );
 
     // 构建 Admin API 路由（如果配置了非空的 admin_api_key）
@@ -116,12 +189,39 @@ async fn main() {
         .map(|k| !k.trim().is_empty())
         .unwrap_or(false);
 
+    // 构建 /metrics 路由（可选要求 Admin API Key 认证）
+    let metrics_state = metrics::MetricsState::new(token_manager.clone());
+    let metrics_app = if config.metrics_require_admin_key {
+        if admin_key_valid {
+            let admin_key = config.admin_api_key.as_deref().unwrap_or_default();
+            Some(metrics::create_metrics_router(
+                metrics_state.with_admin_api_key(admin_key),
+            ))
+        } else {
+            tracing::warn!("metricsRequireAdminKey 已开启但未配置 adminApiKey，/metrics 未启用");
+            None
+        }
+    } else {
+        Some(metrics::create_metrics_router(metrics_state))
+    };
+    let metrics_enabled = metrics_app.is_some();
+    let anthropic_app = match metrics_app {
+        Some(metrics_app) => anthropic_app.merge(metrics_app),
+        None => anthropic_app,
+    };
+
     let app = if let Some(admin_key) = &config.admin_api_key {
         if admin_key.trim().is_empty() {
             tracing::warn!("admin_api_key 配置为空，Admin API 未启用");
             anthropic_app
         } else {
-            let admin_service = admin::AdminService::new(token_manager.clone());
+            let admin_service = admin::AdminService::new(
+                token_manager.clone(),
+                api_key_store.clone(),
+                usage_ledger.clone(),
+                response_cache.clone(),
+                reloader.clone(),
+            );
             let admin_state = admin::AdminState::new(admin_key, admin_service);
             let admin_app = admin::create_admin_router(admin_state);
 
@@ -138,6 +238,9 @@ async fn main() {
         anthropic_app
     };
 
+    // 请求指标中间件需要在所有路由合并后添加，才能覆盖全部路由
+    let app = app.layer(axum::middleware::from_fn(metrics::track_requests));
+
     // 启动服务器
     let addr = format!("{}:{}", config.host, config.port);
     tracing::info!("启动 Anthropic API 端点: {}", addr);
@@ -146,6 +249,11 @@ async fn main() {
     tracing::info!("  GET  /v1/models");
     tracing::info!("  POST /v1/messages");
     tracing::info!("  POST /v1/messages/count_tokens");
+    tracing::info!("  POST /v1/chat/completions");
+    tracing::info!("  POST /v1/messages/batches");
+    if metrics_enabled {
+        tracing::info!("  GET  /metrics");
+    }
     if admin_key_valid {
         tracing::info!("Admin API:");
         tracing::info!("  GET  /api/admin/credentials");
@@ -153,6 +261,19 @@ async fn main() {
         tracing::info!("  POST /api/admin/credentials/:index/priority");
         tracing::info!("  POST /api/admin/credentials/:index/reset");
         tracing::info!("  GET  /api/admin/credentials/:index/balance");
+        tracing::info!("  GET  /api/admin/api-keys");
+        tracing::info!("  POST /api/admin/api-keys");
+        tracing::info!("  PUT  /api/admin/api-keys/:id");
+        tracing::info!("  DELETE /api/admin/api-keys/:id");
+        tracing::info!("  GET  /api/admin/usage");
+        tracing::info!("  GET  /api/admin/cache");
+        tracing::info!("  DELETE /api/admin/cache");
+        tracing::info!("  GET  /api/admin/count-tokens");
+        tracing::info!("  POST /api/admin/count-tokens/reset");
+        tracing::info!("  GET  /api/admin/config");
+        tracing::info!("  PATCH /api/admin/config");
+        tracing::info!("  POST /api/admin/prompt-rules/dry-run");
+        tracing::info!("  POST /api/admin/reload");
         tracing::info!("Admin UI:");
         tracing::info!("  GET  /admin");
     }
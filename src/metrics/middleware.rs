@@ -0,0 +1,44 @@
+//! 请求指标中间件
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{body::Body, extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use parking_lot::Mutex;
+
+tokio::task_local! {
+    /// 当前请求的模型标签，由处理器在解析请求体后填写
+    static REQUEST_MODEL: Arc<Mutex<Option<String>>>;
+}
+
+/// 为当前请求设置模型标签（应为 [`super::model_label`] 归一化后的值）
+///
+/// 只在 [`track_requests`] 包裹的请求内生效，其他上下文中调用会被忽略
+pub(crate) fn set_request_model(label: &str) {
+    let _ = REQUEST_MODEL.try_with(|slot| *slot.lock() = Some(label.to_string()));
+}
+
+/// 请求计数与耗时中间件
+///
+/// 路由标签使用匹配到的路由模板（如 `/api/admin/credentials/{id}/balance`），
+/// 未匹配的请求统一记为 `unmatched`，避免路径参数导致标签基数膨胀
+pub async fn track_requests(request: Request<Body>, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let slot = Arc::new(Mutex::new(None));
+    let started = Instant::now();
+    let response = REQUEST_MODEL.scope(slot.clone(), next.run(request)).await;
+
+    let model = slot.lock().take().unwrap_or_else(|| "none".to_string());
+    super::record_http_request(
+        &route,
+        &model,
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    response
+}
@@ -0,0 +1,220 @@
+//! Prometheus 指标模块
+//!
+//! 通过 `/metrics` 端点以 Prometheus 文本格式暴露代理与凭据健康状况：
+//! - 按路由/模型/状态码统计的请求数与请求耗时
+//! - 上游调用的首字节时间（TTFB）、总耗时、重试与凭据故障转移次数
+//! - 每个凭据的成功/失败次数、Token 刷新结果
+//! - 估算的输入/输出 token 数
+//! - 抓取时计算的凭据数量与禁用状态
+//!
+//! 指标保存在进程内的全局注册表中，进程重启后归零。
+
+mod middleware;
+mod registry;
+mod router;
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use registry::{CounterVec, HistogramVec};
+
+pub(crate) use middleware::set_request_model;
+pub use middleware::track_requests;
+pub use router::{MetricsState, create_metrics_router};
+
+/// 请求耗时桶（秒），覆盖快速失败到长时间流式响应
+const REQUEST_DURATION_BUCKETS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// 上游首字节时间桶（秒）
+const UPSTREAM_TTFB_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 30.0, 60.0];
+
+/// 全局指标注册表
+pub(crate) struct Metrics {
+    http_requests: CounterVec,
+    http_request_duration: HistogramVec,
+    upstream_requests: CounterVec,
+    upstream_ttfb: HistogramVec,
+    upstream_duration: HistogramVec,
+    upstream_retries: CounterVec,
+    credential_failovers: CounterVec,
+    credential_requests: CounterVec,
+    token_refreshes: CounterVec,
+    tokens: CounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self {
+            http_requests: CounterVec::new(
+                "kiro_http_requests_total",
+                "客户端请求总数",
+                &["route", "model", "status"],
+            ),
+            http_request_duration: HistogramVec::new(
+                "kiro_http_request_duration_seconds",
+                "客户端请求耗时（流式请求统计到响应头返回）",
+                &["route"],
+                REQUEST_DURATION_BUCKETS,
+            ),
+            upstream_requests: CounterVec::new(
+                "kiro_upstream_requests_total",
+                "上游 API 请求次数（每次尝试单独计数）",
+                &["api", "status"],
+            ),
+            upstream_ttfb: HistogramVec::new(
+                "kiro_upstream_ttfb_seconds",
+                "上游 API 首字节时间（收到响应头）",
+                &["api"],
+                UPSTREAM_TTFB_BUCKETS,
+            ),
+            upstream_duration: HistogramVec::new(
+                "kiro_upstream_request_duration_seconds",
+                "上游 API 调用总耗时（含重试）",
+                &["api", "result"],
+                REQUEST_DURATION_BUCKETS,
+            ),
+            upstream_retries: CounterVec::new(
+                "kiro_upstream_retries_total",
+                "上游 API 重试次数",
+                &["api"],
+            ),
+            credential_failovers: CounterVec::new(
+                "kiro_credential_failovers_total",
+                "凭据故障转移（切换）次数",
+                &["reason"],
+            ),
+            credential_requests: CounterVec::new(
+                "kiro_credential_requests_total",
+                "按凭据统计的上游调用结果",
+                &["credential", "result"],
+            ),
+            token_refreshes: CounterVec::new(
+                "kiro_token_refreshes_total",
+                "Token 刷新次数",
+                &["result"],
+            ),
+            tokens: CounterVec::new(
+                "kiro_tokens_total",
+                "估算的 token 使用量",
+                &["model", "type"],
+            ),
+        }
+    }
+
+    /// 渲染全部累计指标
+    fn render(&self, out: &mut String) {
+        self.http_requests.render(out);
+        self.http_request_duration.render(out);
+        self.upstream_requests.render(out);
+        self.upstream_ttfb.render(out);
+        self.upstream_duration.render(out);
+        self.upstream_retries.render(out);
+        self.credential_failovers.render(out);
+        self.credential_requests.render(out);
+        self.token_refreshes.render(out);
+        self.tokens.render(out);
+    }
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub(crate) fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// 上游 API 类型标签
+pub(crate) fn api_label(is_stream: bool) -> &'static str {
+    if is_stream { "stream" } else { "non_stream" }
+}
+
+/// 将客户端传入的模型名归一化为指标标签
+///
+/// 只使用注册表中的模型 ID，避免任意模型名导致标签基数膨胀
+pub(crate) fn model_label(model: &str) -> String {
+    crate::model::registry::registry()
+        .resolve(model)
+        .map(|m| m.id.clone())
+        .unwrap_or_else(|| "other".to_string())
+}
+
+/// 记录一次客户端请求
+pub(crate) fn record_http_request(route: &str, model: &str, status: u16, duration: Duration) {
+    let m = metrics();
+    m.http_requests
+        .inc(&[route, model, status.to_string().as_str()]);
+    m.http_request_duration.observe_duration(&[route], duration);
+}
+
+/// 记录一次收到响应的上游请求（含首字节时间）
+pub(crate) fn record_upstream_response(api: &str, status: u16, ttfb: Duration) {
+    let m = metrics();
+    m.upstream_requests.inc(&[api, status.to_string().as_str()]);
+    m.upstream_ttfb.observe_duration(&[api], ttfb);
+}
+
+/// 记录一次发送失败（网络错误）的上游请求
+pub(crate) fn record_upstream_error(api: &str) {
+    metrics().upstream_requests.inc(&[api, "network_error"]);
+}
+
+/// 记录上游调用总耗时
+pub(crate) fn record_upstream_duration(api: &str, success: bool, duration: Duration) {
+    let result = if success { "success" } else { "failure" };
+    metrics()
+        .upstream_duration
+        .observe_duration(&[api, result], duration);
+}
+
+/// 记录一次上游重试
+pub(crate) fn record_upstream_retry(api: &str) {
+    metrics().upstream_retries.inc(&[api]);
+}
+
+/// 记录一次凭据故障转移
+pub(crate) fn record_credential_failover(reason: &str) {
+    metrics().credential_failovers.inc(&[reason]);
+}
+
+/// 记录凭据调用结果（success / failure / quota_exhausted）
+pub(crate) fn record_credential_result(id: u64, result: &str) {
+    metrics()
+        .credential_requests
+        .inc(&[id.to_string().as_str(), result]);
+}
+
+/// 记录一次 Token 刷新
+pub(crate) fn record_token_refresh(success: bool) {
+    let result = if success { "success" } else { "failure" };
+    metrics().token_refreshes.inc(&[result]);
+}
+
+/// 记录估算的输入/输出 token 数
+pub(crate) fn record_tokens(model: &str, input_tokens: i32, output_tokens: i32) {
+    let m = metrics();
+    m.tokens
+        .inc_by(&[model, "input"], input_tokens.max(0) as u64);
+    m.tokens
+        .inc_by(&[model, "output"], output_tokens.max(0) as u64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_tokens_ignores_negative_values() {
+        record_tokens("test-model-negative", -5, 12);
+        record_tokens("test-model-negative", 3, 0);
+
+        let m = metrics();
+        assert_eq!(m.tokens.get(&["test-model-negative", "input"]), 3);
+        assert_eq!(m.tokens.get(&["test-model-negative", "output"]), 12);
+    }
+
+    #[test]
+    fn test_model_label_falls_back_to_other() {
+        assert_eq!(model_label("definitely-not-a-model"), "other");
+    }
+}
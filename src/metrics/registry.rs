@@ -0,0 +1,235 @@
+//! Prometheus 文本格式的最小实现
+//!
+//! 只实现本项目需要的带标签计数器与直方图，输出遵循
+//! [text exposition format](https://prometheus.io/docs/instrumenting/exposition_formats/)
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+/// 带标签的计数器
+pub(crate) struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    label_names: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterVec {
+    pub(crate) const fn new(
+        name: &'static str,
+        help: &'static str,
+        label_names: &'static [&'static str],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            label_names,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 计数加一
+    pub(crate) fn inc(&self, labels: &[&str]) {
+        self.inc_by(labels, 1);
+    }
+
+    /// 计数增加指定值
+    pub(crate) fn inc_by(&self, labels: &[&str], value: u64) {
+        debug_assert_eq!(labels.len(), self.label_names.len());
+        let key: Vec<String> = labels.iter().map(|s| s.to_string()).collect();
+        *self.values.lock().entry(key).or_insert(0) += value;
+    }
+
+    /// 获取指定标签组合的当前值（测试用）
+    #[cfg(test)]
+    pub(crate) fn get(&self, labels: &[&str]) -> u64 {
+        let key: Vec<String> = labels.iter().map(|s| s.to_string()).collect();
+        self.values.lock().get(&key).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn render(&self, out: &mut String) {
+        write_header(out, self.name, self.help, "counter");
+        for (values, count) in self.values.lock().iter() {
+            let labels = zip_labels(self.label_names, values);
+            write_sample(out, self.name, &labels, count);
+        }
+    }
+}
+
+/// 直方图单个标签组合的累计数据
+struct HistogramState {
+    /// 各桶（非累积）计数，最后一个元素对应 +Inf
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// 带标签的直方图
+pub(crate) struct HistogramVec {
+    name: &'static str,
+    help: &'static str,
+    label_names: &'static [&'static str],
+    /// 桶上界（秒），需升序
+    bounds: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, HistogramState>>,
+}
+
+impl HistogramVec {
+    pub(crate) const fn new(
+        name: &'static str,
+        help: &'static str,
+        label_names: &'static [&'static str],
+        bounds: &'static [f64],
+    ) -> Self {
+        Self {
+            name,
+            help,
+            label_names,
+            bounds,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 记录一次耗时
+    pub(crate) fn observe_duration(&self, labels: &[&str], duration: Duration) {
+        self.observe(labels, duration.as_secs_f64());
+    }
+
+    /// 记录一次观测值
+    pub(crate) fn observe(&self, labels: &[&str], value: f64) {
+        debug_assert_eq!(labels.len(), self.label_names.len());
+        let key: Vec<String> = labels.iter().map(|s| s.to_string()).collect();
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+
+        let mut values = self.values.lock();
+        let state = values.entry(key).or_insert_with(|| HistogramState {
+            buckets: vec![0; self.bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        });
+        state.buckets[index] += 1;
+        state.sum += value;
+        state.count += 1;
+    }
+
+    pub(crate) fn render(&self, out: &mut String) {
+        write_header(out, self.name, self.help, "histogram");
+        let bucket_name = format!("{}_bucket", self.name);
+        let sum_name = format!("{}_sum", self.name);
+        let count_name = format!("{}_count", self.name);
+
+        for (values, state) in self.values.lock().iter() {
+            let labels = zip_labels(self.label_names, values);
+
+            let mut cumulative = 0;
+            for (i, count) in state.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = self
+                    .bounds
+                    .get(i)
+                    .map(|b| b.to_string())
+                    .unwrap_or_else(|| "+Inf".to_string());
+                let mut bucket_labels = labels.clone();
+                bucket_labels.push(("le", le));
+                write_sample(out, &bucket_name, &bucket_labels, cumulative);
+            }
+            write_sample(out, &sum_name, &labels, state.sum);
+            write_sample(out, &count_name, &labels, state.count);
+        }
+    }
+}
+
+fn zip_labels(names: &[&'static str], values: &[String]) -> Vec<(&'static str, String)> {
+    names.iter().copied().zip(values.iter().cloned()).collect()
+}
+
+/// 写入指标的 HELP / TYPE 行
+pub(crate) fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// 写入一行样本
+pub(crate) fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, String)],
+    value: impl Display,
+) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        out.push('{');
+        for (i, (key, value)) in labels.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"{}\"", key, escape_label_value(value));
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+/// 转义标签值中的反斜杠、双引号与换行
+fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counter_render() {
+        let counter = CounterVec::new("test_total", "测试计数", &["route", "status"]);
+        counter.inc(&["/v1/messages", "200"]);
+        counter.inc(&["/v1/messages", "200"]);
+        counter.inc_by(&["/v1/models", "401"], 3);
+
+        let mut out = String::new();
+        counter.render(&mut out);
+        assert!(out.contains("# TYPE test_total counter\n"));
+        assert!(out.contains("test_total{route=\"/v1/messages\",status=\"200\"} 2\n"));
+        assert!(out.contains("test_total{route=\"/v1/models\",status=\"401\"} 3\n"));
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = HistogramVec::new("test_seconds", "测试耗时", &["api"], &[0.1, 1.0]);
+        histogram.observe(&["stream"], 0.05);
+        histogram.observe(&["stream"], 0.5);
+        histogram.observe(&["stream"], 5.0);
+
+        let mut out = String::new();
+        histogram.render(&mut out);
+        assert!(out.contains("# TYPE test_seconds histogram\n"));
+        assert!(out.contains("test_seconds_bucket{api=\"stream\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("test_seconds_bucket{api=\"stream\",le=\"1\"} 2\n"));
+        assert!(out.contains("test_seconds_bucket{api=\"stream\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("test_seconds_sum{api=\"stream\"} 5.55\n"));
+        assert!(out.contains("test_seconds_count{api=\"stream\"} 3\n"));
+    }
+
+    #[test]
+    fn test_label_value_escaping() {
+        let mut out = String::new();
+        write_sample(&mut out, "m", &[("v", "a\"b\\c\nd".to_string())], 1);
+        assert_eq!(out, "m{v=\"a\\\"b\\\\c\\nd\"} 1\n");
+    }
+}
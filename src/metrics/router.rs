@@ -0,0 +1,146 @@
+//! `/metrics` 路由
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+
+use crate::common::auth;
+use crate::kiro::token_manager::MultiTokenManager;
+
+use super::registry::{write_header, write_sample};
+
+/// `/metrics` 共享状态
+#[derive(Clone)]
+pub struct MetricsState {
+    token_manager: Arc<MultiTokenManager>,
+    /// 配置后需要 Admin API Key 才能访问
+    admin_api_key: Option<String>,
+}
+
+impl MetricsState {
+    pub fn new(token_manager: Arc<MultiTokenManager>) -> Self {
+        Self {
+            token_manager,
+            admin_api_key: None,
+        }
+    }
+
+    /// 要求使用 Admin API Key 访问
+    pub fn with_admin_api_key(mut self, key: impl Into<String>) -> Self {
+        self.admin_api_key = Some(key.into());
+        self
+    }
+}
+
+/// 创建 `/metrics` 路由
+pub fn create_metrics_router(state: MetricsState) -> Router {
+    Router::new()
+        .route("/metrics", get(get_metrics))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics_auth_middleware,
+        ))
+        .with_state(state)
+}
+
+async fn metrics_auth_middleware(
+    State(state): State<MetricsState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(admin_key) = &state.admin_api_key else {
+        return next.run(request).await;
+    };
+
+    match auth::extract_api_key(&request) {
+        Some(key) if auth::constant_time_eq(&key, admin_key) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, "unauthorized\n").into_response(),
+    }
+}
+
+/// GET /metrics
+async fn get_metrics(State(state): State<MetricsState>) -> impl IntoResponse {
+    let mut out = String::new();
+    super::metrics().render(&mut out);
+    render_credential_gauges(&state.token_manager, &mut out);
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        out,
+    )
+}
+
+/// 抓取时根据凭据快照生成的 gauge
+fn render_credential_gauges(token_manager: &MultiTokenManager, out: &mut String) {
+    let snapshot = token_manager.snapshot();
+
+    write_header(out, "kiro_credentials", "凭据数量", "gauge");
+    write_sample(
+        out,
+        "kiro_credentials",
+        &[("state", "total".to_string())],
+        snapshot.total,
+    );
+    write_sample(
+        out,
+        "kiro_credentials",
+        &[("state", "available".to_string())],
+        snapshot.available,
+    );
+
+    write_header(
+        out,
+        "kiro_credential_disabled",
+        "凭据是否已禁用（1 为禁用）",
+        "gauge",
+    );
+    for entry in &snapshot.entries {
+        write_sample(
+            out,
+            "kiro_credential_disabled",
+            &[("credential", entry.id.to_string())],
+            u8::from(entry.disabled),
+        );
+    }
+
+    write_header(
+        out,
+        "kiro_credential_consecutive_failures",
+        "凭据当前连续失败次数",
+        "gauge",
+    );
+    for entry in &snapshot.entries {
+        write_sample(
+            out,
+            "kiro_credential_consecutive_failures",
+            &[("credential", entry.id.to_string())],
+            entry.failure_count,
+        );
+    }
+
+    write_header(
+        out,
+        "kiro_credential_current",
+        "当前优先使用的凭据（1 为当前）",
+        "gauge",
+    );
+    for entry in &snapshot.entries {
+        write_sample(
+            out,
+            "kiro_credential_current",
+            &[("credential", entry.id.to_string())],
+            u8::from(entry.id == snapshot.current_id),
+        );
+    }
+}
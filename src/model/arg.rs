@@ -11,4 +11,8 @@ pub struct Args {
     /// 凭证文件路径
     #[arg(long)]
     pub credentials: Option<String>,
+
+    /// 客户端 API Key 文件路径（默认为凭证文件同目录下的 api_keys.json）
+    #[arg(long)]
+    pub api_keys: Option<String>,
 }
@@ -1,9 +1,15 @@
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+use super::prompt_rules::{
+    PromptRewriter, PromptRule, default_prompt_rules, is_default_prompt_rules,
+};
+use super::registry::{ModelEntry, SystemPromptStrategy, default_models, is_default_models};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
 pub enum TlsBackend {
     Rustls,
@@ -16,6 +22,536 @@ impl Default for TlsBackend {
     }
 }
 
+/// 响应缓存存储方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStorage {
+    /// 进程内 LRU（重启后丢失）
+    #[default]
+    Memory,
+    /// 磁盘文件（每个条目一个 JSON 文件）
+    Disk,
+}
+
+/// 非流式响应缓存配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseCacheConfig {
+    /// 是否启用（默认关闭）
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 缓存条目有效期（秒）
+    #[serde(default = "default_cache_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// 最大缓存条目数，超出后按 LRU 淘汰
+    #[serde(default = "default_cache_max_entries")]
+    pub max_entries: usize,
+
+    /// 存储方式：`memory` 或 `disk`
+    #[serde(default)]
+    pub storage: CacheStorage,
+
+    /// 磁盘缓存目录（仅 `disk` 存储使用，默认为凭据文件同目录的 `cache/`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: default_cache_ttl_secs(),
+            max_entries: default_cache_max_entries(),
+            storage: CacheStorage::default(),
+            dir: None,
+        }
+    }
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_cache_max_entries() -> usize {
+    1000
+}
+
+/// document 内容块与 URL 来源配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentConfig {
+    /// 单个文档（或 URL 资源）解码后的最大字节数
+    #[serde(default = "default_document_max_bytes")]
+    pub max_bytes: usize,
+
+    /// PDF 最大页数
+    #[serde(default = "default_document_max_pdf_pages")]
+    pub max_pdf_pages: usize,
+
+    /// 是否下载 `url` 来源的图片和文档（关闭后此类请求直接返回 400）
+    ///
+    /// 默认关闭：开启后客户端可以让代理访问任意公网 URL
+    #[serde(default)]
+    pub fetch_urls: bool,
+
+    /// URL 下载超时（秒）
+    #[serde(default = "default_document_fetch_timeout_secs")]
+    pub fetch_timeout_secs: u64,
+}
+
+impl Default for DocumentConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: default_document_max_bytes(),
+            max_pdf_pages: default_document_max_pdf_pages(),
+            fetch_urls: false,
+            fetch_timeout_secs: default_document_fetch_timeout_secs(),
+        }
+    }
+}
+
+fn default_document_max_bytes() -> usize {
+    32 * 1024 * 1024
+}
+
+fn default_document_max_pdf_pages() -> usize {
+    100
+}
+
+fn default_document_fetch_timeout_secs() -> u64 {
+    30
+}
+
+/// web_search 工具配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSearchConfig {
+    /// 搜索后端，按顺序尝试，前一个失败时回退到下一个
+    #[serde(default = "default_search_backends")]
+    pub backends: Vec<SearchBackendConfig>,
+
+    /// 每次搜索最多返回的结果数（域名过滤之后）
+    #[serde(default = "default_search_max_results")]
+    pub max_results: usize,
+
+    /// 外部搜索后端的请求超时（秒）
+    #[serde(default = "default_search_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for WebSearchConfig {
+    fn default() -> Self {
+        Self {
+            backends: default_search_backends(),
+            max_results: default_search_max_results(),
+            timeout_secs: default_search_timeout_secs(),
+        }
+    }
+}
+
+/// 单个搜索后端
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SearchBackendConfig {
+    /// Kiro MCP 内置搜索
+    Kiro,
+    /// SearxNG 实例（JSON 输出需在实例中启用）
+    Searxng {
+        /// 实例地址，如 `https://searx.example.com`
+        url: String,
+        /// 搜索语言，如 `zh-CN`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        language: Option<String>,
+        /// 额外请求头
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
+    },
+    /// 通用 HTTP JSON 搜索 API
+    Http {
+        /// URL 模板，支持 `{query}`、`{country}`、`{region}`、`{city}`、`{timezone}` 占位符
+        url: String,
+        /// 额外请求头（如 API Key）
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
+        /// 结果数组在响应中的路径，以 `.` 分隔，留空表示响应本身就是数组
+        #[serde(default)]
+        results_path: String,
+        /// 标题字段名
+        #[serde(default = "default_http_title_field")]
+        title_field: String,
+        /// 链接字段名
+        #[serde(default = "default_http_url_field")]
+        url_field: String,
+        /// 摘要字段名
+        #[serde(default = "default_http_snippet_field")]
+        snippet_field: String,
+        /// 发布时间字段名（毫秒时间戳或 RFC 3339 字符串）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        date_field: Option<String>,
+    },
+    /// 本地静态结果文件（离线测试用）
+    Fixture {
+        /// JSON 文件路径
+        path: PathBuf,
+    },
+}
+
+fn default_search_backends() -> Vec<SearchBackendConfig> {
+    vec![SearchBackendConfig::Kiro]
+}
+
+fn default_search_max_results() -> usize {
+    10
+}
+
+fn default_search_timeout_secs() -> u64 {
+    15
+}
+
+fn default_http_title_field() -> String {
+    "title".to_string()
+}
+
+fn default_http_url_field() -> String {
+    "url".to_string()
+}
+
+fn default_http_snippet_field() -> String {
+    "snippet".to_string()
+}
+
+/// web_fetch 工具配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebFetchConfig {
+    /// 抓取超时（秒）
+    #[serde(default = "default_fetch_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// 单个页面最多读取的字节数，超出部分丢弃
+    #[serde(default = "default_fetch_max_bytes")]
+    pub max_bytes: usize,
+
+    /// 工具未指定 `max_content_tokens` 时，单个页面写回对话的最大 token 数
+    #[serde(default = "default_fetch_max_content_tokens")]
+    pub max_content_tokens: usize,
+}
+
+impl Default for WebFetchConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_fetch_timeout_secs(),
+            max_bytes: default_fetch_max_bytes(),
+            max_content_tokens: default_fetch_max_content_tokens(),
+        }
+    }
+}
+
+fn default_fetch_timeout_secs() -> u64 {
+    30
+}
+
+fn default_fetch_max_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_fetch_max_content_tokens() -> usize {
+    50_000
+}
+
+/// Message Batches 配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchConfig {
+    /// 同时执行的批处理请求数上限（实际并发不超过可用凭据数）
+    #[serde(default = "default_batch_concurrency")]
+    pub concurrency: usize,
+
+    /// 已结束批处理的保留天数，超过后连同结果一起删除（0 表示永久保留）
+    #[serde(default = "default_batch_retention_days")]
+    pub retention_days: u32,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: default_batch_concurrency(),
+            retention_days: default_batch_retention_days(),
+        }
+    }
+}
+
+fn default_batch_concurrency() -> usize {
+    4
+}
+
+fn default_batch_retention_days() -> u32 {
+    29
+}
+
+/// 后台 Token 主动刷新配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRefreshConfig {
+    /// 是否启用后台刷新（关闭后仅在请求时按需刷新）
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 在 Token 过期前多少秒刷新
+    #[serde(default = "default_refresh_lead_secs")]
+    pub lead_secs: u64,
+
+    /// 随机提前的最大秒数，避免多个凭据同时刷新
+    #[serde(default = "default_refresh_jitter_secs")]
+    pub jitter_secs: u64,
+
+    /// 刷新失败后的重试间隔（秒）
+    #[serde(default = "default_refresh_retry_secs")]
+    pub retry_secs: u64,
+
+    /// 因连续失败被自动禁用的凭据的验活间隔（秒），验活成功后自动启用
+    #[serde(default = "default_refresh_validate_interval_secs")]
+    pub validate_interval_secs: u64,
+}
+
+impl Default for TokenRefreshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            lead_secs: default_refresh_lead_secs(),
+            jitter_secs: default_refresh_jitter_secs(),
+            retry_secs: default_refresh_retry_secs(),
+            validate_interval_secs: default_refresh_validate_interval_secs(),
+        }
+    }
+}
+
+fn default_refresh_lead_secs() -> u64 {
+    15 * 60
+}
+
+fn default_refresh_jitter_secs() -> u64 {
+    5 * 60
+}
+
+fn default_refresh_retry_secs() -> u64 {
+    2 * 60
+}
+
+fn default_refresh_validate_interval_secs() -> u64 {
+    10 * 60
+}
+
+/// 自动禁用凭据的自动恢复配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoReenableConfig {
+    /// 是否在计划时间自动重新启用（额度用尽：额度重置时间；连续失败：冷却期结束）
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 因连续失败被禁用的凭据在多少秒后自动重新启用（0 表示不按冷却期恢复）
+    #[serde(default = "default_failure_cooldown_secs")]
+    pub failure_cooldown_secs: u64,
+}
+
+impl Default for AutoReenableConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_cooldown_secs: default_failure_cooldown_secs(),
+        }
+    }
+}
+
+fn default_failure_cooldown_secs() -> u64 {
+    30 * 60
+}
+
+/// `quota` 负载均衡模式配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaBalancingConfig {
+    /// 轮询各凭据使用额度的间隔（秒）
+    #[serde(default = "default_quota_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+
+    /// 剩余额度低于总额度的该百分比时停止向该凭据分配请求
+    #[serde(default = "default_quota_reserve_percent")]
+    pub reserve_percent: f64,
+}
+
+impl Default for QuotaBalancingConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: default_quota_poll_interval_secs(),
+            reserve_percent: default_quota_reserve_percent(),
+        }
+    }
+}
+
+fn default_quota_poll_interval_secs() -> u64 {
+    5 * 60
+}
+
+fn default_quota_reserve_percent() -> f64 {
+    2.0
+}
+
+/// 上下文窗口预检的裁剪策略
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ContextStrategy {
+    /// 截断历史中过长的 tool_result（保留首尾）
+    TrimToolResults,
+    /// 丢弃最早的若干轮对话
+    DropOldest,
+    /// 调用模型总结最早的若干轮对话，以摘要代替
+    Summarize,
+}
+
+/// 上下文窗口预检配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextManagementConfig {
+    /// 是否启用（默认关闭）
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 估算的输入 tokens 超过上下文窗口的该百分比时触发裁剪
+    #[serde(default = "default_context_threshold_percent")]
+    pub threshold_percent: u8,
+
+    /// 依次尝试的裁剪策略，估算值回到阈值以内即停止
+    #[serde(default = "default_context_strategies")]
+    pub strategies: Vec<ContextStrategy>,
+
+    /// `trimToolResults` 截断后每个 tool_result 保留的最大字符数
+    #[serde(default = "default_max_tool_result_chars")]
+    pub max_tool_result_chars: usize,
+
+    /// `dropOldest` / `summarize` 至少保留的最近对话轮数
+    #[serde(default = "default_keep_recent_turns")]
+    pub keep_recent_turns: usize,
+
+    /// `summarize` 使用的模型（默认与请求相同）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_model: Option<String>,
+}
+
+impl Default for ContextManagementConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_percent: default_context_threshold_percent(),
+            strategies: default_context_strategies(),
+            max_tool_result_chars: default_max_tool_result_chars(),
+            keep_recent_turns: default_keep_recent_turns(),
+            summary_model: None,
+        }
+    }
+}
+
+fn default_context_threshold_percent() -> u8 {
+    85
+}
+
+fn default_context_strategies() -> Vec<ContextStrategy> {
+    vec![ContextStrategy::TrimToolResults, ContextStrategy::DropOldest]
+}
+
+fn default_max_tool_result_chars() -> usize {
+    8000
+}
+
+fn default_keep_recent_turns() -> usize {
+    4
+}
+
+/// 本地 token 计数配置（count_tokens 与用量估算）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenizerConfig {
+    /// tiktoken 格式的 BPE 词表文件（可选，未配置时按字符估算；相对路径基于配置文件所在目录）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vocab_file: Option<String>,
+
+    /// 计数结果的校准系数（本地计数 × scale）
+    #[serde(default = "default_tokenizer_scale")]
+    pub scale: f64,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            vocab_file: None,
+            scale: default_tokenizer_scale(),
+        }
+    }
+}
+
+fn default_tokenizer_scale() -> f64 {
+    1.0
+}
+
+/// 远程 count_tokens API 的超时、结果缓存与熔断配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensRemoteConfig {
+    /// 单次请求超时（秒）
+    #[serde(default = "default_count_tokens_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// 按内容哈希缓存的结果条数上限（0 表示不缓存）
+    #[serde(default = "default_count_tokens_cache_max_entries")]
+    pub cache_max_entries: usize,
+
+    /// 连续失败该次数后熔断，期间直接使用本地估算
+    #[serde(default = "default_count_tokens_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// 熔断持续时间（秒），到期后放行一次探测请求
+    #[serde(default = "default_count_tokens_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+impl Default for CountTokensRemoteConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_count_tokens_timeout_secs(),
+            cache_max_entries: default_count_tokens_cache_max_entries(),
+            failure_threshold: default_count_tokens_failure_threshold(),
+            cooldown_secs: default_count_tokens_cooldown_secs(),
+        }
+    }
+}
+
+fn default_count_tokens_timeout_secs() -> u64 {
+    10
+}
+
+fn default_count_tokens_cache_max_entries() -> usize {
+    1024
+}
+
+fn default_count_tokens_failure_threshold() -> u32 {
+    3
+}
+
+fn default_count_tokens_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_true() -> bool {
+    true
+}
+
 /// KNA 应用配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -69,6 +605,10 @@ pub struct Config {
     #[serde(default = "default_count_tokens_auth_type")]
     pub count_tokens_auth_type: String,
 
+    /// 远程 count_tokens API 的超时、结果缓存与熔断
+    #[serde(default)]
+    pub count_tokens_remote: CountTokensRemoteConfig,
+
     /// HTTP 代理地址（可选）
     /// 支持格式: http://host:port, https://host:port, socks5://host:port
     #[serde(default)]
@@ -86,10 +626,77 @@ pub struct Config {
     #[serde(default)]
     pub admin_api_key: Option<String>,
 
-    /// 负载均衡模式（"priority" 或 "balanced"）
+    /// 负载均衡模式（"priority"、"balanced" 或 "quota"）
     #[serde(default = "default_load_balancing_mode")]
     pub load_balancing_mode: String,
 
+    /// `/metrics` 端点是否需要 Admin API Key 认证（默认不需要）
+    #[serde(default)]
+    pub metrics_require_admin_key: bool,
+
+    /// 用量账本文件保留天数（0 表示永久保留）
+    #[serde(default = "default_usage_retention_days")]
+    pub usage_retention_days: u32,
+
+    /// 非流式响应缓存（默认关闭）
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+
+    /// document 内容块与 URL 来源的限制
+    #[serde(default)]
+    pub documents: DocumentConfig,
+
+    /// web_search 工具的搜索后端
+    #[serde(default)]
+    pub web_search: WebSearchConfig,
+
+    /// web_fetch 工具的抓取限制
+    #[serde(default)]
+    pub web_fetch: WebFetchConfig,
+
+    /// Message Batches 后台执行配置
+    #[serde(default)]
+    pub batches: BatchConfig,
+
+    /// 后台 Token 主动刷新
+    #[serde(default)]
+    pub token_refresh: TokenRefreshConfig,
+
+    /// `quota` 负载均衡模式的额度轮询与保留阈值
+    #[serde(default)]
+    pub quota_balancing: QuotaBalancingConfig,
+
+    /// 自动禁用凭据的自动恢复
+    #[serde(default)]
+    pub auto_reenable: AutoReenableConfig,
+
+    /// 系统提示词承载方式（`pair` 或 `inline`，可在 `models` 中按模型覆盖）
+    #[serde(default)]
+    pub system_prompt: SystemPromptStrategy,
+
+    /// 上下文窗口预检（默认关闭）
+    #[serde(default)]
+    pub context_management: ContextManagementConfig,
+
+    /// 本地 token 计数
+    #[serde(default)]
+    pub tokenizer: TokenizerConfig,
+
+    /// 模型注册表（未配置时使用内置默认列表）
+    #[serde(default = "default_models", skip_serializing_if = "is_default_models")]
+    pub models: Vec<ModelEntry>,
+
+    /// 提示词改写规则（未配置时使用内置默认规则，配置为 `[]` 表示不改写）
+    #[serde(
+        default = "default_prompt_rules",
+        skip_serializing_if = "is_default_prompt_rules"
+    )]
+    pub prompt_rules: Vec<PromptRule>,
+
+    /// 提示词改写规则文件（可选，配置后忽略 `promptRules`；相对路径基于配置文件所在目录）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_rules_file: Option<String>,
+
     /// 配置文件路径（运行时元数据，不写入 JSON）
     #[serde(skip)]
     config_path: Option<PathBuf>,
@@ -132,6 +739,15 @@ fn default_load_balancing_mode() -> String {
     "priority".to_string()
 }
 
+/// 是否为支持的负载均衡模式
+pub fn is_valid_load_balancing_mode(mode: &str) -> bool {
+    matches!(mode, "priority" | "balanced" | "quota")
+}
+
+fn default_usage_retention_days() -> u32 {
+    90
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -149,11 +765,28 @@ impl Default for Config {
             count_tokens_api_url: None,
             count_tokens_api_key: None,
             count_tokens_auth_type: default_count_tokens_auth_type(),
+            count_tokens_remote: CountTokensRemoteConfig::default(),
             proxy_url: None,
             proxy_username: None,
             proxy_password: None,
             admin_api_key: None,
             load_balancing_mode: default_load_balancing_mode(),
+            metrics_require_admin_key: false,
+            usage_retention_days: default_usage_retention_days(),
+            response_cache: ResponseCacheConfig::default(),
+            documents: DocumentConfig::default(),
+            web_search: WebSearchConfig::default(),
+            web_fetch: WebFetchConfig::default(),
+            batches: BatchConfig::default(),
+            token_refresh: TokenRefreshConfig::default(),
+            quota_balancing: QuotaBalancingConfig::default(),
+            auto_reenable: AutoReenableConfig::default(),
+            system_prompt: SystemPromptStrategy::default(),
+            context_management: ContextManagementConfig::default(),
+            tokenizer: TokenizerConfig::default(),
+            models: default_models(),
+            prompt_rules: default_prompt_rules(),
+            prompt_rules_file: None,
             config_path: None,
         }
     }
@@ -193,6 +826,127 @@ impl Config {
         Ok(config)
     }
 
+    /// 校验字段取值（热重载与 Admin API 修改配置时使用）
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.host.trim().is_empty() {
+            anyhow::bail!("host 不能为空");
+        }
+        if self.port == 0 {
+            anyhow::bail!("port 必须大于 0");
+        }
+        for (name, value) in [
+            ("region", Some(&self.region)),
+            ("authRegion", self.auth_region.as_ref()),
+            ("apiRegion", self.api_region.as_ref()),
+        ] {
+            if let Some(value) = value
+                && (value.is_empty() || value.chars().any(char::is_whitespace))
+            {
+                anyhow::bail!("{} 不能为空或包含空白字符", name);
+            }
+        }
+        for (name, value) in [
+            ("kiroVersion", &self.kiro_version),
+            ("systemVersion", &self.system_version),
+            ("nodeVersion", &self.node_version),
+        ] {
+            if value.trim().is_empty() {
+                anyhow::bail!("{} 不能为空", name);
+            }
+        }
+        if !is_valid_load_balancing_mode(&self.load_balancing_mode) {
+            anyhow::bail!(
+                "loadBalancingMode 必须是 'priority'、'balanced' 或 'quota': {}",
+                self.load_balancing_mode
+            );
+        }
+        if !matches!(self.count_tokens_auth_type.as_str(), "x-api-key" | "bearer") {
+            anyhow::bail!(
+                "countTokensAuthType 必须是 'x-api-key' 或 'bearer': {}",
+                self.count_tokens_auth_type
+            );
+        }
+        if let Some(url) = &self.count_tokens_api_url {
+            let parsed = reqwest::Url::parse(url)
+                .map_err(|e| anyhow::anyhow!("countTokensApiUrl 无效: {}", e))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                anyhow::bail!("countTokensApiUrl 仅支持 http/https: {}", url);
+            }
+        }
+        let remote = &self.count_tokens_remote;
+        if !(1..=300).contains(&remote.timeout_secs) {
+            anyhow::bail!("countTokensRemote.timeoutSecs 必须在 [1, 300] 范围内");
+        }
+        if remote.failure_threshold == 0 || remote.cooldown_secs == 0 {
+            anyhow::bail!("countTokensRemote.failureThreshold 与 cooldownSecs 必须大于 0");
+        }
+        if let Some(url) = &self.proxy_url {
+            let parsed =
+                reqwest::Url::parse(url).map_err(|e| anyhow::anyhow!("proxyUrl 无效: {}", e))?;
+            if !matches!(parsed.scheme(), "http" | "https" | "socks5" | "socks5h") {
+                anyhow::bail!("proxyUrl 仅支持 http/https/socks5: {}", url);
+            }
+        }
+        let refresh = &self.token_refresh;
+        // accessToken 有效期约 1 小时，提前量过大会导致每次刷新后立即再次到期
+        if refresh.lead_secs.saturating_add(refresh.jitter_secs) > 30 * 60 {
+            anyhow::bail!("tokenRefresh.leadSecs + jitterSecs 不能超过 1800 秒");
+        }
+        if refresh.retry_secs == 0 || refresh.validate_interval_secs == 0 {
+            anyhow::bail!("tokenRefresh.retrySecs 与 validateIntervalSecs 必须大于 0");
+        }
+        let quota = &self.quota_balancing;
+        if quota.poll_interval_secs < 60 {
+            anyhow::bail!("quotaBalancing.pollIntervalSecs 不能小于 60 秒");
+        }
+        if !(0.0..100.0).contains(&quota.reserve_percent) {
+            anyhow::bail!("quotaBalancing.reservePercent 必须在 [0, 100) 范围内");
+        }
+        if self.auto_reenable.failure_cooldown_secs > 30 * 24 * 3600 {
+            anyhow::bail!("autoReenable.failureCooldownSecs 不能超过 30 天");
+        }
+        let context = &self.context_management;
+        if !(1..=100).contains(&context.threshold_percent) {
+            anyhow::bail!("contextManagement.thresholdPercent 必须在 [1, 100] 范围内");
+        }
+        if context.max_tool_result_chars < 200 {
+            anyhow::bail!("contextManagement.maxToolResultChars 不能小于 200");
+        }
+        if !(self.tokenizer.scale > 0.0 && self.tokenizer.scale <= 10.0) {
+            anyhow::bail!("tokenizer.scale 必须在 (0, 10] 范围内");
+        }
+        if let Some(path) = self.tokenizer_vocab_path() {
+            crate::token::load_vocab(&path)?;
+        }
+        PromptRewriter::new(self.load_prompt_rules()?)?;
+        Ok(())
+    }
+
+    /// 获取生效的提示词改写规则（配置了 `promptRulesFile` 时从文件读取）
+    pub fn load_prompt_rules(&self) -> anyhow::Result<Vec<PromptRule>> {
+        let Some(file) = &self.prompt_rules_file else {
+            return Ok(self.prompt_rules.clone());
+        };
+        let path = Path::new(file);
+        let path = match self.config_path.as_deref().and_then(Path::parent) {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("读取提示词改写规则文件失败: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("解析提示词改写规则文件失败: {}", path.display()))
+    }
+
+    /// 获取 `tokenizer.vocabFile` 的实际路径（相对路径基于配置文件所在目录）
+    pub fn tokenizer_vocab_path(&self) -> Option<PathBuf> {
+        let path = Path::new(self.tokenizer.vocab_file.as_ref()?);
+        Some(match self.config_path.as_deref().and_then(Path::parent) {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        })
+    }
+
     /// 获取配置文件路径（如果有）
     pub fn config_path(&self) -> Option<&Path> {
         self.config_path.as_deref()
@@ -210,3 +964,85 @@ impl Config {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_default_config() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_values() {
+        let cases: Vec<fn(&mut Config)> = vec![
+            |c| c.port = 0,
+            |c| c.region = "us east".to_string(),
+            |c| c.api_region = Some(String::new()),
+            |c| c.kiro_version = " ".to_string(),
+            |c| c.load_balancing_mode = "random".to_string(),
+            |c| c.count_tokens_auth_type = "basic".to_string(),
+            |c| c.count_tokens_api_url = Some("not a url".to_string()),
+            |c| c.count_tokens_remote.timeout_secs = 0,
+            |c| c.count_tokens_remote.failure_threshold = 0,
+            |c| c.proxy_url = Some("ftp://127.0.0.1:21".to_string()),
+            |c| c.token_refresh.lead_secs = 3600,
+            |c| c.token_refresh.retry_secs = 0,
+            |c| c.quota_balancing.poll_interval_secs = 10,
+            |c| c.quota_balancing.reserve_percent = 100.0,
+            |c| c.auto_reenable.failure_cooldown_secs = u64::MAX,
+            |c| c.context_management.threshold_percent = 0,
+            |c| c.context_management.max_tool_result_chars = 10,
+            |c| c.tokenizer.scale = 0.0,
+            |c| c.tokenizer.vocab_file = Some("/nonexistent/vocab.tiktoken".to_string()),
+            |c| c.prompt_rules_file = Some("/nonexistent/prompt_rules.json".to_string()),
+            |c| {
+                c.prompt_rules = serde_json::from_value(serde_json::json!([
+                    {"name": "bad", "match": {"systemPattern": "["}, "actions": []}
+                ]))
+                .unwrap()
+            },
+        ];
+        for mutate in cases {
+            let mut config = Config::default();
+            mutate(&mut config);
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn test_prompt_rules_file_relative_to_config() {
+        let dir = std::env::temp_dir().join(format!("kiro-config-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("config.json"),
+            r#"{"promptRulesFile": "rules.json"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("rules.json"),
+            r#"[{"name": "brief", "actions": [{"target": "system", "op": "append", "text": "Be brief."}]}]"#,
+        )
+        .unwrap();
+
+        let config = Config::load(dir.join("config.json")).unwrap();
+        let rules = config.load_prompt_rules().unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].name, "brief");
+        assert!(config.validate().is_ok());
+        // 未配置规则文件时使用内置默认规则
+        assert_eq!(Config::default().load_prompt_rules().unwrap(), default_prompt_rules());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_validate_accepts_socks5_proxy() {
+        let config = Config {
+            proxy_url: Some("socks5://127.0.0.1:1080".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+    }
+}
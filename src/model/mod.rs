@@ -2,3 +2,5 @@
 
 pub mod arg;
 pub mod config;
+pub mod prompt_rules;
+pub mod registry;
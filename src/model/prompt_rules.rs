@@ -0,0 +1,573 @@
+//! 提示词改写规则
+//!
+//! 转换为 Kiro 请求前，按规则改写系统提示词与工具描述。
+//! 规则来自 `config.json` 的 `promptRules` 字段或 `promptRulesFile` 指定的文件，
+//! 未配置时使用内置默认规则（为 Claude Code 的 Write/Edit 工具追加分块写入说明）。
+//!
+//! # 匹配规则
+//! - 每条规则的匹配条件全部满足才命中，未配置的条件视为满足
+//! - 同一条件内的多个取值任一命中即可
+//! - 模型、客户端、工具名支持 `*` 通配符，不区分大小写
+//! - 命中的规则按配置顺序依次应用，后面的规则看到的是前面规则改写后的文本
+
+use std::sync::{Arc, LazyLock};
+
+use parking_lot::RwLock;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// 追加到 Write 工具 description 末尾的内容
+const WRITE_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the content to write exceeds 150 lines, you MUST only write the first 50 lines using this tool, then use `Edit` tool to append the remaining content in chunks of no more than 50 lines each. If needed, leave a unique placeholder to help append content. Do NOT attempt to write all content at once.";
+
+/// 追加到 Edit 工具 description 末尾的内容
+const EDIT_TOOL_DESCRIPTION_SUFFIX: &str = "- IMPORTANT: If the `new_string` content exceeds 50 lines, you MUST split it into multiple Edit calls, each replacing no more than 50 lines at a time. If used to append content, leave a unique placeholder to help append content. On the final chunk, do NOT include the placeholder.";
+
+/// 追加到系统提示词的分块写入策略
+const SYSTEM_CHUNKED_POLICY: &str = "\
+When the Write or Edit tool has content size limits, always comply silently. \
+Never suggest bypassing these limits via alternative tools. \
+Never ask the user whether to switch approaches. \
+Complete all chunked operations without commentary.";
+
+/// 请求入口
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Route {
+    /// 标准端点 `/v1`（包括 OpenAI 兼容端点与 Message Batches）
+    #[default]
+    V1,
+    /// Claude Code 兼容端点 `/cc/v1`
+    Cc,
+}
+
+/// 改写规则
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptRule {
+    /// 规则名称（日志与 dry-run 结果中用于标识命中的规则）
+    pub name: String,
+
+    /// 是否启用
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 匹配条件
+    #[serde(default, rename = "match")]
+    pub matcher: RuleMatch,
+
+    /// 命中后依次执行的改写动作
+    pub actions: Vec<RewriteAction>,
+}
+
+/// 规则匹配条件
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleMatch {
+    /// 请求中的模型名
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+
+    /// 客户端 API Key 的名称或 ID（使用主 API Key 的请求不会命中）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clients: Vec<String>,
+
+    /// 请求入口
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<Route>,
+
+    /// 请求声明的工具名（声明了任一工具即命中）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+
+    /// 系统提示词需匹配的正则表达式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_pattern: Option<String>,
+}
+
+/// 改写目标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RewriteTarget {
+    /// 系统提示词
+    System,
+    /// 工具描述
+    ToolDescription,
+}
+
+/// 改写方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RewriteOp {
+    /// 追加到末尾（原文非空时以换行分隔）
+    Append,
+    /// 插入到开头（原文非空时以换行分隔）
+    Prepend,
+    /// 替换 `pattern` 匹配的部分，未配置 `pattern` 时替换全文
+    Replace,
+}
+
+/// 改写动作
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RewriteAction {
+    /// 改写目标
+    pub target: RewriteTarget,
+
+    /// 目标工具名（`target` 为 `toolDescription` 时必填）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+
+    /// 改写方式
+    pub op: RewriteOp,
+
+    /// 写入的文本（`replace` 带 `pattern` 时支持 `$1` 等捕获组引用）
+    #[serde(default)]
+    pub text: String,
+
+    /// `replace` 使用的正则表达式
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl RewriteAction {
+    fn append_tool(tool: &str, text: &str) -> Self {
+        Self {
+            target: RewriteTarget::ToolDescription,
+            tool: Some(tool.to_string()),
+            op: RewriteOp::Append,
+            text: text.to_string(),
+            pattern: None,
+        }
+    }
+}
+
+/// 内置默认规则
+///
+/// 为 Write/Edit 工具追加分块写入说明，并在系统提示词非空时追加分块写入策略
+pub fn default_prompt_rules() -> Vec<PromptRule> {
+    vec![
+        PromptRule {
+            name: "chunked-write-tools".to_string(),
+            enabled: true,
+            matcher: RuleMatch::default(),
+            actions: vec![
+                RewriteAction::append_tool("Write", WRITE_TOOL_DESCRIPTION_SUFFIX),
+                RewriteAction::append_tool("Edit", EDIT_TOOL_DESCRIPTION_SUFFIX),
+            ],
+        },
+        PromptRule {
+            name: "chunked-write-policy".to_string(),
+            enabled: true,
+            matcher: RuleMatch {
+                system_pattern: Some(r"\S".to_string()),
+                ..Default::default()
+            },
+            actions: vec![RewriteAction {
+                target: RewriteTarget::System,
+                tool: None,
+                op: RewriteOp::Append,
+                text: SYSTEM_CHUNKED_POLICY.to_string(),
+                pattern: None,
+            }],
+        },
+    ]
+}
+
+/// 判断规则列表是否与内置默认值相同（用于跳过序列化）
+pub fn is_default_prompt_rules(rules: &Vec<PromptRule>) -> bool {
+    *rules == default_prompt_rules()
+}
+
+/// 不区分大小写的 `*` 通配符匹配
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let value = value.to_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // 没有通配符，要求完全相等
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+fn compile(pattern: &str, field: &str, rule: &str) -> anyhow::Result<Regex> {
+    Regex::new(pattern)
+        .map_err(|e| anyhow::anyhow!("提示词改写规则 {} 的 {} 无效: {}", rule, field, e))
+}
+
+/// 用于匹配规则的请求信息
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RuleContext<'a> {
+    /// 请求中的模型名
+    pub model: &'a str,
+    /// 客户端 API Key（ID 与名称）
+    pub client: Option<(u64, &'a str)>,
+    /// 请求入口
+    pub route: Route,
+    /// 请求声明的工具名
+    pub tools: &'a [&'a str],
+    /// 合并后的系统提示词
+    pub system: &'a str,
+}
+
+/// 编译后的改写动作
+#[derive(Debug)]
+struct CompiledAction {
+    action: RewriteAction,
+    pattern: Option<Regex>,
+}
+
+impl CompiledAction {
+    fn apply(&self, text: String) -> String {
+        let action = &self.action;
+        match action.op {
+            RewriteOp::Append if text.is_empty() => action.text.clone(),
+            RewriteOp::Append => format!("{}\n{}", text, action.text),
+            RewriteOp::Prepend if text.is_empty() => action.text.clone(),
+            RewriteOp::Prepend => format!("{}\n{}", action.text, text),
+            RewriteOp::Replace => match &self.pattern {
+                Some(pattern) => pattern
+                    .replace_all(&text, action.text.as_str())
+                    .into_owned(),
+                None => action.text.clone(),
+            },
+        }
+    }
+}
+
+/// 编译后的规则
+#[derive(Debug)]
+struct CompiledRule {
+    rule: PromptRule,
+    system_pattern: Option<Regex>,
+    actions: Vec<CompiledAction>,
+}
+
+impl CompiledRule {
+    fn new(rule: PromptRule) -> anyhow::Result<Self> {
+        if rule.name.trim().is_empty() {
+            anyhow::bail!("提示词改写规则的 name 不能为空");
+        }
+        if rule.actions.is_empty() {
+            anyhow::bail!("提示词改写规则 {} 至少需要一个动作", rule.name);
+        }
+        let system_pattern = rule
+            .matcher
+            .system_pattern
+            .as_deref()
+            .map(|p| compile(p, "match.systemPattern", &rule.name))
+            .transpose()?;
+        let actions = rule
+            .actions
+            .iter()
+            .map(|action| {
+                if action.target == RewriteTarget::ToolDescription
+                    && action.tool.as_deref().is_none_or(|t| t.trim().is_empty())
+                {
+                    anyhow::bail!(
+                        "提示词改写规则 {} 的 toolDescription 动作必须指定 tool",
+                        rule.name
+                    );
+                }
+                if action.pattern.is_some() && action.op != RewriteOp::Replace {
+                    anyhow::bail!("提示词改写规则 {} 的 pattern 仅用于 replace", rule.name);
+                }
+                let pattern = action
+                    .pattern
+                    .as_deref()
+                    .map(|p| compile(p, "actions.pattern", &rule.name))
+                    .transpose()?;
+                Ok(CompiledAction {
+                    action: action.clone(),
+                    pattern,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            rule,
+            system_pattern,
+            actions,
+        })
+    }
+
+    fn matches(&self, ctx: &RuleContext<'_>) -> bool {
+        let m = &self.rule.matcher;
+        let any =
+            |patterns: &[String], value: &str| patterns.iter().any(|p| wildcard_match(p, value));
+
+        self.rule.enabled
+            && (m.models.is_empty() || any(&m.models, ctx.model))
+            && (m.clients.is_empty()
+                || ctx.client.is_some_and(|(id, name)| {
+                    any(&m.clients, name) || any(&m.clients, &id.to_string())
+                }))
+            && (m.routes.is_empty() || m.routes.contains(&ctx.route))
+            && (m.tools.is_empty() || ctx.tools.iter().any(|t| any(&m.tools, t)))
+            && self
+                .system_pattern
+                .as_ref()
+                .is_none_or(|p| p.is_match(ctx.system))
+    }
+}
+
+/// 提示词改写器
+#[derive(Debug)]
+pub struct PromptRewriter {
+    rules: Vec<CompiledRule>,
+}
+
+impl Default for PromptRewriter {
+    fn default() -> Self {
+        Self::new(default_prompt_rules()).expect("内置提示词改写规则无效")
+    }
+}
+
+impl PromptRewriter {
+    /// 编译规则列表（正则无效、缺少必填字段时返回错误）
+    pub fn new(rules: Vec<PromptRule>) -> anyhow::Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(CompiledRule::new)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// 选出命中当前请求的规则
+    pub fn select(&self, ctx: &RuleContext<'_>) -> Rewrite<'_> {
+        Rewrite {
+            rules: self.rules.iter().filter(|r| r.matches(ctx)).collect(),
+        }
+    }
+}
+
+/// 命中当前请求的规则集合
+#[derive(Debug, Default)]
+pub struct Rewrite<'a> {
+    rules: Vec<&'a CompiledRule>,
+}
+
+impl Rewrite<'_> {
+    /// 命中的规则名称
+    pub fn rule_names(&self) -> Vec<String> {
+        self.rules.iter().map(|r| r.rule.name.clone()).collect()
+    }
+
+    fn actions(&self, target: RewriteTarget) -> impl Iterator<Item = &CompiledAction> {
+        self.rules
+            .iter()
+            .flat_map(|r| r.actions.iter())
+            .filter(move |a| a.action.target == target)
+    }
+
+    /// 改写系统提示词
+    pub fn system(&self, text: String) -> String {
+        self.actions(RewriteTarget::System)
+            .fold(text, |text, action| action.apply(text))
+    }
+
+    /// 改写指定工具的描述
+    pub fn tool_description(&self, tool: &str, description: String) -> String {
+        self.actions(RewriteTarget::ToolDescription)
+            .filter(|a| {
+                a.action
+                    .tool
+                    .as_deref()
+                    .is_some_and(|p| wildcard_match(p, tool))
+            })
+            .fold(description, |text, action| action.apply(text))
+    }
+}
+
+/// 全局提示词改写器（未初始化时使用内置默认规则）
+static REWRITER: LazyLock<RwLock<Arc<PromptRewriter>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(PromptRewriter::default())));
+
+/// 初始化（或替换）全局提示词改写器
+///
+/// 应在应用启动和配置热重载时调用
+pub fn init_rewriter(rewriter: PromptRewriter) {
+    *REWRITER.write() = Arc::new(rewriter);
+}
+
+/// 获取当前的全局提示词改写器
+pub fn rewriter() -> Arc<PromptRewriter> {
+    REWRITER.read().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(json: serde_json::Value) -> PromptRule {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("claude-*", "Claude-Sonnet-4-6"));
+        assert!(wildcard_match("*sonnet*", "claude-sonnet-4-6"));
+        assert!(wildcard_match("Write", "write"));
+        assert!(!wildcard_match("Write", "WriteFile"));
+        assert!(!wildcard_match("*opus", "claude-opus-4-6"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn test_default_rules_reproduce_builtin_behavior() {
+        let rewriter = PromptRewriter::default();
+        let tools = ["Write", "Edit", "Read"];
+        let ctx = RuleContext {
+            model: "claude-sonnet-4-6",
+            tools: &tools,
+            system: "You are helpful.",
+            ..Default::default()
+        };
+        let rewrite = rewriter.select(&ctx);
+        assert_eq!(
+            rewrite.rule_names(),
+            vec!["chunked-write-tools", "chunked-write-policy"]
+        );
+        assert_eq!(
+            rewrite.system("You are helpful.".to_string()),
+            format!("You are helpful.\n{}", SYSTEM_CHUNKED_POLICY)
+        );
+        assert_eq!(
+            rewrite.tool_description("Write", "Writes a file".to_string()),
+            format!("Writes a file\n{}", WRITE_TOOL_DESCRIPTION_SUFFIX)
+        );
+        assert_eq!(
+            rewrite.tool_description("Edit", "Edits a file".to_string()),
+            format!("Edits a file\n{}", EDIT_TOOL_DESCRIPTION_SUFFIX)
+        );
+        assert_eq!(
+            rewrite.tool_description("Read", "Reads".to_string()),
+            "Reads"
+        );
+
+        // 没有系统提示词时不注入分块写入策略
+        let rewrite = rewriter.select(&RuleContext { system: "", ..ctx });
+        assert_eq!(rewrite.rule_names(), vec!["chunked-write-tools"]);
+        assert_eq!(rewrite.system(String::new()), "");
+    }
+
+    #[test]
+    fn test_rule_conditions() {
+        let rewriter = PromptRewriter::new(vec![rule(serde_json::json!({
+            "name": "cc-support-bot",
+            "match": {
+                "models": ["*opus*"],
+                "clients": ["support-*", "7"],
+                "routes": ["cc"],
+                "tools": ["lookup_*"],
+                "systemPattern": "(?i)support"
+            },
+            "actions": [{"target": "system", "op": "prepend", "text": "Be brief."}]
+        }))])
+        .unwrap();
+
+        let tools = ["lookup_order"];
+        let ctx = RuleContext {
+            model: "claude-opus-4-6",
+            client: Some((3, "support-eu")),
+            route: Route::Cc,
+            tools: &tools,
+            system: "Customer Support agent",
+        };
+        assert_eq!(rewriter.select(&ctx).rule_names(), vec!["cc-support-bot"]);
+        // 客户端也可以按 ID 匹配
+        let by_id = RuleContext {
+            client: Some((7, "other")),
+            ..ctx
+        };
+        assert_eq!(rewriter.select(&by_id).rule_names().len(), 1);
+
+        let misses = [
+            RuleContext {
+                model: "claude-sonnet-4-6",
+                ..ctx
+            },
+            RuleContext {
+                client: None,
+                ..ctx
+            },
+            RuleContext {
+                route: Route::V1,
+                ..ctx
+            },
+            RuleContext { tools: &[], ..ctx },
+            RuleContext {
+                system: "Coding agent",
+                ..ctx
+            },
+        ];
+        for miss in misses {
+            assert!(rewriter.select(&miss).rule_names().is_empty(), "{:?}", miss);
+        }
+    }
+
+    #[test]
+    fn test_actions_apply_in_order() {
+        let rewriter = PromptRewriter::new(vec![
+            rule(serde_json::json!({
+                "name": "rename",
+                "actions": [
+                    {"target": "system", "op": "replace", "pattern": "Claude Code", "text": "Assistant"},
+                    {"target": "toolDescription", "tool": "*", "op": "prepend", "text": "[tool]"}
+                ]
+            })),
+            rule(serde_json::json!({
+                "name": "disabled",
+                "enabled": false,
+                "actions": [{"target": "system", "op": "replace", "text": "ignored"}]
+            })),
+            rule(serde_json::json!({
+                "name": "inject",
+                "actions": [{"target": "system", "op": "append", "text": "Answer in English."}]
+            })),
+        ])
+        .unwrap();
+
+        let rewrite = rewriter.select(&RuleContext::default());
+        assert_eq!(rewrite.rule_names(), vec!["rename", "inject"]);
+        assert_eq!(
+            rewrite.system("You are Claude Code.".to_string()),
+            "You are Assistant.\nAnswer in English."
+        );
+        // 空系统提示词追加后即为动作文本
+        assert_eq!(rewrite.system(String::new()), "Answer in English.");
+        assert_eq!(rewrite.tool_description("Bash", String::new()), "[tool]");
+    }
+
+    #[test]
+    fn test_invalid_rules_are_rejected() {
+        let cases = [
+            serde_json::json!({"name": "", "actions": [{"target": "system", "op": "append", "text": "x"}]}),
+            serde_json::json!({"name": "no-actions", "actions": []}),
+            serde_json::json!({"name": "bad-regex", "match": {"systemPattern": "("}, "actions": [{"target": "system", "op": "append", "text": "x"}]}),
+            serde_json::json!({"name": "no-tool", "actions": [{"target": "toolDescription", "op": "append", "text": "x"}]}),
+            serde_json::json!({"name": "pattern-append", "actions": [{"target": "system", "op": "append", "pattern": "x", "text": "y"}]}),
+        ];
+        for case in cases {
+            assert!(
+                PromptRewriter::new(vec![rule(case.clone())]).is_err(),
+                "{}",
+                case
+            );
+        }
+    }
+}
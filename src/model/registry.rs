@@ -0,0 +1,403 @@
+//! 模型注册表
+//!
+//! 声明对外公开的模型 ID、别名、对应的 Kiro modelId 以及 max_tokens、上下文窗口等元数据。
+//! 注册表来自 `config.json` 的 `models` 字段，未配置时使用内置默认列表，
+//! 新增上游模型只需修改配置文件，无需重新编译。
+//!
+//! # 模型解析规则
+//! 1. 去掉 `-thinking` 后缀后，按 `id` / `aliases` 精确匹配（不区分大小写）
+//! 2. 未命中时按 `matchPatterns` 模糊匹配：每个模式由空格分隔的关键字组成，
+//!    所有关键字都出现在模型名中即视为命中；关键字最多的模式优先，同数量时按配置顺序
+//! 3. 带 `-thinking` 后缀但模型未声明 thinking 变体时视为不支持
+//!
+//! # 系统提示词策略
+//! Kiro 请求没有独立的系统提示词字段，`systemPrompt` 决定系统提示词的承载方式，
+//! 模型未配置时使用 `config.json` 顶层的 `systemPrompt`（默认 `pair`）
+
+use std::sync::{Arc, LazyLock};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+/// thinking 变体后缀
+pub const THINKING_SUFFIX: &str = "-thinking";
+
+/// 系统提示词承载方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemPromptStrategy {
+    /// 在历史开头插入一对 user（系统提示词）+ assistant（"I will follow these instructions."）消息
+    #[default]
+    Pair,
+    /// 以 `<system>` 标签并入第一条 user 消息，不插入虚构的 assistant 回复；
+    /// 历史末尾未配对的 user 消息并入当前消息，而不是补一条 "OK"。
+    ///
+    /// 有损：系统提示词与第一条 user 消息无法再区分，末尾 user 消息的轮次边界丢失
+    Inline,
+}
+
+/// 模型定义
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelEntry {
+    /// 对外公开的模型 ID（/v1/models 返回值）
+    pub id: String,
+
+    /// 显示名称（未配置时使用 id）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+
+    /// 精确匹配的别名
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+
+    /// 模糊匹配模式（空格分隔的关键字，需全部包含）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub match_patterns: Vec<String>,
+
+    /// 上游 Kiro modelId
+    pub kiro_model_id: String,
+
+    /// 最大输出 tokens
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: i32,
+
+    /// 上下文窗口大小（用于从 contextUsageEvent 百分比换算 input_tokens）
+    #[serde(default = "default_context_window")]
+    pub context_window: i32,
+
+    /// 是否提供 `-thinking` 变体
+    #[serde(default = "default_true")]
+    pub thinking: bool,
+
+    /// thinking 变体是否使用 adaptive 类型（否则为 enabled）
+    #[serde(default)]
+    pub adaptive_thinking: bool,
+
+    /// 发布时间（Unix 秒，/v1/models 的 created 字段）
+    #[serde(default)]
+    pub created: i64,
+
+    /// 系统提示词承载方式（未配置时使用全局 `systemPrompt`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<SystemPromptStrategy>,
+}
+
+fn default_max_tokens() -> i32 {
+    32000
+}
+
+/// 默认上下文窗口大小（200k tokens）
+pub const DEFAULT_CONTEXT_WINDOW: i32 = 200_000;
+
+fn default_context_window() -> i32 {
+    DEFAULT_CONTEXT_WINDOW
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ModelEntry {
+    fn builtin(
+        id: &str,
+        display_name: &str,
+        aliases: &[&str],
+        match_patterns: &[&str],
+        kiro_model_id: &str,
+        created: i64,
+    ) -> Self {
+        Self {
+            id: id.to_string(),
+            display_name: Some(display_name.to_string()),
+            aliases: aliases.iter().map(|s| s.to_string()).collect(),
+            match_patterns: match_patterns.iter().map(|s| s.to_string()).collect(),
+            kiro_model_id: kiro_model_id.to_string(),
+            max_tokens: default_max_tokens(),
+            context_window: default_context_window(),
+            thinking: true,
+            adaptive_thinking: false,
+            created,
+            system_prompt: None,
+        }
+    }
+
+    /// 获取显示名称
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.id)
+    }
+
+    /// 是否精确匹配 id 或别名
+    fn matches_exact(&self, name: &str) -> bool {
+        self.id.eq_ignore_ascii_case(name)
+            || self.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    }
+
+    /// 返回命中的最长模糊模式的关键字数量
+    fn pattern_score(&self, name_lower: &str) -> Option<usize> {
+        self.match_patterns
+            .iter()
+            .filter_map(|pattern| {
+                let keywords: Vec<String> = pattern
+                    .split_whitespace()
+                    .map(|k| k.to_lowercase())
+                    .collect();
+                (!keywords.is_empty() && keywords.iter().all(|k| name_lower.contains(k.as_str())))
+                    .then_some(keywords.len())
+            })
+            .max()
+    }
+}
+
+/// 内置默认模型列表
+pub fn default_models() -> Vec<ModelEntry> {
+    vec![
+        ModelEntry::builtin(
+            "claude-sonnet-4-5-20250929",
+            "Claude Sonnet 4.5",
+            &["claude-sonnet-4-5"],
+            &["sonnet"],
+            "claude-sonnet-4.5",
+            1727568000,
+        ),
+        ModelEntry::builtin(
+            "claude-opus-4-5-20251101",
+            "Claude Opus 4.5",
+            &["claude-opus-4-5"],
+            &["opus 4-5", "opus 4.5"],
+            "claude-opus-4.5",
+            1730419200,
+        ),
+        ModelEntry::builtin(
+            "claude-sonnet-4-6",
+            "Claude Sonnet 4.6",
+            &[],
+            &["sonnet 4-6", "sonnet 4.6"],
+            "claude-sonnet-4.6",
+            1770314400,
+        ),
+        ModelEntry {
+            adaptive_thinking: true,
+            ..ModelEntry::builtin(
+                "claude-opus-4-6",
+                "Claude Opus 4.6",
+                &[],
+                &["opus"],
+                "claude-opus-4.6",
+                1770314400,
+            )
+        },
+        ModelEntry::builtin(
+            "claude-haiku-4-5-20251001",
+            "Claude Haiku 4.5",
+            &["claude-haiku-4-5"],
+            &["haiku"],
+            "claude-haiku-4.5",
+            1727740800,
+        ),
+    ]
+}
+
+/// 判断模型列表是否与内置默认值相同（用于跳过序列化）
+pub fn is_default_models(models: &Vec<ModelEntry>) -> bool {
+    *models == default_models()
+}
+
+/// 模型注册表
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    models: Vec<ModelEntry>,
+    /// 模型未配置 `systemPrompt` 时使用的系统提示词策略
+    system_prompt: SystemPromptStrategy,
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        Self::new(default_models())
+    }
+}
+
+impl ModelRegistry {
+    /// 从模型列表创建注册表
+    pub fn new(models: Vec<ModelEntry>) -> Self {
+        Self {
+            models,
+            system_prompt: SystemPromptStrategy::default(),
+        }
+    }
+
+    /// 设置默认的系统提示词策略
+    pub fn with_system_prompt(mut self, strategy: SystemPromptStrategy) -> Self {
+        self.system_prompt = strategy;
+        self
+    }
+
+    /// 获取所有模型定义
+    pub fn models(&self) -> &[ModelEntry] {
+        &self.models
+    }
+
+    /// 解析请求中的模型名
+    ///
+    /// 返回匹配的模型定义；带 `-thinking` 后缀但模型未提供 thinking 变体时返回 None
+    pub fn resolve(&self, model: &str) -> Option<&ModelEntry> {
+        let lower = model.to_lowercase();
+        let (base, wants_thinking) = match lower.strip_suffix(THINKING_SUFFIX) {
+            Some(base) => (base, true),
+            None => (lower.as_str(), false),
+        };
+
+        let entry = self
+            .models
+            .iter()
+            .find(|m| m.matches_exact(base))
+            .or_else(|| {
+                let mut best: Option<(usize, &ModelEntry)> = None;
+                for entry in &self.models {
+                    if let Some(score) = entry.pattern_score(base)
+                        && best.is_none_or(|(best_score, _)| score > best_score)
+                    {
+                        best = Some((score, entry));
+                    }
+                }
+                best.map(|(_, entry)| entry)
+            })?;
+
+        if wants_thinking && !entry.thinking {
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// 获取请求模型使用的系统提示词策略
+    pub fn system_prompt_strategy(&self, model: &str) -> SystemPromptStrategy {
+        self.resolve(model)
+            .and_then(|entry| entry.system_prompt)
+            .unwrap_or(self.system_prompt)
+    }
+
+    /// 获取对外公开的模型 ID 列表（包含 thinking 变体）
+    pub fn public_ids(&self) -> Vec<String> {
+        let mut ids = Vec::new();
+        for entry in &self.models {
+            ids.push(entry.id.clone());
+            if entry.thinking {
+                ids.push(format!("{}{}", entry.id, THINKING_SUFFIX));
+            }
+        }
+        ids
+    }
+}
+
+/// 全局模型注册表（未初始化时使用内置默认列表）
+static REGISTRY: LazyLock<RwLock<Arc<ModelRegistry>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(ModelRegistry::default())));
+
+/// 初始化（或替换）全局模型注册表
+///
+/// 应在应用启动时根据 `Config::models` 与 `Config::system_prompt` 调用
+pub fn init_registry(models: Vec<ModelEntry>, system_prompt: SystemPromptStrategy) {
+    *REGISTRY.write() = Arc::new(ModelRegistry::new(models).with_system_prompt(system_prompt));
+}
+
+/// 获取当前的全局模型注册表
+pub fn registry() -> Arc<ModelRegistry> {
+    REGISTRY.read().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kiro_id(registry: &ModelRegistry, model: &str) -> Option<String> {
+        registry.resolve(model).map(|m| m.kiro_model_id.clone())
+    }
+
+    #[test]
+    fn test_default_registry_resolves_public_ids() {
+        let registry = ModelRegistry::default();
+        for id in registry.public_ids() {
+            assert!(registry.resolve(&id).is_some(), "{} 应可解析", id);
+        }
+    }
+
+    #[test]
+    fn test_pattern_with_more_keywords_wins() {
+        let registry = ModelRegistry::default();
+        assert_eq!(
+            kiro_id(&registry, "claude-opus-4-5-20251101"),
+            Some("claude-opus-4.5".to_string())
+        );
+        assert_eq!(
+            kiro_id(&registry, "claude-opus-4-20250514"),
+            Some("claude-opus-4.6".to_string())
+        );
+        assert_eq!(
+            kiro_id(&registry, "claude-sonnet-4.6"),
+            Some("claude-sonnet-4.6".to_string())
+        );
+        assert_eq!(
+            kiro_id(&registry, "claude-3-5-sonnet-20241022"),
+            Some("claude-sonnet-4.5".to_string())
+        );
+        assert_eq!(kiro_id(&registry, "gpt-4"), None);
+    }
+
+    #[test]
+    fn test_custom_model_from_config() {
+        let models: Vec<ModelEntry> = serde_json::from_str(
+            r#"[{
+                "id": "claude-sonnet-5",
+                "aliases": ["sonnet-latest"],
+                "kiroModelId": "claude-sonnet-5",
+                "contextWindow": 1000000,
+                "thinking": false
+            }]"#,
+        )
+        .unwrap();
+        let registry = ModelRegistry::new(models);
+
+        let entry = registry.resolve("SONNET-LATEST").unwrap();
+        assert_eq!(entry.kiro_model_id, "claude-sonnet-5");
+        assert_eq!(entry.max_tokens, 32000);
+        assert_eq!(entry.context_window, 1_000_000);
+        assert_eq!(entry.display_name(), "claude-sonnet-5");
+
+        // 未声明 thinking 变体
+        assert!(registry.resolve("claude-sonnet-5-thinking").is_none());
+        assert_eq!(registry.public_ids(), vec!["claude-sonnet-5".to_string()]);
+    }
+
+    #[test]
+    fn test_system_prompt_strategy_per_model() {
+        let mut models = default_models();
+        models[0].system_prompt = Some(SystemPromptStrategy::Inline);
+        let sonnet = models[0].id.clone();
+
+        let registry = ModelRegistry::new(models.clone());
+        assert_eq!(
+            registry.system_prompt_strategy(&sonnet),
+            SystemPromptStrategy::Inline
+        );
+        assert_eq!(
+            registry.system_prompt_strategy("claude-opus-4-6"),
+            SystemPromptStrategy::Pair
+        );
+
+        // 模型级配置优先于全局默认值
+        models[0].system_prompt = Some(SystemPromptStrategy::Pair);
+        let registry = ModelRegistry::new(models).with_system_prompt(SystemPromptStrategy::Inline);
+        assert_eq!(
+            registry.system_prompt_strategy(&sonnet),
+            SystemPromptStrategy::Pair
+        );
+        assert_eq!(
+            registry.system_prompt_strategy("claude-opus-4-6"),
+            SystemPromptStrategy::Inline
+        );
+        assert_eq!(
+            registry.system_prompt_strategy("unknown"),
+            SystemPromptStrategy::Inline
+        );
+    }
+}
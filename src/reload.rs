@@ -0,0 +1,642 @@
+//! 配置与凭据热重载
+//!
+//! 以下三种方式都会重新读取 `config.json` 与 `credentials.json`：
+//! - 后台轮询文件的修改时间与大小，发生变化时自动重载
+//! - 收到 SIGHUP 信号（仅 Unix）
+//! - Admin API `POST /api/admin/reload`
+//!
+//! 两个文件都解析并校验通过后才会生效：运行时配置原子替换，凭据按 ID 增量更新到
+//! [`MultiTokenManager`]，不会重置统计数据，也不会中断进行中的请求。
+//! 监听地址、API Key 等在启动时绑定的字段变化后只会在报告中提示需要重启。
+
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::anthropic;
+use crate::common::io::blocking_io;
+use crate::http_client::ProxyConfig;
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
+use crate::kiro::token_manager::{CredentialsDiff, MultiTokenManager, validate_credential_ids};
+use crate::model::config::Config;
+use crate::model::prompt_rules::{self, PromptRewriter};
+use crate::model::registry;
+use crate::search;
+use crate::token;
+
+/// 文件变化轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 变化后需要重启才能生效的配置字段
+pub const RESTART_REQUIRED_FIELDS: &[&str] = &[
+    "host",
+    "port",
+    "apiKey",
+    "adminApiKey",
+    "metricsRequireAdminKey",
+    "usageRetentionDays",
+    "responseCache",
+    "batches",
+];
+
+/// 根据配置构建全局代理
+pub fn proxy_config(config: &Config) -> Option<ProxyConfig> {
+    config.proxy_url.as_ref().map(|url| {
+        let mut proxy = ProxyConfig::new(url);
+        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
+            proxy = proxy.with_auth(username, password);
+        }
+        proxy
+    })
+}
+
+/// 初始化（或替换）各模块的全局运行时配置
+///
+/// 启动时与热重载时调用
+pub fn init_runtime_config(config: &Config, proxy: Option<ProxyConfig>) {
+    token::init_config(token::CountTokensConfig {
+        api_url: config.count_tokens_api_url.clone(),
+        api_key: config.count_tokens_api_key.clone(),
+        auth_type: config.count_tokens_auth_type.clone(),
+        remote: config.count_tokens_remote.clone(),
+        proxy: proxy.clone(),
+        tls_backend: config.tls_backend,
+    });
+    anthropic::init_document_config(anthropic::DocumentOptions {
+        limits: config.documents.clone(),
+        proxy: proxy.clone(),
+        tls_backend: config.tls_backend,
+    });
+    search::init_config(search::SearchOptions {
+        config: config.web_search.clone(),
+        proxy: proxy.clone(),
+        tls_backend: config.tls_backend,
+    });
+    anthropic::init_web_fetch_config(anthropic::WebFetchOptions {
+        config: config.web_fetch.clone(),
+        proxy,
+        tls_backend: config.tls_backend,
+    });
+    registry::init_registry(config.models.clone(), config.system_prompt);
+    anthropic::init_context_config(config.context_management.clone());
+    init_prompt_rules(config);
+    init_tokenizer(config);
+}
+
+/// 初始化（或替换）本地分词器，词表文件无效时继续使用当前分词器
+fn init_tokenizer(config: &Config) {
+    let vocab = match config.tokenizer_vocab_path() {
+        Some(path) => match token::load_vocab(&path) {
+            Ok(bpe) => Some(bpe),
+            Err(e) => {
+                tracing::warn!("加载分词器词表失败，继续使用当前分词器: {:#}", e);
+                return;
+            }
+        },
+        None => None,
+    };
+    token::init_tokenizer(vocab, config.tokenizer.scale);
+}
+
+/// 初始化（或替换）提示词改写规则，规则无效时继续使用当前规则
+fn init_prompt_rules(config: &Config) {
+    match config.load_prompt_rules().and_then(PromptRewriter::new) {
+        Ok(rewriter) => prompt_rules::init_rewriter(rewriter),
+        Err(e) => tracing::warn!("加载提示词改写规则失败，继续使用当前规则: {:#}", e),
+    }
+}
+
+/// 一次重载的结果
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReloadReport {
+    /// 已生效的配置字段（config.json 中的字段名）
+    pub config_changed: Vec<String>,
+    /// 已变化但需要重启才能生效的配置字段
+    pub restart_required: Vec<String>,
+    /// 凭据变化
+    pub credentials: CredentialsDiff,
+}
+
+impl ReloadReport {
+    /// 是否没有任何变化
+    pub fn is_empty(&self) -> bool {
+        self.config_changed.is_empty()
+            && self.restart_required.is_empty()
+            && self.credentials.is_empty()
+    }
+}
+
+/// 文件指纹（修改时间与大小）
+type Fingerprint = Option<(SystemTime, u64)>;
+
+fn fingerprint(path: &PathBuf) -> Fingerprint {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 上次重载时的文件状态
+struct ReloadState {
+    config: Fingerprint,
+    credentials: Fingerprint,
+    /// 上次应用的凭据文件内容哈希，内容未变化时不重新应用凭据
+    credentials_hash: Option<u64>,
+}
+
+/// 配置热重载器
+pub struct ConfigReloader {
+    config_path: PathBuf,
+    credentials_path: PathBuf,
+    token_manager: Arc<MultiTokenManager>,
+    /// 串行化重载，同时记录上次重载时的文件状态
+    state: Mutex<ReloadState>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        config_path: impl Into<PathBuf>,
+        credentials_path: impl Into<PathBuf>,
+        token_manager: Arc<MultiTokenManager>,
+    ) -> Self {
+        let config_path = config_path.into();
+        let credentials_path = credentials_path.into();
+        let state = ReloadState {
+            config: fingerprint(&config_path),
+            credentials: fingerprint(&credentials_path),
+            credentials_hash: std::fs::read_to_string(&credentials_path)
+                .ok()
+                .map(|content| content_hash(&content)),
+        };
+        Self {
+            config_path,
+            credentials_path,
+            token_manager,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// 重新读取并应用两个文件
+    ///
+    /// 任一文件校验失败时不做任何修改；凭据文件内容未变化时不重新应用凭据
+    pub fn reload(&self) -> anyhow::Result<ReloadReport> {
+        let mut state = self.state.lock();
+        state.config = fingerprint(&self.config_path);
+        state.credentials = fingerprint(&self.credentials_path);
+
+        let previous = self.token_manager.config();
+        let (config, (credentials_hash, credentials)) =
+            blocking_io(|| anyhow::Ok((self.load_config(&previous)?, self.load_credentials()?)))?;
+
+        let (config_changed, restart_required) = self.apply_config(&previous, config)?;
+        let credentials = if state.credentials_hash == Some(credentials_hash) {
+            CredentialsDiff::default()
+        } else {
+            let diff = self.token_manager.reload_credentials(credentials)?;
+            state.credentials_hash = Some(credentials_hash);
+            diff
+        };
+
+        Ok(ReloadReport {
+            config_changed,
+            restart_required,
+            credentials,
+        })
+    }
+
+    /// 以配置文件的当前内容为基础修改配置，校验通过后写回文件并立即应用
+    ///
+    /// 返回（已生效的变化字段，需要重启的变化字段）
+    pub fn update_config(
+        &self,
+        update: impl FnOnce(&mut Config),
+    ) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let mut state = self.state.lock();
+        let previous = self.token_manager.config();
+
+        let mut config = blocking_io(|| self.load_config(&previous))?;
+        update(&mut config);
+        config.validate()?;
+        blocking_io(|| config.save())?;
+        state.config = fingerprint(&self.config_path);
+
+        self.apply_config(&previous, config)
+    }
+
+    /// 应用新配置，返回（已生效的变化字段，需要重启的变化字段）
+    fn apply_config(
+        &self,
+        previous: &Config,
+        config: Config,
+    ) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let (changed, restart_required) = diff_config(previous, &config)?;
+        if !changed.is_empty() || !restart_required.is_empty() {
+            let proxy = proxy_config(&config);
+            init_runtime_config(&config, proxy.clone());
+            self.token_manager.apply_config(config, proxy);
+        } else if config.prompt_rules_file.is_some() {
+            // 规则文件不在监听范围内，SIGHUP 或 Admin API 重载时重新读取
+            init_prompt_rules(&config);
+        }
+        Ok((changed, restart_required))
+    }
+
+    /// 读取并校验配置文件
+    fn load_config(&self, previous: &Config) -> anyhow::Result<Config> {
+        if !self.config_path.exists() {
+            anyhow::bail!("配置文件不存在: {}", self.config_path.display());
+        }
+        let mut config = Config::load(&self.config_path)
+            .with_context(|| format!("解析配置文件失败: {}", self.config_path.display()))?;
+        // systemVersion 未配置时每次加载都会随机选取，重载时沿用当前值
+        let raw: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&self.config_path)?)?;
+        if raw.get("systemVersion").is_none() {
+            config.system_version = previous.system_version.clone();
+        }
+        if config.api_key.is_none() {
+            anyhow::bail!("配置文件中未设置 apiKey");
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 读取并校验凭据文件，返回（文件内容哈希，凭据列表）
+    fn load_credentials(&self) -> anyhow::Result<(u64, Vec<KiroCredentials>)> {
+        // 编辑器保存时可能短暂出现空文件，不视为删除全部凭据（如需清空请写入 `[]`）
+        let content = std::fs::read_to_string(&self.credentials_path)
+            .with_context(|| format!("读取凭据文件失败: {}", self.credentials_path.display()))?;
+        if content.trim().is_empty() {
+            anyhow::bail!("凭据文件为空: {}", self.credentials_path.display());
+        }
+        let credentials = CredentialsConfig::load(&self.credentials_path)
+            .with_context(|| format!("解析凭据文件失败: {}", self.credentials_path.display()))?
+            .into_sorted_credentials();
+        validate_credential_ids(&credentials)
+            .with_context(|| format!("校验凭据文件失败: {}", self.credentials_path.display()))?;
+
+        Ok((content_hash(&content), credentials))
+    }
+
+    /// 任一文件的指纹与上次重载时不同
+    fn files_changed(&self) -> bool {
+        let state = self.state.lock();
+        state.config != fingerprint(&self.config_path)
+            || state.credentials != fingerprint(&self.credentials_path)
+    }
+
+    /// 执行重载并记录结果
+    fn reload_and_log(&self, trigger: &str) {
+        match self.reload() {
+            Ok(report) if report.is_empty() => {
+                tracing::debug!("配置重载（{}）：没有变化", trigger);
+            }
+            Ok(report) => log_report(trigger, &report),
+            Err(e) => tracing::warn!("配置重载（{}）失败，继续使用当前配置: {:#}", trigger, e),
+        }
+    }
+
+    /// 启动后台文件轮询
+    pub fn spawn_watcher(self: &Arc<Self>) {
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if blocking_io(|| reloader.files_changed()) {
+                    reloader.reload_and_log("文件变化");
+                }
+            }
+        });
+    }
+
+    /// 收到 SIGHUP 时重载（仅 Unix）
+    #[cfg(unix)]
+    pub fn spawn_signal_handler(self: &Arc<Self>) {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("注册 SIGHUP 处理失败: {}", e);
+                return;
+            }
+        };
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                reloader.reload_and_log("SIGHUP");
+            }
+        });
+    }
+
+    /// 收到 SIGHUP 时重载（仅 Unix）
+    #[cfg(not(unix))]
+    pub fn spawn_signal_handler(self: &Arc<Self>) {}
+}
+
+fn log_report(trigger: &str, report: &ReloadReport) {
+    tracing::info!(
+        config_changed = ?report.config_changed,
+        credentials_added = ?report.credentials.added,
+        credentials_updated = ?report.credentials.updated,
+        credentials_removed = ?report.credentials.removed,
+        "配置已重载（{}）",
+        trigger
+    );
+    if !report.restart_required.is_empty() {
+        tracing::warn!(
+            "以下配置需要重启服务才能生效: {}",
+            report.restart_required.join(", ")
+        );
+    }
+}
+
+/// 对比两份配置的顶层字段，返回（可热更新的变化字段，需要重启的变化字段）
+fn diff_config(previous: &Config, next: &Config) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+    let previous = serde_json::to_value(previous).context("序列化配置失败")?;
+    let next = serde_json::to_value(next).context("序列化配置失败")?;
+    let empty = serde_json::Map::new();
+    let previous = previous.as_object().unwrap_or(&empty);
+    let next = next.as_object().unwrap_or(&empty);
+
+    let mut keys: Vec<&String> = previous.keys().chain(next.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut changed = Vec::new();
+    let mut restart_required = Vec::new();
+    for key in keys {
+        if previous.get(key) == next.get(key) {
+            continue;
+        }
+        if RESTART_REQUIRED_FIELDS.contains(&key.as_str()) {
+            restart_required.push(key.clone());
+        } else {
+            changed.push(key.clone());
+        }
+    }
+    Ok((changed, restart_required))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(id: u64, refresh_token: &str, priority: u32) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "refreshToken": refresh_token,
+            "accessToken": format!("access-{}", id),
+            "expiresAt": "2099-01-01T00:00:00Z",
+            "authMethod": "social",
+            "machineId": "a".repeat(64),
+            "priority": priority
+        })
+    }
+
+    #[test]
+    fn test_diff_config_splits_restart_required_fields() {
+        let previous = Config::default();
+        let mut next = previous.clone();
+        next.port = 9000;
+        next.region = "eu-west-1".to_string();
+        next.web_fetch.timeout_secs = 5;
+
+        let (changed, restart_required) = diff_config(&previous, &next).unwrap();
+        assert_eq!(changed, ["region", "webFetch"]);
+        assert_eq!(restart_required, ["port"]);
+    }
+
+    #[test]
+    fn test_reload_applies_config_and_credentials_diff() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config_path = dir.join("config.json");
+        let credentials_path = dir.join("credentials.json");
+        std::fs::write(&config_path, r#"{"apiKey": "k", "region": "us-east-1"}"#).unwrap();
+        std::fs::write(
+            &credentials_path,
+            serde_json::to_string(&[credential(1, "r1", 0), credential(2, "r2", 1)]).unwrap(),
+        )
+        .unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        let credentials = CredentialsConfig::load(&credentials_path)
+            .unwrap()
+            .into_sorted_credentials();
+        let manager = Arc::new(
+            MultiTokenManager::new(
+                config,
+                credentials,
+                None,
+                Some(credentials_path.clone()),
+                true,
+            )
+            .unwrap(),
+        );
+        manager.report_success(2);
+        let reloader = ConfigReloader::new(&config_path, &credentials_path, manager.clone());
+
+        // 没有修改时没有变化
+        assert!(reloader.reload().unwrap().is_empty());
+
+        std::fs::write(
+            &config_path,
+            r#"{"apiKey": "k2", "region": "eu-west-1", "loadBalancingMode": "balanced"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &credentials_path,
+            serde_json::to_string(&[credential(2, "r2", 5), credential(3, "r3", 0)]).unwrap(),
+        )
+        .unwrap();
+
+        let report = reloader.reload().unwrap();
+        assert_eq!(report.config_changed, ["loadBalancingMode", "region"]);
+        assert_eq!(report.restart_required, ["apiKey"]);
+        assert_eq!(report.credentials.added, [3]);
+        assert_eq!(report.credentials.updated, [2]);
+        assert_eq!(report.credentials.removed, [1]);
+
+        assert_eq!(manager.config().region, "eu-west-1");
+        assert_eq!(manager.get_load_balancing_mode(), "balanced");
+        let snapshot = manager.snapshot();
+        assert_eq!(snapshot.total, 2);
+        let updated = snapshot.entries.iter().find(|e| e.id == 2).unwrap();
+        assert_eq!(updated.priority, 5);
+        // 统计数据保留
+        assert_eq!(updated.success_count, 1);
+        // 当前凭据被删除后切换到优先级最高的凭据
+        assert_eq!(snapshot.current_id, 3);
+    }
+
+    #[test]
+    fn test_reload_single_format_credentials_keeps_entry() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config_path = dir.join("config.json");
+        let credentials_path = dir.join("credentials.json");
+        std::fs::write(&config_path, r#"{"apiKey": "k", "region": "us-east-1"}"#).unwrap();
+        let mut single = credential(0, "r1", 0);
+        single.as_object_mut().unwrap().remove("id");
+        std::fs::write(&credentials_path, single.to_string()).unwrap();
+
+        let manager = Arc::new(
+            MultiTokenManager::new(
+                Config::load(&config_path).unwrap(),
+                CredentialsConfig::load(&credentials_path)
+                    .unwrap()
+                    .into_sorted_credentials(),
+                None,
+                Some(credentials_path.clone()),
+                false,
+            )
+            .unwrap(),
+        );
+        manager.report_success(1);
+        let reloader = ConfigReloader::new(&config_path, &credentials_path, manager.clone());
+
+        // 只修改配置文件：凭据文件内容未变化，不重新应用凭据（单凭据格式下 Admin API 的修改只在内存中）
+        manager.set_priority(1, 5).unwrap();
+        std::fs::write(&config_path, r#"{"apiKey": "k", "region": "eu-west-1"}"#).unwrap();
+        let report = reloader.reload().unwrap();
+        assert_eq!(report.config_changed, ["region"]);
+        assert!(report.credentials.is_empty());
+        assert_eq!(manager.snapshot().entries[0].priority, 5);
+
+        // 修改凭据文件：没有 ID 的单凭据按位置匹配，原地更新
+        single["priority"] = serde_json::json!(3);
+        std::fs::write(&credentials_path, single.to_string()).unwrap();
+        let report = reloader.reload().unwrap();
+        assert!(report.credentials.added.is_empty());
+        assert!(report.credentials.removed.is_empty());
+        assert_eq!(report.credentials.updated, [1]);
+
+        let snapshot = manager.snapshot();
+        assert_eq!(snapshot.total, 1);
+        assert_eq!(snapshot.entries[0].id, 1);
+        assert_eq!(snapshot.entries[0].priority, 3);
+        assert_eq!(snapshot.entries[0].success_count, 1);
+    }
+
+    #[test]
+    fn test_reload_rejects_invalid_files_without_changes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config_path = dir.join("config.json");
+        let credentials_path = dir.join("credentials.json");
+        std::fs::write(&config_path, r#"{"apiKey": "k"}"#).unwrap();
+        std::fs::write(
+            &credentials_path,
+            serde_json::to_string(&[credential(1, "r1", 0)]).unwrap(),
+        )
+        .unwrap();
+
+        let manager = Arc::new(
+            MultiTokenManager::new(
+                Config::load(&config_path).unwrap(),
+                CredentialsConfig::load(&credentials_path)
+                    .unwrap()
+                    .into_sorted_credentials(),
+                None,
+                None,
+                true,
+            )
+            .unwrap(),
+        );
+        let reloader = ConfigReloader::new(&config_path, &credentials_path, manager.clone());
+
+        // 凭据文件为空（编辑器保存中）
+        std::fs::write(&config_path, r#"{"apiKey": "k", "region": "eu-west-1"}"#).unwrap();
+        std::fs::write(&credentials_path, "").unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(manager.config().region, "us-east-1");
+        assert_eq!(manager.total_count(), 1);
+
+        // 重复 ID
+        std::fs::write(
+            &credentials_path,
+            serde_json::to_string(&[credential(1, "r1", 0), credential(1, "r2", 0)]).unwrap(),
+        )
+        .unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(manager.config().region, "us-east-1");
+        assert_eq!(manager.total_count(), 1);
+
+        // 配置文件语法错误
+        std::fs::write(&config_path, "{").unwrap();
+        assert!(reloader.reload().is_err());
+    }
+
+    #[test]
+    fn test_update_config_persists_and_applies() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config_path = dir.join("config.json");
+        let credentials_path = dir.join("credentials.json");
+        std::fs::write(
+            &config_path,
+            r#"{"apiKey": "k", "systemVersion": "darwin#24.6.0"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &credentials_path,
+            serde_json::to_string(&[credential(1, "r1", 0)]).unwrap(),
+        )
+        .unwrap();
+
+        let manager = Arc::new(
+            MultiTokenManager::new(
+                Config::load(&config_path).unwrap(),
+                CredentialsConfig::load(&credentials_path)
+                    .unwrap()
+                    .into_sorted_credentials(),
+                None,
+                None,
+                true,
+            )
+            .unwrap(),
+        );
+        let reloader = ConfigReloader::new(&config_path, &credentials_path, manager.clone());
+
+        let (changed, restart_required) = reloader
+            .update_config(|config| {
+                config.api_region = Some("eu-central-1".to_string());
+                config.port = 9000;
+            })
+            .unwrap();
+        assert_eq!(changed, ["apiRegion"]);
+        assert_eq!(restart_required, ["port"]);
+        assert_eq!(manager.config().effective_api_region(), "eu-central-1");
+
+        let saved = Config::load(&config_path).unwrap();
+        assert_eq!(saved.api_region.as_deref(), Some("eu-central-1"));
+        assert_eq!(saved.api_key.as_deref(), Some("k"));
+        // 写回文件后不会被轮询再次识别为变化
+        assert!(!reloader.files_changed());
+
+        // 校验失败时不写回也不应用
+        assert!(
+            reloader
+                .update_config(|config| config.load_balancing_mode = "random".to_string())
+                .is_err()
+        );
+        assert_eq!(manager.config().load_balancing_mode, "priority");
+        assert_eq!(
+            Config::load(&config_path).unwrap().load_balancing_mode,
+            "priority"
+        );
+    }
+}
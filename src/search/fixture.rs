@@ -0,0 +1,135 @@
+//! 本地静态结果搜索后端
+//!
+//! 从 JSON 文件读取固定的搜索结果，用于离线测试和演示。文件支持两种格式：
+//! - 结果数组：任何查询都返回这组结果
+//! - 对象：以查询词（不区分大小写）为 key，`"*"` 作为未命中时的默认结果
+//!
+//! 结果字段与 Kiro MCP 一致（`title`、`url`、`snippet`、`publishedDate` 等）。
+//! 文件在每次搜索时重新读取，修改后无需重启。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use crate::common::io::blocking_io;
+
+use super::{SearchBackend, SearchRequest, WebSearchResult};
+
+/// 静态结果文件
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Fixture {
+    All(Vec<WebSearchResult>),
+    ByQuery(HashMap<String, Vec<WebSearchResult>>),
+}
+
+impl Fixture {
+    fn lookup(self, query: &str) -> Option<Vec<WebSearchResult>> {
+        match self {
+            Self::All(results) => Some(results),
+            Self::ByQuery(mut map) => {
+                let query = query.trim().to_lowercase();
+                let key = map
+                    .keys()
+                    .find(|k| k.trim().to_lowercase() == query)
+                    .cloned()
+                    .unwrap_or_else(|| "*".to_string());
+                map.remove(&key)
+            }
+        }
+    }
+}
+
+/// 本地静态结果搜索后端
+pub(super) struct FixtureBackend {
+    path: PathBuf,
+}
+
+impl FixtureBackend {
+    pub(super) fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl SearchBackend for FixtureBackend {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn search<'a>(
+        &'a self,
+        request: &'a SearchRequest<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<WebSearchResult>>> {
+        async move {
+            let content = blocking_io(|| std::fs::read_to_string(&self.path)).map_err(|e| {
+                anyhow::anyhow!("读取搜索结果文件失败 {}: {}", self.path.display(), e)
+            })?;
+            let fixture: Fixture = serde_json::from_str(&content)?;
+            fixture
+                .lookup(request.query)
+                .ok_or_else(|| anyhow::anyhow!("搜索结果文件中没有查询 {:?} 的结果", request.query))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_fixture(content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("kiro-search-fixture-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    async fn search(backend: &FixtureBackend, query: &str) -> anyhow::Result<Vec<WebSearchResult>> {
+        let request = SearchRequest {
+            query,
+            user_location: None,
+        };
+        backend.search(&request).await
+    }
+
+    #[tokio::test]
+    async fn test_fixture_by_query_with_fallback() {
+        let path = write_fixture(
+            r#"{
+                "Rust": [{"title": "Rust", "url": "https://rust-lang.org", "publishedDate": 1000}],
+                "*": [{"title": "Other", "url": "https://example.com"}]
+            }"#,
+        );
+        let backend = FixtureBackend::new(path.clone());
+
+        let results = search(&backend, " rust ").await.unwrap();
+        assert_eq!(results[0].url, "https://rust-lang.org");
+        assert_eq!(results[0].published_date, Some(1000));
+
+        let results = search(&backend, "anything").await.unwrap();
+        assert_eq!(results[0].title, "Other");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fixture_array_and_errors() {
+        let path = write_fixture(r#"[{"title": "A", "url": "https://a.com"}]"#);
+        let backend = FixtureBackend::new(path.clone());
+        assert_eq!(search(&backend, "x").await.unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+
+        // 文件不存在、没有匹配且无默认结果时都视为失败，交给下一个后端
+        assert!(search(&backend, "x").await.is_err());
+        let path = write_fixture(r#"{"rust": []}"#);
+        assert!(
+            search(&FixtureBackend::new(path.clone()), "go")
+                .await
+                .is_err()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+//! 通用 HTTP JSON 搜索后端
+//!
+//! 通过 URL 模板调用任意返回 JSON 的搜索 API，再按配置的字段名把结果映射为
+//! [`WebSearchResult`]。URL 模板中的占位符会被 URL 编码后替换：
+//! `{query}`、`{country}`、`{region}`、`{city}`、`{timezone}`（后四个来自 `user_location`，缺失时为空）。
+
+use std::collections::HashMap;
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+use crate::model::config::SearchBackendConfig;
+
+use super::{SearchBackend, SearchRequest, WebSearchResult};
+
+/// 通用 HTTP JSON 搜索后端
+pub(super) struct HttpJsonBackend {
+    client: reqwest::Client,
+    url: String,
+    headers: HashMap<String, String>,
+    results_path: String,
+    title_field: String,
+    url_field: String,
+    snippet_field: String,
+    date_field: Option<String>,
+}
+
+impl HttpJsonBackend {
+    /// 从 `http` 类型的后端配置创建，其他类型返回 None
+    pub(super) fn from_config(
+        client: reqwest::Client,
+        config: &SearchBackendConfig,
+    ) -> Option<Self> {
+        let SearchBackendConfig::Http {
+            url,
+            headers,
+            results_path,
+            title_field,
+            url_field,
+            snippet_field,
+            date_field,
+        } = config
+        else {
+            return None;
+        };
+        Some(Self {
+            client,
+            url: url.clone(),
+            headers: headers.clone(),
+            results_path: results_path.clone(),
+            title_field: title_field.clone(),
+            url_field: url_field.clone(),
+            snippet_field: snippet_field.clone(),
+            date_field: date_field.clone(),
+        })
+    }
+
+    /// 填充 URL 模板
+    fn render_url(&self, request: &SearchRequest<'_>) -> String {
+        let location = request.user_location;
+        let field = |f: fn(&crate::anthropic::types::UserLocation) -> Option<&String>| {
+            location
+                .and_then(f)
+                .map(|v| urlencoding::encode(v).into_owned())
+                .unwrap_or_default()
+        };
+        self.url
+            .replace("{query}", &urlencoding::encode(request.query))
+            .replace("{country}", &field(|l| l.country.as_ref()))
+            .replace("{region}", &field(|l| l.region.as_ref()))
+            .replace("{city}", &field(|l| l.city.as_ref()))
+            .replace("{timezone}", &field(|l| l.timezone.as_ref()))
+    }
+
+    /// 从响应中取出结果数组并映射字段
+    fn parse_results(&self, body: &Value) -> anyhow::Result<Vec<WebSearchResult>> {
+        let items = self
+            .results_path
+            .split('.')
+            .filter(|segment| !segment.is_empty())
+            .try_fold(body, |value, segment| value.get(segment))
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow::anyhow!("响应中找不到结果数组: {:?}", self.results_path))?;
+
+        let results = items
+            .iter()
+            .filter_map(|item| {
+                let url = item.get(&self.url_field)?.as_str()?.to_string();
+                let title = item
+                    .get(&self.title_field)
+                    .and_then(Value::as_str)
+                    .unwrap_or(&url)
+                    .to_string();
+                let snippet = item
+                    .get(&self.snippet_field)
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                let mut result = WebSearchResult::new(title, url, snippet);
+                result.published_date = self
+                    .date_field
+                    .as_ref()
+                    .and_then(|field| item.get(field))
+                    .and_then(parse_date);
+                Some(result)
+            })
+            .collect();
+        Ok(results)
+    }
+}
+
+impl SearchBackend for HttpJsonBackend {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn search<'a>(
+        &'a self,
+        request: &'a SearchRequest<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<WebSearchResult>>> {
+        async move {
+            let mut builder = self.client.get(self.render_url(request));
+            for (name, value) in &self.headers {
+                builder = builder.header(name, value);
+            }
+            let response = builder.send().await?.error_for_status()?;
+            let body: Value = response.json().await?;
+            self.parse_results(&body)
+        }
+        .boxed()
+    }
+}
+
+/// 发布时间：毫秒时间戳或 RFC 3339 字符串
+fn parse_date(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| dt.timestamp_millis()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::types::UserLocation;
+
+    fn backend(url: &str, results_path: &str) -> HttpJsonBackend {
+        let config: SearchBackendConfig = serde_json::from_value(serde_json::json!({
+            "type": "http",
+            "url": url,
+            "resultsPath": results_path,
+            "snippetField": "desc",
+            "dateField": "date"
+        }))
+        .unwrap();
+        HttpJsonBackend::from_config(reqwest::Client::new(), &config).unwrap()
+    }
+
+    #[test]
+    fn test_render_url_encodes_placeholders() {
+        let backend = backend(
+            "https://api.local/s?q={query}&cc={country}&tz={timezone}",
+            "",
+        );
+        let location = UserLocation {
+            country: Some("US".to_string()),
+            ..Default::default()
+        };
+        let request = SearchRequest {
+            query: "rust & go",
+            user_location: Some(&location),
+        };
+
+        assert_eq!(
+            backend.render_url(&request),
+            "https://api.local/s?q=rust%20%26%20go&cc=US&tz="
+        );
+    }
+
+    #[test]
+    fn test_parse_results_with_path_and_fields() {
+        let backend = backend("https://api.local", "data.items");
+        let body = serde_json::json!({
+            "data": {"items": [
+                {"title": "A", "url": "https://a.com", "desc": "first", "date": "2024-05-01T00:00:00Z"},
+                {"title": "missing url"},
+                {"url": "https://b.com", "date": 1000}
+            ]}
+        });
+
+        let results = backend.parse_results(&body).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].snippet.as_deref(), Some("first"));
+        assert_eq!(results[0].published_date, Some(1714521600000));
+        assert_eq!(results[1].title, "https://b.com");
+        assert_eq!(results[1].published_date, Some(1000));
+
+        assert!(
+            backend
+                .parse_results(&serde_json::json!({"data": {}}))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Kiro MCP 搜索后端
+//!
+//! 通过 Kiro 的 MCP 接口调用内置 `web_search` 工具，是默认的搜索后端。
+
+use std::sync::Arc;
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::kiro::provider::KiroProvider;
+
+use super::{SearchBackend, SearchRequest, WebSearchResult};
+
+/// MCP 请求
+#[derive(Debug, Serialize)]
+pub struct McpRequest {
+    pub id: String,
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: McpParams,
+}
+
+/// MCP 请求参数
+#[derive(Debug, Serialize)]
+pub struct McpParams {
+    pub name: String,
+    pub arguments: McpArguments,
+}
+
+/// MCP 参数
+#[derive(Debug, Serialize)]
+pub struct McpArguments {
+    pub query: String,
+}
+
+/// MCP 响应
+#[derive(Debug, Deserialize)]
+pub struct McpResponse {
+    pub error: Option<McpError>,
+    pub id: String,
+    pub jsonrpc: String,
+    pub result: Option<McpResult>,
+}
+
+/// MCP 错误
+#[derive(Debug, Deserialize)]
+pub struct McpError {
+    pub code: Option<i32>,
+    pub message: Option<String>,
+}
+
+/// MCP 结果
+#[derive(Debug, Deserialize)]
+pub struct McpResult {
+    pub content: Vec<McpContent>,
+    #[serde(rename = "isError")]
+    pub is_error: bool,
+}
+
+/// MCP 内容
+#[derive(Debug, Deserialize)]
+pub struct McpContent {
+    #[serde(rename = "type")]
+    pub content_type: String,
+    pub text: String,
+}
+
+/// WebSearch 搜索结果
+#[derive(Debug, Deserialize)]
+pub struct WebSearchResults {
+    pub results: Vec<WebSearchResult>,
+    #[serde(rename = "totalResults")]
+    pub total_results: Option<i32>,
+    pub query: Option<String>,
+    pub error: Option<String>,
+}
+
+/// 生成22位大小写字母和数字的随机字符串
+fn generate_random_id_22() -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    (0..22)
+        .map(|_| {
+            let idx = fastrand::usize(..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// 生成8位小写字母和数字的随机字符串
+fn generate_random_id_8() -> String {
+    const CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+    (0..8)
+        .map(|_| {
+            let idx = fastrand::usize(..CHARSET.len());
+            CHARSET[idx] as char
+        })
+        .collect()
+}
+
+/// 创建 MCP 请求
+///
+/// ID 格式: web_search_tooluse_{22位随机}_{毫秒时间戳}_{8位随机}
+pub fn create_mcp_request(query: &str) -> (String, McpRequest) {
+    let random_22 = generate_random_id_22();
+    let timestamp = chrono::Utc::now().timestamp_millis();
+    let random_8 = generate_random_id_8();
+
+    let request_id = format!(
+        "web_search_tooluse_{}_{}_{}",
+        random_22, timestamp, random_8
+    );
+
+    // tool_use_id 使用相同格式
+    let tool_use_id = format!(
+        "srvtoolu_{}",
+        &Uuid::new_v4().simple().to_string()[..32]
+    );
+
+    let request = McpRequest {
+        id: request_id,
+        jsonrpc: "2.0".to_string(),
+        method: "tools/call".to_string(),
+        params: McpParams {
+            name: "web_search".to_string(),
+            arguments: McpArguments {
+                query: query.to_string(),
+            },
+        },
+    };
+
+    (tool_use_id, request)
+}
+
+/// 解析 MCP 响应中的搜索结果
+pub fn parse_search_results(mcp_response: &McpResponse) -> Option<WebSearchResults> {
+    let result = mcp_response.result.as_ref()?;
+    let content = result.content.first()?;
+
+    if content.content_type != "text" {
+        return None;
+    }
+
+    serde_json::from_str(&content.text).ok()
+}
+
+/// Kiro MCP 搜索后端
+pub(super) struct KiroBackend {
+    provider: Arc<KiroProvider>,
+}
+
+impl KiroBackend {
+    pub(super) fn new(provider: Arc<KiroProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+impl SearchBackend for KiroBackend {
+    fn name(&self) -> &'static str {
+        "kiro"
+    }
+
+    fn search<'a>(
+        &'a self,
+        request: &'a SearchRequest<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<WebSearchResult>>> {
+        async move {
+            let (_, mcp_request) = create_mcp_request(request.query);
+            let response = call_mcp_api(&self.provider, &mcp_request).await?;
+            if response.result.as_ref().is_some_and(|r| r.is_error) {
+                anyhow::bail!("MCP 搜索返回错误结果");
+            }
+            let results = parse_search_results(&response)
+                .ok_or_else(|| anyhow::anyhow!("无法解析 MCP 搜索结果"))?;
+            if let Some(error) = results.error {
+                anyhow::bail!("MCP 搜索失败: {}", error);
+            }
+            Ok(results.results)
+        }
+        .boxed()
+    }
+}
+
+/// 调用 Kiro MCP API
+async fn call_mcp_api(
+    provider: &KiroProvider,
+    request: &McpRequest,
+) -> anyhow::Result<McpResponse> {
+    let request_body = serde_json::to_string(request)?;
+
+    tracing::debug!("MCP request: {}", request_body);
+
+    let response = provider.call_mcp(&request_body).await?;
+
+    let body = response.text().await?;
+    tracing::debug!("MCP response: {}", body);
+
+    let mcp_response: McpResponse = serde_json::from_str(&body)?;
+
+    if let Some(ref error) = mcp_response.error {
+        anyhow::bail!(
+            "MCP error: {} - {}",
+            error.code.unwrap_or(-1),
+            error.message.as_deref().unwrap_or("Unknown error")
+        );
+    }
+
+    Ok(mcp_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_mcp_request() {
+        let (tool_use_id, request) = create_mcp_request("test query");
+
+        assert!(tool_use_id.starts_with("srvtoolu_"));
+        assert_eq!(request.jsonrpc, "2.0");
+        assert_eq!(request.method, "tools/call");
+        assert_eq!(request.params.name, "web_search");
+        assert_eq!(request.params.arguments.query, "test query");
+
+        // 验证 ID 格式: web_search_tooluse_{22位}_{时间戳}_{8位}
+        assert!(request.id.starts_with("web_search_tooluse_"));
+    }
+
+    #[test]
+    fn test_mcp_request_id_format() {
+        let (_, request) = create_mcp_request("test");
+
+        // 格式: web_search_tooluse_{22位}_{毫秒时间戳}_{8位}
+        let id = &request.id;
+        assert!(id.starts_with("web_search_tooluse_"));
+
+        let suffix = &id["web_search_tooluse_".len()..];
+        let parts: Vec<&str> = suffix.split('_').collect();
+        assert_eq!(parts.len(), 3, "应该有3个部分: 22位随机_时间戳_8位随机");
+
+        // 第一部分: 22位大小写字母和数字
+        assert_eq!(parts[0].len(), 22);
+        assert!(parts[0].chars().all(|c| c.is_ascii_alphanumeric()));
+
+        // 第二部分: 毫秒时间戳
+        assert!(parts[1].parse::<i64>().is_ok());
+
+        // 第三部分: 8位小写字母和数字
+        assert_eq!(parts[2].len(), 8);
+        assert!(
+            parts[2]
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        );
+    }
+
+    #[test]
+    fn test_parse_search_results() {
+        let response = McpResponse {
+            error: None,
+            id: "test_id".to_string(),
+            jsonrpc: "2.0".to_string(),
+            result: Some(McpResult {
+                content: vec![McpContent {
+                    content_type: "text".to_string(),
+                    text: r#"{"results":[{"title":"Test","url":"https://example.com","snippet":"Test snippet"}],"totalResults":1}"#.to_string(),
+                }],
+                is_error: false,
+            }),
+        };
+
+        let results = parse_search_results(&response);
+        assert!(results.is_some());
+        let results = results.unwrap();
+        assert_eq!(results.results.len(), 1);
+        assert_eq!(results.results[0].title, "Test");
+    }
+}
@@ -0,0 +1,449 @@
+//! web_search 搜索后端
+//!
+//! 服务端 web_search 工具的每次搜索都经过 [`WebSearch`]：按 `webSearch.backends` 的顺序
+//! 依次尝试各个后端，前一个出错时回退到下一个，全部失败才向模型报告 `unavailable`。
+//!
+//! 内置后端：
+//! - `kiro`：Kiro MCP 内置搜索（默认）
+//! - `searxng`：SearxNG 实例的 JSON 接口
+//! - `http`：通用 HTTP JSON 搜索 API（URL 模板 + 字段映射）
+//! - `fixture`：本地静态结果文件，便于离线测试
+//!
+//! 工具参数 `allowed_domains` / `blocked_domains` 通过 [`DomainFilter`] 对结果过滤，
+//! `user_location` 会传给支持本地化的后端。
+
+mod fixture;
+mod http;
+mod kiro;
+mod searxng;
+
+use std::sync::{Arc, LazyLock};
+
+use futures::future::BoxFuture;
+use parking_lot::RwLock;
+use serde::Deserialize;
+
+use crate::anthropic::types::UserLocation;
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::provider::KiroProvider;
+use crate::model::config::{SearchBackendConfig, TlsBackend, WebSearchConfig};
+
+/// 单个搜索结果
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebSearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: Option<String>,
+    #[serde(rename = "publishedDate")]
+    pub published_date: Option<i64>,
+    pub id: Option<String>,
+    pub domain: Option<String>,
+    #[serde(rename = "maxVerbatimWordLimit")]
+    pub max_verbatim_word_limit: Option<i32>,
+    #[serde(rename = "publicDomain")]
+    pub public_domain: Option<bool>,
+}
+
+impl WebSearchResult {
+    /// 只有标题、链接和摘要的结果（外部后端使用）
+    fn new(title: String, url: String, snippet: Option<String>) -> Self {
+        Self {
+            title,
+            url,
+            snippet,
+            published_date: None,
+            id: None,
+            domain: None,
+            max_verbatim_word_limit: None,
+            public_domain: None,
+        }
+    }
+}
+
+/// 一次搜索请求
+#[derive(Debug, Clone, Copy)]
+pub struct SearchRequest<'a> {
+    pub query: &'a str,
+    pub user_location: Option<&'a UserLocation>,
+}
+
+/// 搜索后端
+pub trait SearchBackend: Send + Sync {
+    /// 后端名称（用于日志）
+    fn name(&self) -> &'static str;
+
+    /// 执行搜索，出错时由 [`WebSearch`] 回退到下一个后端
+    fn search<'a>(
+        &'a self,
+        request: &'a SearchRequest<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<WebSearchResult>>>;
+}
+
+/// 搜索后端配置
+#[derive(Clone, Default)]
+pub struct SearchOptions {
+    /// 后端列表与结果限制
+    pub config: WebSearchConfig,
+    /// 外部后端使用的代理
+    pub proxy: Option<ProxyConfig>,
+    /// TLS 后端
+    pub tls_backend: TlsBackend,
+}
+
+static SEARCH_OPTIONS: LazyLock<RwLock<Arc<SearchOptions>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(SearchOptions::default())));
+
+/// 初始化（或替换）搜索后端配置
+///
+/// 应在应用启动和配置热重载时调用，未调用时只使用 Kiro MCP 后端
+pub fn init_config(options: SearchOptions) {
+    *SEARCH_OPTIONS.write() = Arc::new(options);
+}
+
+fn options() -> Arc<SearchOptions> {
+    SEARCH_OPTIONS.read().clone()
+}
+
+/// 按配置顺序组合的搜索后端
+pub struct WebSearch {
+    backends: Vec<Box<dyn SearchBackend>>,
+    max_results: usize,
+}
+
+impl WebSearch {
+    pub fn new(backends: Vec<Box<dyn SearchBackend>>, max_results: usize) -> Self {
+        Self {
+            backends,
+            max_results,
+        }
+    }
+
+    /// 根据全局配置构建后端链
+    ///
+    /// 无法创建的后端（如 HTTP 客户端初始化失败）会被跳过并记录警告
+    pub fn from_config(provider: Arc<KiroProvider>) -> Self {
+        let options = options();
+        let config = &options.config;
+        let client = || {
+            build_client(
+                options.proxy.as_ref(),
+                config.timeout_secs,
+                options.tls_backend,
+            )
+        };
+
+        let mut backends: Vec<Box<dyn SearchBackend>> = Vec::new();
+        for backend in &config.backends {
+            match backend {
+                SearchBackendConfig::Kiro => {
+                    backends.push(Box::new(kiro::KiroBackend::new(provider.clone())));
+                }
+                SearchBackendConfig::Searxng {
+                    url,
+                    language,
+                    headers,
+                } => match client() {
+                    Ok(client) => backends.push(Box::new(searxng::SearxngBackend::new(
+                        client,
+                        url.clone(),
+                        language.clone(),
+                        headers.clone(),
+                    ))),
+                    Err(e) => tracing::warn!("创建 SearxNG 搜索客户端失败: {}", e),
+                },
+                SearchBackendConfig::Http { .. } => match client() {
+                    Ok(client) => {
+                        if let Some(http) = http::HttpJsonBackend::from_config(client, backend) {
+                            backends.push(Box::new(http));
+                        }
+                    }
+                    Err(e) => tracing::warn!("创建 HTTP 搜索客户端失败: {}", e),
+                },
+                SearchBackendConfig::Fixture { path } => {
+                    backends.push(Box::new(fixture::FixtureBackend::new(path.clone())));
+                }
+            }
+        }
+
+        Self::new(backends, config.max_results)
+    }
+
+    /// 依次尝试各个后端，返回第一个成功后端的结果（已过滤并截断）
+    pub async fn search(
+        &self,
+        request: &SearchRequest<'_>,
+        filter: &DomainFilter,
+    ) -> anyhow::Result<Vec<WebSearchResult>> {
+        let mut last_error = None;
+        for backend in &self.backends {
+            match backend.search(request).await {
+                Ok(results) => {
+                    let total = results.len();
+                    let mut results = filter.apply(results);
+                    results.truncate(self.max_results);
+                    tracing::debug!(
+                        backend = backend.name(),
+                        query = %request.query,
+                        "搜索完成: {} 条结果，过滤后保留 {} 条",
+                        total,
+                        results.len()
+                    );
+                    return Ok(results);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        backend = backend.name(),
+                        query = %request.query,
+                        "搜索后端失败，尝试下一个: {}",
+                        e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("未配置任何搜索后端")))
+    }
+}
+
+/// `allowed_domains` / `blocked_domains` 结果过滤
+///
+/// 规则匹配域名本身及其子域名，可带路径前缀（如 `example.com/blog`）
+#[derive(Debug, Clone, Default)]
+pub struct DomainFilter {
+    allowed: Vec<String>,
+    blocked: Vec<String>,
+}
+
+impl DomainFilter {
+    pub fn new(allowed: Option<&[String]>, blocked: Option<&[String]>) -> Self {
+        let normalize = |rules: Option<&[String]>| -> Vec<String> {
+            rules
+                .unwrap_or_default()
+                .iter()
+                .map(|rule| normalize_rule(rule))
+                .filter(|rule| !rule.is_empty())
+                .collect()
+        };
+        Self {
+            allowed: normalize(allowed),
+            blocked: normalize(blocked),
+        }
+    }
+
+    /// 检查 URL 是否通过过滤
+    pub fn is_allowed(&self, url: &str) -> bool {
+        let Some((host, path)) = split_url(url) else {
+            // 无法解析主机的结果只在没有白名单时保留
+            return self.allowed.is_empty();
+        };
+        if self
+            .blocked
+            .iter()
+            .any(|rule| matches_rule(&host, path, rule))
+        {
+            return false;
+        }
+        self.allowed.is_empty()
+            || self
+                .allowed
+                .iter()
+                .any(|rule| matches_rule(&host, path, rule))
+    }
+
+    fn apply(&self, results: Vec<WebSearchResult>) -> Vec<WebSearchResult> {
+        if self.allowed.is_empty() && self.blocked.is_empty() {
+            return results;
+        }
+        results
+            .into_iter()
+            .filter(|r| self.is_allowed(&r.url))
+            .collect()
+    }
+}
+
+/// 规则统一为小写、去掉协议和末尾斜杠
+fn normalize_rule(rule: &str) -> String {
+    let rule = rule.trim().to_ascii_lowercase();
+    let rule = rule
+        .strip_prefix("https://")
+        .or_else(|| rule.strip_prefix("http://"))
+        .unwrap_or(&rule);
+    rule.trim_end_matches('/').to_string()
+}
+
+/// 拆分 URL 为小写主机名和路径
+fn split_url(url: &str) -> Option<(String, &str)> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let (authority, path) = match rest.find(['/', '?', '#']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?.trim_end_matches('.');
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_ascii_lowercase(), path))
+}
+
+fn matches_rule(host: &str, path: &str, rule: &str) -> bool {
+    let (rule_host, rule_path) = match rule.split_once('/') {
+        Some((h, p)) => (h, Some(p)),
+        None => (rule, None),
+    };
+    let host_matches = host == rule_host
+        || host
+            .strip_suffix(rule_host)
+            .is_some_and(|prefix| prefix.ends_with('.'));
+    host_matches && rule_path.is_none_or(|prefix| path.trim_start_matches('/').starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingBackend;
+
+    impl SearchBackend for FailingBackend {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn search<'a>(
+            &'a self,
+            _request: &'a SearchRequest<'a>,
+        ) -> BoxFuture<'a, anyhow::Result<Vec<WebSearchResult>>> {
+            Box::pin(async { anyhow::bail!("backend down") })
+        }
+    }
+
+    struct StaticBackend(Vec<&'static str>);
+
+    impl SearchBackend for StaticBackend {
+        fn name(&self) -> &'static str {
+            "static"
+        }
+
+        fn search<'a>(
+            &'a self,
+            _request: &'a SearchRequest<'a>,
+        ) -> BoxFuture<'a, anyhow::Result<Vec<WebSearchResult>>> {
+            let results = self
+                .0
+                .iter()
+                .map(|url| WebSearchResult::new("t".to_string(), url.to_string(), None))
+                .collect();
+            Box::pin(async move { Ok(results) })
+        }
+    }
+
+    fn request() -> SearchRequest<'static> {
+        SearchRequest {
+            query: "rust",
+            user_location: None,
+        }
+    }
+
+    #[test]
+    fn test_domain_filter_matches_subdomains_and_paths() {
+        let allowed = vec![
+            "example.com".to_string(),
+            "https://docs.rs/tokio/".to_string(),
+        ];
+        let filter = DomainFilter::new(Some(&allowed), None);
+
+        assert!(filter.is_allowed("https://example.com/a"));
+        assert!(filter.is_allowed("https://www.Example.com:443/a"));
+        assert!(!filter.is_allowed("https://notexample.com/"));
+        assert!(filter.is_allowed("https://docs.rs/tokio/latest"));
+        assert!(!filter.is_allowed("https://docs.rs/serde"));
+        assert!(!filter.is_allowed("not a url"));
+    }
+
+    #[test]
+    fn test_domain_filter_blocked() {
+        let blocked = vec!["spam.io".to_string()];
+        let filter = DomainFilter::new(None, Some(&blocked));
+
+        assert!(!filter.is_allowed("http://a.spam.io/x"));
+        assert!(filter.is_allowed("http://spam.io.example.com/x"));
+        assert!(filter.is_allowed("not a url"));
+    }
+
+    #[tokio::test]
+    async fn test_web_search_falls_back_and_filters() {
+        let search = WebSearch::new(
+            vec![
+                Box::new(FailingBackend),
+                Box::new(StaticBackend(vec![
+                    "https://a.com/1",
+                    "https://blocked.com/2",
+                    "https://a.com/3",
+                    "https://a.com/4",
+                ])),
+            ],
+            2,
+        );
+        let blocked = vec!["blocked.com".to_string()];
+        let filter = DomainFilter::new(None, Some(&blocked));
+
+        let results = search.search(&request(), &filter).await.unwrap();
+        let urls: Vec<_> = results.iter().map(|r| r.url.as_str()).collect();
+        assert_eq!(urls, ["https://a.com/1", "https://a.com/3"]);
+    }
+
+    #[tokio::test]
+    async fn test_web_search_all_backends_fail() {
+        let search = WebSearch::new(vec![Box::new(FailingBackend)], 10);
+        let err = search
+            .search(&request(), &DomainFilter::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("backend down"));
+
+        let empty = WebSearch::new(Vec::new(), 10);
+        assert!(
+            empty
+                .search(&request(), &DomainFilter::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_web_search_config_deserialize() {
+        let config: WebSearchConfig = serde_json::from_str(
+            r#"{
+                "backends": [
+                    {"type": "searxng", "url": "https://searx.local", "language": "zh-CN"},
+                    {"type": "http", "url": "https://api.local/s?q={query}", "resultsPath": "data.items", "snippetField": "desc"},
+                    {"type": "fixture", "path": "fixtures/search.json"},
+                    {"type": "kiro"}
+                ],
+                "maxResults": 5
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.backends.len(), 4);
+        assert_eq!(config.max_results, 5);
+        assert_eq!(config.timeout_secs, 15);
+        match &config.backends[1] {
+            SearchBackendConfig::Http {
+                results_path,
+                title_field,
+                snippet_field,
+                ..
+            } => {
+                assert_eq!(results_path, "data.items");
+                assert_eq!(title_field, "title");
+                assert_eq!(snippet_field, "desc");
+            }
+            other => panic!("unexpected backend: {:?}", other),
+        }
+        assert_eq!(config.backends[3], SearchBackendConfig::Kiro);
+
+        let default: WebSearchConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(default.backends, vec![SearchBackendConfig::Kiro]);
+    }
+}
@@ -0,0 +1,127 @@
+//! SearxNG 搜索后端
+//!
+//! 调用实例的 `/search?format=json` 接口，实例需在 `settings.yml` 的 `search.formats` 中启用 `json`。
+
+use std::collections::HashMap;
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use super::{SearchBackend, SearchRequest, WebSearchResult};
+
+/// SearxNG 搜索后端
+pub(super) struct SearxngBackend {
+    client: reqwest::Client,
+    url: String,
+    language: Option<String>,
+    headers: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResponse {
+    #[serde(default)]
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearxngResult {
+    #[serde(default)]
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(rename = "publishedDate", default)]
+    published_date: Option<String>,
+}
+
+impl SearxngBackend {
+    pub(super) fn new(
+        client: reqwest::Client,
+        url: String,
+        language: Option<String>,
+        headers: HashMap<String, String>,
+    ) -> Self {
+        Self {
+            client,
+            url,
+            language,
+            headers,
+        }
+    }
+
+    /// 搜索接口地址（兼容配置为实例根地址或完整的 `/search` 地址）
+    fn endpoint(&self) -> String {
+        let base = self.url.trim_end_matches('/');
+        if base.ends_with("/search") {
+            base.to_string()
+        } else {
+            format!("{}/search", base)
+        }
+    }
+}
+
+impl SearchBackend for SearxngBackend {
+    fn name(&self) -> &'static str {
+        "searxng"
+    }
+
+    fn search<'a>(
+        &'a self,
+        request: &'a SearchRequest<'a>,
+    ) -> BoxFuture<'a, anyhow::Result<Vec<WebSearchResult>>> {
+        async move {
+            let mut query = vec![("q", request.query.to_string()), ("format", "json".into())];
+            if let Some(language) = &self.language {
+                query.push(("language", language.clone()));
+            }
+
+            let mut builder = self.client.get(self.endpoint()).query(&query);
+            for (name, value) in &self.headers {
+                builder = builder.header(name, value);
+            }
+            let response = builder.send().await?.error_for_status()?;
+            let body: SearxngResponse = response.json().await?;
+            Ok(body.results.into_iter().map(into_result).collect())
+        }
+        .boxed()
+    }
+}
+
+fn into_result(result: SearxngResult) -> WebSearchResult {
+    let mut converted = WebSearchResult::new(result.title, result.url, result.content);
+    converted.published_date = result.published_date.as_deref().and_then(parse_date);
+    converted
+}
+
+/// 解析 SearxNG 的发布时间（ISO 8601，可能不带时区）为毫秒时间戳
+fn parse_date(value: &str) -> Option<i64> {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(dt.timestamp_millis());
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|dt| dt.and_utc().timestamp_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_searxng_response() {
+        let body: SearxngResponse = serde_json::from_str(
+            r#"{"query":"rust","results":[
+                {"title":"Rust","url":"https://www.rust-lang.org/","content":"A language","publishedDate":"2024-05-01T00:00:00"},
+                {"title":"Docs","url":"https://doc.rust-lang.org/","publishedDate":null}
+            ]}"#,
+        )
+        .unwrap();
+        let results: Vec<_> = body.results.into_iter().map(into_result).collect();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].snippet.as_deref(), Some("A language"));
+        assert_eq!(results[0].published_date, Some(1714521600000));
+        assert_eq!(results[1].published_date, None);
+    }
+}
@@ -0,0 +1,208 @@
+//! 字节级 BPE 分词器
+//!
+//! 词表为 tiktoken 格式（每行 `base64(token) rank`），rank 越小合并优先级越高。
+//! 文本先按 [`pre_tokenize`] 切分成分块，再在每个分块内按 rank 贪心合并字节对。
+//!
+//! 内置词表由 `tools/train_bpe_vocab.py` 训练生成，两侧的预分词规则必须保持一致。
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// 单个分块的最大字符数（避免超长单词导致合并退化为平方复杂度）
+const MAX_CHUNK_CHARS: usize = 64;
+
+/// 数字分块的最大位数
+const MAX_DIGIT_CHARS: usize = 3;
+
+/// 字节级 BPE 分词器
+pub struct Bpe {
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl Bpe {
+    /// 解析 tiktoken 格式的词表
+    pub fn from_tiktoken(data: &str) -> anyhow::Result<Self> {
+        let mut ranks = HashMap::new();
+        for (line_no, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (token, rank) = line
+                .split_once(' ')
+                .with_context(|| format!("词表第 {} 行格式无效", line_no + 1))?;
+            let token = STANDARD
+                .decode(token)
+                .with_context(|| format!("词表第 {} 行 token 不是有效的 base64", line_no + 1))?;
+            let rank: u32 = rank
+                .trim()
+                .parse()
+                .with_context(|| format!("词表第 {} 行 rank 无效", line_no + 1))?;
+            ranks.insert(token, rank);
+        }
+        // 所有单字节都必须在词表中，否则任意文本无法编码
+        if let Some(byte) = (0..=255u8).find(|b| !ranks.contains_key(&[*b][..])) {
+            anyhow::bail!("词表缺少单字节 token: 0x{:02x}", byte);
+        }
+        Ok(Self { ranks })
+    }
+
+    /// 词表大小
+    pub fn vocab_size(&self) -> usize {
+        self.ranks.len()
+    }
+
+    /// 计算文本的 token 数量
+    pub fn count(&self, text: &str) -> usize {
+        pre_tokenize(text)
+            .into_iter()
+            .map(|chunk| self.count_chunk(chunk.as_bytes()))
+            .sum()
+    }
+
+    /// 计算单个分块合并后的 token 数量
+    fn count_chunk(&self, chunk: &[u8]) -> usize {
+        if chunk.len() <= 1 || self.ranks.contains_key(chunk) {
+            return chunk.len().min(1);
+        }
+
+        // 每个部分的起始字节位置，末尾哨兵为 chunk.len()
+        let mut bounds: Vec<usize> = (0..=chunk.len()).collect();
+        while bounds.len() > 2 {
+            let best = (0..bounds.len() - 2)
+                .filter_map(|i| {
+                    self.ranks
+                        .get(&chunk[bounds[i]..bounds[i + 2]])
+                        .map(|rank| (*rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+        bounds.len() - 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Digit,
+    Space,
+    Letter,
+    Other,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_ascii_digit() {
+        CharClass::Digit
+    } else if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_alphabetic() {
+        CharClass::Letter
+    } else {
+        CharClass::Other
+    }
+}
+
+/// 预分词：把文本切分为互不跨越的分块
+///
+/// - 同类字符（字母、数字、其他符号）连续成块，数字每块最多 3 位
+/// - 空白单独成块；紧跟非空白字符的最后一个空格并入下一个分块（如 `" world"`）
+pub(crate) fn pre_tokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let n = chars.len();
+    let offset = |i: usize| chars.get(i).map_or(text.len(), |(pos, _)| *pos);
+
+    let mut chunks = Vec::new();
+    let mut i = 0;
+    while i < n {
+        let mut class = char_class(chars[i].1);
+        let mut start = i;
+        if class == CharClass::Space {
+            let mut j = i;
+            while j < n && char_class(chars[j].1) == CharClass::Space {
+                j += 1;
+            }
+            if j == n || chars[j - 1].1 != ' ' {
+                chunks.push(&text[offset(i)..offset(j)]);
+                i = j;
+                continue;
+            }
+            if j - 1 > i {
+                chunks.push(&text[offset(i)..offset(j - 1)]);
+            }
+            start = j - 1;
+            i = j;
+            class = char_class(chars[i].1);
+        }
+
+        let limit = if class == CharClass::Digit {
+            MAX_DIGIT_CHARS
+        } else {
+            MAX_CHUNK_CHARS
+        };
+        let mut j = i + 1;
+        while j < n && char_class(chars[j].1) == class && j - i < limit {
+            j += 1;
+        }
+        chunks.push(&text[offset(start)..offset(j)]);
+        i = j;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toy_bpe() -> Bpe {
+        let mut data = String::new();
+        for b in 0..=255u8 {
+            data.push_str(&format!("{} {}\n", STANDARD.encode([b]), b));
+        }
+        for (rank, token) in ["he", "ll", "hell", "hello", " w", "or"].iter().enumerate() {
+            data.push_str(&format!("{} {}\n", STANDARD.encode(token), 256 + rank));
+        }
+        Bpe::from_tiktoken(&data).unwrap()
+    }
+
+    #[test]
+    fn test_pre_tokenize() {
+        assert_eq!(
+            pre_tokenize("  x  fn main() {\n    let a = 12345;\n}\t \n你好，世界 end "),
+            vec![
+                " ", " x", " ", " fn", " main", "()", " {", "\n   ", " let", " a", " =", " 123",
+                "45", ";", "\n", "}", "\t \n", "你好", "，", "世界", " end", " ",
+            ]
+        );
+        assert!(pre_tokenize("").is_empty());
+        assert_eq!(pre_tokenize(&"a".repeat(130)).len(), 3);
+    }
+
+    #[test]
+    fn test_count_merges_by_rank() {
+        let bpe = toy_bpe();
+        assert_eq!(bpe.vocab_size(), 262);
+        // "hello" 整体在词表中
+        assert_eq!(bpe.count("hello"), 1);
+        // " world" → " w" + "or" + "l" + "d"
+        assert_eq!(bpe.count(" world"), 4);
+        assert_eq!(bpe.count("hello world"), 5);
+        assert_eq!(bpe.count(""), 0);
+        // 非 ASCII 文本按 UTF-8 字节回退
+        assert_eq!(bpe.count("你"), 3);
+    }
+
+    #[test]
+    fn test_from_tiktoken_rejects_invalid_vocab() {
+        assert!(Bpe::from_tiktoken("aGVsbG8= 0\n").is_err());
+        assert!(Bpe::from_tiktoken("not-base64! 0\n").is_err());
+        assert!(Bpe::from_tiktoken("aGVsbG8=\n").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! 图片 token 估算
+//!
+//! 按 Anthropic 视觉文档（<https://docs.anthropic.com/en/docs/build-with-claude/vision>）的
+//! 近似公式：`tokens = 宽 × 高 / 750`。长边超过 1568px 的图片会先等比缩放，
+//! 单张图片最多约 1600 tokens。无法读取尺寸（URL 来源、格式不支持）时按上限计。
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::Value;
+
+/// 长边超过该像素数时等比缩放
+const MAX_LONG_EDGE: f64 = 1568.0;
+
+/// 每 token 对应的像素数
+const PIXELS_PER_TOKEN: f64 = 750.0;
+
+/// 单张图片的 token 上限
+pub(crate) const MAX_IMAGE_TOKENS: u64 = 1600;
+
+/// 按图片尺寸估算 token 数
+pub(crate) fn image_tokens(width: usize, height: usize) -> u64 {
+    let (mut width, mut height) = (width as f64, height as f64);
+    let long_edge = width.max(height);
+    if long_edge > MAX_LONG_EDGE {
+        let scale = MAX_LONG_EDGE / long_edge;
+        width *= scale;
+        height *= scale;
+    }
+    ((width * height / PIXELS_PER_TOKEN).ceil() as u64).clamp(1, MAX_IMAGE_TOKENS)
+}
+
+/// 估算 `image` 内容块的 token 数
+pub(crate) fn image_block_tokens(block: &Value) -> u64 {
+    let source = &block["source"];
+    match source["data"].as_str() {
+        Some(data) if source["type"].as_str() == Some("base64") => base64_image_tokens(data),
+        _ => MAX_IMAGE_TOKENS,
+    }
+}
+
+/// 估算 base64 编码图片的 token 数
+pub(crate) fn base64_image_tokens(data: &str) -> u64 {
+    STANDARD
+        .decode(data)
+        .ok()
+        .and_then(|bytes| imagesize::blob_size(&bytes).ok())
+        .map_or(MAX_IMAGE_TOKENS, |size| {
+            image_tokens(size.width, size.height)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 1x1 透明 PNG
+    const PNG_1X1: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+    #[test]
+    fn test_image_tokens() {
+        // 文档示例：200x200 约 54 tokens，1000x1000 约 1334 tokens
+        assert_eq!(image_tokens(200, 200), 54);
+        assert_eq!(image_tokens(1000, 1000), 1334);
+        // 1092x1092 接近上限
+        assert_eq!(image_tokens(1092, 1092), 1590);
+        // 超大图片先缩放再封顶
+        assert_eq!(image_tokens(4000, 3000), MAX_IMAGE_TOKENS);
+        assert_eq!(image_tokens(1, 1), 1);
+    }
+
+    #[test]
+    fn test_image_block_tokens() {
+        let block = json!({
+            "type": "image",
+            "source": {"type": "base64", "media_type": "image/png", "data": PNG_1X1}
+        });
+        assert_eq!(image_block_tokens(&block), 1);
+
+        let url =
+            json!({"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}});
+        assert_eq!(image_block_tokens(&url), MAX_IMAGE_TOKENS);
+
+        let broken = json!({"type": "image", "source": {"type": "base64", "data": "AAAA"}});
+        assert_eq!(image_block_tokens(&broken), MAX_IMAGE_TOKENS);
+    }
+}
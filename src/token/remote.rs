@@ -0,0 +1,459 @@
+//! 远程 count_tokens API
+//!
+//! - 结果按请求内容的 SHA-256 缓存，相同请求不会重复调用远程 API
+//! - 连续失败 `failureThreshold` 次后熔断 `cooldownSecs` 秒，期间直接使用本地估算；
+//!   到期后放行一次探测请求，成功则恢复，失败则重新熔断
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::anthropic::types::{CountTokensRequest, CountTokensResponse};
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::{CountTokensRemoteConfig, TlsBackend};
+
+/// Count Tokens API 配置
+#[derive(Clone, Default)]
+pub struct CountTokensConfig {
+    /// 外部 count_tokens API 地址
+    pub api_url: Option<String>,
+    /// count_tokens API 密钥
+    pub api_key: Option<String>,
+    /// count_tokens API 认证类型（"x-api-key" 或 "bearer"）
+    pub auth_type: String,
+    /// 超时、结果缓存与熔断配置
+    pub remote: CountTokensRemoteConfig,
+    /// 代理配置
+    pub proxy: Option<ProxyConfig>,
+
+    pub tls_backend: TlsBackend,
+}
+
+/// 全局配置存储
+static COUNT_TOKENS_CONFIG: LazyLock<RwLock<Option<Arc<CountTokensConfig>>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+/// 按内容哈希缓存的远程计数结果
+static RESULT_CACHE: LazyLock<Mutex<ResultCache>> =
+    LazyLock::new(|| Mutex::new(ResultCache::default()));
+
+/// 远程 API 熔断器
+static BREAKER: LazyLock<Mutex<CircuitBreaker>> =
+    LazyLock::new(|| Mutex::new(CircuitBreaker::default()));
+
+/// 初始化（或替换）count_tokens 配置
+///
+/// 应在应用启动和配置热重载时调用
+pub fn init_config(config: CountTokensConfig) {
+    RESULT_CACHE
+        .lock()
+        .shrink_to(config.remote.cache_max_entries);
+    *COUNT_TOKENS_CONFIG.write() = Some(Arc::new(config));
+}
+
+/// 获取配置
+fn get_config() -> Option<Arc<CountTokensConfig>> {
+    COUNT_TOKENS_CONFIG.read().clone()
+}
+
+/// 调用远程 API 计数（命中缓存时直接返回）
+///
+/// 未配置远程 API、熔断中或调用失败时返回 None，由调用方回退到本地计算
+pub(super) async fn count(request: &CountTokensRequest) -> Option<u64> {
+    let config = get_config()?;
+    let api_url = config.api_url.as_deref()?;
+
+    let key = cache_key(api_url, request);
+    if let Some(tokens) = RESULT_CACHE.lock().get(&key) {
+        tracing::debug!("count_tokens 命中缓存: {}", tokens);
+        return Some(tokens);
+    }
+
+    if !BREAKER.lock().try_acquire(&config.remote, Instant::now()) {
+        tracing::debug!("远程 count_tokens API 熔断中，使用本地计算");
+        return None;
+    }
+
+    match call_remote_count_tokens(api_url, &config, request).await {
+        Ok(tokens) => {
+            tracing::debug!("远程 count_tokens API 返回: {}", tokens);
+            BREAKER.lock().record_success();
+            RESULT_CACHE
+                .lock()
+                .insert(key, tokens, config.remote.cache_max_entries);
+            Some(tokens)
+        }
+        Err(e) => {
+            let tripped =
+                BREAKER
+                    .lock()
+                    .record_failure(e.to_string(), &config.remote, Instant::now());
+            if tripped {
+                tracing::warn!(
+                    "远程 count_tokens API 调用失败，熔断 {} 秒（期间使用本地计算）: {}",
+                    config.remote.cooldown_secs,
+                    e
+                );
+            } else {
+                tracing::warn!("远程 count_tokens API 调用失败，回退到本地计算: {}", e);
+            }
+            None
+        }
+    }
+}
+
+/// 调用远程 count_tokens API
+async fn call_remote_count_tokens(
+    api_url: &str,
+    config: &CountTokensConfig,
+    request: &CountTokensRequest,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let client = build_client(
+        config.proxy.as_ref(),
+        config.remote.timeout_secs,
+        config.tls_backend,
+    )?;
+
+    // 构建请求
+    let mut req_builder = client.post(api_url);
+
+    // 设置认证头
+    if let Some(api_key) = &config.api_key {
+        if config.auth_type == "bearer" {
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", api_key));
+        } else {
+            req_builder = req_builder.header("x-api-key", api_key);
+        }
+    }
+
+    // 发送请求
+    let response = req_builder
+        .header("Content-Type", "application/json")
+        .json(request)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(format!("API 返回错误状态: {}", response.status()).into());
+    }
+
+    let result: CountTokensResponse = response.json().await?;
+    Ok(result.input_tokens as u64)
+}
+
+/// 缓存键：API 地址与请求内容的 SHA-256
+///
+/// 先转换为 `serde_json::Value`（对象键有序），保证 `input_schema` 等 HashMap 字段的序列化结果稳定
+fn cache_key(api_url: &str, request: &CountTokensRequest) -> [u8; 32] {
+    let value = serde_json::to_value(request).unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(api_url.as_bytes());
+    hasher.update([0]);
+    hasher.update(value.to_string().as_bytes());
+    hasher.finalize().into()
+}
+
+/// 远程计数结果缓存（超出上限时淘汰最早写入的条目）
+#[derive(Default)]
+struct ResultCache {
+    entries: HashMap<[u8; 32], u64>,
+    order: VecDeque<[u8; 32]>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ResultCache {
+    fn get(&mut self, key: &[u8; 32]) -> Option<u64> {
+        let tokens = self.entries.get(key).copied();
+        match tokens {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        tokens
+    }
+
+    fn insert(&mut self, key: [u8; 32], tokens: u64, max_entries: usize) {
+        if self.entries.insert(key, tokens).is_none() {
+            self.order.push_back(key);
+        }
+        self.shrink_to(max_entries);
+    }
+
+    fn shrink_to(&mut self, max_entries: usize) {
+        while self.entries.len() > max_entries {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// 熔断器状态
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BreakerState {
+    /// 正常调用远程 API
+    #[default]
+    Closed,
+    /// 熔断中，直接使用本地计算
+    Open,
+    /// 熔断到期，正在放行探测请求
+    HalfOpen,
+}
+
+/// 远程 API 熔断器
+#[derive(Default)]
+struct CircuitBreaker {
+    state: BreakerState,
+    /// Open：熔断到期时间；HalfOpen：探测请求的超时时间
+    deadline: Option<Instant>,
+    consecutive_failures: u32,
+    /// 累计熔断次数
+    trips: u64,
+    open_until: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    last_failure_at: Option<DateTime<Utc>>,
+}
+
+impl CircuitBreaker {
+    /// 是否放行一次远程调用
+    fn try_acquire(&mut self, remote: &CountTokensRemoteConfig, now: Instant) -> bool {
+        let expired = self.deadline.is_none_or(|deadline| now >= deadline);
+        match self.state {
+            BreakerState::Closed => true,
+            // 探测请求超时仍未返回（如请求被取消）时允许重新探测
+            BreakerState::Open | BreakerState::HalfOpen if expired => {
+                self.state = BreakerState::HalfOpen;
+                self.deadline = Some(now + Duration::from_secs(remote.timeout_secs));
+                true
+            }
+            BreakerState::Open | BreakerState::HalfOpen => false,
+        }
+    }
+
+    fn record_success(&mut self) {
+        self.state = BreakerState::Closed;
+        self.deadline = None;
+        self.open_until = None;
+        self.consecutive_failures = 0;
+    }
+
+    /// 记录一次失败，返回是否因此进入熔断
+    fn record_failure(
+        &mut self,
+        error: String,
+        remote: &CountTokensRemoteConfig,
+        now: Instant,
+    ) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.last_error = Some(error);
+        self.last_failure_at = Some(Utc::now());
+        if self.state != BreakerState::HalfOpen
+            && self.consecutive_failures < remote.failure_threshold
+        {
+            return false;
+        }
+        let cooldown = Duration::from_secs(remote.cooldown_secs);
+        self.state = BreakerState::Open;
+        self.deadline = Some(now + cooldown);
+        self.open_until = chrono::Duration::from_std(cooldown)
+            .ok()
+            .map(|cooldown| Utc::now() + cooldown);
+        self.trips += 1;
+        true
+    }
+}
+
+/// 远程 count_tokens 状态（Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensStatus {
+    /// 是否配置了远程 API
+    pub remote_enabled: bool,
+    pub breaker: BreakerStatus,
+    pub cache: CountTokensCacheStats,
+}
+
+/// 熔断器状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// 累计熔断次数
+    pub trips: u64,
+    /// 熔断到期时间（仅 open 状态）
+    pub open_until: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+}
+
+/// 结果缓存统计
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensCacheStats {
+    pub entries: usize,
+    pub max_entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// 获取远程 count_tokens 的熔断器与缓存状态
+pub fn status() -> CountTokensStatus {
+    let config = get_config();
+    let breaker = BREAKER.lock();
+    let cache = RESULT_CACHE.lock();
+    CountTokensStatus {
+        remote_enabled: config.as_ref().is_some_and(|c| c.api_url.is_some()),
+        breaker: BreakerStatus {
+            state: breaker.state,
+            consecutive_failures: breaker.consecutive_failures,
+            trips: breaker.trips,
+            open_until: breaker
+                .open_until
+                .filter(|_| breaker.state == BreakerState::Open),
+            last_error: breaker.last_error.clone(),
+            last_failure_at: breaker.last_failure_at,
+        },
+        cache: CountTokensCacheStats {
+            entries: cache.entries.len(),
+            max_entries: config.map_or(0, |c| c.remote.cache_max_entries),
+            hits: cache.hits,
+            misses: cache.misses,
+        },
+    }
+}
+
+/// 关闭熔断器并清空结果缓存
+pub fn reset() {
+    *BREAKER.lock() = CircuitBreaker::default();
+    let mut cache = RESULT_CACHE.lock();
+    cache.clear();
+    cache.hits = 0;
+    cache.misses = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote_config() -> CountTokensRemoteConfig {
+        CountTokensRemoteConfig {
+            timeout_secs: 5,
+            cache_max_entries: 2,
+            failure_threshold: 2,
+            cooldown_secs: 30,
+        }
+    }
+
+    #[test]
+    fn test_breaker_opens_after_threshold_and_probes_after_cooldown() {
+        let remote = remote_config();
+        let mut breaker = CircuitBreaker::default();
+        let now = Instant::now();
+
+        assert!(breaker.try_acquire(&remote, now));
+        assert!(!breaker.record_failure("timeout".to_string(), &remote, now));
+        assert!(breaker.try_acquire(&remote, now));
+        assert!(breaker.record_failure("timeout".to_string(), &remote, now));
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert_eq!(breaker.trips, 1);
+
+        // 冷却期内直接拒绝
+        assert!(!breaker.try_acquire(&remote, now + Duration::from_secs(10)));
+
+        // 到期后只放行一个探测请求
+        let probe = now + Duration::from_secs(31);
+        assert!(breaker.try_acquire(&remote, probe));
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        assert!(!breaker.try_acquire(&remote, probe));
+
+        // 探测失败立即重新熔断
+        assert!(breaker.record_failure("500".to_string(), &remote, probe));
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert_eq!(breaker.trips, 2);
+
+        // 探测成功后恢复
+        let probe = probe + Duration::from_secs(31);
+        assert!(breaker.try_acquire(&remote, probe));
+        breaker.record_success();
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert_eq!(breaker.consecutive_failures, 0);
+        assert!(breaker.try_acquire(&remote, probe));
+    }
+
+    #[test]
+    fn test_breaker_reprobes_when_probe_never_returns() {
+        let remote = remote_config();
+        let mut breaker = CircuitBreaker::default();
+        let now = Instant::now();
+        breaker.record_failure("a".to_string(), &remote, now);
+        breaker.record_failure("b".to_string(), &remote, now);
+
+        let probe = now + Duration::from_secs(31);
+        assert!(breaker.try_acquire(&remote, probe));
+        // 探测请求超过超时时间仍未返回，允许重新探测
+        assert!(!breaker.try_acquire(&remote, probe + Duration::from_secs(4)));
+        assert!(breaker.try_acquire(&remote, probe + Duration::from_secs(6)));
+    }
+
+    #[test]
+    fn test_result_cache_evicts_oldest() {
+        let mut cache = ResultCache::default();
+        cache.insert([1; 32], 10, 2);
+        cache.insert([2; 32], 20, 2);
+        cache.insert([1; 32], 11, 2);
+        cache.insert([3; 32], 30, 2);
+        assert_eq!(cache.get(&[1; 32]), None);
+        assert_eq!(cache.get(&[2; 32]), Some(20));
+        assert_eq!(cache.get(&[3; 32]), Some(30));
+        assert_eq!((cache.hits, cache.misses), (2, 1));
+
+        cache.shrink_to(0);
+        assert!(cache.entries.is_empty());
+        cache.insert([4; 32], 40, 0);
+        assert_eq!(cache.get(&[4; 32]), None);
+    }
+
+    #[test]
+    fn test_cache_key_is_stable_for_equal_requests() {
+        let request = |schema: serde_json::Value| -> CountTokensRequest {
+            serde_json::from_value(serde_json::json!({
+                "model": "claude-sonnet-4-6",
+                "messages": [{"role": "user", "content": "hi"}],
+                "tools": [{"name": "t", "description": "d", "input_schema": schema}]
+            }))
+            .unwrap()
+        };
+        let schema = serde_json::json!({"type": "object", "properties": {"a": {}}, "required": []});
+        let key = cache_key("https://api.example.com", &request(schema.clone()));
+        for _ in 0..8 {
+            assert_eq!(
+                key,
+                cache_key("https://api.example.com", &request(schema.clone()))
+            );
+        }
+        assert_ne!(
+            key,
+            cache_key("https://other.example.com", &request(schema))
+        );
+        assert_ne!(
+            key,
+            cache_key(
+                "https://api.example.com",
+                &request(serde_json::json!({"type": "object"}))
+            )
+        );
+    }
+}
@@ -0,0 +1,185 @@
+#!/usr/bin/env python3
+"""训练 BPE 词表（tiktoken 格式：每行 `base64(token) rank`），供 `tokenizer.vocabFile` 使用
+
+预分词规则与 src/token/bpe.rs 中的 `pre_tokenize` 保持一致，修改任一侧时需要同步修改。
+
+用法:
+    python3 tools/train_bpe_vocab.py --out vocab.tiktoken \\
+        --repeat README.md src admin-ui/src -- \\
+        /usr/share/vim/vim90/doc /usr/share/perl/5.36.0/pod /usr/lib/python3.11/pydoc_data \\
+        ~/.cargo/registry/src
+
+目录按扩展名（.md .rs .ts .tsx .txt .pod .py）递归收集文本，`--repeat` 指定的路径会重复计入，
+用于提高中文等小语料的权重。
+"""
+
+import argparse
+import base64
+import collections
+import heapq
+import os
+import sys
+
+MAX_CHUNK_CHARS = 64
+EXTENSIONS = (".md", ".rs", ".ts", ".tsx", ".txt", ".pod", ".py")
+REPEAT_WEIGHT = 3
+
+
+def char_class(c):
+    if c in "0123456789":
+        return "digit"
+    if c.isspace():
+        return "space"
+    if c.isalpha():
+        return "letter"
+    return "other"
+
+
+def pre_tokenize(text):
+    """与 bpe.rs 的 pre_tokenize 相同的切分规则"""
+    chunks = []
+    chars = list(text)
+    n = len(chars)
+    i = 0
+    while i < n:
+        cls = char_class(chars[i])
+        start = i
+        if cls == "space":
+            j = i
+            while j < n and char_class(chars[j]) == "space":
+                j += 1
+            if j == n or chars[j - 1] != " ":
+                chunks.append("".join(chars[i:j]))
+                i = j
+                continue
+            # 空白后紧跟非空白时，最后一个空格并入下一个分块
+            if j - 1 > i:
+                chunks.append("".join(chars[i : j - 1]))
+            start = j - 1
+            i = j
+            cls = char_class(chars[i])
+        j = i + 1
+        limit = 3 if cls == "digit" else MAX_CHUNK_CHARS
+        while j < n and char_class(chars[j]) == cls and j - i < limit:
+            j += 1
+        chunks.append("".join(chars[start:j]))
+        i = j
+    return chunks
+
+
+def collect_files(paths):
+    for path in paths:
+        path = os.path.expanduser(path)
+        if os.path.isfile(path):
+            yield path
+            continue
+        for root, dirs, files in os.walk(path):
+            dirs[:] = sorted(d for d in dirs if d not in ("target", "node_modules", ".git"))
+            for name in sorted(files):
+                if name.endswith(EXTENSIONS):
+                    yield os.path.join(root, name)
+
+
+def count_chunks(paths, repeat, max_bytes):
+    counts = collections.Counter()
+    total = 0
+    for weight, group in ((REPEAT_WEIGHT, repeat), (1, paths)):
+        for file in collect_files(group):
+            try:
+                with open(file, encoding="utf-8") as f:
+                    text = f.read()
+            except (UnicodeDecodeError, OSError):
+                continue
+            for chunk in pre_tokenize(text):
+                counts[chunk.encode("utf-8")] += weight
+            total += len(text.encode("utf-8"))
+            if total >= max_bytes:
+                return counts
+    return counts
+
+
+def train(counts, vocab_size, min_count):
+    vocab = [bytes([b]) for b in range(256)]
+    words = []
+    freqs = []
+    for chunk, count in counts.items():
+        if count >= min_count and len(chunk) > 1:
+            words.append(list(chunk))
+            freqs.append(count)
+
+    pair_counts = collections.defaultdict(int)
+    where = collections.defaultdict(set)
+    for idx, word in enumerate(words):
+        for pair in zip(word, word[1:]):
+            pair_counts[pair] += freqs[idx]
+            where[pair].add(idx)
+
+    heap = [(-count, pair) for pair, count in pair_counts.items()]
+    heapq.heapify(heap)
+
+    while len(vocab) < vocab_size and heap:
+        neg, pair = heapq.heappop(heap)
+        count = pair_counts.get(pair, 0)
+        if count <= 0:
+            continue
+        if -neg != count:
+            heapq.heappush(heap, (-count, pair))
+            continue
+        new_id = len(vocab)
+        vocab.append(vocab[pair[0]] + vocab[pair[1]])
+
+        changed = collections.defaultdict(int)
+        for idx in list(where.pop(pair, ())):
+            word = words[idx]
+            freq = freqs[idx]
+            for p in zip(word, word[1:]):
+                changed[p] -= freq
+                if p in where:
+                    where[p].discard(idx)
+            merged = []
+            i = 0
+            while i < len(word):
+                if i + 1 < len(word) and word[i] == pair[0] and word[i + 1] == pair[1]:
+                    merged.append(new_id)
+                    i += 2
+                else:
+                    merged.append(word[i])
+                    i += 1
+            words[idx] = merged
+            for p in zip(merged, merged[1:]):
+                changed[p] += freq
+                where[p].add(idx)
+        for p, delta in changed.items():
+            if delta == 0:
+                continue
+            pair_counts[p] += delta
+            if pair_counts[p] <= 0:
+                pair_counts.pop(p, None)
+            elif delta > 0:
+                heapq.heappush(heap, (-pair_counts[p], p))
+        pair_counts.pop(pair, None)
+        if len(vocab) % 1000 == 0:
+            print(f"{len(vocab)} tokens", file=sys.stderr)
+    return vocab
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--vocab-size", type=int, default=32768)
+    parser.add_argument("--min-count", type=int, default=2)
+    parser.add_argument("--max-bytes", type=int, default=200_000_000)
+    parser.add_argument("--repeat", nargs="*", default=[])
+    parser.add_argument("--out", required=True)
+    parser.add_argument("paths", nargs="+")
+    args = parser.parse_args()
+
+    counts = count_chunks(args.paths, args.repeat, args.max_bytes)
+    print(f"{len(counts)} distinct chunks", file=sys.stderr)
+    vocab = train(counts, args.vocab_size, args.min_count)
+    with open(args.out, "w", encoding="ascii") as f:
+        for rank, token in enumerate(vocab):
+            f.write(f"{base64.b64encode(token).decode()} {rank}\n")
+
+
+if __name__ == "__main__":
+    main()